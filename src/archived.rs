@@ -0,0 +1,33 @@
+use rkyv::{
+    api::high::{HighSerializer, HighValidator},
+    bytecheck::CheckBytes,
+    rancor,
+    ser::allocator::ArenaHandle,
+    util::AlignedVec,
+    Archive, Portable, Serialize,
+};
+
+use crate::{bucket::Bucket, error::Result};
+
+impl Bucket {
+    // archive value with rkyv and put it
+    pub fn put_archived<T>(&mut self, key: impl AsRef<[u8]>, value: &T) -> Result<()>
+    where
+        T: for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rancor::Error>>,
+    {
+        let bytes = rkyv::to_bytes::<rancor::Error>(value)?;
+        self.put(key, bytes.as_slice())
+    }
+
+    // access an archived value in place. the archive is validated but never
+    // deserialized, committed values are read straight from the mmap
+    pub fn get_archived<T: Archive>(&self, key: impl AsRef<[u8]>) -> Result<Option<&T::Archived>>
+    where
+        T::Archived: Portable + for<'a> CheckBytes<HighValidator<'a, rancor::Error>>,
+    {
+        match self.get(key) {
+            None => Ok(None),
+            Some(bytes) => Ok(Some(rkyv::access::<T::Archived, rancor::Error>(bytes)?)),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::{path::Path, sync::mpsc, thread};
+
+use tokio::sync::oneshot;
+
+use crate::{
+    db::{DBBuilder, DB},
+    error::{Result, RoltError},
+    transaction::Transaction,
+};
+
+type Job = Box<dyn FnOnce(&DB) + Send>;
+
+// runs transactions on a blocking thread so async services never stall their
+// executor on commits and fsyncs. DB handles are not Send, so the db lives on
+// a dedicated thread rather than tokio's shared spawn_blocking pool. dropping
+// it lets the thread finish queued jobs and close the db in the background,
+// close waits for that
+pub struct AsyncDB {
+    jobs: mpsc::Sender<Job>,
+    // resolves once the worker closed the db
+    closed: oneshot::Receiver<()>,
+}
+
+impl AsyncDB {
+    pub async fn open<P: AsRef<Path>>(p: P) -> Result<Self> {
+        Self::open_with(DBBuilder::default(), p).await
+    }
+
+    pub async fn open_with<P: AsRef<Path>>(builder: DBBuilder, p: P) -> Result<Self> {
+        let path = p.as_ref().to_path_buf();
+        let (jobs, rx) = mpsc::channel::<Job>();
+        let (opened_tx, opened) = oneshot::channel();
+        let (closed_tx, closed) = oneshot::channel();
+        thread::Builder::new()
+            .name("roltdb".to_string())
+            .spawn(move || {
+                let db = match builder.open(path) {
+                    Ok(db) => {
+                        let _ = opened_tx.send(Ok(()));
+                        db
+                    }
+                    Err(e) => {
+                        let _ = opened_tx.send(Err(e));
+                        return;
+                    }
+                };
+                while let Ok(job) = rx.recv() {
+                    job(&db);
+                }
+                drop(db);
+                let _ = closed_tx.send(());
+            })?;
+        opened
+            .await
+            .map_err(|_| RoltError::from("db worker stopped"))??;
+        Ok(Self { jobs, closed })
+    }
+
+    // run the jobs queued so far and close the db, without blocking the
+    // executor while the worker finishes
+    pub async fn close(self) -> Result<()> {
+        let Self { jobs, closed } = self;
+        drop(jobs);
+        closed
+            .await
+            .map_err(|_| RoltError::from("db worker stopped"))
+    }
+
+    // run f in a writable tx, commit if it succeeds and roll back otherwise
+    pub async fn update<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&Transaction) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.run(move |db| {
+            let tx = db.tx(true)?;
+            match f(&tx) {
+                Ok(r) => {
+                    tx.commit()?;
+                    Ok(r)
+                }
+                Err(e) => {
+                    tx.rollback()?;
+                    Err(e)
+                }
+            }
+        })
+        .await
+    }
+
+    // run f in a read-only tx
+    pub async fn view<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&Transaction) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.run(move |db| {
+            let tx = db.tx(false)?;
+            f(&tx)
+        })
+        .await
+    }
+
+    async fn run<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&DB) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move |db| {
+            let _ = tx.send(f(db));
+        });
+        self.jobs
+            .send(job)
+            .map_err(|_| RoltError::from("db worker stopped"))?;
+        rx.await.map_err(|_| RoltError::from("db worker stopped"))?
+    }
+}
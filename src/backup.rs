@@ -0,0 +1,276 @@
+use std::{
+    fmt::Debug,
+    fs::OpenOptions,
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::{Result, RoltError},
+    page::{page_offset, Page, PageId},
+    transaction::{ITransaction, Txid},
+    Err,
+};
+
+// pages copied between progress reports and throttling checks
+const CHUNK_PAGES: u64 = 64;
+// first word of an incremental backup
+const INCREMENTAL_MAGIC: u64 = u64::from_le_bytes(*b"roltincr");
+
+pub type Progress = Box<dyn Fn(u64, u64)>;
+
+#[derive(Default)]
+pub struct BackupOptions {
+    // cap on bytes written per second, None copies as fast as possible
+    pub rate_limit_bytes_per_sec: Option<u64>,
+    // called with bytes done and total bytes after every chunk
+    pub progress: Option<Progress>,
+}
+
+// where a backup streams to, e.g. an object store upload. chunks arrive in
+// file order and the copy is complete once finish returns. a failing sink
+// fails the backup with its error
+pub trait SnapshotSink {
+    // called before the first chunk with the size of the copy in bytes
+    fn open(&mut self, total: u64) -> Result<()>;
+    fn write_chunk(&mut self, chunk: &[u8]) -> Result<()>;
+    fn finish(&mut self) -> Result<()>;
+    // called instead of finish when the backup fails after open, e.g. to
+    // discard a partial upload
+    fn abort(&mut self) {}
+}
+
+// a sink writing to any io::Write, the target of write_to and backup
+#[derive(Debug)]
+pub struct WriterSink<W: Write>(pub W);
+
+impl<W: Write> SnapshotSink for WriterSink<W> {
+    fn open(&mut self, _total: u64) -> Result<()> {
+        Ok(())
+    }
+    fn write_chunk(&mut self, chunk: &[u8]) -> Result<()> {
+        Ok(self.0.write_all(chunk)?)
+    }
+    fn finish(&mut self) -> Result<()> {
+        Ok(self.0.flush()?)
+    }
+}
+
+impl Debug for BackupOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BackupOptions")
+            .field("rate_limit_bytes_per_sec", &self.rate_limit_bytes_per_sec)
+            .finish()
+    }
+}
+
+impl ITransaction {
+    // write a consistent copy of the db as seen by this tx, returns the
+    // number of bytes written
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<u64> {
+        self.backup(w, BackupOptions::default())
+    }
+
+    // like write_to, reporting progress and throttling as configured
+    pub fn backup<W: Write>(&self, w: &mut W, opts: BackupOptions) -> Result<u64> {
+        self.backup_to(&mut WriterSink(w), opts)
+    }
+
+    // stream a consistent copy into sink, returns the number of bytes written
+    pub fn backup_to<S: SnapshotSink + ?Sized>(
+        &self,
+        sink: &mut S,
+        opts: BackupOptions,
+    ) -> Result<u64> {
+        if opts.rate_limit_bytes_per_sec == Some(0) {
+            return Err!("backup rate limit must be positive");
+        }
+        let db = self.db()?;
+        let page_size = db.page_size();
+        let total = self.meta().num_pages * page_size;
+        sink.open(total)?;
+        match self.copy_to(sink, page_size, total, &opts) {
+            Ok(done) => {
+                sink.finish()?;
+                Ok(done)
+            }
+            Err(e) => {
+                sink.abort();
+                Err(e)
+            }
+        }
+    }
+
+    fn copy_to<S: SnapshotSink + ?Sized>(
+        &self,
+        sink: &mut S,
+        page_size: u64,
+        total: u64,
+        opts: &BackupOptions,
+    ) -> Result<u64> {
+        let meta = self.meta();
+        let start = Instant::now();
+        let mut done = 0;
+        // both meta pages point at this tx's version
+        for id in 0..2 {
+            let mut buf = vec![0u8; page_size as usize];
+            let p = Page::from_buf_mut(&mut buf, 0, 0)?;
+            let mut m = meta.clone();
+            m.page_id = id;
+            m.write(p)?;
+            p.id = id;
+            sink.write_chunk(&buf)?;
+            done += page_size;
+        }
+        let mut id: PageId = 2;
+        while id < meta.num_pages {
+            let end = (id + CHUNK_PAGES).min(meta.num_pages);
+            let chunk = page_offset(id, page_size)
+                .zip(page_offset(end, page_size))
+                .and_then(|(start, end)| self.mmap().get(start..end))
+                .ok_or(RoltError::PageOutOfRange(end - 1))?;
+            sink.write_chunk(chunk)?;
+            done += chunk.len() as u64;
+            id = end;
+            if let Some(progress) = &opts.progress {
+                progress(done, total);
+            }
+            if let Some(rate) = opts.rate_limit_bytes_per_sec {
+                let expected = Duration::from_secs_f64(done as f64 / rate as f64);
+                if let Some(ahead) = expected.checked_sub(start.elapsed()) {
+                    thread::sleep(ahead);
+                }
+            }
+        }
+        Ok(done)
+    }
+}
+
+impl ITransaction {
+    // write every page last written after base_txid, preceded by a manifest:
+    // magic, base txid, txid, page size, page count, number of entries and
+    // (page id, blocks) per entry, all little-endian u64. the meta page of this
+    // tx follows the manifest, then the page data. returns the number of pages
+    pub fn write_changed_since<W: Write>(&self, base_txid: Txid, w: &mut W) -> Result<u64> {
+        let db = self.db()?;
+        let page_size = db.page_size();
+        let meta = self.meta();
+        let mut changed = Vec::new();
+        let free = self.free_pages()?;
+        let mut id: PageId = 2;
+        while id < meta.num_pages {
+            if free.contains(&id) {
+                id += 1;
+                continue;
+            }
+            if (id + 1) * page_size > self.mmap().len() as u64 {
+                return Err!(RoltError::PageOutOfRange(id));
+            }
+            let page = self.page(id)?;
+            let blocks = page.overflow as u64 + 1;
+            if page.txid > base_txid {
+                changed.push((id, blocks));
+            }
+            id += blocks;
+        }
+
+        let header = [
+            INCREMENTAL_MAGIC,
+            base_txid,
+            meta.tx_id,
+            page_size,
+            meta.num_pages,
+            changed.len() as u64,
+        ];
+        let entries = changed.iter().flat_map(|&(id, blocks)| [id, blocks]);
+        for word in header.into_iter().chain(entries) {
+            w.write_all(&word.to_le_bytes())?;
+        }
+        let mut buf = vec![0u8; page_size as usize];
+        let mut m = meta.clone();
+        m.write(Page::from_buf_mut(&mut buf, 0, 0)?)?;
+        w.write_all(&buf)?;
+        let mut pages = 0;
+        for (id, blocks) in changed {
+            let data = page_offset(id, page_size)
+                .zip(page_offset(id + blocks, page_size))
+                .and_then(|(start, end)| self.mmap().get(start..end))
+                .ok_or(RoltError::PageOutOfRange(id))?;
+            w.write_all(data)?;
+            pages += blocks;
+        }
+        w.flush()?;
+        Ok(pages)
+    }
+}
+
+// apply an incremental backup written by write_changed_since onto a full
+// backup taken at or after its base txid, returns the txid it now holds
+pub fn apply_incremental<P: AsRef<Path>, R: Read>(backup: P, r: &mut R) -> Result<Txid> {
+    let mut word = || -> Result<u64> {
+        let mut b = [0u8; 8];
+        r.read_exact(&mut b)?;
+        Ok(u64::from_le_bytes(b))
+    };
+    if word()? != INCREMENTAL_MAGIC {
+        return Err!("not an incremental backup");
+    }
+    let (base_txid, tx_id, page_size, num_pages, count) =
+        (word()?, word()?, word()?, word()?, word()?);
+    let entries = (0..count)
+        .map(|_| Ok((word()?, word()?)))
+        .collect::<Result<Vec<(PageId, u64)>>>()?;
+
+    let mut file = OpenOptions::new().read(true).write(true).open(backup)?;
+    let mut metas = vec![0u8; 2 * page_size as usize];
+    file.read_exact(&mut metas)?;
+    let current = (0..2)
+        .filter_map(|id| Page::from_buf(&metas, id, page_size).ok()?.meta().ok())
+        .filter(|m| m.validate())
+        .map(|m| m.tx_id)
+        .max()
+        .ok_or(RoltError::from("backup has no valid meta page"))?;
+    if current < base_txid {
+        return Err!(format!(
+            "backup is at txid {}, older than base txid {}",
+            current, base_txid
+        ));
+    }
+    // the pages of a newer backup would mix with the older ones written here
+    if current > tx_id {
+        return Err!(format!(
+            "backup is at txid {}, newer than the incremental txid {}",
+            current, tx_id
+        ));
+    }
+
+    let mut buf = vec![0u8; page_size as usize];
+    r.read_exact(&mut buf)?;
+    let meta = Page::from_buf(&buf, 0, 0)?.meta()?;
+    if !meta.validate() {
+        return Err!("incremental backup has an invalid meta page");
+    }
+    let mut meta = meta.clone();
+    for (id, blocks) in entries {
+        buf.resize((blocks * page_size) as usize, 0);
+        r.read_exact(&mut buf)?;
+        file.seek(SeekFrom::Start(id * page_size))?;
+        file.write_all(&buf)?;
+    }
+    file.set_len(file.metadata()?.len().max(num_pages * page_size))?;
+    file.sync_all()?;
+    // switch to the new version only once its pages are durable
+    for id in 0..2 {
+        let mut buf = vec![0u8; page_size as usize];
+        let p = Page::from_buf_mut(&mut buf, 0, 0)?;
+        meta.page_id = id;
+        meta.write(p)?;
+        p.id = id;
+        file.seek(SeekFrom::Start(id * page_size))?;
+        file.write_all(&buf)?;
+    }
+    file.sync_all()?;
+    Ok(tx_id)
+}
@@ -0,0 +1,172 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    db::DB,
+    error::{Result, RoltError},
+    Err,
+};
+
+// bucket the benchmark writes into
+const BENCH_BUCKET: &[u8] = b"bench";
+
+// order keys are written and read in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyOrder {
+    #[default]
+    Sequential,
+    Random,
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    // number of keys written, then read back
+    pub count: usize,
+    // keys written per write tx
+    pub batch_size: usize,
+    // at least 8, keys hold a big-endian index
+    pub key_size: usize,
+    pub value_size: usize,
+    pub order: KeyOrder,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            count: 10_000,
+            batch_size: 1000,
+            key_size: 8,
+            value_size: 32,
+            order: KeyOrder::Sequential,
+        }
+    }
+}
+
+// commit latency distribution of the write phase
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Percentiles {
+    fn from(mut samples: Vec<Duration>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_unstable();
+        let at = |p: usize| samples[(samples.len() * p / 100).min(samples.len() - 1)];
+        Self {
+            p50: at(50),
+            p90: at(90),
+            p99: at(99),
+            max: samples[samples.len() - 1],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BenchReport {
+    pub writes: u64,
+    pub write_duration: Duration,
+    pub reads: u64,
+    pub read_duration: Duration,
+    pub commit_latency: Percentiles,
+}
+
+impl BenchReport {
+    pub fn write_ops_per_sec(&self) -> f64 {
+        ops_per_sec(self.writes, self.write_duration)
+    }
+    pub fn read_ops_per_sec(&self) -> f64 {
+        ops_per_sec(self.reads, self.read_duration)
+    }
+}
+
+fn ops_per_sec(ops: u64, d: Duration) -> f64 {
+    if d.is_zero() {
+        return 0.0;
+    }
+    ops as f64 / d.as_secs_f64()
+}
+
+// write count keys in batches of batch_size, then read them all back in one
+// read tx. keys go into the "bench" bucket, which should not exist yet
+pub fn bench(db: &DB, opts: &BenchOptions) -> Result<BenchReport> {
+    if opts.key_size < 8 {
+        return Err!("bench key size must be at least 8");
+    }
+    if opts.batch_size == 0 {
+        return Err!("bench batch size must be positive");
+    }
+    let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+    let value = vec![0x42u8; opts.value_size];
+    let mut report = BenchReport::default();
+
+    let order = key_order(opts, &mut rng);
+    let mut latencies = Vec::new();
+    let start = Instant::now();
+    for batch in order.chunks(opts.batch_size) {
+        let tx = db.tx(true)?;
+        {
+            let mut b = tx.create_bucket_if_not_exist(BENCH_BUCKET)?;
+            for &i in batch {
+                b.put(key(i, opts.key_size), &value)?;
+            }
+        }
+        let now = Instant::now();
+        tx.commit()?;
+        latencies.push(now.elapsed());
+        report.writes += batch.len() as u64;
+    }
+    report.write_duration = start.elapsed();
+    report.commit_latency = Percentiles::from(latencies);
+
+    let order = key_order(opts, &mut rng);
+    let start = Instant::now();
+    {
+        let tx = db.tx(false)?;
+        let b = tx
+            .bucket(BENCH_BUCKET)
+            .ok_or(RoltError::from("bench bucket is missing"))?;
+        for i in order {
+            if b.get(key(i, opts.key_size)).is_none() {
+                return Err!(format!("bench key {} is missing", i));
+            }
+            report.reads += 1;
+        }
+    }
+    report.read_duration = start.elapsed();
+    Ok(report)
+}
+
+// index i as a big-endian number, zero padded to size bytes
+fn key(i: u64, size: usize) -> Vec<u8> {
+    let mut k = vec![0u8; size];
+    k[size - 8..].copy_from_slice(&i.to_be_bytes());
+    k
+}
+
+fn key_order(opts: &BenchOptions, rng: &mut XorShift) -> Vec<u64> {
+    let mut order: Vec<u64> = (0..opts.count as u64).collect();
+    if opts.order == KeyOrder::Random {
+        // fisher-yates
+        for i in (1..order.len()).rev() {
+            order.swap(i, (rng.next() % (i as u64 + 1)) as usize);
+        }
+    }
+    order
+}
+
+// deterministic generator, runs are comparable across configurations
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
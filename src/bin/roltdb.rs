@@ -0,0 +1,346 @@
+use std::{
+    env,
+    io::{self, BufRead, Write},
+    process,
+};
+
+use anyhow::Result;
+use roltdb::{BenchOptions, Bucket, DBBuilder, KeyOrder, Transaction, DB};
+
+const USAGE: &str = "usage: roltdb <command> [args]
+
+commands:
+    import-bolt <bolt-file> <db-file>    copy all buckets and keys of a bolt/bbolt file
+    bench <db-file> [options]            time writes and reads of generated keys
+    check <db-file>                      verify the page tree and free list
+    dump-page <db-file> <page-id>        decode a page and print it as hex
+    salvage <db-file> <new-file>         copy what can be read of a damaged file
+    shell <db-file> [--write]            browse buckets and keys interactively
+
+bench options:
+    --count <n>         keys to write and read (10000)
+    --batch-size <n>    keys per write tx (1000)
+    --key-size <n>      key bytes, at least 8 (8)
+    --value-size <n>    value bytes (32)
+    --random            write and read keys in random order
+
+shell commands:
+    ls                  list buckets in the current bucket
+    cd <name|..|/>      enter a bucket, its parent or the root
+    get <key>           print the value of key
+    scan [prefix]       print keys and values under prefix
+    put <key> <value>   set key, needs --write
+    del <key>           delete key, needs --write
+    mk <name>           create a bucket in the current one, needs --write
+    exit";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("import-bolt") => import_bolt(&args[1..]),
+        Some("bench") => bench(&args[1..]),
+        Some("check") => check(&args[1..]),
+        Some("dump-page") => dump_page(&args[1..]),
+        Some("salvage") => salvage(&args[1..]),
+        Some("shell") => shell(&args[1..]),
+        _ => usage(),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {:#}", e);
+        process::exit(1);
+    }
+}
+
+fn import_bolt(args: &[String]) -> Result<()> {
+    let (src, dest) = match args {
+        [src, dest] => (src, dest),
+        _ => usage(),
+    };
+    let db = DB::open(dest)?;
+    let stats = roltdb::import_bolt(src, &db)?;
+    println!(
+        "imported {} buckets and {} keys from {}",
+        stats.buckets, stats.keys, src
+    );
+    Ok(())
+}
+
+fn bench(args: &[String]) -> Result<()> {
+    let (path, flags) = match args {
+        [path, flags @ ..] => (path, flags),
+        _ => usage(),
+    };
+    let mut opts = BenchOptions::default();
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        if flag == "--random" {
+            opts.order = KeyOrder::Random;
+            continue;
+        }
+        let n: usize = match flags.next().map(|v| v.parse()) {
+            Some(Ok(n)) => n,
+            _ => usage(),
+        };
+        match flag.as_str() {
+            "--count" => opts.count = n,
+            "--batch-size" => opts.batch_size = n,
+            "--key-size" => opts.key_size = n,
+            "--value-size" => opts.value_size = n,
+            _ => usage(),
+        }
+    }
+    let db = DB::open(path)?;
+    let report = roltdb::bench(&db, &opts)?;
+    let latency = report.commit_latency;
+    println!(
+        "write: {} keys in {:?} ({:.0} ops/sec)",
+        report.writes,
+        report.write_duration,
+        report.write_ops_per_sec()
+    );
+    println!(
+        "commit latency: p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+        latency.p50, latency.p90, latency.p99, latency.max
+    );
+    println!(
+        "read: {} keys in {:?} ({:.0} ops/sec)",
+        report.reads,
+        report.read_duration,
+        report.read_ops_per_sec()
+    );
+    Ok(())
+}
+
+fn check(args: &[String]) -> Result<()> {
+    let path = match args {
+        [path] => path,
+        _ => usage(),
+    };
+    let db = DBBuilder::default().read_only(true).open(path)?;
+    let tx = db.tx(false)?;
+    let mut errors = 0;
+    tx.check_with(|e| {
+        println!("{}: {}\n  fix: {}", e.severity, e, e.remediation());
+        errors += 1;
+    })?;
+    if errors > 0 {
+        anyhow::bail!("found {} problems", errors);
+    }
+    println!("ok");
+    Ok(())
+}
+
+fn dump_page(args: &[String]) -> Result<()> {
+    let (path, id) = match args {
+        [path, id] => match id.parse() {
+            Ok(id) => (path, id),
+            Err(_) => usage(),
+        },
+        _ => usage(),
+    };
+    let db = DBBuilder::default().read_only(true).open(path)?;
+    let tx = db.tx(false)?;
+    print!("{}", tx.dump_page(id)?);
+    Ok(())
+}
+
+fn salvage(args: &[String]) -> Result<()> {
+    let (src, dest) = match args {
+        [src, dest] => (src, dest),
+        _ => usage(),
+    };
+    let report = roltdb::salvage(src, dest)?;
+    for e in report.skipped.iter() {
+        println!("skipped {}", e);
+    }
+    println!(
+        "recovered {} buckets and {} keys ({} in lost+found) into {}",
+        report.buckets, report.keys, report.orphan_keys, dest
+    );
+    Ok(())
+}
+
+fn shell(args: &[String]) -> Result<()> {
+    let (path, write) = match args {
+        [path] => (path, false),
+        [path, flag] if flag == "--write" => (path, true),
+        _ => usage(),
+    };
+    // read-only opens share the file with a live writer
+    let db = DBBuilder::default().read_only(!write).open(path)?;
+    let mut shell = Shell {
+        db,
+        write,
+        cwd: Vec::new(),
+    };
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("{}> ", shell.prompt());
+        io::stdout().flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        let mut words = line.trim().splitn(3, char::is_whitespace);
+        let cmd = words.next().unwrap_or("");
+        let args: Vec<&str> = words.map(str::trim).collect();
+        match cmd {
+            "" => {}
+            "exit" | "quit" => break,
+            "help" => println!("{}", &USAGE[USAGE.find("shell commands:").unwrap()..]),
+            _ => {
+                if let Err(e) = shell.run(cmd, &args) {
+                    println!("error: {:#}", e);
+                }
+            }
+        }
+    }
+    println!();
+    Ok(())
+}
+
+struct Shell {
+    db: DB,
+    write: bool,
+    // bucket names from the root down to the current bucket
+    cwd: Vec<Vec<u8>>,
+}
+
+impl Shell {
+    fn prompt(&self) -> String {
+        let names: Vec<_> = self.cwd.iter().map(|n| show(n)).collect();
+        format!("/{}", names.join("/"))
+    }
+
+    fn run(&mut self, cmd: &str, args: &[&str]) -> Result<()> {
+        match (cmd, args) {
+            ("ls", []) => {
+                let tx = self.db.tx(false)?;
+                let names = if self.cwd.is_empty() {
+                    tx.buckets()?
+                } else {
+                    self.view(&tx, |b| b.buckets())??
+                };
+                for name in names {
+                    println!("{}/", show(&name));
+                }
+            }
+            ("cd", ["/"]) => self.cwd.clear(),
+            ("cd", [".."]) => {
+                self.cwd.pop();
+            }
+            ("cd", [name]) => {
+                let tx = self.db.tx(false)?;
+                let found = if self.cwd.is_empty() {
+                    tx.bucket(name).is_some()
+                } else {
+                    self.view(&tx, |b| b.bucket(name).is_some())?
+                };
+                if !found {
+                    anyhow::bail!("no bucket {}", name);
+                }
+                self.cwd.push(name.as_bytes().to_vec());
+            }
+            ("get", [key]) => {
+                let tx = self.db.tx(false)?;
+                match self.view(&tx, |b| b.get(key).map(show))? {
+                    Some(v) => println!("{}", v),
+                    None => println!("(not found)"),
+                }
+            }
+            ("scan", []) | ("scan", [_]) => {
+                let prefix = args.first().copied().unwrap_or("");
+                let tx = self.db.tx(false)?;
+                let pairs = self.view(&tx, |b| scan(b, prefix.as_bytes()))??;
+                for (k, v) in pairs {
+                    println!("{} = {}", show(&k), show(&v));
+                }
+            }
+            ("put", [key, value]) => self.update(&self.cwd, |b| Ok(b.put(key, value)?))?,
+            ("del", [key]) => self.update(&self.cwd, |b| Ok(b.delete(key)?))?,
+            ("mk", [name]) => {
+                let mut path = self.cwd.clone();
+                path.push(name.as_bytes().to_vec());
+                self.update(&path, |_| Ok(()))?
+            }
+            _ => anyhow::bail!("bad command, try help"),
+        }
+        Ok(())
+    }
+
+    // run f on the current bucket, which may have been dropped by a writer
+    fn view<T, F: FnOnce(&Bucket) -> T>(&self, tx: &Transaction, f: F) -> Result<T> {
+        let (first, rest) = match self.cwd.split_first() {
+            Some(split) => split,
+            None => anyhow::bail!("cd into a bucket first"),
+        };
+        let top = match tx.bucket(first) {
+            Some(b) => b,
+            None => anyhow::bail!("bucket {} is gone", self.prompt()),
+        };
+        let mut b: &Bucket = &top;
+        for name in rest {
+            b = match b.bucket(name) {
+                Some(b) => b,
+                None => anyhow::bail!("bucket {} is gone", self.prompt()),
+            };
+        }
+        Ok(f(b))
+    }
+
+    // run f on the bucket at path in a write tx of its own, creating missing
+    // buckets on the way
+    fn update<F: FnOnce(&mut Bucket) -> Result<()>>(&self, path: &[Vec<u8>], f: F) -> Result<()> {
+        if !self.write {
+            anyhow::bail!("read-only, reopen the shell with --write");
+        }
+        let (first, rest) = match path.split_first() {
+            Some(split) => split,
+            None => anyhow::bail!("cd into a bucket first"),
+        };
+        let tx = self.db.tx(true)?;
+        let result = (|| {
+            let mut top = tx.create_bucket_if_not_exist(first)?;
+            let mut b: &mut Bucket = &mut top;
+            for name in rest {
+                b = b.create_bucket_if_not_exist(name)?;
+            }
+            f(b)
+        })();
+        match result {
+            Ok(()) => Ok(tx.commit()?),
+            Err(e) => {
+                tx.rollback()?;
+                Err(e)
+            }
+        }
+    }
+}
+
+fn scan(b: &Bucket, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut pairs = Vec::new();
+    let mut c = b.cursor();
+    let mut pair = c.seek_prefix(prefix)?;
+    while let Some(key) = pair.key() {
+        // get hides expired keys
+        if let Some(v) = b.get(key).filter(|_| !pair.is_bucket()) {
+            pairs.push((key.to_vec(), v.to_vec()));
+        }
+        pair = c.next()?;
+    }
+    Ok(pairs)
+}
+
+// printable form of a key or value, non-utf8 bytes are escaped
+fn show(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => s.to_string(),
+        Err(_) => bytes.escape_ascii().to_string(),
+    }
+}
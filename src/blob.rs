@@ -0,0 +1,394 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    bucket::Bucket,
+    error::{Result, RoltError},
+    page::{BlobChunk, Page, PageId, VPage},
+    transaction::Transaction,
+    ttl::reserved,
+    Err,
+};
+
+// page blocks in one blob chunk
+const CHUNK_PAGES: usize = 64;
+// leaf value of a blob: total length and first chunk page, little-endian
+const DESCRIPTOR_SIZE: usize = 16;
+
+fn descriptor(len: u64, first: PageId) -> [u8; DESCRIPTOR_SIZE] {
+    let mut d = [0u8; DESCRIPTOR_SIZE];
+    d[..8].copy_from_slice(&len.to_le_bytes());
+    d[8..].copy_from_slice(&first.to_le_bytes());
+    d
+}
+
+pub(crate) fn parse_descriptor(d: &[u8]) -> Result<(u64, PageId)> {
+    if d.len() != DESCRIPTOR_SIZE {
+        return Err!("blob descriptor is truncated");
+    }
+    let len = u64::from_le_bytes(d[..8].try_into()?);
+    let first = u64::from_le_bytes(d[8..].try_into()?);
+    Ok((len, first))
+}
+
+// fill buf from r, short only at the end of the input
+fn read_full<R: Read + ?Sized>(r: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(read) => n += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(n)
+}
+
+// bytes of value a chunk holds
+fn chunk_capacity(page_size: usize) -> usize {
+    CHUNK_PAGES * page_size - Page::page_header_size() - BlobChunk::SIZE
+}
+
+fn chunk_data(chunk: &mut BlobChunk) -> *mut u8 {
+    unsafe { (chunk as *mut BlobChunk as *mut u8).add(BlobChunk::SIZE) }
+}
+
+// the chain of a blob being written. a chunk goes to the file as soon as the
+// next one has a page to link to, so one chunk of the value is in memory at
+// a time however long the value is
+struct ChunkChain {
+    tx: Transaction,
+    first: PageId,
+    // chunk being filled, not written yet
+    chunk: Option<VPage>,
+}
+
+impl ChunkChain {
+    fn new(tx: Transaction) -> Self {
+        Self {
+            tx,
+            first: 0,
+            chunk: None,
+        }
+    }
+
+    // start a chunk for n bytes of value, writing out the one before it
+    fn next(&mut self, n: usize) -> Result<()> {
+        let page_size = self.tx.db()?.page_size();
+        let count = ((Page::page_header_size() + BlobChunk::SIZE + n) as u64).div_ceil(page_size);
+        let id = self.tx.allocate_ids(count)?;
+        let mut p = VPage::new((count * page_size) as usize);
+        p.id = id;
+        p.overflow = (count - 1) as u32;
+        p.txid = self.tx.id();
+        p.page_type = Page::BLOB_PAGE;
+        match self.chunk.replace(p) {
+            Some(mut prev) => {
+                prev.blob_chunk_mut().next = id;
+                self.tx.write_now(&prev)?;
+            }
+            None => self.first = id,
+        }
+        Ok(())
+    }
+
+    // append to the chunk being filled
+    fn extend(&mut self, buf: &[u8]) {
+        let chunk = self
+            .chunk
+            .as_mut()
+            .expect("no chunk started")
+            .blob_chunk_mut();
+        unsafe {
+            let data = chunk_data(chunk).add(chunk.len as usize);
+            std::ptr::copy_nonoverlapping(buf.as_ptr(), data, buf.len());
+        }
+        chunk.len += buf.len() as u64;
+    }
+
+    // write out the last chunk, the whole chain is readable after
+    fn finish(&mut self) -> Result<()> {
+        match self.chunk.take() {
+            Some(p) => self.tx.write_now(&p),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Bucket {
+    // stream a value from r into key. values of at least a page go to a chain
+    // of blob pages with only a small descriptor in the leaf, so they are
+    // never held by a node or copied when it splits. returns the value length
+    pub fn put_blob<K: AsRef<[u8]>, R: Read + ?Sized>(&mut self, key: K, r: &mut R) -> Result<u64> {
+        let key = key.as_ref();
+        let tx = self.tx()?;
+        let page_size = tx.db()?.page_size() as usize;
+        let mut buf = vec![0u8; chunk_capacity(page_size)];
+        let mut n = read_full(r, &mut buf)?;
+        if n < page_size {
+            self.put(key, &buf[..n])?;
+            return Ok(n as u64);
+        }
+        if !tx.writable() {
+            return Err!(RoltError::NotWritable);
+        }
+        if self.dup || self.value_width.is_some() {
+            return Err!(RoltError::IncompatibleValue);
+        }
+        reserved(key)?;
+        let mut chain = ChunkChain::new(tx);
+        let mut total = 0u64;
+        let mut write = || -> Result<()> {
+            while n > 0 {
+                chain.next(n)?;
+                chain.extend(&buf[..n]);
+                total += n as u64;
+                n = if n < buf.len() {
+                    0
+                } else {
+                    read_full(r, &mut buf)?
+                };
+            }
+            chain.finish()
+        };
+        let res = write()
+            .and_then(|_| self.put_entry(key, &descriptor(total, chain.first), Self::BLOB_FLAG));
+        // a value that wasn't stored gives its pages back
+        if let Err(e) = res {
+            if chain.first != 0 {
+                chain.finish()?;
+                self.free_blob(&descriptor(total, chain.first))?;
+            }
+            return Err(e);
+        }
+        Ok(total)
+    }
+
+    // read the value of key whether it was stored as a blob or inline, None
+    // when the key is missing or holds a nested bucket
+    pub fn get_blob_reader<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<BlobReader<'_>>> {
+        let key = key.as_ref();
+        let mut c = self.cursor();
+        let pair = c.seek(key)?;
+        if pair.key() != Some(key) || pair.is_bucket() || self.expired(key) {
+            return Ok(None);
+        }
+        let value = pair.value().unwrap_or(&[]);
+        if !pair.is_blob() {
+            return Ok(Some(BlobReader {
+                tx: self.tx()?,
+                len: value.len() as u64,
+                chunk: value,
+                next: 0,
+            }));
+        }
+        self.blob_reader(value).map(Some)
+    }
+
+    // read the chain a blob descriptor points at
+    pub(crate) fn blob_reader(&self, descriptor: &[u8]) -> Result<BlobReader<'_>> {
+        let (len, first) = parse_descriptor(descriptor)?;
+        Ok(BlobReader {
+            tx: self.tx()?,
+            len,
+            chunk: &[],
+            next: first,
+        })
+    }
+
+    // stream the value of key, same as get_blob_reader
+    pub fn value_reader<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<BlobReader<'_>>> {
+        self.get_blob_reader(key)
+    }
+
+    // write a value of exactly len bytes into key piece by piece. it is
+    // stored once the last byte is written, values of a page or more go
+    // straight to blob pages instead of being buffered
+    pub fn value_writer<K: AsRef<[u8]>>(&mut self, key: K, len: u64) -> Result<ValueWriter<'_>> {
+        let tx = self.tx()?;
+        if !tx.writable() {
+            return Err!(RoltError::NotWritable);
+        }
+        reserved(key.as_ref())?;
+        let inline = len < tx.db()?.page_size();
+        if !inline && (self.dup || self.value_width.is_some()) {
+            return Err!(RoltError::IncompatibleValue);
+        }
+        let mut w = ValueWriter {
+            bucket: self,
+            tx: tx.clone(),
+            key: key.as_ref().to_vec(),
+            len,
+            written: 0,
+            inline: inline.then(Vec::new),
+            chain: ChunkChain::new(tx),
+            room: 0,
+            stored: false,
+        };
+        if len == 0 {
+            w.store()?;
+        }
+        Ok(w)
+    }
+
+    // release the pages of the blob behind descriptor
+    pub(crate) fn free_blob(&self, descriptor: &[u8]) -> Result<()> {
+        let (_, mut id) = parse_descriptor(descriptor)?;
+        let tx = self.tx()?;
+        let db = tx.db()?;
+        while id != 0 {
+            let p = tx.page(id)?;
+            if p.page_type != Page::BLOB_PAGE {
+                return Err!(RoltError::InvalidPageType);
+            }
+            id = p.blob_chunk().next;
+            db.free_list.write().free(tx.id(), &p)?;
+        }
+        Ok(())
+    }
+}
+
+// writes a value of a length given up front, see Bucket::value_writer
+pub struct ValueWriter<'a> {
+    bucket: &'a mut Bucket,
+    tx: Transaction,
+    key: Vec<u8>,
+    len: u64,
+    written: u64,
+    // a value under a page is collected here and put inline
+    inline: Option<Vec<u8>>,
+    chain: ChunkChain,
+    // bytes left in the chunk being filled
+    room: usize,
+    stored: bool,
+}
+
+impl ValueWriter<'_> {
+    // bytes still expected
+    pub fn remaining(&self) -> u64 {
+        self.len - self.written
+    }
+
+    // check the whole value was written
+    pub fn finish(self) -> Result<()> {
+        if !self.stored {
+            return Err!("value is shorter than its declared length");
+        }
+        Ok(())
+    }
+
+    fn store(&mut self) -> Result<()> {
+        let stored = match self.inline.as_ref() {
+            Some(value) => self.bucket.put(&self.key, value),
+            None => self.chain.finish().and_then(|_| {
+                self.bucket.put_entry(
+                    &self.key,
+                    &descriptor(self.len, self.chain.first),
+                    Bucket::BLOB_FLAG,
+                )
+            }),
+        };
+        self.stored = stored.is_ok();
+        stored
+    }
+
+    fn write_value(&mut self, buf: &[u8]) -> Result<usize> {
+        if buf.len() as u64 > self.remaining() {
+            return Err!("value is longer than its declared length");
+        }
+        let n = match self.inline.as_mut() {
+            Some(value) => {
+                value.extend_from_slice(buf);
+                buf.len()
+            }
+            None => {
+                if self.room == 0 && !buf.is_empty() {
+                    self.next_chunk()?;
+                }
+                let n = buf.len().min(self.room);
+                self.chain.extend(&buf[..n]);
+                self.room -= n;
+                n
+            }
+        };
+        self.written += n as u64;
+        if n > 0 && self.written == self.len {
+            self.store()?;
+        }
+        Ok(n)
+    }
+
+    // start a chunk sized for what is left of the value
+    fn next_chunk(&mut self) -> Result<()> {
+        let page_size = self.tx.db()?.page_size() as usize;
+        let n = (chunk_capacity(page_size) as u64).min(self.remaining()) as usize;
+        self.chain.next(n)?;
+        self.room = n;
+        Ok(())
+    }
+}
+
+impl Write for ValueWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_value(buf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for ValueWriter<'_> {
+    // a value left unfinished is not stored, give its pages back
+    fn drop(&mut self) {
+        if !self.stored && self.chain.first != 0 && self.chain.finish().is_ok() {
+            let _ = self
+                .bucket
+                .free_blob(&descriptor(self.len, self.chain.first));
+        }
+    }
+}
+
+// reads a value stored by put_blob one chunk at a time
+pub struct BlobReader<'a> {
+    // keeps the pages of the chain alive
+    tx: Transaction,
+    len: u64,
+    // rest of the chunk being read
+    chunk: &'a [u8],
+    next: PageId,
+}
+
+impl BlobReader<'_> {
+    // length of the whole value
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Read for BlobReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() && self.next != 0 {
+            let p = self
+                .tx
+                .page(self.next)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            if p.page_type != Page::BLOB_PAGE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    RoltError::InvalidPageType,
+                ));
+            }
+            let chunk = unsafe { &*(p.blob_chunk() as *const BlobChunk) };
+            self.next = chunk.next;
+            self.chunk = chunk.data();
+        }
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk[..n]);
+        self.chunk = &self.chunk[n..];
+        Ok(n)
+    }
+}
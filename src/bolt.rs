@@ -0,0 +1,284 @@
+use std::{
+    fs::File,
+    hash::Hasher,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use fnv::FnvHasher;
+
+use crate::{
+    bucket::Bucket,
+    db::DB,
+    error::{Result, RoltError},
+    Err,
+};
+
+// on-disk layout of go bolt/bbolt files (little-endian)
+const MAGIC: u32 = 0xED0C_DAED;
+const PAGE_HEADER_SIZE: usize = 16;
+const ELEMENT_SIZE: usize = 16;
+const BUCKET_HEADER_SIZE: usize = 16;
+const META_SIZE: usize = 64;
+const BRANCH_PAGE_FLAG: u16 = 0x01;
+const LEAF_PAGE_FLAG: u16 = 0x02;
+const BUCKET_LEAF_FLAG: u32 = 0x01;
+
+// what an import copied
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImportStats {
+    pub buckets: u64,
+    pub keys: u64,
+}
+
+// copy every bucket and key of a bolt file into dest in one write tx
+pub fn import_bolt<P: AsRef<Path>>(src: P, dest: &DB) -> Result<ImportStats> {
+    let bolt = BoltFile::open(src.as_ref())?;
+    let mut stats = ImportStats::default();
+    let tx = dest.tx(true)?;
+    {
+        let mut root = tx.root.write();
+        bolt.copy_bucket(&BoltRoot::Page(bolt.root), &mut root, &mut stats)?;
+    }
+    tx.commit()?;
+    Ok(stats)
+}
+
+// receives key, value and flags of a leaf element
+type Visitor<'a> = dyn FnMut(&[u8], &[u8], u32) -> Result<()> + 'a;
+
+// root of a bolt b+tree, inline buckets carry their only page in the value
+enum BoltRoot {
+    Page(u64),
+    Inline(Vec<u8>),
+}
+
+struct BoltFile {
+    file: File,
+    page_size: usize,
+    // root page of the top-level bucket
+    root: u64,
+}
+
+impl BoltFile {
+    fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let mut first = vec![0u8; PAGE_HEADER_SIZE + META_SIZE];
+        file.read_exact(&mut first)?;
+        let page_size = u32_at(&first, PAGE_HEADER_SIZE + 8)? as usize;
+        if page_size < PAGE_HEADER_SIZE + META_SIZE {
+            return Err!("not a bolt file: invalid page size");
+        }
+        let mut bolt = Self {
+            file,
+            page_size,
+            root: 0,
+        };
+        // use the valid meta with the highest txid
+        let mut best: Option<(u64, u64)> = None;
+        for id in 0..2 {
+            let page = bolt.read(id, 1)?;
+            if let Some((txid, root)) = parse_meta(&page[PAGE_HEADER_SIZE..]) {
+                if best.is_none_or(|(t, _)| txid > t) {
+                    best = Some((txid, root));
+                }
+            }
+        }
+        bolt.root = best
+            .ok_or(RoltError::from("not a bolt file: no valid meta page"))?
+            .1;
+        Ok(bolt)
+    }
+
+    fn read(&self, id: u64, blocks: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; self.page_size * blocks];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(id * self.page_size as u64))?;
+        file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    // read a page together with its overflow blocks
+    fn page(&self, id: u64) -> Result<Vec<u8>> {
+        let first = self.read(id, 1)?;
+        let overflow = u32_at(&first, 12)? as usize;
+        if overflow == 0 {
+            Ok(first)
+        } else {
+            self.read(id, overflow + 1)
+        }
+    }
+
+    fn copy_bucket(
+        &self,
+        root: &BoltRoot,
+        dst: &mut Bucket,
+        stats: &mut ImportStats,
+    ) -> Result<()> {
+        let page = match root {
+            BoltRoot::Page(id) => self.page(*id)?,
+            BoltRoot::Inline(bytes) => bytes.clone(),
+        };
+        self.walk(&page, &mut |key, value, flags| {
+            if flags & BUCKET_LEAF_FLAG == 0 {
+                stats.keys += 1;
+                return dst.put(key, value);
+            }
+            if value.len() < BUCKET_HEADER_SIZE {
+                return Err!("bucket header is truncated");
+            }
+            let child_root = match u64_at(value, 0)? {
+                0 => BoltRoot::Inline(value[BUCKET_HEADER_SIZE..].to_vec()),
+                id => BoltRoot::Page(id),
+            };
+            let child = dst.create_bucket_if_not_exist(key)?;
+            child.bucket.sequence = u64_at(value, 8)?;
+            stats.buckets += 1;
+            self.copy_bucket(&child_root, child, stats)
+        })
+    }
+
+    // call f with key, value and flags of every leaf element under page
+    fn walk(&self, page: &[u8], f: &mut Visitor) -> Result<()> {
+        let flags = u16_at(page, 8)?;
+        let count = u16_at(page, 10)? as usize;
+        for i in 0..count {
+            let elem = PAGE_HEADER_SIZE + i * ELEMENT_SIZE;
+            if flags & LEAF_PAGE_FLAG != 0 {
+                let elem_flags = u32_at(page, elem)?;
+                let start = elem + u32_at(page, elem + 4)? as usize;
+                let k_size = u32_at(page, elem + 8)? as usize;
+                let v_size = u32_at(page, elem + 12)? as usize;
+                let key = slice_at(page, start, k_size)?;
+                let value = slice_at(page, start + k_size, v_size)?;
+                f(key, value, elem_flags)?;
+            } else if flags & BRANCH_PAGE_FLAG != 0 {
+                let child = self.page(u64_at(page, elem + 8)?)?;
+                self.walk(&child, f)?;
+            } else {
+                return Err!(format!("unexpected bolt page flags {:#x}", flags));
+            }
+        }
+        Ok(())
+    }
+}
+
+// return txid and root page of a valid meta
+fn parse_meta(meta: &[u8]) -> Option<(u64, u64)> {
+    if u32_at(meta, 0).ok()? != MAGIC {
+        return None;
+    }
+    let mut hash = FnvHasher::default();
+    hash.write(&meta[..META_SIZE - 8]);
+    if hash.finish() != u64_at(meta, META_SIZE - 8).ok()? {
+        return None;
+    }
+    Some((u64_at(meta, 48).ok()?, u64_at(meta, 16).ok()?))
+}
+
+fn slice_at(buf: &[u8], start: usize, len: usize) -> Result<&[u8]> {
+    buf.get(start..start + len)
+        .ok_or_else(|| "bolt element is out of page bounds".into())
+}
+
+fn u16_at(buf: &[u8], at: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(slice_at(buf, at, 2)?.try_into()?))
+}
+
+fn u32_at(buf: &[u8], at: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(slice_at(buf, at, 4)?.try_into()?))
+}
+
+fn u64_at(buf: &[u8], at: usize) -> Result<u64> {
+    Ok(u64::from_le_bytes(slice_at(buf, at, 8)?.try_into()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const PAGE_SIZE: usize = 4096;
+
+    fn put_u16(buf: &mut [u8], at: usize, v: u16) {
+        buf[at..at + 2].copy_from_slice(&v.to_le_bytes());
+    }
+    fn put_u32(buf: &mut [u8], at: usize, v: u32) {
+        buf[at..at + 4].copy_from_slice(&v.to_le_bytes());
+    }
+    fn put_u64(buf: &mut [u8], at: usize, v: u64) {
+        buf[at..at + 8].copy_from_slice(&v.to_le_bytes());
+    }
+
+    // a leaf page holding the given (flags, key, value) elements
+    fn leaf(id: u64, elems: &[(u32, &[u8], &[u8])], size: usize) -> Vec<u8> {
+        let mut p = vec![0u8; size];
+        put_u64(&mut p, 0, id);
+        put_u16(&mut p, 8, LEAF_PAGE_FLAG);
+        put_u16(&mut p, 10, elems.len() as u16);
+        let mut data = PAGE_HEADER_SIZE + elems.len() * ELEMENT_SIZE;
+        for (i, (flags, k, v)) in elems.iter().enumerate() {
+            let e = PAGE_HEADER_SIZE + i * ELEMENT_SIZE;
+            put_u32(&mut p, e, *flags);
+            put_u32(&mut p, e + 4, (data - e) as u32);
+            put_u32(&mut p, e + 8, k.len() as u32);
+            put_u32(&mut p, e + 12, v.len() as u32);
+            p[data..data + k.len()].copy_from_slice(k);
+            data += k.len();
+            p[data..data + v.len()].copy_from_slice(v);
+            data += v.len();
+        }
+        p
+    }
+
+    fn bolt_file(path: &str) {
+        let mut file = vec![0u8; PAGE_SIZE * 4];
+        for id in 0..2 {
+            let p = &mut file[id * PAGE_SIZE..];
+            put_u64(p, 0, id as u64);
+            put_u16(p, 8, 0x04);
+            let m = PAGE_HEADER_SIZE;
+            put_u32(p, m, MAGIC);
+            put_u32(p, m + 4, 2);
+            put_u32(p, m + 8, PAGE_SIZE as u32);
+            put_u64(p, m + 16, 3);
+            put_u64(p, m + 32, 2);
+            put_u64(p, m + 40, 4);
+            put_u64(p, m + 48, id as u64);
+            let mut hash = FnvHasher::default();
+            hash.write(&p[m..m + META_SIZE - 8]);
+            let sum = hash.finish();
+            put_u64(p, m + META_SIZE - 8, sum);
+        }
+        // inline bucket "b" with two keys and sequence 7
+        let inline = leaf(0, &[(0, b"k1", b"v1"), (0, b"k2", b"v2")], 64);
+        let mut bucket = vec![0u8; BUCKET_HEADER_SIZE];
+        put_u64(&mut bucket, 8, 7);
+        bucket.extend_from_slice(&inline);
+        let root = leaf(3, &[(BUCKET_LEAF_FLAG, b"b", &bucket)], PAGE_SIZE);
+        file[3 * PAGE_SIZE..].copy_from_slice(&root);
+        File::create(path).unwrap().write_all(&file).unwrap();
+    }
+
+    #[test]
+    fn import() {
+        let src = "./tests/import.bolt";
+        let dest = "./tests/import.db";
+        let _ = std::fs::remove_file(dest);
+        bolt_file(src);
+        let db = DB::open(dest).unwrap();
+        let stats = import_bolt(src, &db).unwrap();
+        std::fs::remove_file(src).unwrap();
+        assert_eq!(
+            stats,
+            ImportStats {
+                buckets: 1,
+                keys: 2
+            }
+        );
+        let tx = db.tx(true).unwrap();
+        let b = tx.create_bucket_if_not_exist("b").unwrap();
+        assert_eq!(b.get("k2"), Some(&b"v2"[..]));
+        assert_eq!({ b.bucket.sequence }, 7);
+    }
+}
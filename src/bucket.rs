@@ -1,32 +1,57 @@
 use crate::{
-    cursor::Cursor,
+    cursor::{Cursor, ElementRef},
     data::RawPtr,
     error::{Result, RoltError},
     node::{Node, WeakNode},
     page::{LeafPageElement, Page, PageId},
     transaction::{Transaction, WeakTransaction},
+    ttl::{reserved, TTL_BUCKET},
     utils::struct_to_slice,
+    watch::ChangeOp,
     Err,
 };
-use anyhow::anyhow;
 use either::Either;
 use std::{
-    borrow::BorrowMut, collections::HashMap, intrinsics::copy_nonoverlapping, mem::size_of,
-    ops::Deref,
+    cell::{Cell, RefCell},
+    collections::hash_map::Entry,
 };
-use std::{cell::RefCell, collections::hash_map::Entry};
+use std::{collections::HashMap, intrinsics::copy_nonoverlapping, mem::size_of, ops::Deref};
+// bytes a key and value take in a leaf page, as counted in TxMemory
+fn element_size(key: &[u8], value: &[u8]) -> usize {
+    LeafPageElement::SIZE + key.len() + value.len()
+}
+
 // a collection of kev-value pairs
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Bucket {
     pub(crate) bucket: IBucket,
-    // nested bucket
-    pub(crate) buckets: RefCell<HashMap<String, Bucket>>,
+    // nested bucket, boxed since nodes point back at their bucket and must
+    // not move when the map grows
+    pub(crate) buckets: RefCell<HashMap<Vec<u8>, Box<Bucket>>>,
     pub(crate) tx: WeakTransaction,
     pub(crate) page: Option<RawPtr<Page>>,
     pub(crate) root: Option<Node>,
     pub(crate) fill_percent: f64,
     pub(crate) nodes: HashMap<PageId, Node>,
-    dirty: bool,
+    // keys of the buckets from the top level down to this one, empty for
+    // the root bucket
+    pub(crate) path: Vec<Vec<u8>>,
+    // levels below the root bucket, which is at 0
+    pub(crate) depth: usize,
+    // keys hold sets of values, see create_dup_bucket
+    pub(crate) dup: bool,
+    // every value is this many bytes, see create_fixed_bucket
+    pub(crate) value_width: Option<u8>,
+    // stack of a dropped cursor, reused so point lookups don't allocate
+    pub(crate) cursor_stack: RefCell<Vec<ElementRef>>,
+    // rightmost leaf, kept once a put went past the last key so further
+    // appends skip the search. it splits full instead of at fill_percent
+    pub(crate) tail: Option<Node>,
+    // set once a lookup found no ttl index, so gets and puts in buckets
+    // that never used a ttl don't search for it every time
+    pub(crate) ttl_absent: Cell<bool>,
+    // set once a key was removed, so commit rebalances the nodes
+    pub(crate) dirty: bool,
 }
 
 #[allow(dead_code)]
@@ -36,11 +61,43 @@ impl Bucket {
     pub(crate) const MAX_FILL_PERCENT: f64 = 1.0;
     pub(crate) const BUCKET_HEADER_SIZE: usize = size_of::<Self>();
     pub(crate) const FLAG: u32 = 1;
+    // set next to FLAG on buckets made by create_dup_bucket
+    pub(crate) const DUP_FLAG: u32 = 2;
+    // the value width of a fixed bucket sits in the second byte of its flags
+    pub(crate) const WIDTH_SHIFT: u32 = 8;
+    // the value is a descriptor of a blob stored in its own pages
+    pub(crate) const BLOB_FLAG: u32 = 4;
+    // flags owned by the engine, below the user flags
+    pub(crate) const ENGINE_FLAGS: u32 = (1 << Self::USER_FLAGS_SHIFT) - 1;
+    // the upper half of the flags word belongs to applications
+    pub(crate) const USER_FLAGS_SHIFT: u32 = 16;
+
+    // key of this bucket in its parent, empty for the root bucket
+    pub(crate) fn name(&self) -> &[u8] {
+        self.path.last().map_or(&[], |name| name)
+    }
 
     pub fn tx(&self) -> Result<Transaction> {
-        self.tx
-            .upgrade()
-            .ok_or_else(|| RoltError::TxNotValid.into())
+        self.tx.upgrade().ok_or(RoltError::TxNotValid)
+    }
+
+    // names of the nested buckets opened through this one, in order
+    pub(crate) fn open_buckets(&self) -> Vec<String> {
+        let buckets = match self.buckets.try_borrow() {
+            Ok(buckets) => buckets,
+            Err(_) => return Vec::new(),
+        };
+        let mut names: Vec<String> = buckets
+            .keys()
+            .map(|k| String::from_utf8_lossy(k).into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    // whether the tx this bucket belongs to can write, false once it's gone
+    pub fn writable(&self) -> bool {
+        self.tx().map(|tx| tx.writable()).unwrap_or(false)
     }
 
     pub fn new(tx: WeakTransaction) -> Self {
@@ -52,90 +109,235 @@ impl Bucket {
             page: None,
             fill_percent: Self::DEFAULT_FILL_PERCENT,
             tx,
+            path: Vec::new(),
+            depth: 0,
+            dup: false,
+            value_width: None,
+            cursor_stack: RefCell::new(Vec::new()),
+            tail: None,
+            ttl_absent: Cell::new(false),
             dirty: false,
         }
     }
-    // create a bucket and put it in the root node
-    pub(crate) fn create_bucket(&mut self, name: String) -> Result<&mut Bucket> {
+    // create a bucket nested in this one
+    pub fn create_bucket<K: AsRef<[u8]>>(&mut self, key: K) -> Result<&mut Bucket> {
+        reserved(key.as_ref())?;
+        if self.dup {
+            return Err!(RoltError::IncompatibleValue);
+        }
+        self.create_nested(key.as_ref(), Self::FLAG)
+    }
+
+    // create a nested bucket whose keys each hold a sorted set of values,
+    // written with put_dup and read with get_all or Cursor::next_dup
+    pub fn create_dup_bucket<K: AsRef<[u8]>>(&mut self, key: K) -> Result<&mut Bucket> {
+        reserved(key.as_ref())?;
+        if self.dup {
+            return Err!(RoltError::IncompatibleValue);
+        }
+        self.create_nested(key.as_ref(), Self::FLAG | Self::DUP_FLAG)
+    }
+
+    // create a nested bucket whose values are all width bytes, stored in
+    // leaf pages without per-value sizes. nested buckets, ttls and user
+    // flags are refused in it
+    pub fn create_fixed_bucket<K: AsRef<[u8]>>(
+        &mut self,
+        key: K,
+        width: u8,
+    ) -> Result<&mut Bucket> {
+        reserved(key.as_ref())?;
+        if self.dup || width == 0 {
+            return Err!(RoltError::IncompatibleValue);
+        }
+        let flags = Self::FLAG | (width as u32) << Self::WIDTH_SHIFT;
+        self.create_nested(key.as_ref(), flags)
+    }
+
+    // create a nested bucket with the given element flags
+    pub(crate) fn create_nested(&mut self, key: &[u8], flags: u32) -> Result<&mut Bucket> {
         if !self.tx()?.writable() {
             panic!("tx not writable")
         }
-        let key = name.as_bytes();
+        // a fixed leaf page has no room for bucket headers
+        if self.value_width.is_some() {
+            return Err!(RoltError::IncompatibleValue);
+        }
         let mut cursor = self.cursor();
         let pair = cursor.seek_to(key)?;
         if Some(key) == pair.key() {
             return Err!(RoltError::BucketExist);
         }
+        if let Some(max) = self.tx()?.db()?.max_bucket_depth {
+            // the ttl index and value sets are bookkeeping, not levels the
+            // user created
+            if self.depth >= max && key != TTL_BUCKET && !self.dup {
+                return Err!(RoltError::BucketTooDeep(max));
+            }
+        }
         {
             let mut b = Bucket::new(self.tx.clone());
             b.root = Some(Node::new(RawPtr::new(&b), crate::node::NodeType::Leaf));
             b.fill_percent = Self::DEFAULT_FILL_PERCENT;
             let bytes = b.as_bytes();
-            cursor.node()?.put(key, key, &bytes, 0, Self::FLAG);
+            cursor.node()?.put(key, key, &bytes, 0, flags);
             self.page = None;
         }
-        self.get_bucket(name)
+        self.tx()?.changes.write().record(
+            &self.path,
+            key,
+            ChangeOp::CreateBucket,
+            &flags.to_le_bytes(),
+        );
+        if key == TTL_BUCKET {
+            self.ttl_absent.set(false);
+        }
+        self.get_bucket(key)
             .map(|b| unsafe { &mut *b })
-            .ok_or(anyhow!("cannot get bucket"))
+            .ok_or(RoltError::BucketNotFound)
     }
 
-    pub(crate) fn create_bucket_if_not_exist(&mut self, name: String) -> Result<&mut Bucket> {
-        let self_mut = unsafe { &mut *(self as *mut Self) };
-        match self_mut.create_bucket(name.clone()) {
-            Ok(b) => Ok(b),
-            Err(_) => self
-                .get_bucket(name)
-                .map(|b| unsafe { &mut *b })
-                .ok_or(anyhow!("cannot get bucket")),
+    // open a nested bucket, creating it when missing
+    pub fn create_bucket_if_not_exist<K: AsRef<[u8]>>(&mut self, name: K) -> Result<&mut Bucket> {
+        let name = name.as_ref();
+        reserved(name)?;
+        if let Some(b) = self.get_bucket(name) {
+            return Ok(unsafe { &mut *b });
         }
+        self.create_bucket(name)
+    }
+    // open a nested bucket for reading
+    pub fn bucket<K: AsRef<[u8]>>(&self, name: K) -> Option<&Bucket> {
+        reserved(name.as_ref()).ok()?;
+        self.get_bucket(name.as_ref()).map(|b| unsafe { &*b })
+    }
+    // names of the nested buckets in key order
+    pub fn buckets(&self) -> Result<Vec<Vec<u8>>> {
+        let mut names = Vec::new();
+        // value sets of a dup bucket are not buckets to the user
+        if self.dup {
+            return Ok(names);
+        }
+        let mut c = self.cursor();
+        let mut pair = c.first()?;
+        while let Some(key) = pair.key() {
+            if pair.is_bucket() && key != TTL_BUCKET {
+                names.push(key.to_vec());
+            }
+            pair = c.next()?;
+        }
+        Ok(names)
     }
     // get a bucket from nested buckets
-    fn get_bucket(&self, key: String) -> Option<*mut Bucket> {
-        if let Some(b) = self.buckets.borrow_mut().get_mut(&key) {
-            return Some(b);
+    pub(crate) fn get_bucket(&self, key: &[u8]) -> Option<*mut Bucket> {
+        if let Some(b) = self.buckets.borrow_mut().get_mut(key) {
+            return Some(&mut **b);
         };
 
         let mut cursor = self.cursor();
-        let pair = match cursor.seek_to(key.as_bytes()) {
+        let pair = match cursor.seek_to(key) {
             Err(_) => {
                 return None;
             }
             Ok(p) => p,
         };
-        if Some(key.as_bytes()) != pair.key() {
+        if Some(key) != pair.key() || !pair.is_bucket() {
             return None;
         }
         // get a sub-bucket from value
-        let child = self.open_bucket(pair.value().unwrap());
+        let mut child = self.open_bucket(pair.value().unwrap())?;
+        child.path = self.path.clone();
+        child.path.push(key.to_vec());
+        child.dup = pair.flags & Self::DUP_FLAG != 0;
+        child.value_width = match (pair.flags >> Self::WIDTH_SHIFT) as u8 {
+            0 => None,
+            width => Some(width),
+        };
         let mut buckets = self.buckets.borrow_mut();
-        let bucket = match buckets.entry(key) {
+        let bucket = match buckets.entry(key.to_vec()) {
             Entry::Occupied(e) => {
                 let b = e.into_mut();
-                *b = child;
+                **b = child;
                 b
             }
-            Entry::Vacant(e) => e.insert(child),
+            Entry::Vacant(e) => e.insert(Box::new(child)),
         };
-        Some(bucket)
+        Some(&mut **bucket)
     }
-    // get sub-bucket
-    fn open_bucket(&self, bytes: &[u8]) -> Bucket {
+    // get sub-bucket, None when the value is too short to hold one
+    fn open_bucket(&self, bytes: &[u8]) -> Option<Bucket> {
+        if bytes.len() < IBucket::SIZE {
+            return None;
+        }
         let mut child = Bucket::new(self.tx.clone());
-        child.bucket = unsafe { *(bytes.as_ptr() as *const IBucket) };
+        child.depth = self.depth + 1;
+        child.bucket = unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const IBucket) };
         // sub-bucket is inline
         if child.bucket.root == 0 {
             let slice = &bytes[IBucket::SIZE..];
-            let p = Page::from_buf_direct(slice);
-            child.page = Some(RawPtr::new(p));
+            child.page = Some(RawPtr::new(Page::from_buf_direct(slice).ok()?));
+        }
+        Some(child)
+    }
+    // the smallest key and its value, nested buckets and expired keys skipped
+    pub fn first(&self) -> Option<(&[u8], &[u8])> {
+        let mut c = self.cursor();
+        let mut pair = c.first().ok()?;
+        while let Some((key, value)) = pair.key().zip(pair.value()) {
+            if !pair.is_bucket() && !self.expired(key) {
+                return Some((key, value));
+            }
+            pair = c.next().ok()?;
         }
-        child
+        None
     }
-    // get finds the value by key
-    pub fn get(&self, target: &[u8]) -> Option<&[u8]> {
+
+    // the largest key and its value, nested buckets and expired keys skipped
+    pub fn last(&self) -> Option<(&[u8], &[u8])> {
+        let mut c = self.cursor();
+        let mut pair = c.last().ok()?;
+        while let Some((key, value)) = pair.key().zip(pair.value()) {
+            if !pair.is_bucket() && !self.expired(key) {
+                return Some((key, value));
+            }
+            pair = c.prev().ok()?;
+        }
+        None
+    }
+
+    // remove and return the smallest entry
+    pub fn pop_first(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let entry = self.first().map(|(k, v)| (k.to_vec(), v.to_vec()));
+        self.pop(entry)
+    }
+
+    // remove and return the largest entry
+    pub fn pop_last(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let entry = self.last().map(|(k, v)| (k.to_vec(), v.to_vec()));
+        self.pop(entry)
+    }
+
+    fn pop(&mut self, entry: Option<(Vec<u8>, Vec<u8>)>) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        if !self.writable() {
+            return Err!(RoltError::NotWritable);
+        }
+        if let Some((key, _)) = &entry {
+            self.delete(key)?;
+        }
+        Ok(entry)
+    }
+
+    // get finds the value by key, the smallest of its values in a dup bucket
+    pub fn get<K: AsRef<[u8]>>(&self, target: K) -> Option<&[u8]> {
+        let target = target.as_ref();
+        if self.dup {
+            return self.dup_set(target)?.first().map(|(v, _)| v);
+        }
         let mut c = self.cursor();
         let pair = c.seek(target).unwrap();
         let (key, value) = (pair.key(), pair.value());
-        if pair.flags == Self::FLAG || key != Some(target) {
+        c.recycle();
+        if pair.is_bucket() || pair.is_blob() || key != Some(target) || self.expired(target) {
             None
         } else {
             // notice: lifetime of reference to value
@@ -144,23 +346,259 @@ impl Bucket {
     }
 
     // put key and value
-    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+    pub fn put<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) -> Result<()> {
+        self.put_with_flags(key, value, 0)
+    }
+
+    // put key and value tagged with application flags, read back through
+    // KVPair::user_flags. a plain put clears them
+    pub fn put_with_flags<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &mut self,
+        key: K,
+        value: V,
+        flags: u16,
+    ) -> Result<()> {
+        let (key, value) = (key.as_ref(), value.as_ref());
         if !self.tx()?.writable() {
-            return Err!("tx not writable");
+            return Err!(RoltError::NotWritable);
         }
         if key.is_empty() {
             return Err!("empty key");
         }
+        reserved(key)?;
+        if self.dup {
+            return Err!(RoltError::IncompatibleValue);
+        }
+        if let Some(width) = self.value_width {
+            if value.len() != width as usize || flags != 0 {
+                return Err!(RoltError::IncompatibleValue);
+            }
+        }
+        self.put_entry(key, value, (flags as u32) << Self::USER_FLAGS_SHIFT)
+    }
+
+    // put a checked key and value with raw element flags
+    pub(crate) fn put_entry(&mut self, key: &[u8], value: &[u8], flags: u32) -> Result<()> {
+        if let Some(mut tail) = self.append_target(key) {
+            self.tx()?.resize_nodes(element_size(key, value), 0)?;
+            tail.put(key, key, value, 0, flags);
+            return self.record_put(key, value, flags, ChangeOp::Insert);
+        }
+        // seek_to stays in the leaf the branches pick for key instead of
+        // stepping to the head of the next one, so get finds the key there
+        // before commit rewrites the separators
+        let mut cursor = self.cursor();
+        let pair = cursor.seek_to(key)?;
+        let op = if Some(key) == pair.key() {
+            ChangeOp::Update
+        } else {
+            ChangeOp::Insert
+        };
+        // a value would orphan the nested bucket's pages, delete refuses too
+        if op == ChangeOp::Update && pair.is_bucket() {
+            return Err!(RoltError::IncompatibleValue);
+        }
+        // nothing at or after key in its leaf, it goes to the end
+        let past_end = pair.key().is_none();
+        // the pages of a blob being replaced go back to the free list
+        let old_blob = match pair.value() {
+            Some(v) if op == ChangeOp::Update && pair.is_blob() => Some(v.to_vec()),
+            _ => None,
+        };
+        let replaced = match op {
+            ChangeOp::Update => element_size(key, pair.value().unwrap_or_default()),
+            _ => 0,
+        };
+        self.tx()?
+            .resize_nodes(element_size(key, value), replaced)?;
+        let mut node = cursor.node()?;
+        cursor.recycle();
+        if let Some(descriptor) = old_blob {
+            self.free_blob(&descriptor)?;
+        }
+        node.put(key, key, value, 0, flags);
+        if past_end && node.is_rightmost() {
+            self.tail = Some(node);
+        }
+        self.record_put(key, value, flags, op)
+    }
+
+    // the rightmost leaf when key sorts after every key in it
+    fn append_target(&self, key: &[u8]) -> Option<Node> {
+        let tail = self.tail.as_ref()?;
+        let inodes = tail.inodes.borrow();
+        match inodes.last() {
+            Some(last) if key > &last.key()[..] => Some(tail.clone()),
+            _ => None,
+        }
+    }
+
+    fn record_put(&mut self, key: &[u8], value: &[u8], flags: u32, op: ChangeOp) -> Result<()> {
+        // blob contents are not held in memory, changes carry no value
+        let recorded = if flags & Self::BLOB_FLAG != 0 {
+            &[]
+        } else {
+            value
+        };
+        self.tx()?
+            .changes
+            .write()
+            .record(&self.path, key, op, recorded);
+        // a plain put makes the key permanent again
+        self.clear_ttl(key)
+    }
+
+    // delete a key, deleting a missing key is not an error
+    pub fn delete<K: AsRef<[u8]>>(&mut self, key: K) -> Result<()> {
+        let key = key.as_ref();
+        self.remove(key)?;
+        self.clear_ttl(key)
+    }
+
+    // add value to the set of key in a dup bucket, adding it twice is a no-op
+    pub fn put_dup<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) -> Result<()> {
+        let (key, value) = (key.as_ref(), value.as_ref());
+        if !self.writable() {
+            return Err!(RoltError::NotWritable);
+        }
+        if !self.dup {
+            return Err!(RoltError::IncompatibleValue);
+        }
+        if key.is_empty() || value.is_empty() {
+            return Err!("empty key or value");
+        }
+        reserved(key)?;
+        let set = match self.get_bucket(key) {
+            Some(set) => unsafe { &mut *set },
+            None => self.create_nested(key, Self::FLAG)?,
+        };
+        // values of a set are keys of a plain bucket, any of them is fine
+        set.put_entry(value, &[], 0)
+    }
+
+    // every value of key in a dup bucket in sorted order
+    pub fn get_all<K: AsRef<[u8]>>(&self, key: K) -> Vec<&[u8]> {
+        match self.dup_set(key.as_ref()) {
+            Some(set) => set.values(),
+            None => Vec::new(),
+        }
+    }
+
+    // remove one value of key in a dup bucket, a key left without values
+    // reads as missing
+    pub fn delete_dup<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) -> Result<()> {
+        if !self.dup {
+            return Err!(RoltError::IncompatibleValue);
+        }
+        match self.get_bucket(key.as_ref()) {
+            Some(set) => unsafe { &mut *set }.delete(value),
+            None => Ok(()),
+        }
+    }
+
+    // the nested bucket holding the values of key
+    pub(crate) fn dup_set(&self, key: &[u8]) -> Option<&Bucket> {
+        if !self.dup {
+            return None;
+        }
+        self.bucket(key)
+    }
+
+    // keys of a value set, which are the values of its key
+    fn values(&self) -> Vec<&[u8]> {
+        let mut values = Vec::new();
+        let mut c = self.cursor();
+        let mut pair = c.first().ok();
+        while let Some(key) = pair.as_ref().and_then(|p| p.key()) {
+            values.push(key);
+            pair = c.next().ok();
+        }
+        values
+    }
+
+    // remove a key from the leaf node holding it
+    pub(crate) fn remove(&mut self, key: &[u8]) -> Result<()> {
+        if !self.tx()?.writable() {
+            return Err!(RoltError::NotWritable);
+        }
         let mut cursor = self.cursor();
         let pair = cursor.seek(key)?;
-        if Some(key) == pair.key() {}
+        if Some(key) != pair.key() {
+            return Ok(());
+        }
+        if pair.is_bucket() {
+            return Err!(RoltError::IncompatibleValue);
+        }
+        let blob = pair.value().filter(|_| pair.is_blob()).map(|v| v.to_vec());
+        let removed = element_size(key, pair.value().unwrap_or_default());
         let mut node = cursor.node()?;
-        node.put(key, key, value, 0, 0);
+        cursor.recycle();
+        if let Some(descriptor) = blob {
+            self.free_blob(&descriptor)?;
+        }
+        node.remove(key);
+        self.tx()?.resize_nodes(0, removed)?;
+        self.tx()?
+            .changes
+            .write()
+            .record(&self.path, key, ChangeOp::Delete, &[]);
         Ok(())
     }
 
+    // delete many keys at once, returns how many existed. the keys are
+    // sorted and each leaf is searched for once and then cleared of every
+    // key that falls in it. missing keys are skipped, a nested bucket among
+    // them fails with RoltError::IncompatibleValue
+    pub fn delete_batch<K: AsRef<[u8]>>(&mut self, keys: &[K]) -> Result<usize> {
+        if !self.tx()?.writable() {
+            return Err!(RoltError::NotWritable);
+        }
+        let mut keys: Vec<&[u8]> = keys.iter().map(|k| k.as_ref()).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        let mut deleted = 0;
+        let mut rest = &keys[..];
+        while let Some(&first) = rest.first() {
+            let mut cursor = self.cursor();
+            let pair = cursor.seek(first)?;
+            if Some(first) != pair.key() {
+                rest = &rest[1..];
+                continue;
+            }
+            let mut node = cursor.node()?;
+            cursor.recycle();
+            // keys up to the last one of this leaf
+            let last = node.inodes.borrow().last().map(|i| i.key().clone());
+            let n = rest.partition_point(|k| Some(*k) <= last.as_deref());
+            let (batch, tail) = rest.split_at(n);
+            rest = tail;
+            let nested = node
+                .inodes
+                .borrow()
+                .iter()
+                .any(|i| i.is_bucket() && batch.binary_search(&&i.key()[..]).is_ok());
+            if nested {
+                return Err!(RoltError::IncompatibleValue);
+            }
+            for inode in node.remove_sorted(batch) {
+                let (key, value) = (inode.key(), inode.value().cloned().unwrap_or_default());
+                if inode.flags() & Self::BLOB_FLAG != 0 {
+                    self.free_blob(&value)?;
+                }
+                self.tx()?.resize_nodes(0, element_size(key, &value))?;
+                self.tx()?
+                    .changes
+                    .write()
+                    .record(&self.path, key, ChangeOp::Delete, &[]);
+                self.clear_ttl(key)?;
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
+
     // create a new cursor
-    fn cursor(&self) -> Cursor {
+    pub fn cursor(&self) -> Cursor<'_> {
         Cursor::new(self)
     }
 
@@ -169,6 +607,45 @@ impl Bucket {
         self.bucket.root
     }
 
+    // last value handed out by next_sequence
+    pub fn sequence(&self) -> u64 {
+        self.bucket.sequence
+    }
+
+    // bump and return the sequence of this bucket, e.g. for ids. the new
+    // value is saved with the tx
+    pub fn next_sequence(&mut self) -> Result<u64> {
+        let sequence = self.bucket.sequence + 1;
+        self.set_sequence(sequence)?;
+        Ok(sequence)
+    }
+
+    // overwrite the sequence, saved with the tx
+    pub(crate) fn set_sequence(&mut self, sequence: u64) -> Result<()> {
+        if !self.writable() {
+            return Err!(RoltError::NotWritable);
+        }
+        // spill only writes back buckets with a root node
+        if self.root.is_none() {
+            self.node(self.root_id(), WeakNode::new());
+        }
+        self.bucket.sequence = sequence;
+        Ok(())
+    }
+
+    // put value under the next sequence as an 8 byte big-endian key and
+    // return the sequence, so entries iterate in the order they were
+    // appended and each put takes the fast path for keys past the end.
+    // mixing in other keys that sort after those makes appends overwrite
+    pub fn append<V: AsRef<[u8]>>(&mut self, value: V) -> Result<u64> {
+        let seq = self.next_sequence()?;
+        if let Err(e) = self.put(seq.to_be_bytes(), value) {
+            self.bucket.sequence -= 1;
+            return Err(e);
+        }
+        Ok(seq)
+    }
+
     // get page or a node
     pub(crate) fn page_node(&self, id: PageId) -> Result<PageNode> {
         // use inline page
@@ -195,14 +672,20 @@ impl Bucket {
         self.buckets.borrow_mut().clear();
         self.root = None;
         self.nodes.clear();
+        self.tail = None;
     }
 
     // write nodes to dirty pages
     pub(crate) fn spill(&mut self) -> Result<()> {
+        let deterministic = self.tx()?.db()?.deterministic;
         let mut buckets = self.buckets.borrow_mut();
+        let mut children: Vec<(&Vec<u8>, &mut Box<Bucket>)> = buckets.iter_mut().collect();
+        if deterministic {
+            children.sort_by(|a, b| a.0.cmp(b.0));
+        }
 
-        for (name, child) in buckets.iter_mut() {
-            let u8_name = name.as_bytes();
+        for (name, child) in children {
+            let u8_name = name.as_slice();
             let value = {
                 child.spill()?;
                 unsafe {
@@ -218,7 +701,7 @@ impl Bucket {
             let mut c = self.cursor();
             let pair = c.seek(u8_name)?;
             if Some(u8_name) != pair.key {
-                return Err(anyhow::anyhow!("bucket header not match"));
+                return Err!("bucket header not match");
             }
             let mut node = c.node()?;
             node.put(u8_name, u8_name, value.as_slice(), 0, pair.flags);
@@ -226,25 +709,31 @@ impl Bucket {
 
         // spill root node
         if self.root.is_some() {
-            let mut root = self.root.clone().ok_or(anyhow!("root is empty"))?;
+            let mut root = self.root.clone().ok_or(RoltError::from("root is empty"))?;
             root.spill()?;
             self.root = Some(root);
             let page_id = self.root.as_ref().unwrap().page_id();
             self.bucket.root = page_id;
         }
+        // split into several leaves
+        self.tail = None;
         Ok(())
     }
 
     pub(crate) fn rebalance(&mut self) -> Result<()> {
+        // every open bucket is visited, one may only be the way down to a
+        // bucket that had removals
         for (_, b) in self.buckets.borrow_mut().iter_mut() {
-            // recursively rebalance
-            if b.dirty {
-                self.dirty = true;
-                b.rebalance()?;
-            }
+            b.rebalance()?;
+        }
+        if !self.dirty {
+            return Ok(());
         }
-        if self.dirty {
-            for node in self.nodes.borrow_mut().values_mut() {
+        self.dirty = false;
+        // merges take nodes out of the map, skip those already gone
+        let nodes: Vec<Node> = self.nodes.values().cloned().collect();
+        for mut node in nodes {
+            if self.nodes.contains_key(&node.page_id()) {
                 node.rebalance()?;
             }
         }
@@ -257,14 +746,18 @@ impl Bucket {
 
         let mut node = Node::new(RawPtr::new(self), crate::node::NodeType::Leaf);
 
+        let metrics = self.tx().unwrap().db().unwrap().metrics.clone();
         // node crated
         if let Some(n) = self.nodes.get(&page_id) {
+            metrics.cache_hit();
             return n.clone();
         }
+        metrics.cache_miss();
 
         match parent.upgrade() {
             Some(p) => {
                 p.children.borrow_mut().push(node.clone());
+                *node.parent.borrow_mut() = parent;
             }
             None => {
                 // set new root if parent is empty
@@ -281,6 +774,7 @@ impl Bucket {
             let page = self.tx().unwrap().page(page_id).unwrap();
             node.read(&*page).unwrap();
         }
+        self.tx().unwrap().charge_node(node.size());
         self.nodes.insert(page_id, node.clone());
         node
     }
@@ -320,9 +814,24 @@ impl Bucket {
         true
     }
 }
+
+// names and counts only, deriving would walk the node graph and print keys
+impl std::fmt::Debug for Bucket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Bucket")
+            .field("name", &String::from_utf8_lossy(self.name()))
+            .field("root", &{ self.bucket.root })
+            .field("depth", &self.depth)
+            .field("dirty_nodes", &self.nodes.len())
+            .field("buckets", &self.open_buckets())
+            .finish()
+    }
+}
+
 // on-file representation of bucket
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
 pub(crate) struct IBucket {
     pub(crate) root: PageId,
     // increase monotonically
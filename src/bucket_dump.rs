@@ -0,0 +1,178 @@
+use std::io::{Read, Write};
+
+use crate::{
+    bucket::Bucket,
+    error::{Result, RoltError},
+    ttl::TTL_BUCKET,
+    Err,
+};
+
+// "roltbkt1", first word of a bucket dump
+const MAGIC: u64 = u64::from_le_bytes(*b"roltbkt1");
+
+// record tags, the records of a bucket end with END
+const END: u8 = 0;
+const VALUE: u8 = 1;
+const BLOB: u8 = 2;
+const BUCKET: u8 = 3;
+
+impl Bucket {
+    // write this bucket and everything nested in it to w in a compact
+    // binary form read back by restore, returns the number of values
+    // written. a dump is the magic and the sequence followed by records and
+    // END. a record is a tag, the element flags (u32) and the key (u32
+    // length), then the value (u64 length) of a value or blob, or the
+    // sequence and records of a nested bucket. integers are little-endian
+    pub fn dump<W: Write>(&self, w: &mut W) -> Result<u64> {
+        w.write_all(&MAGIC.to_le_bytes())?;
+        w.write_all(&self.sequence().to_le_bytes())?;
+        let n = self.dump_records(w)?;
+        w.flush()?;
+        Ok(n)
+    }
+
+    // put the contents of a dump into this bucket, overwriting keys it
+    // already has and merging into nested buckets of the same name. the
+    // sequences are set to the dumped ones. returns the number of values
+    // restored
+    pub fn restore<R: Read>(&mut self, r: &mut R) -> Result<u64> {
+        if !self.writable() {
+            return Err!(RoltError::NotWritable);
+        }
+        if read_u64(r)? != MAGIC {
+            return Err!("not a bucket dump");
+        }
+        let sequence = read_u64(r)?;
+        self.restore_records(r, sequence)
+    }
+
+    fn dump_records<W: Write>(&self, w: &mut W) -> Result<u64> {
+        let mut n = 0;
+        let mut ttl = None;
+        let mut c = self.cursor();
+        let mut pair = c.first()?;
+        while let Some(key) = pair.key() {
+            let value = pair.value().unwrap_or_default();
+            // the ttl index goes last, restoring a key clears its deadline
+            if key == TTL_BUCKET {
+                ttl = Some(pair.flags);
+            } else {
+                n += self.dump_record(w, key, pair.flags, value)?;
+            }
+            pair = c.next()?;
+        }
+        if let Some(flags) = ttl {
+            n += self.dump_record(w, TTL_BUCKET, flags, &[])?;
+        }
+        w.write_all(&[END])?;
+        Ok(n)
+    }
+
+    fn dump_record<W: Write>(
+        &self,
+        w: &mut W,
+        key: &[u8],
+        flags: u32,
+        value: &[u8],
+    ) -> Result<u64> {
+        let head = |w: &mut W, tag: u8| -> Result<()> {
+            w.write_all(&[tag])?;
+            w.write_all(&flags.to_le_bytes())?;
+            w.write_all(&(key.len() as u32).to_le_bytes())?;
+            w.write_all(key)?;
+            Ok(())
+        };
+        if flags & Self::FLAG != 0 {
+            let child = match self.get_bucket(key) {
+                Some(b) => unsafe { &*b },
+                None => return Err!(RoltError::BucketNotFound),
+            };
+            head(w, BUCKET)?;
+            w.write_all(&child.sequence().to_le_bytes())?;
+            return child.dump_records(w);
+        }
+        if flags & Self::BLOB_FLAG != 0 {
+            let mut reader = self.blob_reader(value)?;
+            let len = reader.len();
+            head(w, BLOB)?;
+            w.write_all(&len.to_le_bytes())?;
+            if std::io::copy(&mut reader, w)? != len {
+                return Err!(RoltError::Corruption(
+                    "blob is shorter than its length".into()
+                ));
+            }
+            return Ok(1);
+        }
+        head(w, VALUE)?;
+        w.write_all(&(value.len() as u64).to_le_bytes())?;
+        w.write_all(value)?;
+        Ok(1)
+    }
+
+    fn restore_records<R: Read>(&mut self, r: &mut R, sequence: u64) -> Result<u64> {
+        self.set_sequence(sequence)?;
+        let mut n = 0;
+        loop {
+            let tag = read_u8(r)?;
+            if tag == END {
+                return Ok(n);
+            }
+            let flags = read_u32(r)?;
+            let len = read_u32(r)?;
+            let key = read_bytes(r, len as u64)?;
+            match tag {
+                VALUE => {
+                    let len = read_u64(r)?;
+                    let value = read_bytes(r, len)?;
+                    let user_flags = (flags >> Self::USER_FLAGS_SHIFT) as u16;
+                    self.put_with_flags(&key, &value, user_flags)?;
+                }
+                BLOB => {
+                    let len = read_u64(r)?;
+                    if self.put_blob(&key, &mut r.take(len))? != len {
+                        return Err!("bucket dump is truncated");
+                    }
+                }
+                BUCKET if flags & Self::FLAG != 0 => {
+                    let sequence = read_u64(r)?;
+                    let child = match self.get_bucket(&key) {
+                        Some(b) => unsafe { &mut *b },
+                        None => self.create_nested(&key, flags & Self::ENGINE_FLAGS)?,
+                    };
+                    n += child.restore_records(r, sequence)?;
+                    continue;
+                }
+                _ => return Err!("bucket dump has an unknown record"),
+            }
+            n += 1;
+        }
+    }
+}
+
+fn read_u8<R: Read>(r: &mut R) -> Result<u8> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32<R: Read>(r: &mut R) -> Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+// len bytes, read without trusting len for the allocation
+fn read_bytes<R: Read>(r: &mut R, len: u64) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    r.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err!("bucket dump is truncated");
+    }
+    Ok(buf)
+}
@@ -0,0 +1,671 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    fs::File,
+    mem::size_of,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Sender},
+    },
+    thread,
+};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::{
+    blob::parse_descriptor,
+    bucket::{Bucket, IBucket},
+    db::{map_file, DB},
+    error::Result,
+    meta::Meta,
+    page::{page_offset, BranchPageElement, FixedLeafElement, LeafPageElement, Page, PageId},
+    transaction::{ITransaction, Txid},
+    Err,
+};
+
+// what kind of problem check found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IssueKind {
+    // a meta page is missing, unreadable or fails its checksum
+    Meta,
+    // the file ends before the last page the meta page counts
+    Truncated,
+    // the free list page is damaged or lists pages it shouldn't
+    FreeList,
+    // a page id points outside the file
+    OutOfRange,
+    // a page is in use and free at once, or in use twice
+    SharedPage,
+    // a page is neither in use nor free
+    LeakedPage,
+    // a branch or leaf page can't be read
+    CorruptPage,
+    // keys are out of order
+    KeyOrder,
+    // a blob chain is broken or doesn't match its descriptor
+    Blob,
+    // a reachable page was written by a tx newer than the meta page, a
+    // commit only partly reached the disk
+    TornWrite,
+}
+
+impl IssueKind {
+    // how bad a problem of this kind is on its own
+    pub fn severity(&self) -> Severity {
+        match self {
+            IssueKind::LeakedPage => Severity::Warning,
+            IssueKind::Meta
+            | IssueKind::OutOfRange
+            | IssueKind::CorruptPage
+            | IssueKind::KeyOrder
+            | IssueKind::Blob
+            | IssueKind::TornWrite => Severity::Error,
+            IssueKind::Truncated | IssueKind::FreeList | IssueKind::SharedPage => Severity::Fatal,
+        }
+    }
+
+    // what to do about a problem of this kind
+    pub fn remediation(&self) -> &'static str {
+        match self {
+            IssueKind::Meta => "verify with ignore_checksum and copy out what it reaches",
+            IssueKind::Truncated => "restore the file from a backup",
+            IssueKind::FreeList => "stop writing and compact the file to rebuild the free list",
+            IssueKind::SharedPage => "stop writing and restore the file from a backup",
+            IssueKind::LeakedPage => "compact the file to reclaim the pages",
+            IssueKind::TornWrite => "restore the file from a backup or salvage it",
+            IssueKind::OutOfRange
+            | IssueKind::CorruptPage
+            | IssueKind::KeyOrder
+            | IssueKind::Blob => "copy the readable keys to a new file or restore a backup",
+        }
+    }
+}
+
+// how bad a problem is, from least to most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    // space is wasted, every key can still be read
+    Warning,
+    // some keys can't be read or read back wrong
+    Error,
+    // the file can't be trusted, writing to it may destroy more data
+    Fatal,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+            Severity::Fatal => "fatal",
+        };
+        f.write_str(name)
+    }
+}
+
+// a problem found by check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckError {
+    pub kind: IssueKind,
+    pub severity: Severity,
+    pub page_id: PageId,
+    // names of the nested buckets leading to the page, empty for the root
+    // bucket and for pages outside the tree
+    pub bucket: Vec<Vec<u8>>,
+    pub message: String,
+}
+
+impl CheckError {
+    pub(crate) fn new(kind: IssueKind, page_id: PageId, bucket: &[Vec<u8>], message: &str) -> Self {
+        Self {
+            kind,
+            severity: kind.severity(),
+            page_id,
+            bucket: bucket.to_vec(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn remediation(&self) -> &'static str {
+        self.kind.remediation()
+    }
+}
+
+impl Display for CheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "page {}", self.page_id)?;
+        if !self.bucket.is_empty() {
+            let path: Vec<_> = self
+                .bucket
+                .iter()
+                .map(|b| String::from_utf8_lossy(b))
+                .collect();
+            write!(f, " in bucket {}", path.join("/"))?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+// problems found by check
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckReport {
+    pub issues: Vec<CheckError>,
+}
+
+impl CheckReport {
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    // the worst severity found, None for a clean file
+    pub fn severity(&self) -> Option<Severity> {
+        self.issues.iter().map(|e| e.severity).max()
+    }
+}
+
+impl ITransaction {
+    // verify the pages committed as of this tx: every page must be reachable
+    // from the root bucket exactly once or be free, and keys must be sorted.
+    // problems are passed to f as soon as they are found
+    pub fn check_with<F: FnMut(CheckError)>(&self, f: F) -> Result<()> {
+        let db = self.db()?;
+        walk_file(self.mmap(), db.page_size(), &self.meta(), f);
+        Ok(())
+    }
+
+    // like check_with, collecting the problems
+    pub fn check(&self) -> Result<CheckReport> {
+        let mut report = CheckReport::default();
+        self.check_with(|e| report.issues.push(e))?;
+        Ok(report)
+    }
+}
+
+// options of DB::verify
+#[derive(Debug, Clone, Default)]
+pub struct VerifyOptions {
+    // page size the file was written with, read from the meta pages when None
+    pub page_size: Option<u64>,
+    // accept a meta page whose checksum doesn't match, to salvage what it
+    // points at
+    pub ignore_checksum: bool,
+}
+
+// result of DB::verify
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    // txid of the meta page the scan started from, None if neither is usable
+    pub tx_id: Option<Txid>,
+    pub errors: Vec<CheckError>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.tx_id.is_some() && self.errors.is_empty()
+    }
+
+    // the worst severity found, None for a clean file
+    pub fn severity(&self) -> Option<Severity> {
+        match self.tx_id {
+            None => Some(Severity::Fatal),
+            Some(_) => self.errors.iter().map(|e| e.severity).max(),
+        }
+    }
+}
+
+impl DB {
+    // scan the file at path without opening it as a db: both meta pages, page
+    // headers, element bounds, key order and the free list. no locks are
+    // taken, so it also works on files open() refuses
+    pub fn verify<P: AsRef<Path>>(path: P, opts: VerifyOptions) -> Result<VerifyReport> {
+        let file = File::open(path)?;
+        if file.metadata()?.len() == 0 {
+            return Err!("db file is empty");
+        }
+        let mmap = map_file(&file)?;
+        let mut report = VerifyReport::default();
+        let page_size = opts.page_size.unwrap_or_else(|| guess_page_size(&mmap));
+        let mut best: Option<&Meta> = None;
+        for id in 0..2 {
+            if (id + 1) * page_size > mmap.len() as u64 {
+                report.errors.push(CheckError::new(
+                    IssueKind::Meta,
+                    id,
+                    &[],
+                    "meta page is missing",
+                ));
+                continue;
+            }
+            let meta = match Page::from_buf(&mmap, id, page_size).and_then(Page::meta) {
+                Ok(m) => m,
+                Err(_) => {
+                    report.errors.push(CheckError::new(
+                        IssueKind::Meta,
+                        id,
+                        &[],
+                        "meta page has the wrong type",
+                    ));
+                    continue;
+                }
+            };
+            if !meta.has_magic() {
+                report.errors.push(CheckError::new(
+                    IssueKind::Meta,
+                    id,
+                    &[],
+                    "meta page has a bad magic number",
+                ));
+                continue;
+            }
+            if !meta.sum_matches() {
+                report.errors.push(CheckError::new(
+                    IssueKind::Meta,
+                    id,
+                    &[],
+                    "meta page checksum mismatch",
+                ));
+                if !opts.ignore_checksum {
+                    continue;
+                }
+            }
+            // files created by init_file leave the page size at zero
+            if meta.page_size != 0 && meta.page_size as u64 != page_size {
+                report.errors.push(CheckError::new(
+                    IssueKind::Meta,
+                    id,
+                    &[],
+                    "meta page has a different page size",
+                ));
+            }
+            if best.is_none_or(|b| meta.tx_id > b.tx_id) {
+                best = Some(meta);
+            }
+        }
+        let meta = match best {
+            Some(m) => m.clone(),
+            None => return Ok(report),
+        };
+        // the pages of another format version would be misread
+        meta.check_version()?;
+        report.tx_id = Some(meta.tx_id);
+        walk_file(&mmap, page_size, &meta, |e| report.errors.push(e));
+        Ok(report)
+    }
+}
+
+// page size recorded in the first meta page that carries the magic number
+pub(crate) fn guess_page_size(mmap: &[u8]) -> u64 {
+    if mmap.len() < Page::page_header_size() + size_of::<Meta>() {
+        return page_size::get() as u64;
+    }
+    match Page::from_buf(mmap, 0, 0).and_then(Page::meta) {
+        Ok(m) if m.has_magic() && m.page_size > 0 => m.page_size as u64,
+        _ => page_size::get() as u64,
+    }
+}
+
+// check the tree meta points at in the mapped file, passing problems to f
+fn walk_file<F: FnMut(CheckError)>(mmap: &[u8], page_size: u64, meta: &Meta, mut f: F) {
+    let num_pages = meta.num_pages.min(mmap.len() as u64 / page_size);
+    let walker = Walker {
+        mmap,
+        page_size,
+        num_pages,
+        tx_id: meta.tx_id,
+        free: HashSet::new(),
+        reachable: (0..num_pages).map(|_| AtomicBool::new(false)).collect(),
+    };
+    let (root, free_list, meta_pages) = (meta.root.root, meta.free_list, meta.num_pages);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|s| {
+        s.spawn(move || {
+            let mut walker = walker;
+            walker.run(root, free_list, meta_pages, &sender)
+        });
+        for e in receiver {
+            f(e);
+        }
+    });
+}
+
+// walks the page tree straight over the mapped file, so subtrees can be
+// checked from several threads
+struct Walker<'a> {
+    mmap: &'a [u8],
+    page_size: u64,
+    // pages backed by the mapping
+    num_pages: PageId,
+    // txid of the meta page, no reachable page may be newer
+    tx_id: Txid,
+    free: HashSet<PageId>,
+    reachable: Vec<AtomicBool>,
+}
+
+impl<'a> Walker<'a> {
+    fn run(&mut self, root: PageId, free_list: PageId, meta_pages: u64, out: &Sender<CheckError>) {
+        if meta_pages > self.num_pages {
+            report(
+                out,
+                IssueKind::Truncated,
+                meta_pages - 1,
+                &[],
+                "file is shorter than the meta page count",
+            );
+        }
+        self.free = self.free_pages(free_list, out);
+        if let Some(p) = self.visit(free_list, &[], out) {
+            if p.page_type != Page::FREE_LIST_PAGE {
+                let message = "free list page has the wrong type";
+                report(out, IssueKind::FreeList, free_list, &[], message);
+            }
+        }
+        self.walk(root, &[], &[], out);
+        for id in 2..self.num_pages {
+            if !self.reachable[id as usize].load(Ordering::Relaxed) && !self.free.contains(&id) {
+                report(
+                    out,
+                    IssueKind::LeakedPage,
+                    id,
+                    &[],
+                    "page is neither reachable nor free",
+                );
+            }
+        }
+    }
+
+    // free page ids stored in the committed free list page
+    fn free_pages(&self, id: PageId, out: &Sender<CheckError>) -> HashSet<PageId> {
+        let word = |i: usize| -> Option<u64> {
+            let start = page_offset(id, self.page_size)? + Page::page_header_size() + i * 8;
+            let bytes = self.mmap.get(start..start + size_of::<u64>())?;
+            Some(u64::from_ne_bytes(bytes.try_into().ok()?))
+        };
+        let page = match Page::from_buf(self.mmap, id, self.page_size) {
+            Ok(page) if id < self.num_pages => page,
+            _ => {
+                report(
+                    out,
+                    IssueKind::FreeList,
+                    id,
+                    &[],
+                    "free list page is out of range",
+                );
+                return HashSet::new();
+            }
+        };
+        let (count, begin) = match page.count {
+            // count overflow, the real count is stored in the first elem
+            u16::MAX => (word(0).unwrap_or(0) as usize, 1),
+            n => (n as usize, 0),
+        };
+        let mut free = HashSet::new();
+        for i in begin..begin + count {
+            match word(i) {
+                Some(free_id) if free_id < 2 || free_id >= self.num_pages => report(
+                    out,
+                    IssueKind::FreeList,
+                    free_id,
+                    &[],
+                    "free page is out of range",
+                ),
+                Some(free_id) => {
+                    free.insert(free_id);
+                }
+                None => {
+                    let message = "free list runs past the end of the file";
+                    report(out, IssueKind::FreeList, id, &[], message);
+                    break;
+                }
+            }
+        }
+        free
+    }
+
+    // mark a page and its overflow blocks as reachable, None when it can't be
+    // walked safely
+    fn visit(&self, id: PageId, path: &[Vec<u8>], out: &Sender<CheckError>) -> Option<&'a Page> {
+        let p = match Page::from_buf(self.mmap, id, self.page_size) {
+            Ok(p) if id >= 2 && id < self.num_pages => p,
+            _ => {
+                report(out, IssueKind::OutOfRange, id, path, "page is out of range");
+                return None;
+            }
+        };
+        let last = id + p.overflow as PageId;
+        if last >= self.num_pages {
+            let message = "page overflows past the end of the file";
+            report(out, IssueKind::OutOfRange, id, path, message);
+            return None;
+        }
+        if { p.txid } > self.tx_id {
+            let message = format!(
+                "page was written by tx {}, after the meta page's tx {}",
+                { p.txid },
+                self.tx_id
+            );
+            report(out, IssueKind::TornWrite, id, path, &message);
+        }
+        let mut ok = true;
+        for block in id..=last {
+            if self.free.contains(&block) {
+                report(
+                    out,
+                    IssueKind::SharedPage,
+                    block,
+                    path,
+                    "reachable page is free",
+                );
+            }
+            if self.reachable[block as usize].swap(true, Ordering::Relaxed) {
+                let message = "page is referenced more than once";
+                report(out, IssueKind::SharedPage, block, path, message);
+                ok = false;
+            }
+        }
+        ok.then_some(p)
+    }
+
+    // check the tree rooted at id, all of its keys must be at least min.
+    // path names the bucket the tree belongs to
+    fn walk(&self, id: PageId, min: &[u8], path: &[Vec<u8>], out: &Sender<CheckError>) {
+        let p = match self.visit(id, path, out) {
+            Some(p) => p,
+            None => return,
+        };
+        let span = (p.overflow as usize + 1) * self.page_size as usize;
+        if !elements_fit(p, span) {
+            let message = "elements run past the end of the page";
+            return report(out, IssueKind::CorruptPage, id, path, message);
+        }
+        match p.page_type {
+            Page::BRANCH_PAGE => {
+                let elems = p.branch_elements().unwrap_or(&[]);
+                if !sorted(elems.iter().map(|e| e.key()), min) {
+                    report(
+                        out,
+                        IssueKind::KeyOrder,
+                        id,
+                        path,
+                        "branch keys are out of order",
+                    );
+                }
+                #[cfg(feature = "rayon")]
+                elems
+                    .par_iter()
+                    .for_each(|e| self.walk(e.id, e.key(), path, out));
+                #[cfg(not(feature = "rayon"))]
+                elems
+                    .iter()
+                    .for_each(|e| self.walk(e.id, e.key(), path, out));
+            }
+            Page::LEAF_PAGE | Page::FIXED_LEAF_PAGE => self.walk_leaf(p, min, path, out),
+            t => {
+                let message = format!("unexpected page type {}", t);
+                report(out, IssueKind::CorruptPage, id, path, &message)
+            }
+        }
+    }
+
+    // mark the chunks of the blob behind a leaf value as reachable
+    fn walk_blob(
+        &self,
+        leaf: PageId,
+        descriptor: &[u8],
+        path: &[Vec<u8>],
+        out: &Sender<CheckError>,
+    ) {
+        let blob = |id, message| report(out, IssueKind::Blob, id, path, message);
+        let (len, mut id) = match parse_descriptor(descriptor) {
+            Ok(d) => d,
+            Err(_) => return blob(leaf, "blob descriptor is truncated"),
+        };
+        let mut seen = 0;
+        while id != 0 {
+            let p = match self.visit(id, path, out) {
+                Some(p) => p,
+                None => return,
+            };
+            if p.page_type != Page::BLOB_PAGE {
+                return blob(id, "blob chain runs into a page of another type");
+            }
+            let chunk = p.blob_chunk();
+            let span = (p.overflow as usize + 1) * self.page_size as usize;
+            if p.used_bytes() > span {
+                return blob(id, "blob chunk runs past the end of the page");
+            }
+            seen += chunk.len;
+            id = chunk.next;
+        }
+        if seen != len {
+            blob(leaf, "blob is shorter or longer than its descriptor");
+        }
+    }
+
+    fn walk_leaf(&self, p: &Page, min: &[u8], path: &[Vec<u8>], out: &Sender<CheckError>) {
+        let elems = leaf_entries(p);
+        if !sorted(elems.iter().map(|e| e.0), min) {
+            report(
+                out,
+                IssueKind::KeyOrder,
+                p.id,
+                path,
+                "leaf keys are out of order",
+            );
+        }
+        for e in elems.iter().filter(|e| e.2 & Bucket::BLOB_FLAG != 0) {
+            self.walk_blob(p.id, e.1, path, out);
+        }
+        let buckets: Vec<(&[u8], &[u8])> = elems
+            .iter()
+            .filter(|e| e.2 & Bucket::FLAG != 0)
+            .map(|e| (e.0, e.1))
+            .collect();
+        let walk_bucket = |&(key, value): &(&[u8], &[u8])| {
+            let mut path = path.to_vec();
+            path.push(key.to_vec());
+            let corrupt = |message| report(out, IssueKind::CorruptPage, p.id, &path, message);
+            if value.len() < IBucket::SIZE {
+                return corrupt("nested bucket header is truncated");
+            }
+            let b = unsafe { std::ptr::read_unaligned(value.as_ptr() as *const IBucket) };
+            if b.root != 0 {
+                return self.walk(b.root, &[], &path, out);
+            }
+            // inline bucket, its page lives in the value
+            let buf = &value[IBucket::SIZE..];
+            let inline = match Page::from_buf_direct(buf) {
+                Ok(inline) => inline,
+                Err(_) => return corrupt("inline bucket is truncated"),
+            };
+            let sorted = inline.is_leaf()
+                && elements_fit(inline, buf.len())
+                && sorted(leaf_entries(inline).iter().map(|e| e.0), &[]);
+            if !sorted {
+                return corrupt("inline bucket is not a sorted leaf");
+            }
+            for e in leaf_entries(inline) {
+                if e.2 & Bucket::BLOB_FLAG != 0 {
+                    self.walk_blob(p.id, e.1, &path, out);
+                }
+            }
+        };
+        #[cfg(feature = "rayon")]
+        buckets.par_iter().for_each(walk_bucket);
+        #[cfg(not(feature = "rayon"))]
+        buckets.iter().for_each(walk_bucket);
+    }
+}
+
+// elements of a branch or leaf page and the keys and values they point at
+// all lie within the first span bytes of the page
+pub(crate) fn elements_fit(p: &Page, span: usize) -> bool {
+    let header = Page::page_header_size();
+    let count = p.count as usize;
+    let ends: Vec<usize> = match p.page_type {
+        Page::BRANCH_PAGE if header + count * BranchPageElement::SIZE <= span => p
+            .branch_elements()
+            .unwrap_or(&[])
+            .iter()
+            .enumerate()
+            .map(|(i, e)| header + i * BranchPageElement::SIZE + (e.pos + e.k_size) as usize)
+            .collect(),
+        Page::LEAF_PAGE if header + count * LeafPageElement::SIZE <= span => p
+            .leaf_elements()
+            .unwrap_or(&[])
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                header + i * LeafPageElement::SIZE + (e.pos + e.k_size + e.v_size) as usize
+            })
+            .collect(),
+        Page::FIXED_LEAF_PAGE
+            if header + count * (FixedLeafElement::SIZE + p.value_width as usize) <= span =>
+        {
+            p.fixed_elements()
+                .unwrap_or(&[])
+                .iter()
+                .enumerate()
+                .map(|(i, e)| header + i * FixedLeafElement::SIZE + (e.pos + e.k_size) as usize)
+                .collect()
+        }
+        Page::BRANCH_PAGE | Page::LEAF_PAGE | Page::FIXED_LEAF_PAGE => return false,
+        _ => return true,
+    };
+    ends.iter().all(|&end| end <= span)
+}
+
+// key, value and flags of every element of a leaf page of either kind
+pub(crate) fn leaf_entries(p: &Page) -> Vec<(&[u8], &[u8], u32)> {
+    (0..p.count as usize)
+        .map_while(|i| p.leaf_at(i).ok())
+        .collect()
+}
+
+// keys are strictly ascending and none is below min
+pub(crate) fn sorted<'k>(mut keys: impl Iterator<Item = &'k [u8]>, min: &[u8]) -> bool {
+    let mut prev = match keys.next() {
+        None => return true,
+        Some(k) if k < min => return false,
+        Some(k) => k,
+    };
+    for k in keys {
+        if k <= prev {
+            return false;
+        }
+        prev = k;
+    }
+    true
+}
+
+fn report(
+    out: &Sender<CheckError>,
+    kind: IssueKind,
+    page_id: PageId,
+    bucket: &[Vec<u8>],
+    message: &str,
+) {
+    // the receiver only goes away once the walk is over
+    let _ = out.send(CheckError::new(kind, page_id, bucket, message));
+}
@@ -0,0 +1,55 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{bucket::Bucket, error::Result};
+
+// serialization format used by typed puts and gets
+pub trait Codec {
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>>;
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T>;
+}
+
+// compact binary encoding
+pub struct Bincode;
+
+impl Codec for Bincode {
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(value)?)
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+// human readable encoding
+pub struct Json;
+
+impl Codec for Json {
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(value)?)
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+impl Bucket {
+    // encode value with codec C and put it
+    pub fn put_typed<C: Codec, T: Serialize>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: &T,
+    ) -> Result<()> {
+        let bytes = C::encode(value)?;
+        self.put(key, &bytes)
+    }
+    // get a value and decode it with codec C
+    pub fn get_typed<C: Codec, T: DeserializeOwned>(
+        &self,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Option<T>> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(bytes) => C::decode(bytes).map(Some),
+        }
+    }
+}
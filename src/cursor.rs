@@ -1,29 +1,76 @@
-use std::{cell::RefCell, cmp::Ordering, marker::PhantomData, ops::Deref};
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    marker::PhantomData,
+    ops::Deref,
+};
 
 use crate::{
     bucket::{Bucket, PageNode},
     error::{Result, RoltError},
     node::{Node, WeakNode},
     page::{Page, PageId},
+    utils::{debug_span, search_keys},
 };
-use anyhow::anyhow;
-pub(crate) struct Cursor<'a> {
+
+// walks a bucket in key order. pairs borrow straight from page memory, or from
+// the owned node when the page was modified in this tx, so scans don't copy
+pub struct Cursor<'a> {
     bucket: &'a Bucket,
     stack: RefCell<Vec<ElementRef>>,
+    // leaves entered one after another by next
+    leaf_moves: Cell<usize>,
+    // branch page and child index up to which leaves were read ahead
+    read_ahead: Cell<(PageId, usize)>,
+    // set by seek_prefix, next and prev stop at keys without it
+    prefix: RefCell<Option<Vec<u8>>>,
+    // the last move ran off the end, or there was none yet
+    done: Cell<bool>,
+    // walks the values of the current key in a dup bucket
+    dups: RefCell<Option<Box<Cursor<'a>>>>,
     // constrains the lifetime of pair
     _f: PhantomData<KVPair<'a>>,
 }
 
 #[allow(dead_code)]
 impl<'a> Cursor<'a> {
-    pub fn new(b: &'a Bucket) -> Self {
+    // leaves to walk in order before reading ahead
+    const SEQUENTIAL_LEAVES: usize = 2;
+    // leaves to read ahead of the cursor
+    const READ_AHEAD: usize = 8;
+
+    pub(crate) fn new(b: &'a Bucket) -> Self {
         Self {
             bucket: b,
-            stack: RefCell::new(Vec::new()),
+            // reuse the stack of an earlier cursor on the bucket
+            stack: RefCell::new(b.cursor_stack.take()),
+            leaf_moves: Cell::new(0),
+            read_ahead: Cell::new((0, 0)),
+            prefix: RefCell::new(None),
+            done: Cell::new(true),
+            dups: RefCell::new(None),
             _f: PhantomData,
         }
     }
 
+    // forget the position so the cursor can be moved again from scratch,
+    // keeping its stack allocation
+    pub fn reset(&mut self) {
+        self.stack.get_mut().clear();
+        self.leaf_moves.set(0);
+        self.read_ahead.set((0, 0));
+        self.prefix.replace(None);
+        self.done.set(true);
+        self.dups.replace(None);
+    }
+
+    // give the stack back to the bucket for its next cursor
+    pub(crate) fn recycle(self) {
+        let mut stack = self.stack.into_inner();
+        stack.clear();
+        self.bucket.cursor_stack.replace(stack);
+    }
+
     pub(crate) fn bucket(&self) -> &Bucket {
         self.bucket
     }
@@ -33,8 +80,11 @@ impl<'a> Cursor<'a> {
         unsafe { &mut *(self.bucket as *const Bucket as *mut Bucket) }
     }
 
-    pub fn first(&mut self) -> Result<KVPair> {
+    // move to the first key of the bucket
+    pub fn first(&mut self) -> Result<KVPair<'a>> {
         self.stack.borrow_mut().clear();
+        self.leaf_moves.set(0);
+        self.prefix.replace(None);
         let root_elem = self.bucket().page_node(self.bucket().root_id())?;
         self.stack.borrow_mut().push(ElementRef {
             page_node: root_elem,
@@ -42,25 +92,38 @@ impl<'a> Cursor<'a> {
         });
         // look for the first leaf node
         self.first_leaf()?;
+        // a leaf emptied in this tx, move on to the next key
+        if self.leaf_empty()? {
+            let pair = self.step_next()?;
+            return Ok(self.settle(pair));
+        }
 
-        let pair = self.kv_pair()?;
+        let pair = self.settle(self.kv_pair()?);
 
         Ok(pair)
     }
+
+    fn leaf_empty(&self) -> Result<bool> {
+        let stack = self.stack.borrow();
+        let elem = stack.last().ok_or(RoltError::StackEmpty)?;
+        Ok(elem.count() == 0)
+    }
     fn first_leaf(&self) -> Result<()> {
         loop {
-            let stack = self.stack.borrow();
-            let elem = stack.last().ok_or(anyhow!(RoltError::StackEmpty))?;
-            // stop when find a leaf
-            if elem.is_leaf() {
-                break;
-            }
-            // if it is branch then go deeper
-            let page_id = match elem.upgrade() {
-                either::Either::Left(p) => p.branch_elements()?[elem.index].id,
-                either::Either::Right(n) => n.inodes.borrow()[elem.index]
-                    .page_id()
-                    .ok_or(anyhow::anyhow!("does not have page id"))?,
+            let page_id = {
+                let stack = self.stack.borrow();
+                let elem = stack.last().ok_or(RoltError::StackEmpty)?;
+                // stop when find a leaf
+                if elem.is_leaf() {
+                    break;
+                }
+                // if it is branch then go deeper
+                match elem.upgrade() {
+                    either::Either::Left(p) => p.branch_elements()?[elem.index].id,
+                    either::Either::Right(n) => n.inodes.borrow()[elem.index]
+                        .page_id()
+                        .ok_or(RoltError::from("does not have page id"))?,
+                }
             };
             let page_node = self.bucket().page_node(page_id)?;
             self.stack.borrow_mut().push(ElementRef {
@@ -70,37 +133,121 @@ impl<'a> Cursor<'a> {
         }
         Ok(())
     }
+    // next just entered a new leaf. once a few leaves were walked in order,
+    // hint the kernel about the siblings the parent branch page points at
+    fn read_ahead(&self) -> Result<()> {
+        let moves = self.leaf_moves.get() + 1;
+        self.leaf_moves.set(moves);
+        if moves < Self::SEQUENTIAL_LEAVES {
+            return Ok(());
+        }
+        let stack = self.stack.borrow();
+        let parent = match stack.len().checked_sub(2).map(|i| &stack[i]) {
+            Some(parent) => parent,
+            None => return Ok(()),
+        };
+        // pages modified in this tx are already in memory
+        let p = match parent.upgrade() {
+            either::Either::Left(p) => p,
+            either::Either::Right(_) => return Ok(()),
+        };
+        let (id, done) = self.read_ahead.get();
+        let mut from = parent.index + 1;
+        if id == p.id {
+            from = from.max(done);
+        }
+        let branches = p.branch_elements()?;
+        let to = (parent.index + 1 + Self::READ_AHEAD).min(branches.len());
+        if from >= to {
+            return Ok(());
+        }
+        let ids: Vec<PageId> = branches[from..to].iter().map(|b| b.id).collect();
+        self.bucket().tx()?.db()?.prefetch(&ids);
+        self.read_ahead.set((p.id, to));
+        Ok(())
+    }
+
     // move to the next leaf element
     fn next_leaf(&self) -> Result<KVPair> {
         todo!()
     }
-    pub fn last(&self) -> Result<KVPair> {
-        todo!()
+    // move to the last key of the bucket
+    pub fn last(&mut self) -> Result<KVPair<'a>> {
+        self.stack.borrow_mut().clear();
+        self.leaf_moves.set(0);
+        self.prefix.replace(None);
+        let page_node = self.bucket().page_node(self.bucket().root_id())?;
+        let index = page_node.count().saturating_sub(1);
+        self.stack
+            .borrow_mut()
+            .push(ElementRef { page_node, index });
+        self.last_leaf()?;
+        if self.leaf_empty()? {
+            let pair = self.step_prev()?;
+            return Ok(self.settle(pair));
+        }
+        Ok(self.settle(self.kv_pair()?))
+    }
+    // like first_leaf, following the last child down
+    fn last_leaf(&self) -> Result<()> {
+        loop {
+            let page_id = {
+                let stack = self.stack.borrow();
+                let elem = stack.last().ok_or(RoltError::StackEmpty)?;
+                if elem.is_leaf() {
+                    break;
+                }
+                match elem.upgrade() {
+                    either::Either::Left(p) => p.branch_elements()?[elem.index].id,
+                    either::Either::Right(n) => n.inodes.borrow()[elem.index]
+                        .page_id()
+                        .ok_or(RoltError::from("does not have page id"))?,
+                }
+            };
+            let page_node = self.bucket().page_node(page_id)?;
+            let index = page_node.count().saturating_sub(1);
+            self.stack
+                .borrow_mut()
+                .push(ElementRef { index, page_node })
+        }
+        Ok(())
     }
 
+    // move to the next key, the pair is empty past the last one or once
+    // keys leave the prefix of seek_prefix
     pub fn next(&self) -> Result<KVPair<'a>> {
+        let pair = self.step_next()?;
+        Ok(self.settle(pair))
+    }
+
+    fn step_next(&self) -> Result<KVPair<'a>> {
         loop {
-            let mut stack = self.stack.borrow_mut();
-            let mut i = stack.len() as isize - 1;
-            while i >= 0 {
-                let e = &mut stack[i as usize];
-                if e.index + 1 < e.count() {
-                    e.index += 1;
-                    break;
+            {
+                let mut stack = self.stack.borrow_mut();
+                let mut i = stack.len() as isize - 1;
+                while i >= 0 {
+                    let e = &mut stack[i as usize];
+                    if e.index + 1 < e.count() {
+                        e.index += 1;
+                        break;
+                    }
+                    i -= 1;
                 }
-                i -= 1;
-            }
-            // reach root page
-            if i == -1 {
-                return Ok(KVPair::null());
+                // reach root page
+                if i == -1 {
+                    return Ok(KVPair::null());
+                }
+                // descend again from the element that moved
+                stack.truncate(i as usize + 1);
             }
             self.first_leaf()?;
+            self.read_ahead()?;
 
             if self
                 .stack
                 .borrow()
                 .last()
-                .ok_or(anyhow!("empty stack"))?
+                .ok_or(RoltError::from("empty stack"))?
                 .count()
                 != 0
             {
@@ -108,34 +255,153 @@ impl<'a> Cursor<'a> {
             }
         }
     }
-    pub fn prev(&self) -> Result<KVPair> {
-        todo!()
+    // move to the previous key, the pair is empty before the first one or
+    // once keys leave the prefix of seek_prefix
+    pub fn prev(&self) -> Result<KVPair<'a>> {
+        let pair = self.step_prev()?;
+        Ok(self.settle(pair))
     }
 
-    pub(crate) fn seek(&mut self, target: &[u8]) -> Result<KVPair<'a>> {
+    fn step_prev(&self) -> Result<KVPair<'a>> {
+        loop {
+            {
+                let mut stack = self.stack.borrow_mut();
+                let mut i = stack.len() as isize - 1;
+                while i >= 0 {
+                    let e = &mut stack[i as usize];
+                    if e.index > 0 {
+                        e.index -= 1;
+                        break;
+                    }
+                    i -= 1;
+                }
+                if i == -1 {
+                    return Ok(KVPair::null());
+                }
+                stack.truncate(i as usize + 1);
+            }
+            self.last_leaf()?;
+
+            if self
+                .stack
+                .borrow()
+                .last()
+                .ok_or(RoltError::from("empty stack"))?
+                .count()
+                != 0
+            {
+                return self.kv_pair();
+            }
+        }
+    }
+
+    // move to target, or to the next key after it when it is missing. same
+    // as seek_ge
+    pub fn seek(&mut self, target: &[u8]) -> Result<KVPair<'a>> {
+        self.seek_ge(target)
+    }
+
+    // move to the first key at or after target, the pair is empty when
+    // every key sorts before it
+    pub fn seek_ge(&mut self, target: &[u8]) -> Result<KVPair<'a>> {
         let mut pair = self.seek_to(target)?;
         let elem = self
             .stack
             .borrow()
             .last()
-            .ok_or(anyhow!(RoltError::StackEmpty))?
+            .ok_or(RoltError::StackEmpty)?
             .clone();
 
         // last element of a page, move to the next one
         if elem.index >= elem.count() {
             pair = self.next()?;
         }
-        Ok(pair)
+        Ok(self.settle(pair))
     }
 
-    // move cursor to a key
+    // move to the last key at or before target, the pair is empty when
+    // every key sorts after it
+    pub fn seek_le(&mut self, target: &[u8]) -> Result<KVPair<'a>> {
+        let pair = self.seek_ge(target)?;
+        match pair.key() {
+            Some(key) if key == target => Ok(pair),
+            Some(_) => self.prev(),
+            None => self.last(),
+        }
+    }
+
+    // move to the first key starting with prefix and keep next and prev
+    // within such keys, until the cursor is moved by first, last or a seek
+    pub fn seek_prefix(&mut self, prefix: &[u8]) -> Result<KVPair<'a>> {
+        let pair = self.seek(prefix)?;
+        self.prefix.replace(Some(prefix.to_vec()));
+        Ok(self.settle(pair))
+    }
+
+    // the entry under the cursor without moving it, empty before the first
+    // move and once a move ran off the end
+    pub fn current(&self) -> Result<KVPair<'a>> {
+        if self.done.get() {
+            return Ok(KVPair::null());
+        }
+        self.kv_pair()
+    }
+
+    // end the pair where keys leave the prefix bound and remember whether
+    // the cursor still points at an entry
+    fn settle(&self, pair: KVPair<'a>) -> KVPair<'a> {
+        let pair = match (&*self.prefix.borrow(), pair.key()) {
+            (Some(prefix), Some(key)) if !key.starts_with(prefix) => KVPair::null(),
+            _ => pair,
+        };
+        self.done.set(pair.key().is_none());
+        // a moved cursor starts over at the first value of its key
+        self.dups.replace(None);
+        pair
+    }
+
+    // the smallest value of the current key in a dup bucket, None when the
+    // cursor is not on a key with values
+    pub fn first_dup(&self) -> Result<Option<&'a [u8]>> {
+        let pair = self.current()?;
+        let set = match pair.key().filter(|_| pair.is_bucket()) {
+            Some(key) => self.bucket.dup_set(key),
+            None => None,
+        };
+        let mut c = match set {
+            Some(set) => Box::new(Cursor::new(set)),
+            None => return Ok(None),
+        };
+        let value = c.first()?.key();
+        self.dups.replace(Some(c));
+        Ok(value)
+    }
+
+    // the next value of the current key in a dup bucket, the cursor stays
+    // on the key. a cursor that just moved is on the first value
+    pub fn next_dup(&self) -> Result<Option<&'a [u8]>> {
+        if self.dups.borrow().is_none() && self.first_dup()?.is_none() {
+            return Ok(None);
+        }
+        match &*self.dups.borrow() {
+            Some(c) => Ok(c.next()?.key()),
+            None => Ok(None),
+        }
+    }
+
+    // move to the slot of target in the leaf the branches pick for it
+    // without stepping into the next leaf, where writes put the key. the
+    // pair is empty when target sorts after every key of that leaf
     pub(crate) fn seek_to(&mut self, target: &[u8]) -> Result<KVPair<'a>> {
+        debug_span!("seek", key_len = target.len());
         self.stack.borrow_mut().clear();
+        self.leaf_moves.set(0);
+        self.prefix.replace(None);
         let root_id = self.bucket().root_id();
         self.search(target, root_id)?;
         // if target is found
         let stack = self.stack.borrow();
-        let elem = stack.last().ok_or(anyhow!(RoltError::StackEmpty))?;
+        let elem = stack.last().ok_or(RoltError::StackEmpty)?;
         // target probably not found
         if elem.index >= elem.count() {
             Ok(KVPair::null())
@@ -168,7 +434,7 @@ impl<'a> Cursor<'a> {
     // find target key in a page
     fn search_page(&mut self, target: &[u8], p: &Page) -> Result<()> {
         let branches = p.branch_elements()?;
-        let (found, mut index) = match branches.binary_search_by(|b| b.key().cmp(target)) {
+        let (found, mut index) = match search_keys(branches, target, |b| b.key()) {
             Ok(mut v) => {
                 // find the highest index
                 let start = v;
@@ -194,7 +460,7 @@ impl<'a> Cursor<'a> {
         self.stack
             .borrow_mut()
             .last_mut()
-            .ok_or(anyhow!(RoltError::StackEmpty))?
+            .ok_or(RoltError::StackEmpty)?
             .index = index;
 
         // recursively search the next node
@@ -205,32 +471,31 @@ impl<'a> Cursor<'a> {
     // find target key in a node
     fn search_node(&mut self, target: &[u8], n: &Node) -> Result<()> {
         let inodes = n.inodes.borrow();
-        let (found, mut index) =
-            match inodes.binary_search_by(|inode| inode.key().as_slice().cmp(target)) {
-                Ok(mut v) => {
-                    // find the highest index
-                    let start = v;
-                    for i in start..(inodes.len() - 1) {
-                        match inodes[i].key().as_slice().cmp(target) {
-                            Ordering::Equal => v = i,
-                            _ => break,
-                        }
+        let (found, mut index) = match search_keys(&inodes, target, |inode| inode.key()) {
+            Ok(mut v) => {
+                // find the highest index
+                let start = v;
+                for i in start..(inodes.len() - 1) {
+                    match inodes[i].key().as_ref().cmp(target) {
+                        Ordering::Equal => v = i,
+                        _ => break,
                     }
-                    (true, v)
                 }
-                Err(i) => (false, i),
-            };
+                (true, v)
+            }
+            Err(i) => (false, i),
+        };
         if !found && index > 0 {
             index -= 1;
         }
         self.stack
             .borrow_mut()
             .last_mut()
-            .ok_or(anyhow!(RoltError::StackEmpty))?
+            .ok_or(RoltError::StackEmpty)?
             .index = index;
         let page_id = inodes[index]
             .page_id()
-            .ok_or(anyhow!("leaf inode does not have page id"))?;
+            .ok_or(RoltError::from("leaf inode does not have page id"))?;
         self.search(target, page_id)?;
         Ok(())
     }
@@ -238,11 +503,15 @@ impl<'a> Cursor<'a> {
     // search leaf node for the key
     fn search_leaf(&mut self, target: &[u8]) -> Result<()> {
         let mut stack = self.stack.borrow_mut();
-        let elem = stack.last_mut().ok_or(anyhow!(RoltError::StackEmpty))?;
+        let elem = stack.last_mut().ok_or(RoltError::StackEmpty)?;
         match elem.upgrade() {
             either::Either::Left(p) => {
-                let leaves = p.leaf_elements()?;
-                let index = match leaves.binary_search_by(|l| l.key().cmp(target)) {
+                let found = if p.page_type == Page::FIXED_LEAF_PAGE {
+                    search_keys(p.fixed_elements()?, target, |l| l.key())
+                } else {
+                    search_keys(p.leaf_elements()?, target, |l| l.key())
+                };
+                let index = match found {
                     Ok(i) => i,
                     Err(i) => i,
                 };
@@ -250,11 +519,7 @@ impl<'a> Cursor<'a> {
                 Ok(())
             }
             either::Either::Right(n) => {
-                let index = match n
-                    .inodes
-                    .borrow()
-                    .binary_search_by(|inode| inode.key().as_slice().cmp(target))
-                {
+                let index = match search_keys(&n.inodes.borrow(), target, |inode| inode.key()) {
                     Ok(i) => i,
                     Err(i) => i,
                 };
@@ -264,16 +529,44 @@ impl<'a> Cursor<'a> {
         }
     }
 
+    // index and element count of every level from the root to the leaf
+    pub(crate) fn path(&self) -> Vec<(usize, usize)> {
+        let stack = self.stack.borrow();
+        stack.iter().map(|e| (e.index, e.count())).collect()
+    }
+
+    // bytes of the keys and values in the leaf the cursor is on
+    pub(crate) fn leaf_bytes(&self) -> u64 {
+        let stack = self.stack.borrow();
+        let leaf = match stack.last() {
+            Some(leaf) => leaf,
+            None => return 0,
+        };
+        (0..leaf.count())
+            .map(|index| {
+                let pair = KVPair::from(&ElementRef {
+                    index,
+                    page_node: leaf.page_node.clone(),
+                });
+                let (key, value) = (
+                    pair.key().unwrap_or_default(),
+                    pair.value().unwrap_or_default(),
+                );
+                (key.len() + value.len()) as u64
+            })
+            .sum()
+    }
+
     fn kv_pair(&self) -> Result<KVPair<'a>> {
         let stack = self.stack.borrow();
-        let elem = stack.last().ok_or(anyhow!(RoltError::StackEmpty))?;
+        let elem = stack.last().ok_or(RoltError::StackEmpty)?;
         Ok(KVPair::from(elem))
     }
 
     pub(crate) fn node(&mut self) -> Result<Node> {
         {
             let stack = self.stack.borrow();
-            let elem = stack.last().ok_or(anyhow!(RoltError::StackEmpty))?;
+            let elem = stack.last().ok_or(RoltError::StackEmpty)?;
             // leaf node is on the top of stack
             if elem.is_leaf() & elem.is_right() {
                 return Ok(elem.as_ref().right().unwrap().clone());
@@ -295,7 +588,7 @@ impl<'a> Cursor<'a> {
     }
 }
 #[derive(Debug, Clone)]
-struct ElementRef {
+pub(crate) struct ElementRef {
     index: usize,
     page_node: PageNode,
 }
@@ -307,8 +600,9 @@ impl Deref for ElementRef {
     }
 }
 
+// a key/value pair under a cursor, both empty once the cursor ran off the end
 #[derive(Debug)]
-pub(crate) struct KVPair<'a> {
+pub struct KVPair<'a> {
     pub(crate) key: Option<&'a [u8]>,
     pub(crate) value: Option<&'a [u8]>,
     pub(crate) flags: u32,
@@ -323,14 +617,24 @@ impl<'a> KVPair<'a> {
             flags: 0,
         }
     }
-    pub(crate) fn key(&self) -> Option<&'a [u8]> {
+    pub fn key(&self) -> Option<&'a [u8]> {
         self.key
     }
-    pub(crate) fn value(&self) -> Option<&'a [u8]> {
+    pub fn value(&self) -> Option<&'a [u8]> {
         self.value
     }
-    pub(crate) fn is_bucket(&self) -> bool {
-        false
+    // the value is a nested bucket header rather than user data
+    pub fn is_bucket(&self) -> bool {
+        self.flags & Bucket::FLAG != 0
+    }
+    // the value is the descriptor of a blob, read it with
+    // Bucket::get_blob_reader
+    pub fn is_blob(&self) -> bool {
+        self.flags & Bucket::BLOB_FLAG != 0
+    }
+    // flags the application stored with Bucket::put_with_flags
+    pub fn user_flags(&self) -> u16 {
+        (self.flags >> Bucket::USER_FLAGS_SHIFT) as u16
     }
 }
 
@@ -342,20 +646,20 @@ impl<'a> From<&ElementRef> for KVPair<'a> {
         unsafe {
             match elem.upgrade() {
                 either::Either::Left(p) => {
-                    let leaf = &p.leaf_elements().unwrap()[elem.index];
+                    let (key, value, flags) = p.leaf_at(elem.index).unwrap();
                     Self {
-                        key: Some(&*(leaf.key() as *const [u8])),
-                        value: Some(&*(leaf.value() as *const [u8])),
-                        flags: 0,
+                        key: Some(&*(key as *const [u8])),
+                        value: Some(&*(value as *const [u8])),
+                        flags,
                     }
                 }
                 either::Either::Right(n) => {
                     let inode = &n.inodes.borrow()[elem.index];
                     let value = inode.value().ok_or("does not have value").unwrap();
                     Self {
-                        key: Some(&*(inode.key().as_slice() as *const [u8])),
-                        value: Some(&*(value.as_slice() as *const [u8])),
-                        flags: 0,
+                        key: Some(&*(inode.key().as_ref() as *const [u8])),
+                        value: Some(&*(value.as_ref() as *const [u8])),
+                        flags: inode.flags(),
                     }
                 }
             }
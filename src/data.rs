@@ -1,9 +1,12 @@
 use std::{
     fmt::Debug,
     ops::{Deref, DerefMut},
+    sync::Arc,
 };
 
-pub type Entry = Vec<u8>;
+// keys and values held by nodes. cloning shares the bytes, so moving keys
+// between nodes and parents while splitting and spilling doesn't copy them
+pub type Entry = Arc<[u8]>;
 
 // a wrapper of raw pointer
 #[derive(Clone, Copy, Debug)]
@@ -1,38 +1,93 @@
-use anyhow::anyhow;
 use fs2::FileExt;
 use memmap::Mmap;
 use parking_lot::{Mutex, RwLock};
 
 use crate::{
+    double_write::{buffer_path, DoubleWrite},
     error::{Result, RoltError},
     free_list::FreeList,
+    growth::GrowthPolicy,
+    maintenance::{Maintenance, MaintenanceOptions},
     meta::Meta,
-    page::{Page, PageId},
-    transaction::Transaction,
+    metrics::{Metrics, MetricsHook},
+    optimistic::CommitHistory,
+    page::{page_offset, Page, PageId, PagePool},
+    replication::ReplicationLog,
+    slow_op::{SlowOp, SlowOpHook, SlowOpThresholds},
+    snapshot::SnapshotHandle,
+    stats::{Counters, Stats},
+    sync_policy::{SyncPolicy, SyncState},
+    transaction::{ReadTransaction, Transaction, Txid, WriteTransaction},
+    utils::debug_span,
+    watch::{ChangeEvent, Watcher},
     Err,
 };
 use std::{
     cmp::Ordering as CmpOrdering,
+    collections::BTreeMap,
     fmt::Debug,
-    fs::{File, OpenOptions},
-    io::{Read, Seek, SeekFrom, Write},
+    fs::{self, File, OpenOptions},
+    io::{IoSlice, Read, Seek, SeekFrom, Write},
     ops::Deref,
-    path::Path,
+    path::{Path, PathBuf},
     rc::{Rc, Weak},
+    sync::mpsc::{channel, Receiver},
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
         Arc,
     },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-#[derive(Debug)]
+// how often a lock held by another process is retried
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+// free page ids loaded between open progress reports
+const FREE_LIST_CHUNK: usize = 64 * 1024;
+
+// steps of opening a db, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenPhase {
+    // waiting for the file locks
+    Lock,
+    // mapping the file and reading the meta pages
+    Map,
+    // loading the free page ids, done and total count ids
+    FreeList,
+    // opening the replication log
+    Replication,
+}
+
+// called with the phase, work done and total work of that phase
+pub type OpenProgress = Arc<dyn Fn(OpenPhase, u64, u64) + Send + Sync>;
+
+// handle to an open db. clones are cheap and share the mapped file, its
+// locks and the one writable tx, so parts of an application can each hold
+// a handle without opening the file again. the file is closed once the
+// last handle is dropped
+#[derive(Clone)]
 pub struct DB(pub Rc<Idb>);
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WeakDB(pub Weak<Idb>);
 
 pub struct DBBuilder {
     page_size: u64,
     num_pages: u64,
+    metrics: MetricsHook,
+    slow_ops: Option<SlowOpHook>,
+    replication_log: Option<PathBuf>,
+    read_only: bool,
+    lock_timeout: Option<Duration>,
+    no_lock: bool,
+    maintenance: Option<MaintenanceOptions>,
+    max_bucket_depth: Option<usize>,
+    growth: GrowthPolicy,
+    sync_policy: SyncPolicy,
+    double_write: bool,
+    node_memory_limit: Option<u64>,
+    deterministic: bool,
+    open_progress: Option<OpenProgress>,
+    application_id: Option<u32>,
 }
 
 #[allow(dead_code)]
@@ -41,21 +96,263 @@ impl DBBuilder {
         self.page_size = size;
         self
     }
+    // pages of a new file, at least 4
     pub fn num_pages(mut self, num: u64) -> Self {
-        if num < 4 {
-            panic!("Must have 4 pages or mode");
-        }
         self.num_pages = num;
         self
     }
+    // install a sink for commit, fsync, page write and allocation metrics
+    pub fn metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = MetricsHook::new(metrics);
+        self
+    }
+    // call back when a commit, fsync or page write takes longer than its threshold
+    pub fn slow_ops<F>(mut self, thresholds: SlowOpThresholds, callback: F) -> Self
+    where
+        F: Fn(&SlowOp) + Send + Sync + 'static,
+    {
+        self.slow_ops = Some(SlowOpHook::new(thresholds, Arc::new(callback)));
+        self
+    }
+    // append every committed write tx to a log followers can tail
+    pub fn replication_log<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.replication_log = Some(path.as_ref().to_path_buf());
+        self
+    }
+    // open an existing file next to a live writer, write txs are refused and
+    // every read tx starts from the latest root the writer committed
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+    // how long open waits for a lock held by another process before failing
+    // with RoltError::Locked. None waits forever, zero fails right away
+    pub fn lock_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+    // skip file locking, for filesystems where flock fails or lies such as
+    // some nfs mounts. DANGER: nothing stops a second process from opening
+    // the db for writing too, and two writers corrupt the file. only use it
+    // when something else guarantees a single writer. no lock file is made
+    pub fn no_lock(mut self, enabled: bool) -> Self {
+        self.no_lock = enabled;
+        self
+    }
+    // compact or truncate the file when write txs open, as opts describe
+    pub fn maintenance(mut self, opts: MaintenanceOptions) -> Self {
+        self.maintenance = Some(opts);
+        self
+    }
+    // how deep buckets may nest, top-level buckets are at depth 1. creating
+    // a deeper one fails with RoltError::BucketTooDeep. None is unlimited
+    pub fn max_bucket_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_bucket_depth = depth;
+        self
+    }
+    // how far the file is extended when a commit outgrows it, Exact by default
+    pub fn growth(mut self, policy: GrowthPolicy) -> Self {
+        self.growth = policy;
+        self
+    }
+    // when commits fsync the file, Always by default. see SyncPolicy for
+    // what a crash may lose under the others
+    pub fn sync_policy(mut self, policy: SyncPolicy) -> Self {
+        self.sync_policy = policy;
+        self
+    }
+    // copy the pages of each commit to a scratch file next to the db and
+    // sync it before writing them in place, so pages torn by a crash are
+    // restored on the next open. costs an extra write and fsync per commit
+    pub fn double_write(mut self, enabled: bool) -> Self {
+        self.double_write = enabled;
+        self
+    }
+    // most bytes of nodes a write tx may hold, see TxMemory. a put that
+    // would go past it fails with RoltError::NodeMemoryLimit and leaves the
+    // tx as it was, so it can still be committed. None is unlimited
+    pub fn node_memory_limit(mut self, limit: Option<u64>) -> Self {
+        self.node_memory_limit = limit;
+        self
+    }
+    // spill buckets in name order instead of hash order, so the same
+    // operations always put the same pages in the same places and two runs
+    // leave byte-identical files. free runs are already taken lowest id
+    // first. costs a sort of the dirty buckets per commit
+    pub fn deterministic(mut self, enabled: bool) -> Self {
+        self.deterministic = enabled;
+        self
+    }
+    // report progress while open works through its phases, each phase is
+    // reported at least once with done == total when it finishes
+    pub fn open_progress<F>(mut self, f: F) -> Self
+    where
+        F: Fn(OpenPhase, u64, u64) + Send + Sync + 'static,
+    {
+        self.open_progress = Some(Arc::new(f));
+        self
+    }
+    // stamp new files with id and refuse to open a file carrying any other
+    // id with RoltError::ApplicationId, see DB::application_id
+    pub fn application_id(mut self, id: u32) -> Self {
+        self.application_id = Some(id);
+        self
+    }
+    fn lock(&self, file: &File, exclusive: bool) -> Result<()> {
+        match self.no_lock {
+            true => Ok(()),
+            false => lock_file(file, exclusive, self.lock_timeout),
+        }
+    }
+    fn report(&self, phase: OpenPhase, done: u64, total: u64) {
+        if let Some(f) = &self.open_progress {
+            f(phase, done, total);
+        }
+    }
+    // check the settings don't contradict each other or the platform, open
+    // does this first and fails with RoltError::InvalidConfig
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |msg: String| Err!(RoltError::InvalidConfig(msg));
+        let os_page_size = page_size::get() as u64;
+        if !self.page_size.is_power_of_two() {
+            return invalid(format!(
+                "page size {} is not a power of two",
+                self.page_size
+            ));
+        }
+        // files are always read with the os page size
+        if self.page_size != os_page_size {
+            return invalid(format!(
+                "page size {} differs from the os page size {}",
+                self.page_size, os_page_size
+            ));
+        }
+        if self.num_pages < 4 {
+            return invalid(format!(
+                "a new file needs at least 4 pages, got {}",
+                self.num_pages
+            ));
+        }
+        if self.max_bucket_depth == Some(0) {
+            return invalid("max bucket depth of 0 allows no buckets".to_string());
+        }
+        if self.node_memory_limit == Some(0) {
+            return invalid("node memory limit of 0 allows no writes".to_string());
+        }
+        match self.growth {
+            GrowthPolicy::Step(0) | GrowthPolicy::Double { max_step: 0 } => {
+                return invalid("growth step must be positive".to_string())
+            }
+            _ => {}
+        }
+        if let Some(opts) = &self.maintenance {
+            let ratio = opts.compact_free_ratio;
+            if !(0.0..=1.0).contains(&ratio) {
+                return invalid(format!("compact free ratio {} is not in [0, 1]", ratio));
+            }
+        }
+        if self.read_only && self.replication_log.is_some() {
+            return invalid("a read-only db has no commits to log".to_string());
+        }
+        Ok(())
+    }
     pub fn open<P: AsRef<Path>>(&self, p: P) -> Result<DB> {
-        let p = p.as_ref();
-        let f = if !p.exists() {
-            Idb::init_file(p, self.page_size, self.num_pages)?
+        Ok(DB(Rc::new(self.open_idb(p.as_ref())?)))
+    }
+    fn open_idb(&self, p: &Path) -> Result<Idb> {
+        self.validate()?;
+        self.report(OpenPhase::Lock, 0, 1);
+        let mut db = if self.read_only {
+            let f = loop {
+                let f = OpenOptions::new().read(true).open(p)?;
+                match self.lock(&f, false) {
+                    // a reader only keeps compaction from swapping the file
+                    // under it, go on unlocked where the fs has no locks
+                    Err(RoltError::Io(e)) if locks_unsupported(&e) => {}
+                    res => res?,
+                }
+                // a compaction may have swapped the file while we waited
+                if still_linked(&f, p) {
+                    break f;
+                }
+            };
+            self.report(OpenPhase::Lock, 1, 1);
+            let mut db = Idb::open_with(f, self.open_progress.as_ref())?;
+            db.read_only = true;
+            db
         } else {
-            OpenOptions::new().read(true).write(true).open(p)?
+            // writers exclude each other through a lock file, the db file itself
+            // only carries shared locks so read-only opens can join a live writer
+            let lock = match self.no_lock {
+                true => None,
+                false => Some(lock_writer(p, self.lock_timeout)?),
+            };
+            let f = if !p.exists() {
+                let id = self.application_id.unwrap_or(0);
+                Idb::init_file(p, self.page_size, self.num_pages, id)?
+            } else {
+                OpenOptions::new().read(true).write(true).open(p)?
+            };
+            self.lock(&f, false)?;
+            self.report(OpenPhase::Lock, 1, 1);
+            // put back pages a crash tore before anything reads them
+            let double_write = match self.double_write {
+                true => {
+                    let buffer = DoubleWrite::open(&buffer_path(p))?;
+                    buffer.recover(&f)?;
+                    Some(buffer)
+                }
+                false => None,
+            };
+            let mut db = Idb::open_with(f, self.open_progress.as_ref())?;
+            db.writer_lock = lock;
+            db.double_write = double_write;
+            db
         };
-        let db = Idb::open(f)?;
+        if let Some(expected) = self.application_id {
+            let found = db.meta()?.application_id;
+            if found != expected {
+                return Err!(RoltError::ApplicationId { expected, found });
+            }
+        }
+        db.path = p.to_path_buf();
+        db.metrics = self.metrics.clone();
+        db.slow_ops = self.slow_ops.clone();
+        db.maintenance = self.maintenance.clone().map(Maintenance::new);
+        db.max_bucket_depth = self.max_bucket_depth;
+        db.growth = self.growth.clone();
+        db.sync_policy = self.sync_policy;
+        db.node_memory_limit = self.node_memory_limit;
+        db.deterministic = self.deterministic;
+        db.no_lock = self.no_lock;
+        if let Some(path) = &self.replication_log {
+            self.report(OpenPhase::Replication, 0, 1);
+            db.replication = Some(ReplicationLog::open(path)?);
+            self.report(OpenPhase::Replication, 1, 1);
+        }
+        Ok(db)
+    }
+    // open a fresh db in the temp dir whose file is unlinked right away, so
+    // it vanishes once the db is closed or the process dies
+    pub fn open_temp(&self) -> Result<DB> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let name = format!(
+            "roltdb-{}-{}-{}.db",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed),
+            nanos
+        );
+        let path = std::env::temp_dir().join(name);
+        let db = self.open_idb(&path);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(lock_path(&path));
+        let _ = fs::remove_file(buffer_path(&path));
+        let mut db = db?;
+        db.temp = true;
         Ok(DB(Rc::new(db)))
     }
 }
@@ -64,25 +361,136 @@ impl DB {
     pub fn open<P: AsRef<Path>>(p: P) -> Result<DB> {
         DBBuilder::default().open(p)
     }
+    // a scratch db deleted on close, see DBBuilder::open_temp
+    pub fn open_temp() -> Result<DB> {
+        DBBuilder::default().open_temp()
+    }
     pub fn tx(&self, writable: bool) -> Result<Transaction> {
+        if self.read_only {
+            if writable {
+                return Err!(RoltError::ReadOnly);
+            }
+            self.refresh()?;
+        }
+        if writable && self.poisoned() {
+            return Err!(RoltError::Poisoned);
+        }
         if self.has_write.load(Ordering::Relaxed) {
             return Err!(RoltError::WritableTxNotAllowed);
         }
         if writable {
+            if let Err(e) = self.maintain_if_due() {
+                self.metrics.maintenance_failed(&e);
+            }
+            // a maintenance commit that failed poisoned the db
+            if self.poisoned() {
+                return Err!(RoltError::Poisoned);
+            }
             self.has_write.store(true, Ordering::Relaxed);
         }
         Ok(Transaction::new(WeakDB::from(self), writable))
     }
+    // begin a tx that can only read, writes don't compile against it
+    pub fn read_tx(&self) -> Result<ReadTransaction> {
+        self.tx(false).map(ReadTransaction)
+    }
+    // begin the writable tx, committed on drop unless already finished
+    pub fn write_tx(&self) -> Result<WriteTransaction> {
+        self.tx(true).map(WriteTransaction)
+    }
+    // subscribe to keys under prefix in bucket, every committed write tx
+    // sends one event per key it inserted, updated or deleted
+    pub fn watch_prefix<B: AsRef<[u8]>, P: AsRef<[u8]>>(
+        &self,
+        bucket: B,
+        prefix: P,
+    ) -> Receiver<ChangeEvent> {
+        let (sender, receiver) = channel();
+        self.watchers
+            .lock()
+            .push(Watcher::new(bucket.as_ref(), prefix.as_ref(), sender));
+        receiver
+    }
+    // re-read the meta pages and map the whole file again if the writer grew
+    // it past the current mapping, returns the latest committed txid
+    pub fn refresh(&self) -> Result<Txid> {
+        // a compaction that ran without our lock renamed a new file over
+        // the path, this one won't see another commit
+        if self.read_only && !still_linked(&self.file.lock(), &self.path) {
+            return Err!(RoltError::FileReplaced);
+        }
+        let meta = self.meta()?;
+        self.map_through(meta.num_pages * self.page_size)?;
+        Ok(meta.tx_id)
+    }
+    // map the file at least up to needed bytes, growing it first unless
+    // read-only
+    pub(crate) fn map_through(&self, needed: u64) -> Result<()> {
+        if needed > self.mmap().len() as u64 {
+            if !self.read_only {
+                self.grow(needed)?;
+            }
+            self.remap()?;
+        }
+        Ok(())
+    }
+    // pin the latest committed version so read txs can be opened against it later
+    pub fn snapshot_handle(&self) -> Result<SnapshotHandle> {
+        let meta = self.meta()?;
+        *self.snapshots.lock().entry(meta.tx_id).or_insert(0) += 1;
+        Ok(SnapshotHandle::new(WeakDB::from(self), meta))
+    }
     pub(crate) fn release_write_tx(&mut self) {
         self.has_write.store(false, Ordering::Relaxed);
+        self.unmap_superseded();
     }
     pub(crate) fn write_at<T: Read>(&mut self, addr: u64, mut buf: T) -> Result<()> {
         let mut file = self.file.lock(); // unlock automatically
         file.seek(SeekFrom::Start(addr))
-            .map_err(|_| anyhow!("can't write db file at give position"))?;
-        std::io::copy(&mut buf, &mut *file)?;
+            .map_err(|_| RoltError::from("can't write db file at give position"))?;
+        let n = std::io::copy(&mut buf, &mut *file)?;
+        Counters::add(&self.counters.write_calls, 1);
+        Counters::add(&self.counters.write_bytes, n);
+        Ok(())
+    }
+    // write bufs back to back starting at addr
+    pub(crate) fn write_vectored_at(&self, addr: u64, bufs: &[&[u8]]) -> Result<()> {
+        let mut file = self.file.lock();
+        file.seek(SeekFrom::Start(addr))
+            .map_err(|_| RoltError::from("can't write db file at give position"))?;
+        let mut slices: Vec<IoSlice> = bufs.iter().map(|b| IoSlice::new(b)).collect();
+        let mut slices = &mut slices[..];
+        let mut written = 0;
+        while !slices.is_empty() {
+            let n = file.write_vectored(slices)?;
+            if n == 0 {
+                return Err!("failed to write pages to db file");
+            }
+            written += n as u64;
+            IoSlice::advance_slices(&mut slices, n);
+        }
+        Counters::add(&self.counters.write_calls, 1);
+        Counters::add(&self.counters.write_bytes, written);
         Ok(())
     }
+    // id the application that created the file stamped it with, 0 if none.
+    // set it with Transaction::set_application_id
+    pub fn application_id(&self) -> Result<u32> {
+        Ok(self.meta()?.application_id)
+    }
+    // snapshot of io and allocation counters
+    pub fn stats(&self) -> Stats {
+        self.counters.snapshot()
+    }
+    // counts since the snapshot prev, e.g. for per-interval rates
+    pub fn stats_since(&self, prev: &Stats) -> Stats {
+        self.stats().since(prev)
+    }
+    // zero the io and allocation counters, counting the reset in
+    // Stats::resets
+    pub fn reset_stats(&self) {
+        self.counters.reset()
+    }
 }
 
 impl Default for DBBuilder {
@@ -90,18 +498,155 @@ impl Default for DBBuilder {
         Self {
             page_size: page_size::get() as u64,
             num_pages: 32,
+            metrics: MetricsHook::default(),
+            slow_ops: None,
+            replication_log: None,
+            read_only: false,
+            lock_timeout: None,
+            no_lock: false,
+            maintenance: None,
+            max_bucket_depth: None,
+            growth: GrowthPolicy::default(),
+            sync_policy: SyncPolicy::default(),
+            double_write: false,
+            node_memory_limit: None,
+            deterministic: false,
+            open_progress: None,
+            application_id: None,
         }
     }
 }
 
-#[derive(Debug)]
+// map the whole file, refusing files larger than the address space since
+// the mapping length would otherwise be truncated on 32-bit targets. the file
+// is mapped in one piece, so on those targets a db is limited to what fits
+// in the address space
+pub(crate) fn map_file(f: &File) -> Result<Mmap> {
+    let len = f.metadata()?.len();
+    if usize::try_from(len).is_err() {
+        return Err!(RoltError::FileTooLarge(len));
+    }
+    Ok(unsafe { Mmap::map(f)? })
+}
+
+// take a shared or exclusive flock, polling until timeout when one is set
+fn lock_file(file: &File, exclusive: bool, timeout: Option<Duration>) -> Result<()> {
+    let timeout = match timeout {
+        None if exclusive => return Ok(file.lock_exclusive()?),
+        None => return Ok(FileExt::lock_shared(file)?),
+        Some(t) => t,
+    };
+    let start = Instant::now();
+    loop {
+        let res = if exclusive {
+            file.try_lock_exclusive()
+        } else {
+            FileExt::try_lock_shared(file)
+        };
+        match res {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == fs2::lock_contended_error().kind() => {
+                let waited = start.elapsed();
+                if waited >= timeout {
+                    return Err!(RoltError::Locked);
+                }
+                thread::sleep((timeout - waited).min(LOCK_RETRY_INTERVAL));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+// take the lock file of p. a closing writer removes the file, so a lock won
+// on one that is no longer at the path is retried on a fresh file
+fn lock_writer(p: &Path, timeout: Option<Duration>) -> Result<File> {
+    let path = lock_path(p);
+    loop {
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+        lock_file(&lock, true, timeout)?;
+        if still_linked(&lock, &path) {
+            return Ok(lock);
+        }
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn still_linked(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+// open files can't be removed, so the file at the path is the one locked
+#[cfg(not(unix))]
+pub(crate) fn still_linked(_file: &File, _path: &Path) -> bool {
+    true
+}
+
+// whether a lock failed because the filesystem doesn't do locks at all
+fn locks_unsupported(e: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    if matches!(e.raw_os_error(), Some(libc::ENOLCK | libc::EOPNOTSUPP)) {
+        return true;
+    }
+    e.kind() == std::io::ErrorKind::Unsupported
+}
+
+// lock file held by the process that opened the db for writing
+fn lock_path(p: &Path) -> PathBuf {
+    let mut path = p.as_os_str().to_owned();
+    path.push(".lock");
+    PathBuf::from(path)
+}
+
 pub struct Idb {
-    // pub(crate) mmap: RwLock<Mmap>,
-    pub(crate) mmap: Arc<Mmap>,
-    file: Mutex<File>,
+    // the current mapping last, before it the superseded ones some read tx
+    // still holds. the write tx reads through the current one, so none is
+    // dropped while it's open
+    mmaps: Mutex<Vec<Arc<Mmap>>>,
+    // the latest mapping, read on every page access without the lock
+    current: AtomicPtr<Mmap>,
+    pub(crate) file: Mutex<File>,
+    pub(crate) path: PathBuf,
+    // lock file of a writable db
+    writer_lock: Option<File>,
+    pub(crate) read_only: bool,
     page_size: u64,
     pub(crate) free_list: RwLock<FreeList>,
     has_write: AtomicBool,
+    // set when a commit failed halfway through writing, the in-memory state
+    // may no longer match the file so only reads are allowed until reopen
+    poisoned: AtomicBool,
+    pub(crate) metrics: MetricsHook,
+    pub(crate) slow_ops: Option<SlowOpHook>,
+    pub(crate) counters: Counters,
+    pub(crate) page_pool: PagePool,
+    // pinned snapshot txids and how many handles hold each
+    pub(crate) snapshots: Mutex<BTreeMap<Txid, usize>>,
+    pub(crate) watchers: Mutex<Vec<Watcher>>,
+    // keys of commits made while snapshots are pinned, see CommitHistory
+    pub(crate) commits: Mutex<CommitHistory>,
+    pub(crate) replication: Option<ReplicationLog>,
+    pub(crate) maintenance: Option<Maintenance>,
+    pub(crate) max_bucket_depth: Option<usize>,
+    growth: GrowthPolicy,
+    sync_policy: SyncPolicy,
+    sync_state: Mutex<SyncState>,
+    pub(crate) double_write: Option<DoubleWrite>,
+    pub(crate) node_memory_limit: Option<u64>,
+    pub(crate) deterministic: bool,
+    // opened without file locks, see DBBuilder::no_lock
+    pub(crate) no_lock: bool,
+    // the file was unlinked after open and must not reappear on disk
+    pub(crate) temp: bool,
+    // read txs not dropped yet
+    pub(crate) open_reads: AtomicUsize,
 }
 
 #[allow(dead_code)]
@@ -109,33 +654,72 @@ impl Idb {
     pub(crate) fn page_size(&self) -> u64 {
         self.page_size
     }
+    // the caller is expected to hold a lock on file
     pub fn open(file: File) -> Result<Self> {
-        file.lock_exclusive()?;
+        Self::open_with(file, None)
+    }
+    fn open_with(file: File, progress: Option<&OpenProgress>) -> Result<Self> {
+        let report = |phase, done, total| {
+            if let Some(f) = progress {
+                f(phase, done, total);
+            }
+        };
+        report(OpenPhase::Map, 0, 1);
         let page_size = page_size::get() as u64;
 
-        let mmap = unsafe { Mmap::map(&file)? };
+        let mmap = Arc::new(map_file(&file)?);
 
         let db = Idb {
-            mmap: Arc::new(mmap),
+            current: AtomicPtr::new(Arc::as_ptr(&mmap) as *mut Mmap),
+            mmaps: Mutex::new(vec![mmap]),
             page_size,
             file: Mutex::new(file),
+            path: PathBuf::new(),
+            writer_lock: None,
+            read_only: false,
             free_list: RwLock::new(FreeList::new()),
             has_write: AtomicBool::new(false),
+            poisoned: AtomicBool::new(false),
+            metrics: MetricsHook::default(),
+            slow_ops: None,
+            counters: Counters::default(),
+            page_pool: PagePool::new(page_size as usize),
+            snapshots: Mutex::new(BTreeMap::new()),
+            watchers: Mutex::new(Vec::new()),
+            commits: Mutex::new(CommitHistory::default()),
+            replication: None,
+            maintenance: None,
+            max_bucket_depth: None,
+            growth: GrowthPolicy::default(),
+            sync_policy: SyncPolicy::default(),
+            sync_state: Mutex::new(SyncState::default()),
+            double_write: None,
+            node_memory_limit: None,
+            deterministic: false,
+            no_lock: false,
+            temp: false,
+            open_reads: AtomicUsize::new(0),
         };
         {
             let meta = db.meta()?;
-            let free_page = Page::from_buf(&db.mmap, meta.free_list, page_size);
+            let free_page = Page::from_buf(db.mmap(), meta.free_list, page_size)?;
             let free_list = free_page.free_list()?;
-            if !free_list.is_empty() {
-                db.free_list.write().init(free_list);
+            report(OpenPhase::Map, 1, 1);
+            let total = free_list.len() as u64;
+            report(OpenPhase::FreeList, 0, total);
+            let mut done = 0;
+            for chunk in free_list.chunks(FREE_LIST_CHUNK) {
+                db.free_list.write().init(chunk);
+                done += chunk.len() as u64;
+                report(OpenPhase::FreeList, done, total);
             }
         }
         Ok(db)
     }
     pub(crate) fn meta(&self) -> Result<Meta> {
-        let buf = self.mmap.as_ref();
-        let meta0 = Page::from_buf(buf, 0, self.page_size).meta()?;
-        let meta1 = Page::from_buf(buf, 1, self.page_size).meta()?;
+        let buf = self.mmap();
+        let meta0 = Page::from_buf(buf, 0, self.page_size)?.meta()?;
+        let meta1 = Page::from_buf(buf, 1, self.page_size)?.meta()?;
         let meta = match (meta0.validate(), meta1.validate()) {
             (true, true) => {
                 if meta0.tx_id >= meta1.tx_id {
@@ -146,12 +730,18 @@ impl Idb {
             }
             (true, false) => meta0,
             (false, true) => meta1,
-            (false, false) => panic!("both meta not valid"),
+            (false, false) => {
+                return Err!(RoltError::Corruption(
+                    "both meta pages are invalid".to_string()
+                ))
+            }
         };
+        // pages of another format version can't be read
+        meta.check_version()?;
         Ok(meta.clone())
     }
     // init an empty file
-    fn init_file(p: &Path, page_size: u64, page_num: u64) -> Result<File> {
+    fn init_file(p: &Path, page_size: u64, page_num: u64, application_id: u32) -> Result<File> {
         let mut file = OpenOptions::new()
             .create(true)
             .read(true)
@@ -172,6 +762,7 @@ impl Idb {
                     // must before init
                     m.free_list = 2;
                     m.num_pages = 4;
+                    m.application_id = application_id;
                     m.init(i);
                 }
                 CmpOrdering::Equal => {
@@ -193,22 +784,164 @@ impl Idb {
         Ok(file)
     }
 
+    // the current mapping of the file
+    pub(crate) fn mmap(&self) -> &[u8] {
+        let current = self.current.load(Ordering::Acquire);
+        // the current mapping is only dropped once superseded
+        unsafe { (*current).as_ref() }
+    }
+    // hold the current mapping, a read tx keeps it alive across remaps
+    pub(crate) fn pin_mmap(&self) -> Arc<Mmap> {
+        self.mmaps.lock().last().expect("db has no mapping").clone()
+    }
+    // make mmap the current mapping
+    pub(crate) fn push_mmap(&self, mmap: Mmap) {
+        let mmap = Arc::new(mmap);
+        let mut mmaps = self.mmaps.lock();
+        self.current
+            .store(Arc::as_ptr(&mmap) as *mut Mmap, Ordering::Release);
+        mmaps.push(mmap);
+        drop(mmaps);
+        self.unmap_superseded();
+    }
+    // unmap superseded mappings no read tx holds anymore. the write tx
+    // may still point into any of them until it ends
+    pub(crate) fn unmap_superseded(&self) {
+        if self.has_write.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut mmaps = self.mmaps.lock();
+        let last = mmaps.len() - 1;
+        let mut i = 0;
+        mmaps.retain(|m| {
+            i += 1;
+            i - 1 == last || Arc::strong_count(m) > 1
+        });
+    }
+
     // get a page from mmap
-    pub(crate) fn page(&self, id: PageId) -> &Page {
-        let p = Page::from_buf(self.mmap.as_ref(), id, self.page_size);
-        p
+    pub(crate) fn page(&self, id: PageId) -> Result<&Page> {
+        Page::from_buf(self.mmap(), id, self.page_size)
     }
 
-    pub(crate) fn resize_mmap(&mut self, size: u64) -> Result<()> {
+    // tell the kernel these pages will be read soon, so a cold scan doesn't
+    // stall on a fault per leaf
+    pub(crate) fn prefetch(&self, ids: &[PageId]) {
+        for &id in ids {
+            self.advise(id, 1);
+        }
+    }
+
+    // prefetch count pages starting at id, returns how many were in the file
+    pub(crate) fn advise(&self, id: PageId, count: u64) -> u64 {
+        let mmap = self.mmap();
+        let os_page = page_size::get();
+        let start = match page_offset(id, self.page_size) {
+            Some(start) => start,
+            None => return 0,
+        };
+        let end = start
+            .saturating_add((count * self.page_size) as usize)
+            .min(mmap.len());
+        if start >= end {
+            return 0;
+        }
+        // madvise wants an address aligned to the os page
+        advise_willneed(&mmap[start - start % os_page..end]);
+        let pages = ((end - start) as u64).div_ceil(self.page_size);
+        Counters::add(&self.counters.readahead_pages, pages);
+        pages
+    }
+
+    // extend the file to at least needed bytes as the growth policy says
+    fn grow(&self, needed: u64) -> Result<()> {
+        let current = self.mmap().len() as u64;
+        let size = self.growth.next_size(current, needed);
+        let size = size.div_ceil(self.page_size) * self.page_size;
+        let f = self.file.lock();
+        if f.metadata()?.len() < size {
+            f.allocate(size)?;
+        }
+        Ok(())
+    }
+    // map the file again to see data past the end of the current mapping
+    pub(crate) fn remap(&self) -> Result<()> {
         let f = self.file.lock();
-        f.allocate(size)?;
-        let new_mmap = unsafe { Mmap::map(&f).unwrap() };
-        self.mmap = Arc::new(new_mmap);
+        let mmap = map_file(&f)?;
+        self.push_mmap(mmap);
         Ok(())
     }
+
+    pub(crate) fn resize_mmap(&self, size: u64) -> Result<()> {
+        self.file.lock().allocate(size)?;
+        self.remap()
+    }
+    // unpin a snapshot txid once its last handle is gone
+    pub(crate) fn release_snapshot(&self, tx_id: Txid) {
+        let mut snapshots = self.snapshots.lock();
+        if let Some(count) = snapshots.get_mut(&tx_id) {
+            *count -= 1;
+            if *count == 0 {
+                snapshots.remove(&tx_id);
+            }
+        }
+    }
+    // whether a failed commit left the db read-only
+    pub fn poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+    pub(crate) fn poison(&self) {
+        self.poisoned.store(true, Ordering::Relaxed);
+    }
+    // whether the commit being written should fsync, see SyncPolicy
+    pub(crate) fn sync_due(&self) -> bool {
+        self.sync_state.lock().due(self.sync_policy)
+    }
     pub(crate) fn sync(&self) -> Result<()> {
-        let mut f = self.file.lock();
-        f.flush().map_err(|_| anyhow!("cannot sync data to file"))
+        debug_span!("fsync");
+        let start = Instant::now();
+        let f = self.file.lock();
+        f.sync_data()
+            .map_err(|_| RoltError::from("cannot sync data to file"))?;
+        let elapsed = start.elapsed();
+        Counters::add(&self.counters.syncs, 1);
+        self.metrics.fsync(elapsed);
+        if let Some(hook) = &self.slow_ops {
+            hook.fsync(elapsed);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Idb {
+    fn drop(&mut self) {
+        // commits the sync policy left unsynced
+        if self.sync_state.lock().pending() {
+            let _ = self.sync();
+        }
+        // removed while still held, see lock_writer
+        if self.writer_lock.is_some() {
+            let _ = fs::remove_file(lock_path(&self.path));
+        }
+    }
+}
+
+impl std::fmt::Debug for DB {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// settings and the committed txid only, deriving would print the mappings
+impl std::fmt::Debug for Idb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DB")
+            .field("path", &self.path)
+            .field("page_size", &self.page_size)
+            .field("read_only", &self.read_only)
+            .field("tx_id", &self.meta().ok().map(|m| m.tx_id))
+            .field("poisoned", &self.poisoned())
+            .finish()
     }
 }
 
@@ -231,6 +964,21 @@ impl From<&DB> for WeakDB {
     }
 }
 
+#[cfg(unix)]
+fn advise_willneed(buf: &[u8]) {
+    // only a hint, failures are ignored
+    unsafe {
+        libc::madvise(
+            buf.as_ptr() as *mut libc::c_void,
+            buf.len(),
+            libc::MADV_WILLNEED,
+        );
+    }
+}
+
+#[cfg(not(unix))]
+fn advise_willneed(_buf: &[u8]) {}
+
 #[cfg(test)]
 mod tests {
     use crate::data::RawPtr;
@@ -247,4 +995,61 @@ mod tests {
             p.page_type = 4;
         }
     }
+    #[test]
+    fn poison_on_failed_write() {
+        let path = "./tests/poison.db";
+        let _ = std::fs::remove_file(path);
+        let db = DB::open(path).unwrap();
+        let tx = db.tx(true).unwrap();
+        tx.create_bucket("test").unwrap().put("a", "1").unwrap();
+        tx.commit().unwrap();
+        drop(tx);
+
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+        b.put("b", "2").unwrap();
+        drop(b);
+        // writes through a read-only handle fail
+        *db.file.lock() = File::open(path).unwrap();
+        assert!(tx.commit().is_err());
+        drop(tx);
+        assert!(db.poisoned());
+        let err = db.tx(true).unwrap_err();
+        assert!(matches!(err, RoltError::Poisoned));
+
+        let tx = db.tx(false).unwrap();
+        let b = tx.bucket("test").unwrap();
+        assert_eq!(b.get("a"), Some(&b"1"[..]));
+        assert_eq!(b.get("b"), None);
+    }
+
+    #[test]
+    fn unmap_superseded_mappings() {
+        let path = "./tests/unmap.db";
+        let _ = std::fs::remove_file(path);
+        let db = DBBuilder::default().num_pages(8).open(path).unwrap();
+        let grow = |n: u32| {
+            let tx = db.tx(true).unwrap();
+            let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+            for i in 0..50u32 {
+                b.put(format!("{}-{}", n, i), [1u8; 512]).unwrap();
+            }
+            drop(b);
+            tx.commit().unwrap();
+        };
+        let reader = db.tx(false).unwrap();
+        for n in 0..20 {
+            grow(n);
+        }
+        // the reader keeps its mapping, the ones in between are gone
+        assert_eq!(db.mmaps.lock().len(), 2);
+        assert!(reader.bucket("test").is_none());
+        drop(reader);
+        assert_eq!(db.mmaps.lock().len(), 1);
+        let tx = db.tx(false).unwrap();
+        assert_eq!(
+            tx.bucket("test").unwrap().get("19-49"),
+            Some(&[1u8; 512][..])
+        );
+    }
 }
@@ -0,0 +1,163 @@
+use std::{
+    fs::{File, OpenOptions},
+    hash::Hasher,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use fnv::FnvHasher;
+use parking_lot::Mutex;
+
+use crate::{
+    check::guess_page_size,
+    db::map_file,
+    error::Result,
+    page::{Page, PageId},
+    transaction::Txid,
+};
+
+// "roltdwb1", marks a buffer file
+const MAGIC: u64 = 0x726f_6c74_6477_6231;
+// bytes before the body: magic, body length and its fnv checksum (u64 each)
+const HEADER_SIZE: usize = 24;
+
+// copies of the pages of the last commit, written and synced before the
+// pages go to their place in the db file. if a crash tears a page write of
+// a commit whose meta page made it to disk, open puts the copies back
+#[derive(Debug)]
+pub(crate) struct DoubleWrite {
+    file: Mutex<File>,
+}
+
+// scratch file next to the db that holds the buffer
+pub(crate) fn buffer_path(p: &Path) -> PathBuf {
+    let mut path = p.as_os_str().to_owned();
+    path.push(".dwb");
+    PathBuf::from(path)
+}
+
+impl DoubleWrite {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    // replace the buffer with the pages tx_id is about to write and sync it
+    pub(crate) fn write(
+        &self,
+        tx_id: Txid,
+        page_size: u64,
+        pages: &[(PageId, &[u8])],
+    ) -> Result<()> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&tx_id.to_le_bytes());
+        body.extend_from_slice(&page_size.to_le_bytes());
+        body.extend_from_slice(&(pages.len() as u64).to_le_bytes());
+        for (id, data) in pages {
+            body.extend_from_slice(&id.to_le_bytes());
+            body.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            body.extend_from_slice(data);
+        }
+        let mut buf = Vec::with_capacity(HEADER_SIZE + body.len());
+        buf.extend_from_slice(&MAGIC.to_le_bytes());
+        buf.extend_from_slice(&(body.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&checksum(&body).to_le_bytes());
+        buf.extend_from_slice(&body);
+        let mut file = self.file.lock();
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&buf)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    // write the buffered pages back into db when they belong to the commit
+    // its meta page names, returns how many were restored. a buffer that is
+    // torn, stale or from a tx that never committed is left alone
+    pub(crate) fn recover(&self, db: &File) -> Result<usize> {
+        let mut buf = Vec::new();
+        {
+            let mut file = self.file.lock();
+            file.seek(SeekFrom::Start(0))?;
+            file.read_to_end(&mut buf)?;
+        }
+        let (tx_id, page_size, pages) = match parse(&buf) {
+            Some(parsed) => parsed,
+            None => return Ok(0),
+        };
+        if committed_tx(db)? != Some(tx_id) {
+            return Ok(0);
+        }
+        let mut db = db;
+        for (id, data) in pages.iter() {
+            db.seek(SeekFrom::Start(id * page_size))?;
+            db.write_all(data)?;
+        }
+        db.sync_data()?;
+        Ok(pages.len())
+    }
+}
+
+fn checksum(body: &[u8]) -> u64 {
+    let mut hash = FnvHasher::default();
+    hash.write(body);
+    hash.finish()
+}
+
+fn take<'a>(buf: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if buf.len() < n {
+        return None;
+    }
+    let (head, rest) = buf.split_at(n);
+    *buf = rest;
+    Some(head)
+}
+
+fn take_u64(buf: &mut &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(take(buf, 8)?.try_into().ok()?))
+}
+
+// txid, page size and the id and contents of each page
+type Buffer<'a> = (Txid, u64, Vec<(PageId, &'a [u8])>);
+
+// contents of a complete buffer
+fn parse(mut buf: &[u8]) -> Option<Buffer<'_>> {
+    let buf = &mut buf;
+    if take_u64(buf)? != MAGIC {
+        return None;
+    }
+    let len = take_u64(buf)? as usize;
+    let sum = take_u64(buf)?;
+    let mut body = take(buf, len)?;
+    if checksum(body) != sum {
+        return None;
+    }
+    let body = &mut body;
+    let (tx_id, page_size, count) = (take_u64(body)?, take_u64(body)?, take_u64(body)?);
+    let mut pages = Vec::new();
+    for _ in 0..count {
+        let id = take_u64(body)?;
+        let len = take_u64(body)? as usize;
+        pages.push((id, take(body, len)?));
+    }
+    Some((tx_id, page_size, pages))
+}
+
+// txid of the newest intact meta page of the db file
+fn committed_tx(db: &File) -> Result<Option<Txid>> {
+    let mmap = map_file(db)?;
+    let page_size = guess_page_size(&mmap);
+    Ok((0..2)
+        .filter(|id| (id + 1) * page_size <= mmap.len() as u64)
+        .filter_map(|id| Page::from_buf(&mmap, id, page_size).ok()?.meta().ok())
+        .filter(|m| m.has_magic() && m.sum_matches())
+        .map(|m| m.tx_id)
+        .max())
+}
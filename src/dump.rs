@@ -0,0 +1,282 @@
+use std::{fmt::Write, mem::size_of};
+
+use crate::{
+    bucket::{Bucket, IBucket},
+    error::{Result, RoltError},
+    meta::Meta,
+    page::{
+        page_offset, BlobChunk, BranchPageElement, FixedLeafElement, LeafPageElement, Page, PageId,
+    },
+    transaction::ITransaction,
+    Err,
+};
+
+// bytes of a key or value shown in an element line
+const PREVIEW: usize = 32;
+
+impl ITransaction {
+    // decoded header, elements, free ids or meta fields of a page followed by
+    // a hexdump of it. every offset is checked against the page, so damaged
+    // pages can be dumped too
+    pub fn dump_page(&self, id: PageId) -> Result<String> {
+        if id >= self.meta().num_pages {
+            return Err!(RoltError::PageOutOfRange(id));
+        }
+        let buf = self.page_bytes(id)?;
+        let p = Page::from_buf_direct(&buf)?;
+        let mut out = String::new();
+        write!(out, "page {}", id)?;
+        if p.id != id {
+            write!(out, " (header id {})", { p.id })?;
+        }
+        writeln!(
+            out,
+            ": type {} (0x{:02x}), count {}, overflow {}, txid {}",
+            type_name(p.page_type),
+            p.page_type,
+            { p.count },
+            { p.overflow },
+            { p.txid }
+        )?;
+        match p.page_type {
+            Page::BRANCH_PAGE => dump_branch(&buf, p, &mut out)?,
+            Page::LEAF_PAGE => dump_leaf(&buf, p, &mut out)?,
+            Page::FIXED_LEAF_PAGE => dump_fixed(&buf, p, &mut out)?,
+            Page::META_PAGE => dump_meta(&buf, p, &mut out)?,
+            Page::FREE_LIST_PAGE => dump_free_list(&buf, p, &mut out)?,
+            Page::BLOB_PAGE => dump_blob(&buf, p, &mut out)?,
+            _ => {}
+        }
+        writeln!(out, "hex:")?;
+        hexdump(&buf, &mut out)?;
+        Ok(out)
+    }
+
+    // copy of the blocks of a page that exist, dirty pages of a write tx
+    // included
+    fn page_bytes(&self, id: PageId) -> Result<Vec<u8>> {
+        let page_size = self.page_size() as usize;
+        if let Some(p) = self.pages.read().get(&id) {
+            let span = ((p.overflow as usize + 1) * page_size).min(p.size());
+            let data = unsafe { std::slice::from_raw_parts(p.data_ptr(), span) };
+            return Ok(data.to_vec());
+        }
+        let mmap = self.mmap();
+        let start = match page_offset(id, page_size as u64) {
+            Some(start) if start + page_size <= mmap.len() => start,
+            _ => return Err!(RoltError::PageOutOfRange(id)),
+        };
+        let p = Page::from_buf(mmap, id, page_size as u64)?;
+        let span = (p.overflow as usize + 1).saturating_mul(page_size);
+        let end = start.saturating_add(span).min(mmap.len());
+        Ok(mmap[start..end].to_vec())
+    }
+}
+
+fn type_name(t: u8) -> &'static str {
+    match t {
+        Page::BRANCH_PAGE => "branch",
+        Page::LEAF_PAGE => "leaf",
+        Page::META_PAGE => "meta",
+        Page::FREE_LIST_PAGE => "free list",
+        Page::FIXED_LEAF_PAGE => "fixed leaf",
+        Page::BLOB_PAGE => "blob",
+        _ => "unknown",
+    }
+}
+
+fn u32_at(buf: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_ne_bytes(buf.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u64_at(buf: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_ne_bytes(buf.get(at..at + 8)?.try_into().ok()?))
+}
+
+// size and escaped start of a key or value, or why it can't be shown
+fn preview(buf: &[u8], start: usize, len: u32) -> String {
+    let bytes = match buf.get(start..start.saturating_add(len as usize)) {
+        Some(b) => b,
+        None => return format!("{} <past the end of the page>", len),
+    };
+    let shown: String = bytes[..bytes.len().min(PREVIEW)]
+        .iter()
+        .flat_map(|&b| std::ascii::escape_default(b))
+        .map(char::from)
+        .collect();
+    let more = if bytes.len() > PREVIEW { "..." } else { "" };
+    format!("{} \"{}{}\"", len, shown, more)
+}
+
+// offset of element i and whether the element array reaches past the page
+fn element_at(buf: &[u8], i: usize, size: usize) -> Option<usize> {
+    let at = Page::page_header_size() + i * size;
+    (at + size <= buf.len()).then_some(at)
+}
+
+fn past_end(out: &mut String, i: usize) -> std::fmt::Result {
+    writeln!(out, "  [{}] <element past the end of the page>", i)
+}
+
+fn dump_branch(buf: &[u8], p: &Page, out: &mut String) -> Result<()> {
+    for i in 0..p.count as usize {
+        let at = match element_at(buf, i, BranchPageElement::SIZE) {
+            Some(at) => at,
+            None => return Ok(past_end(out, i)?),
+        };
+        let (pos, k_size) = (u32_at(buf, at).unwrap(), u32_at(buf, at + 4).unwrap());
+        let child = u64_at(buf, at + 8).unwrap();
+        let key = preview(buf, at + pos as usize, k_size);
+        writeln!(out, "  [{}] pos {} child {} key {}", i, pos, child, key)?;
+    }
+    Ok(())
+}
+
+fn dump_leaf(buf: &[u8], p: &Page, out: &mut String) -> Result<()> {
+    for i in 0..p.count as usize {
+        let at = match element_at(buf, i, LeafPageElement::SIZE) {
+            Some(at) => at,
+            None => return Ok(past_end(out, i)?),
+        };
+        let flags = u32_at(buf, at).unwrap();
+        let (pos, k_size, v_size) = (
+            u32_at(buf, at + 4).unwrap(),
+            u32_at(buf, at + 8).unwrap(),
+            u32_at(buf, at + 12).unwrap(),
+        );
+        let start = at + pos as usize;
+        let value = start.saturating_add(k_size as usize);
+        write!(
+            out,
+            "  [{}] flags 0x{:x} pos {} key {} value {}",
+            i,
+            flags,
+            pos,
+            preview(buf, start, k_size),
+            preview(buf, value, v_size)
+        )?;
+        if flags & Bucket::FLAG != 0 && v_size as usize >= IBucket::SIZE {
+            if let Some(root) = u64_at(buf, value) {
+                write!(out, " (bucket root {})", root)?;
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn dump_fixed(buf: &[u8], p: &Page, out: &mut String) -> Result<()> {
+    let width = p.value_width as usize;
+    let count = p.count as usize;
+    writeln!(out, "  value width {}", width)?;
+    // the values sit together right after the elements
+    let values = Page::page_header_size() + count * FixedLeafElement::SIZE;
+    for i in 0..count {
+        let at = match element_at(buf, i, FixedLeafElement::SIZE) {
+            Some(at) => at,
+            None => return Ok(past_end(out, i)?),
+        };
+        let (pos, k_size) = (u32_at(buf, at).unwrap(), u32_at(buf, at + 4).unwrap());
+        writeln!(
+            out,
+            "  [{}] pos {} key {} value {}",
+            i,
+            pos,
+            preview(buf, at + pos as usize, k_size),
+            preview(buf, values + i * width, width as u32)
+        )?;
+    }
+    Ok(())
+}
+
+fn dump_meta(buf: &[u8], p: &Page, out: &mut String) -> Result<()> {
+    if buf.len() < Page::page_header_size() + size_of::<Meta>() {
+        writeln!(out, "  <meta past the end of the page>")?;
+        return Ok(());
+    }
+    let m = p.meta()?;
+    writeln!(
+        out,
+        "  page id {}, magic 0x{:x}{}, version {}, page size {}, application id 0x{:x}",
+        { m.page_id },
+        { m.magic_number },
+        if m.has_magic() { "" } else { " (bad)" },
+        { m.version },
+        { m.page_size },
+        { m.application_id }
+    )?;
+    writeln!(
+        out,
+        "  tx {}, free list {}, root {}, sequence {}, pages {}, checksum {}",
+        { m.tx_id },
+        { m.free_list },
+        { m.root.root },
+        { m.root.sequence },
+        { m.num_pages },
+        if m.sum_matches() { "ok" } else { "mismatch" }
+    )?;
+    Ok(())
+}
+
+fn dump_free_list(buf: &[u8], p: &Page, out: &mut String) -> Result<()> {
+    let header = Page::page_header_size();
+    let (count, skip) = match p.count {
+        // count overflow, the real count is stored in the first elem
+        u16::MAX => (u64_at(buf, header).unwrap_or(0) as usize, 1),
+        n => (n as usize, 0),
+    };
+    let ids: Vec<String> = (skip..skip + count)
+        .map_while(|i| u64_at(buf, header + i * size_of::<PageId>()))
+        .map(|id| id.to_string())
+        .collect();
+    writeln!(out, "  {} free ids", count)?;
+    if !ids.is_empty() {
+        writeln!(out, "  {}", ids.join(" "))?;
+    }
+    if ids.len() < count {
+        writeln!(out, "  <free ids past the end of the page>")?;
+    }
+    Ok(())
+}
+
+fn dump_blob(buf: &[u8], p: &Page, out: &mut String) -> Result<()> {
+    if buf.len() < Page::page_header_size() + BlobChunk::SIZE {
+        writeln!(out, "  <chunk header past the end of the page>")?;
+        return Ok(());
+    }
+    let chunk = p.blob_chunk();
+    writeln!(out, "  next {}, len {}", { chunk.next }, { chunk.len })?;
+    Ok(())
+}
+
+// 16 bytes a line with offsets and ascii, runs of repeated lines collapsed
+// to a single '*'
+fn hexdump(buf: &[u8], out: &mut String) -> Result<()> {
+    let mut prev: Option<&[u8]> = None;
+    let mut skipping = false;
+    for (i, line) in buf.chunks(16).enumerate() {
+        if prev == Some(line) {
+            if !skipping {
+                writeln!(out, "*")?;
+                skipping = true;
+            }
+            continue;
+        }
+        skipping = false;
+        prev = Some(line);
+        let hex: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(out, "{:08x}  {:<47}  |{}|", i * 16, hex.join(" "), ascii)?;
+    }
+    writeln!(out, "{:08x}", buf.len())?;
+    Ok(())
+}
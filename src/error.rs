@@ -1,13 +1,20 @@
 use thiserror::Error;
 
-pub type Result<T> = anyhow::Result<T>;
+pub type Result<T> = std::result::Result<T, RoltError>;
 
+// every error the api returns. match on the variant to tell failures apart,
+// errors without a variant of their own come as Other
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum RoltError {
     #[error("invalid page type")]
     InvalidPageType,
     #[error("tx not valid")]
     TxNotValid,
+    #[error("tx has already been committed")]
+    TxCommitted,
+    #[error("tx has been rolled back")]
+    TxRolledBack,
     #[error("page is empty")]
     PageEmpty,
     #[error("inode is overflow")]
@@ -20,12 +27,81 @@ pub enum RoltError {
     StackEmpty,
     #[error("only allow one writable tx")]
     WritableTxNotAllowed,
+    #[error("incompatible value")]
+    IncompatibleValue,
+    #[error("key is reserved for the engine's own use")]
+    ReservedKey,
+    #[error("db is locked by another process")]
+    Locked,
+    #[error("db file was replaced by a compaction, reopen it")]
+    FileReplaced,
+    #[error("db is opened read-only")]
+    ReadOnly,
+    #[error("db is read-only after a failed commit, reopen it to write")]
+    Poisoned,
+    #[error("replication log missed a commit, followers need a fresh copy of the db")]
+    ReplicationBroken,
+    #[error("page {0} is out of range")]
+    PageOutOfRange(u64),
+    #[error("db file of {0} bytes is larger than the address space of this target")]
+    FileTooLarge(u64),
+    #[error("buckets may not nest more than {0} levels deep")]
+    BucketTooDeep(usize),
+    #[error("db belongs to application {found:#x}, expected {expected:#x}")]
+    ApplicationId { expected: u32, found: u32 },
+    #[error("db file has format version {found}, this build reads version {expected}")]
+    IncompatibleVersion { expected: u32, found: u32 },
+    #[error("write tx would hold more than {0} bytes of nodes, commit and go on in a new tx")]
+    NodeMemoryLimit(u64),
+    #[error("a commit since the tx started touched the same keys, retry the tx")]
+    Conflict,
+    #[error("tx is read-only")]
+    NotWritable,
+    #[error("invalid db config: {0}")]
+    InvalidConfig(String),
+    #[error("bucket not found")]
+    BucketNotFound,
+    #[error("db file is corrupted: {0}")]
+    Corruption(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Other(Box<dyn std::error::Error + Send + Sync>),
 }
 
+impl From<&str> for RoltError {
+    fn from(msg: &str) -> Self {
+        RoltError::Other(msg.into())
+    }
+}
+
+impl From<String> for RoltError {
+    fn from(msg: String) -> Self {
+        RoltError::Other(msg.into())
+    }
+}
+
+// errors of std and dependencies that have no variant of their own
+macro_rules! other {
+    ($($t:ty),* $(,)?) => {$(
+        impl From<$t> for RoltError {
+            fn from(e: $t) -> Self {
+                RoltError::Other(e.into())
+            }
+        }
+    )*};
+}
+
+other!(std::fmt::Error, std::array::TryFromSliceError);
+#[cfg(feature = "serde")]
+other!(bincode::Error, serde_json::Error);
+#[cfg(feature = "rkyv")]
+other!(rkyv::rancor::Error);
+
 #[macro_export]
 macro_rules! Err {
     ($err:expr $(,)?) => {{
         let error = $err;
-        Err(anyhow::anyhow!(error))
+        Err($crate::RoltError::from(error))
     }};
 }
@@ -0,0 +1,124 @@
+use std::io::Write;
+
+use crate::{bucket::Bucket, error::Result, Err};
+
+const HEX: &[u8; 16] = b"0123456789abcdef";
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// how raw bytes are written into a csv field
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    // fails on bytes that are not valid utf-8
+    #[default]
+    Utf8,
+    // lowercase hex
+    Hex,
+    // standard alphabet with padding
+    Base64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvOptions {
+    pub key: Encoding,
+    pub value: Encoding,
+    // write a `key,value` header row first
+    pub header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            key: Encoding::Utf8,
+            value: Encoding::Utf8,
+            header: true,
+        }
+    }
+}
+
+impl Bucket {
+    // write every key/value pair of this bucket as csv rows, nested buckets
+    // and expired keys are skipped. returns the number of rows written
+    pub fn export_csv<W: Write>(&self, out: &mut W, opts: CsvOptions) -> Result<u64> {
+        if opts.header {
+            out.write_all(b"key,value\n")?;
+        }
+        let mut rows = 0;
+        let mut c = self.cursor();
+        let mut pair = c.first()?;
+        while let Some(key) = pair.key() {
+            if !pair.is_bucket() && !self.expired(key) {
+                let value = pair.value().unwrap_or_default();
+                write_field(out, key, opts.key)?;
+                out.write_all(b",")?;
+                write_field(out, value, opts.value)?;
+                out.write_all(b"\n")?;
+                rows += 1;
+            }
+            pair = c.next()?;
+        }
+        out.flush()?;
+        Ok(rows)
+    }
+}
+
+fn write_field<W: Write>(out: &mut W, bytes: &[u8], encoding: Encoding) -> Result<()> {
+    match encoding {
+        Encoding::Utf8 => {
+            let s = match std::str::from_utf8(bytes) {
+                Ok(s) => s,
+                Err(_) => return Err!("field is not valid utf-8, export it as hex or base64"),
+            };
+            // quote fields that would break the row, doubling inner quotes
+            if s.contains([',', '"', '\n', '\r']) {
+                write!(out, "\"{}\"", s.replace('"', "\"\""))?;
+            } else {
+                out.write_all(s.as_bytes())?;
+            }
+        }
+        Encoding::Hex => out.write_all(&hex(bytes))?,
+        Encoding::Base64 => out.write_all(&base64(bytes))?,
+    }
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .iter()
+        .flat_map(|b| [HEX[(b >> 4) as usize], HEX[(b & 0xf) as usize]])
+        .collect()
+}
+
+fn base64(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = u32::from_be_bytes([0, b[0], b[1], b[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize]);
+            } else {
+                out.push(b'=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodings() {
+        assert_eq!(hex(b"\x00\xffa"), b"00ff61");
+        assert_eq!(base64(b""), b"");
+        assert_eq!(base64(b"f"), b"Zg==");
+        assert_eq!(base64(b"fo"), b"Zm8=");
+        assert_eq!(base64(b"foo"), b"Zm9v");
+        assert_eq!(base64(b"foob"), b"Zm9vYg==");
+    }
+}
@@ -1,15 +1,92 @@
 use crate::error::{Result, RoltError};
 use crate::page::{Page, PageId};
+use crate::utils::debug_span;
 use crate::Err;
-use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::collections::{BTreeMap, HashSet};
 use std::mem::size_of;
 
+// ordered set of page ids. with the roaring feature it's a compressed
+// bitmap, which holds the long runs of a large free list in a fraction of
+// the 8 bytes a page an id tree takes
+#[derive(Debug, Default, Clone)]
+pub(crate) struct PageSet(
+    #[cfg(feature = "roaring")] roaring::RoaringTreemap,
+    #[cfg(not(feature = "roaring"))] std::collections::BTreeSet<PageId>,
+);
+
+#[cfg(feature = "roaring")]
+impl PageSet {
+    pub(crate) fn insert(&mut self, id: PageId) -> bool {
+        self.0.insert(id)
+    }
+    pub(crate) fn remove(&mut self, id: PageId) -> bool {
+        self.0.remove(id)
+    }
+    pub(crate) fn contains(&self, id: PageId) -> bool {
+        self.0.contains(id)
+    }
+    pub(crate) fn len(&self) -> usize {
+        self.0.len() as usize
+    }
+    pub(crate) fn iter(&self) -> impl Iterator<Item = PageId> + '_ {
+        self.0.iter()
+    }
+}
+
+#[cfg(not(feature = "roaring"))]
+impl PageSet {
+    pub(crate) fn insert(&mut self, id: PageId) -> bool {
+        self.0.insert(id)
+    }
+    pub(crate) fn remove(&mut self, id: PageId) -> bool {
+        self.0.remove(&id)
+    }
+    pub(crate) fn contains(&self, id: PageId) -> bool {
+        self.0.contains(&id)
+    }
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub(crate) fn iter(&self) -> impl Iterator<Item = PageId> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+impl PageSet {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    // first id of the lowest run of len consecutive ids
+    pub(crate) fn find_run(&self, len: usize) -> Option<PageId> {
+        let mut start: PageId = 0;
+        let mut prev: PageId = 0;
+        for id in self.iter() {
+            // find gap
+            if prev == 0 || id - prev != 1 {
+                start = id;
+            }
+            if id - start + 1 >= len as u64 {
+                return Some(start);
+            }
+            prev = id;
+        }
+        None
+    }
+}
+
+impl FromIterator<PageId> for PageSet {
+    fn from_iter<I: IntoIterator<Item = PageId>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
 #[derive(Debug)]
 #[repr(C)]
 pub(crate) struct FreeList {
     pending: BTreeMap<PageId, Vec<PageId>>,
-    free_pages: BTreeSet<PageId>, // in-memory look up
-    cache: HashSet<PageId>,
+    free_pages: PageSet, // in-memory look up
+    // free and pending ids
+    cache: PageSet,
 }
 
 #[allow(dead_code)]
@@ -17,8 +94,8 @@ impl FreeList {
     pub fn new() -> FreeList {
         FreeList {
             pending: BTreeMap::new(),
-            free_pages: BTreeSet::new(),
-            cache: HashSet::new(),
+            free_pages: PageSet::default(),
+            cache: PageSet::default(),
         }
     }
     pub fn init(&mut self, free_pages: &[PageId]) {
@@ -29,33 +106,37 @@ impl FreeList {
     }
     // allocate a sequence of free pages
     pub fn allocate(&mut self, len: usize) -> Option<PageId> {
+        debug_span!("free_list_allocate", len);
         if self.free_pages.is_empty() || self.free_pages.len() < len {
             return None;
         }
-        let mut start: PageId = 0;
-        let mut prev: PageId = 0;
-        for id in self.free_pages.iter().cloned() {
-            // find gap
-            if prev == 0 || id - prev != 1 {
-                start = id;
-            }
-            if id - start + 1 >= len as u64 {
-                for id in start..start + len as u64 {
-                    self.free_pages.remove(&id);
-                    self.cache.remove(&id);
-                }
-                return Some(start);
-            }
-            prev = id;
+        let start = self.free_pages.find_run(len)?;
+        for id in start..start + len as u64 {
+            self.free_pages.remove(id);
+            self.cache.remove(id);
         }
-        None
+        Some(start)
     }
 
     // release a page for a transaction
     pub fn free(&mut self, tx_id: u64, p: &Page) -> Result<()> {
+        debug_span!("free_list_free", tx_id, page_id = { p.id });
         let free_ids = self.pending.entry(tx_id).or_insert_with(Vec::new);
         for id in (p.id)..=(p.id + p.overflow as PageId) {
-            if self.free_pages.contains(&id) {
+            if self.free_pages.contains(id) {
+                return Err!(RoltError::InodeOverFlow);
+            }
+            free_ids.push(id);
+            self.cache.insert(id);
+        }
+        Ok(())
+    }
+
+    // release pages by id, for pages whose header can't be trusted
+    pub(crate) fn free_ids(&mut self, tx_id: u64, ids: &[PageId]) -> Result<()> {
+        let free_ids = self.pending.entry(tx_id).or_default();
+        for &id in ids {
+            if self.cache.contains(id) {
                 return Err!(RoltError::InodeOverFlow);
             }
             free_ids.push(id);
@@ -64,13 +145,30 @@ impl FreeList {
         Ok(())
     }
 
+    // drop the run of free pages that ends right before end, returns the
+    // first page id of the run, or end when the page before it is in use
+    pub(crate) fn take_tail(&mut self, end: PageId) -> PageId {
+        let mut start = end;
+        while start > 0 && self.free_pages.contains(start - 1) {
+            start -= 1;
+            self.free_pages.remove(start);
+            self.cache.remove(start);
+        }
+        start
+    }
+
     pub fn is_free(&self, id: PageId) -> bool {
-        self.cache.contains(&id)
+        self.cache.contains(id)
+    }
+    // free and pending page ids
+    pub(crate) fn ids(&self) -> HashSet<PageId> {
+        self.cache.iter().collect()
     }
     // remove pages from a given tx id
     pub fn rollback(&mut self, tx_id: u64) {
+        debug_span!("free_list_rollback", tx_id);
         if let Some(pages) = self.pending.get(&tx_id) {
-            for id in pages {
+            for &id in pages {
                 self.cache.remove(id);
             }
         }
@@ -78,25 +176,27 @@ impl FreeList {
     }
     // read from freeList page
     pub fn read(&mut self, p: &Page) -> Result<()> {
-        let mut count = p.count as usize;
-        let mut begin = 0;
-        // count overflow
-        if count == u16::MAX as usize {
-            let list = p.free_list()?;
-            count = list[0] as usize;
-            // skip the first elem
-            begin = 1;
-        }
-        if count == 0 {
-            self.free_pages.clear();
+        let list = Self::ids_in(p)?;
+        if list.is_empty() {
+            self.free_pages = PageSet::default();
         } else {
-            let list = p.free_list()?;
-            for id in list[begin..].iter() {
+            for id in list.iter() {
                 self.free_pages.insert(*id);
             }
         }
         Ok(())
     }
+    // ids stored in a free list page
+    pub(crate) fn ids_in(p: &Page) -> Result<&[PageId]> {
+        let list = p.free_list()?;
+        // count overflow, the real count is stored in the first elem
+        if p.count == u16::MAX {
+            let count = list[0] as usize;
+            let list = unsafe { std::slice::from_raw_parts(list.as_ptr(), count + 1) };
+            return Ok(&list[1..]);
+        }
+        Ok(list)
+    }
     pub fn write(&self, p: &mut Page) -> Result<()> {
         let count = self.count();
         p.page_type = Page::FREE_LIST_PAGE;
@@ -126,8 +226,7 @@ impl FreeList {
 
     fn page_ids(&self) -> Vec<PageId> {
         let mut ids = Vec::with_capacity(self.count());
-        let free_pages: Vec<PageId> = self.free_pages.iter().copied().collect();
-        ids.extend_from_slice(&free_pages);
+        ids.extend(self.free_pages.iter());
         for list in self.pending.values() {
             ids.extend_from_slice(list);
         }
@@ -137,17 +236,15 @@ impl FreeList {
 
     // rebuild cache
     fn reindex(&mut self) {
-        self.cache = HashSet::new();
-        for id in self.free_pages.iter() {
-            self.cache.insert(*id);
-        }
+        self.cache = self.free_pages.clone();
         for pages in self.pending.values() {
-            for id in pages {
-                self.cache.insert(*id);
+            for &id in pages {
+                self.cache.insert(id);
             }
         }
     }
     pub(crate) fn reload(&mut self, p: &Page) {
+        debug_span!("free_list_reload");
         self.read(p).unwrap();
         let mut t_cache = HashSet::new();
         for (_, ids) in self.pending.iter() {
@@ -156,13 +253,11 @@ impl FreeList {
             }
         }
         // add pages not in pending list to free pages
-        let mut free_pages = BTreeSet::new();
-        for id in self.free_pages.iter() {
-            if !t_cache.contains(id) {
-                free_pages.insert(*id);
-            }
-        }
-        self.free_pages = free_pages;
+        self.free_pages = self
+            .free_pages
+            .iter()
+            .filter(|id| !t_cache.contains(id))
+            .collect();
         self.reindex();
     }
     pub(crate) fn size(&self) -> usize {
@@ -183,11 +278,31 @@ mod tests {
         let mut b1 = vec![0u8; 4096];
         let mut b2 = vec![0u8; 4096];
 
-        let mut p1 = Page::from_buf_mut(&mut b1, 0, 0);
+        let mut p1 = Page::from_buf_mut(&mut b1, 0, 0).unwrap();
         p1.id = 2;
-        let p2 = Page::from_buf_mut(&mut b2, 0, 0);
+        let p2 = Page::from_buf_mut(&mut b2, 0, 0).unwrap();
         list.free(0, &p1).unwrap();
         list.write(p2).unwrap();
         let _ = p2.free_list().unwrap();
     }
+    #[test]
+    fn test_find_run() {
+        let set: PageSet = [3, 4, 6, 7, 8, 10].into_iter().collect();
+        assert_eq!(set.find_run(1), Some(3));
+        assert_eq!(set.find_run(2), Some(3));
+        assert_eq!(set.find_run(3), Some(6));
+        assert_eq!(set.find_run(4), None);
+        assert!(set.contains(10) && !set.contains(9));
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![3, 4, 6, 7, 8, 10]);
+    }
+    #[test]
+    fn test_take_tail() {
+        let mut list = FreeList::new();
+        list.init(&[4, 5, 7, 8, 9]);
+        assert_eq!(list.take_tail(11), 11);
+        assert_eq!(list.take_tail(10), 7);
+        assert!(!list.is_free(8));
+        assert!(list.is_free(5));
+        assert_eq!(list.count(), 2);
+    }
 }
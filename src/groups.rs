@@ -0,0 +1,135 @@
+use crate::{bucket::Bucket, cursor::Cursor};
+
+// how Bucket::groups cuts the group prefix out of a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    // up to and including the first delimiter, the whole key without one
+    Delimiter(u8),
+    // the first n bytes, the whole key when it is shorter
+    Len(usize),
+}
+
+impl GroupBy {
+    // the group prefix of key and whether keys past it can be skipped by
+    // seeking, which is not the case for a whole key standing as its group
+    fn prefix<'k>(&self, key: &'k [u8]) -> (&'k [u8], bool) {
+        match *self {
+            GroupBy::Delimiter(d) => match key.iter().position(|&b| b == d) {
+                Some(i) => (&key[..=i], true),
+                None => (key, false),
+            },
+            GroupBy::Len(n) if key.len() >= n => (&key[..n], true),
+            GroupBy::Len(_) => (key, false),
+        }
+    }
+}
+
+// distinct key prefixes of a bucket in key order, each with an iterator over
+// its entries. a cursor error ends the iteration
+pub struct Groups<'a> {
+    bucket: &'a Bucket,
+    cursor: Cursor<'a>,
+    by: GroupBy,
+    // the group returned last and whether to seek past it
+    last: Option<(Vec<u8>, bool)>,
+    done: bool,
+}
+
+impl<'a> Iterator for Groups<'a> {
+    type Item = (Vec<u8>, GroupEntries<'a>);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let pair = match &self.last {
+            None => self.cursor.first(),
+            // jump over the rest of the group instead of walking it
+            Some((prefix, true)) => match successor(prefix) {
+                Some(next) => self.cursor.seek(&next),
+                None => {
+                    self.done = true;
+                    return None;
+                }
+            },
+            Some((_, false)) => self.cursor.next(),
+        };
+        let key = match pair.ok().and_then(|p| p.key()) {
+            Some(key) => key,
+            None => {
+                self.done = true;
+                return None;
+            }
+        };
+        let (prefix, seek) = self.by.prefix(key);
+        self.last = Some((prefix.to_vec(), seek));
+        let entries = GroupEntries {
+            bucket: self.bucket,
+            cursor: self.bucket.cursor(),
+            prefix: prefix.to_vec(),
+            started: false,
+        };
+        Some((prefix.to_vec(), entries))
+    }
+}
+
+// keys and values of one group, nested buckets and expired keys skipped
+pub struct GroupEntries<'a> {
+    bucket: &'a Bucket,
+    cursor: Cursor<'a>,
+    prefix: Vec<u8>,
+    started: bool,
+}
+
+impl<'a> Iterator for GroupEntries<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let pair = if self.started {
+                self.cursor.next().ok()?
+            } else {
+                self.started = true;
+                self.cursor.seek_prefix(&self.prefix).ok()?
+            };
+            let (key, value) = pair.key().zip(pair.value())?;
+            if !pair.is_bucket() && !self.bucket.expired(key) {
+                return Some((key, value));
+            }
+        }
+    }
+}
+
+// the smallest key above every key starting with prefix, None when there is
+// none because prefix is all 0xff bytes
+fn successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let i = prefix.iter().rposition(|&b| b != u8::MAX)?;
+    let mut next = prefix[..=i].to_vec();
+    next[i] += 1;
+    Some(next)
+}
+
+impl Bucket {
+    // distinct prefixes of the keys in this bucket, seeking from one group to
+    // the next rather than reading every key. nested buckets and expired
+    // keys still start groups, their entries leave them out
+    pub fn groups(&self, by: GroupBy) -> Groups<'_> {
+        Groups {
+            bucket: self,
+            cursor: self.cursor(),
+            by,
+            last: None,
+            done: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_successor() {
+        assert_eq!(successor(b"a/"), Some(b"a0".to_vec()));
+        assert_eq!(successor(&[1, 0xff, 0xff]), Some(vec![2]));
+        assert_eq!(successor(&[0xff]), None);
+    }
+}
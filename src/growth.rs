@@ -0,0 +1,81 @@
+use std::{fmt::Debug, sync::Arc};
+
+pub type GrowthFn = Arc<dyn Fn(u64, u64) -> u64 + Send + Sync>;
+
+// how far the file is extended once a commit outgrows the mapping. growing
+// ahead of need means fewer remaps at the cost of unused space on disk
+#[derive(Clone, Default)]
+pub enum GrowthPolicy {
+    // map only the pages in use
+    #[default]
+    Exact,
+    // double the file until a step would exceed max_step bytes, then grow by
+    // max_step at a time
+    Double {
+        max_step: u64,
+    },
+    // grow by a fixed number of bytes
+    Step(u64),
+    // called with the current and needed size in bytes, returns the new size
+    Custom(GrowthFn),
+}
+
+impl GrowthPolicy {
+    // size in bytes to extend the file to, never less than needed
+    pub(crate) fn next_size(&self, current: u64, needed: u64) -> u64 {
+        let size = match self {
+            GrowthPolicy::Exact => needed,
+            GrowthPolicy::Double { max_step } => {
+                let mut size = current.max(1);
+                while size < needed {
+                    size += size.min(*max_step).max(1);
+                }
+                size
+            }
+            GrowthPolicy::Step(step) => {
+                let step = (*step).max(1);
+                current + (needed.saturating_sub(current)).div_ceil(step) * step
+            }
+            GrowthPolicy::Custom(f) => f(current, needed),
+        };
+        size.max(needed)
+    }
+}
+
+impl Debug for GrowthPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GrowthPolicy::Exact => write!(f, "Exact"),
+            GrowthPolicy::Double { max_step } => f
+                .debug_struct("Double")
+                .field("max_step", max_step)
+                .finish(),
+            GrowthPolicy::Step(step) => f.debug_tuple("Step").field(step).finish(),
+            GrowthPolicy::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_size() {
+        const MB: u64 = 1 << 20;
+        assert_eq!(GrowthPolicy::Exact.next_size(4 * MB, 5 * MB), 5 * MB);
+        let double = GrowthPolicy::Double { max_step: 16 * MB };
+        assert_eq!(double.next_size(4 * MB, 5 * MB), 8 * MB);
+        assert_eq!(double.next_size(4 * MB, 9 * MB), 16 * MB);
+        assert_eq!(double.next_size(16 * MB, 17 * MB), 32 * MB);
+        assert_eq!(double.next_size(32 * MB, 33 * MB), 48 * MB);
+        let step = GrowthPolicy::Step(2 * MB);
+        assert_eq!(step.next_size(4 * MB, 5 * MB), 6 * MB);
+        assert_eq!(step.next_size(4 * MB, 7 * MB), 8 * MB);
+        let custom = GrowthPolicy::Custom(Arc::new(|_, needed| needed + MB));
+        assert_eq!(custom.next_size(4 * MB, 5 * MB), 6 * MB);
+        // a policy returning too little still covers what is needed
+        let custom = GrowthPolicy::Custom(Arc::new(|current, _| current));
+        assert_eq!(custom.next_size(4 * MB, 5 * MB), 5 * MB);
+    }
+}
@@ -6,13 +6,13 @@ use crate::{data::Entry, page::PageId, Bucket};
 pub(crate) struct Inode(pub(crate) Either<BranchINode, LeafINode>);
 
 impl Inode {
-    pub(crate) fn key(&self) -> &Vec<u8> {
+    pub(crate) fn key(&self) -> &Entry {
         match &self.0 {
             Either::Left(b) => &b.key,
             Either::Right(l) => &l.key,
         }
     }
-    pub(crate) fn value(&self) -> Option<&Vec<u8>> {
+    pub(crate) fn value(&self) -> Option<&Entry> {
         match &self.0 {
             Either::Left(_) => None,
             Either::Right(l) => Some(&l.value),
@@ -31,7 +31,7 @@ impl Inode {
         }
     }
     pub(crate) fn is_bucket(&self) -> bool {
-        self.flags() == Bucket::FLAG
+        self.flags() & Bucket::FLAG != 0
     }
 }
 impl From<BranchINode> for Inode {
@@ -1,18 +1,82 @@
+#[cfg(feature = "rkyv")]
+mod archived;
+#[cfg(feature = "async")]
+mod async_db;
+mod backup;
+mod bench;
+mod blob;
+mod bolt;
 mod bucket;
+mod bucket_dump;
+mod check;
+#[cfg(feature = "serde")]
+mod codec;
 mod cursor;
 mod data;
 mod db;
+mod double_write;
+mod dump;
 mod error;
+mod export;
 mod free_list;
+mod groups;
+mod growth;
 mod inode;
+mod maintenance;
 mod meta;
+mod metrics;
 mod node;
+mod optimistic;
 mod page;
+mod paginate;
+mod par_scan;
+mod replication;
+mod salvage;
+#[cfg(feature = "server")]
+mod server;
+mod slow_op;
+mod snapshot;
+mod stats;
+mod sync_policy;
 mod transaction;
+mod tree_stats;
+mod ttl;
+mod u64_key;
 mod utils;
+mod warm;
+mod watch;
+#[cfg(feature = "async")]
+pub use async_db::AsyncDB;
+pub use backup::{apply_incremental, BackupOptions, Progress, SnapshotSink, WriterSink};
+pub use bench::{bench, BenchOptions, BenchReport, KeyOrder, Percentiles};
+pub use blob::{BlobReader, ValueWriter};
+pub use bolt::{import_bolt, ImportStats};
 pub use bucket::Bucket;
-pub use db::DB;
-pub use transaction::Transaction;
+pub use check::{CheckError, CheckReport, IssueKind, Severity, VerifyOptions, VerifyReport};
+#[cfg(feature = "serde")]
+pub use codec::{Bincode, Codec, Json};
+pub use cursor::{Cursor, KVPair};
+pub use db::{DBBuilder, OpenPhase, OpenProgress, DB};
+pub use error::{Result, RoltError};
+pub use export::{CsvOptions, Encoding};
+pub use groups::{GroupBy, GroupEntries, Groups};
+pub use growth::{GrowthFn, GrowthPolicy};
+pub use maintenance::{MaintenanceAction, MaintenanceOptions};
+pub use metrics::Metrics;
+pub use optimistic::OptimisticTransaction;
+pub use page::{PageId, PageInfo, PageKind};
+pub use paginate::EntryPage;
+pub use replication::{LogEntry, LogRecord, ReplicationStream};
+pub use salvage::{salvage, SalvageReport, LOST_AND_FOUND};
+#[cfg(feature = "server")]
+pub use server::{Client, Server};
+pub use slow_op::{CommitTimings, SlowOp, SlowOpThresholds};
+pub use snapshot::SnapshotHandle;
+pub use stats::{Stats, TxMemory};
+pub use sync_policy::SyncPolicy;
+pub use transaction::{Pages, ReadTransaction, Transaction, TxState, WriteTransaction};
+pub use tree_stats::{FillStats, Histogram, RangeEstimate, SizeHistogram, TreeStats};
+pub use watch::{ChangeEvent, ChangeOp, ChangeSet};
 
 #[cfg(test)]
 mod tests {
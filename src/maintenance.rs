@@ -0,0 +1,266 @@
+use std::{
+    fs::{self, OpenOptions},
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use fs2::FileExt;
+use parking_lot::Mutex;
+
+use crate::{
+    bucket::Bucket,
+    check::IssueKind,
+    db::{map_file, DBBuilder, DB},
+    error::{Result, RoltError},
+    free_list::FreeList,
+    page::{Page, PageId},
+    Err,
+};
+
+// when and how a db tidies up its file, see DBBuilder::maintenance
+#[derive(Debug, Clone)]
+pub struct MaintenanceOptions {
+    // least time between two runs, a run happens when a write tx is opened
+    // and reports its errors to Metrics::maintenance_failed
+    pub interval: Duration,
+    // compact into a fresh file once this fraction of the pages is free and
+    // no read-only handle in another process has the file open
+    pub compact_free_ratio: f64,
+    // give free pages at the end of the file back to the filesystem
+    pub truncate_tail: bool,
+}
+
+impl Default for MaintenanceOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            compact_free_ratio: 0.5,
+            truncate_tail: true,
+        }
+    }
+}
+
+// what a maintenance run did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaintenanceAction {
+    Nothing,
+    // the file was cut down to this many pages
+    Truncated { pages: u64 },
+    // live data was copied into a fresh file that replaced the old one
+    Compacted { before_pages: u64, after_pages: u64 },
+}
+
+#[derive(Debug)]
+pub(crate) struct Maintenance {
+    opts: MaintenanceOptions,
+    last_run: Mutex<Instant>,
+    // set during a run, whose own write txs must not start another
+    running: AtomicBool,
+}
+
+impl Maintenance {
+    pub(crate) fn new(opts: MaintenanceOptions) -> Self {
+        Self {
+            opts,
+            last_run: Mutex::new(Instant::now()),
+            running: AtomicBool::new(false),
+        }
+    }
+}
+
+impl DB {
+    // run maintenance when it's enabled and the interval has passed
+    pub(crate) fn maintain_if_due(&self) -> Result<()> {
+        let m = match &self.maintenance {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+        {
+            let mut last_run = m.last_run.lock();
+            if last_run.elapsed() < m.opts.interval {
+                return Ok(());
+            }
+            *last_run = Instant::now();
+        }
+        if m.running.swap(true, Ordering::Acquire) {
+            return Ok(());
+        }
+        let res = self.maintain_with(&m.opts.clone());
+        m.running.store(false, Ordering::Release);
+        res.map(|_| ())
+    }
+
+    // look at the free list now and compact or truncate the file as the
+    // configured options ask, default options when maintenance is off
+    pub fn maintain(&self) -> Result<MaintenanceAction> {
+        let opts = self
+            .maintenance
+            .as_ref()
+            .map(|m| m.opts.clone())
+            .unwrap_or_default();
+        self.maintain_with(&opts)
+    }
+
+    fn maintain_with(&self, opts: &MaintenanceOptions) -> Result<MaintenanceAction> {
+        if self.read_only {
+            return Ok(MaintenanceAction::Nothing);
+        }
+        let meta = self.meta()?;
+        let free = self.free_list.read().count() as f64;
+        let idle = self.open_reads.load(Ordering::Relaxed) == 0 && self.snapshots.lock().is_empty();
+        if idle
+            && free / meta.num_pages as f64 >= opts.compact_free_ratio
+            && self.lock_readers_out()
+        {
+            let res = self.compact();
+            if res.is_err() {
+                self.let_readers_in()?;
+            }
+            return Ok(MaintenanceAction::Compacted {
+                before_pages: meta.num_pages,
+                after_pages: res?,
+            });
+        }
+        if opts.truncate_tail {
+            return self.truncate_tail();
+        }
+        Ok(MaintenanceAction::Nothing)
+    }
+
+    // give pages that are neither reachable nor free back to the free list,
+    // returning how many there were. any other problem check finds makes gc
+    // refuse, as pages a damaged tree lost track of may still be salvaged
+    pub fn gc(&self) -> Result<u64> {
+        let tx = self.tx(true)?;
+        let report = tx.check()?;
+        if let Some(e) = report
+            .issues
+            .iter()
+            .find(|e| e.kind != IssueKind::LeakedPage)
+        {
+            tx.rollback()?;
+            return Err!(format!("gc refused, the file is damaged: {}", e));
+        }
+        let leaked: Vec<PageId> = report.issues.iter().map(|e| e.page_id).collect();
+        if leaked.is_empty() {
+            tx.rollback()?;
+            return Ok(0);
+        }
+        self.free_list.write().free_ids(tx.id(), &leaked)?;
+        tx.commit()?;
+        Ok(leaked.len() as u64)
+    }
+
+    // drop free pages at the end of the file along with space allocated
+    // past the last page
+    fn truncate_tail(&self) -> Result<MaintenanceAction> {
+        // the tail leaves the free list only inside the tx, a rollback or a
+        // failed commit reloads the list from the file and puts it back
+        let tx = self.tx(true)?;
+        let num_pages = tx.meta().num_pages;
+        let end = self.free_list.write().take_tail(num_pages);
+        if end < num_pages {
+            tx.set_num_pages(end);
+            tx.commit()?;
+        } else {
+            tx.rollback()?;
+        }
+        drop(tx);
+        let num_pages = self.meta()?.num_pages;
+        let size = num_pages * self.page_size();
+        let file = self.file.lock();
+        if file.metadata()?.len() <= size {
+            return Ok(MaintenanceAction::Nothing);
+        }
+        file.set_len(size)?;
+        file.sync_all()?;
+        Ok(MaintenanceAction::Truncated { pages: num_pages })
+    }
+
+    // read-only handles in other processes hold shared locks on the file and
+    // would keep reading the old inode after a swap, so compaction only runs
+    // when our lock turns exclusive. without locks refresh catches the swap
+    fn lock_readers_out(&self) -> bool {
+        self.no_lock || FileExt::try_lock_exclusive(&*self.file.lock()).is_ok()
+    }
+    fn let_readers_in(&self) -> Result<()> {
+        if !self.no_lock {
+            FileExt::lock_shared(&*self.file.lock())?;
+        }
+        Ok(())
+    }
+
+    // copy live data into a shadow file, rename it over the db file and
+    // switch to it. returns the page count of the new file
+    fn compact(&self) -> Result<u64> {
+        let path = self.path.clone();
+        let mut shadow = path.clone().into_os_string();
+        shadow.push(".compact");
+        let shadow = PathBuf::from(shadow);
+        let _ = fs::remove_file(&shadow);
+        {
+            let dest = DBBuilder::default()
+                .page_size(self.page_size())
+                .no_lock(self.no_lock)
+                .open(&shadow)?;
+            let src = self.tx(false)?;
+            let dest_tx = dest.tx(true)?;
+            // keep txids growing across the swap
+            dest_tx.set_id(src.id() + 1);
+            dest_tx.set_application_id(src.meta().application_id)?;
+            copy_bucket(&src.root.read(), &mut dest_tx.root.write())?;
+            dest_tx.commit()?;
+        }
+        fs::rename(&shadow, &path)?;
+
+        // switch to the new file, the old mapping goes once nothing holds it
+        let f = OpenOptions::new().read(true).write(true).open(&path)?;
+        if !self.no_lock {
+            FileExt::lock_shared(&f)?;
+        }
+        if self.temp {
+            fs::remove_file(&path)?;
+        }
+        let mmap = map_file(&f)?;
+        *self.file.lock() = f;
+        self.push_mmap(mmap);
+        let meta = self.meta()?;
+        let mut free_list = FreeList::new();
+        let free_page = Page::from_buf(self.mmap(), meta.free_list, self.page_size())?;
+        free_list.init(free_page.free_list()?);
+        *self.free_list.write() = free_list;
+        Ok(meta.num_pages)
+    }
+}
+
+// copy every key and nested bucket of src into dest
+fn copy_bucket(src: &Bucket, dest: &mut Bucket) -> Result<()> {
+    dest.bucket.sequence = src.bucket.sequence;
+    let mut c = src.cursor();
+    let mut pair = c.first()?;
+    while let Some(key) = pair.key() {
+        if pair.is_bucket() {
+            let child = src
+                .get_bucket(key)
+                .ok_or(RoltError::from("cannot open nested bucket"))?;
+            let child = unsafe { &*child };
+            // keep dup and fixed width modes
+            let flags = pair.flags & Bucket::ENGINE_FLAGS;
+            copy_bucket(child, dest.create_nested(key, flags)?)?;
+        } else if pair.is_blob() {
+            // the chunks live in pages of the old file, copy the contents.
+            // an expired blob has no reader and is dropped
+            if let Some(mut blob) = src.get_blob_reader(key)? {
+                dest.put_blob(key, &mut blob)?;
+            }
+        } else {
+            match pair.value() {
+                Some(value) => dest.put_with_flags(key, value, pair.user_flags())?,
+                None => return Err!("key without a value"),
+            }
+        }
+        pair = c.next()?;
+    }
+    Ok(())
+}
@@ -3,19 +3,22 @@ use std::{hash::Hasher, intrinsics::copy_nonoverlapping, mem::size_of, slice::fr
 
 use crate::{
     bucket::IBucket,
-    error::Result,
+    error::{Result, RoltError},
     page::{Page, PageId},
     transaction::Txid,
     utils::struct_to_slice,
+    Err,
 };
 
 #[derive(Debug, Clone)]
-#[repr(C)]
+#[repr(C, packed)]
 pub(crate) struct Meta {
     pub(crate) page_id: PageId,
     pub(crate) magic_number: u32,
     pub(crate) version: u32,
     pub(crate) page_size: u32,
+    // set by the application embedding the db, 0 when it never set one
+    pub(crate) application_id: u32,
     pub(crate) free_list: PageId, // page id of free list
     pub(crate) tx_id: Txid,
     pub(crate) root: IBucket,
@@ -34,13 +37,14 @@ impl Default for Meta {
             magic_number: Meta::MAGIC,
             version: Meta::VERSION,
             page_size: page_size::get() as u32,
+            application_id: 0,
             num_pages: 0,
         }
     }
 }
 impl Meta {
     const MAGIC: u32 = 0xF0F43F;
-    const VERSION: u32 = 1;
+    pub(crate) const VERSION: u32 = 3;
     const META_SIZE: usize = size_of::<Self>();
     const SUM_SIZE: usize = size_of::<u64>();
     pub fn init(&mut self, page_id: PageId) {
@@ -64,6 +68,7 @@ impl Meta {
             copy_nonoverlapping(bytes.as_ptr(), p.ptr_mut(), bytes.len());
             p.count = 0;
             p.page_type = Page::META_PAGE;
+            p.txid = self.tx_id;
         }
         Ok(())
     }
@@ -79,6 +84,22 @@ impl Meta {
         hash.finish()
     }
     pub(crate) fn validate(&self) -> bool {
-        self.magic_number == Self::MAGIC && self.check_sum == self.sum64()
+        self.has_magic() && self.sum_matches()
+    }
+    pub(crate) fn has_magic(&self) -> bool {
+        self.magic_number == Self::MAGIC
+    }
+    pub(crate) fn sum_matches(&self) -> bool {
+        self.check_sum == self.sum64()
+    }
+    // fail unless the file was written in the page format of this version
+    pub(crate) fn check_version(&self) -> Result<()> {
+        if self.version != Self::VERSION {
+            return Err!(RoltError::IncompatibleVersion {
+                expected: Self::VERSION,
+                found: self.version,
+            });
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,59 @@
+use std::{fmt::Debug, ops::Deref, sync::Arc, time::Duration};
+
+use crate::error::RoltError;
+
+// receives counters and timings from db internals, implement it to bridge
+// into prometheus, metrics-rs and the like. every method is a no-op by default
+pub trait Metrics: Send + Sync {
+    // a write transaction finished committing
+    fn commit(&self, _elapsed: Duration) {}
+    // data was flushed to the file
+    fn fsync(&self, _elapsed: Duration) {}
+    // dirty pages written to the file by a commit
+    fn pages_written(&self, _count: u64) {}
+    // pages handed out to a write transaction
+    fn allocate(&self, _pages: u64) {}
+    // a node was served from the bucket's node cache
+    fn cache_hit(&self) {}
+    // a node had to be materialized from its page
+    fn cache_miss(&self) {}
+    // a maintenance run due when a write tx opened failed, the tx goes on
+    // and the run is retried once the interval passes again
+    fn maintenance_failed(&self, _error: &RoltError) {}
+    // the commit a write tx dropped while active runs failed, the tx was
+    // rolled back instead
+    fn implicit_commit_failed(&self, _error: &RoltError) {}
+}
+
+struct NoopMetrics;
+
+impl Metrics for NoopMetrics {}
+
+// the installed metrics sink, falls back to a no-op one
+#[derive(Clone)]
+pub(crate) struct MetricsHook(Arc<dyn Metrics>);
+
+impl MetricsHook {
+    pub(crate) fn new(metrics: Arc<dyn Metrics>) -> Self {
+        Self(metrics)
+    }
+}
+
+impl Default for MetricsHook {
+    fn default() -> Self {
+        Self(Arc::new(NoopMetrics))
+    }
+}
+
+impl Deref for MetricsHook {
+    type Target = dyn Metrics;
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+impl Debug for MetricsHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MetricsHook")
+    }
+}
@@ -1,8 +1,5 @@
-use anyhow::anyhow;
-use either::Either;
 use std::{
-    borrow::BorrowMut,
-    cell::RefCell,
+    cell::{Cell, RefCell},
     intrinsics::copy_nonoverlapping,
     ops::Deref,
     rc::{Rc, Weak},
@@ -14,7 +11,8 @@ use crate::{
     data::{Entry, RawPtr},
     error::{Result, RoltError},
     inode::{BranchINode, Inode, LeafINode},
-    page::{BranchPageElement, LeafPageElement, Page, PageId},
+    page::{BranchPageElement, FixedLeafElement, LeafPageElement, Page, PageId},
+    utils::search_keys,
     Err,
 };
 
@@ -44,8 +42,8 @@ impl Deref for WeakNode {
 pub(crate) struct InnerNode {
     pub(crate) bucket: RawPtr<Bucket>,
     pub(crate) page_id: RefCell<PageId>,
-    unbalanced: bool,
-    // spilled: bool,
+    unbalanced: Cell<bool>,
+    spilled: RefCell<bool>,
     pub(crate) inodes: RefCell<Vec<Inode>>,
     pub(crate) children: RefCell<Vec<Node>>,
     pub(crate) parent: RefCell<WeakNode>,
@@ -62,11 +60,6 @@ impl Node {
             ..Default::default()
         }))
     }
-    pub fn default() -> Node {
-        Node {
-            ..Default::default()
-        }
-    }
 
     pub(crate) fn bucket(&self) -> &Bucket {
         &*self.bucket
@@ -79,80 +72,55 @@ impl Node {
 
     pub(crate) fn size(&self) -> usize {
         let mut size = Page::page_header_size();
-        let e_size = self.page_elem_size();
         for inode in self.inodes.borrow().iter() {
-            size += e_size + inode.key().len() + inode.value().unwrap().len();
+            size += self.inode_size(inode);
         }
         size
     }
-
-    pub(crate) fn num_children(&self) -> usize {
-        self.children.borrow().len()
+    // bytes an inode takes in a page, branch inodes have no value
+    fn inode_size(&self, inode: &Inode) -> usize {
+        self.page_elem_size() + inode.key().len() + inode.value().map_or(0, |v| v.len())
     }
+
     pub(crate) fn is_leaf(&self) -> bool {
         match *self.node_type.borrow() {
             NodeType::Branch => false,
             NodeType::Leaf => true,
         }
     }
-    // break up a node into some smaller nodes, return parent of split nodes
-    fn split(&mut self) -> Result<Option<Node>> {
-        let mut nodes = vec![self.clone()];
+    // break up a node into nodes that each fit a page
+    fn split(&mut self) -> Result<Vec<Node>> {
+        let mut nodes = vec![];
         let mut node = self.clone();
+        // appends only ever land in the last piece, leave the others full
+        let full = match &self.bucket().tail {
+            Some(tail) => Rc::ptr_eq(&tail.0, &self.0),
+            None => false,
+        };
         loop {
-            let new_node = node.break_up()?;
+            let new_node = node.break_up(full)?;
             nodes.push(node);
             match new_node {
                 Some(n) => {
-                    node = n.clone();
+                    node = n;
                 }
                 // nothing to break
                 None => break,
             }
         }
-        let parent = match self.parent() {
-            Some(p) => {
-                // remove borrow
-                {
-                    let mut children = p.children.borrow_mut();
-                    let index = children.iter().position(|ch| Rc::ptr_eq(self, ch));
-                    // remove this old node from parent
-                    if let Some(i) = index {
-                        children.remove(i);
-                    }
-                    // insert splitted new nodes to children of parent
-                    for node in nodes {
-                        *node.parent.borrow_mut() = WeakNode::from(&p);
-                        children.push(node);
-                    }
-                }
-                p
-            }
-            None => {
-                let p = Node::default();
-                *p.children.borrow_mut() = nodes;
-                for child in p.children.borrow_mut().iter_mut() {
-                    *child.parent.borrow_mut() = WeakNode::from(&p);
-                }
-                p
-            }
-        };
-        Ok(Some(parent))
+        Ok(nodes)
     }
 
-    // split a node into two nodes
-    fn break_up(&mut self) -> Result<Option<Node>> {
-        if self.fit_page_size() {
+    // split a node into two nodes, filling the first one up when full
+    fn break_up(&mut self, full: bool) -> Result<Option<Node>> {
+        // do not need to break up this node
+        if self.inodes.borrow().len() <= Self::MIN_KEY * 2 || self.fit_page_size() {
             return Ok(None);
         }
-        {
-            let inodes = self.inodes.borrow_mut();
-            // do not need to break up this node
-            if inodes.len() <= Self::MIN_KEY {
-                return Ok(None);
-            }
-        }
-        let mut fill_percent = self.bucket().fill_percent;
+        let mut fill_percent = match full {
+            true => Bucket::MAX_FILL_PERCENT,
+            false => self.bucket().fill_percent,
+        };
         // bound fill_percent
         if fill_percent > Bucket::MAX_FILL_PERCENT {
             fill_percent = Bucket::MAX_FILL_PERCENT;
@@ -164,7 +132,20 @@ impl Node {
         let threshold = ((page_size as f64) * fill_percent) as usize;
         let (index, _) = self.split_index(threshold);
 
-        let new_node = Node::new(self.bucket.clone(), NodeType::Leaf);
+        // splitting the root, the new parent becomes the root of the bucket
+        let parent = match self.parent() {
+            Some(p) => p,
+            None => {
+                let p = Node::new(self.bucket.clone(), NodeType::Branch);
+                p.children.borrow_mut().push(self.clone());
+                *self.parent.borrow_mut() = WeakNode::from(&p);
+                self.bucket_mut().root = Some(p.clone());
+                p
+            }
+        };
+        let new_node = Node::new(self.bucket.clone(), *self.node_type.borrow());
+        *new_node.parent.borrow_mut() = WeakNode::from(&parent);
+        parent.children.borrow_mut().push(new_node.clone());
         // move some inodes to new node
         let inodes: Vec<Inode> = self.inodes.borrow_mut().drain(index..).collect();
         *new_node.inodes.borrow_mut() = inodes;
@@ -175,12 +156,11 @@ impl Node {
     fn split_index(&self, threshold: usize) -> (usize, usize) {
         let mut index = 0;
         let mut size = Page::page_header_size();
-        let elem_size = self.page_elem_size();
         let inodes = self.inodes.borrow();
         let len = inodes.len() - Self::MIN_KEY;
         for (i, inode) in inodes.iter().enumerate().take(len) {
             index = i;
-            let e_size = elem_size + inode.key().len() + inode.value().unwrap().len();
+            let e_size = self.inode_size(inode);
             // have minimum number of keys
             if index >= Self::MIN_KEY && size + e_size > threshold {
                 break;
@@ -193,10 +173,9 @@ impl Node {
     fn fit_page_size(&self) -> bool {
         let head_size = Page::page_header_size();
         let mut size = head_size;
-        let elem_size = self.page_elem_size();
         let page_size = self.page_size() as usize;
         for inode in self.inodes.borrow().iter() {
-            size += elem_size + inode.key().len() as usize + inode.value().unwrap().len();
+            size += self.inode_size(inode);
             if size >= page_size {
                 return false;
             }
@@ -204,6 +183,19 @@ impl Node {
         true
     }
 
+    // the last child of its parent, and so on up to the root
+    pub(crate) fn is_rightmost(&self) -> bool {
+        let mut node = self.clone();
+        while let Some(p) = node.parent() {
+            let last = p.inodes.borrow().last().and_then(|i| i.page_id());
+            if last != Some(node.page_id()) {
+                return false;
+            }
+            node = p;
+        }
+        true
+    }
+
     pub(crate) fn page_id(&self) -> u64 {
         *self.page_id.borrow()
     }
@@ -211,45 +203,36 @@ impl Node {
     pub(crate) fn put(
         &mut self,
         old: &[u8],
-        key: &[u8],
+        key: impl Into<Entry>,
         value: &[u8],
-        _page_id: PageId,
+        page_id: PageId,
         flags: u32,
     ) {
-        let node = self;
-        let mut inodes = node.inodes.borrow_mut();
-        let (found, index) = match inodes.binary_search_by(|inode| inode.key().as_slice().cmp(old))
-        {
-            Ok(i) => (true, i),
-            Err(i) => (false, i),
-        };
-        // old key does not found, insert new inode
-        if !found {
-            inodes.insert(
-                index,
-                Inode::from(LeafINode {
-                    key: key.to_vec(),
-                    value: value.to_vec(),
-                    flags,
-                }),
-            );
+        let inode = if self.is_leaf() {
+            Inode::from(LeafINode {
+                key: key.into(),
+                value: value.into(),
+                flags,
+            })
         } else {
-            let inode = &mut inodes[index];
-            match &mut inode.0 {
-                Either::Right(l) => {
-                    l.key = key.to_vec();
-                    l.value = value.to_vec();
-                    l.flags = flags
-                }
-                _ => unreachable!(),
-            }
+            Inode::from(BranchINode {
+                key: key.into(),
+                page_id,
+                flags,
+            })
+        };
+        let mut inodes = self.inodes.borrow_mut();
+        match search_keys(&inodes, old, |inode| inode.key()) {
+            Ok(i) => inodes[i] = inode,
+            // old key does not found, insert new inode
+            Err(i) => inodes.insert(i, inode),
         };
     }
     // read page to node
     pub fn read(&mut self, p: &Page) -> Result<()> {
         *self.page_id.borrow_mut() = p.id;
         *self.node_type.borrow_mut() = match p.page_type {
-            Page::LEAF_PAGE => NodeType::Leaf,
+            Page::LEAF_PAGE | Page::FIXED_LEAF_PAGE => NodeType::Leaf,
             Page::BRANCH_PAGE => NodeType::Branch,
             _ => panic!("invalid page type"),
         };
@@ -259,23 +242,22 @@ impl Node {
                 .iter()
                 .map(|b| {
                     Inode::from(BranchINode {
-                        key: b.key().to_vec(),
+                        key: b.key().into(),
                         page_id: b.id,
                         flags: 0,
                     })
                 })
                 .collect(),
-            NodeType::Leaf => p
-                .leaf_elements()?
-                .iter()
-                .map(|f| {
-                    Inode::from(LeafINode {
-                        key: f.key().to_vec(),
-                        value: f.value().to_vec(),
-                        flags: 0,
-                    })
+            NodeType::Leaf => (0..p.count as usize)
+                .map(|i| {
+                    let (key, value, flags) = p.leaf_at(i)?;
+                    Ok(Inode::from(LeafINode {
+                        key: key.into(),
+                        value: value.into(),
+                        flags,
+                    }))
                 })
-                .collect(),
+                .collect::<Result<_>>()?,
         });
         self.key.replace(if !self.inodes.borrow().is_empty() {
             let key = self.inodes.borrow()[0].key().clone();
@@ -288,10 +270,13 @@ impl Node {
     // write node to page
     pub fn write(&self, p: &mut Page) -> Result<()> {
         let node = self;
-        p.page_type = match *node.node_type.borrow() {
-            NodeType::Branch => Page::BRANCH_PAGE,
-            NodeType::Leaf => Page::LEAF_PAGE,
+        let width = self.fixed_width();
+        p.page_type = match (*node.node_type.borrow(), width) {
+            (NodeType::Branch, _) => Page::BRANCH_PAGE,
+            (NodeType::Leaf, None) => Page::LEAF_PAGE,
+            (NodeType::Leaf, Some(_)) => Page::FIXED_LEAF_PAGE,
         };
+        p.value_width = width.unwrap_or(0) as u8;
 
         let inodes = node.inodes.borrow_mut();
         if inodes.len() >= u16::MAX as usize {
@@ -305,10 +290,32 @@ impl Node {
         let mut addr = unsafe {
             // offset to write key and value
             // memory: element element .... key value
-            let offset = self.page_elem_size() * inodes.len();
+            // or on a fixed leaf: element element .... value value .... key
+            let offset = (self.page_elem_size() + width.unwrap_or(0)) * inodes.len();
             p.ptr_mut().add(offset)
         };
         drop(inodes);
+        if let Some(width) = width {
+            let values = p.fixed_values_ptr() as *mut u8;
+            let leaves = p.fixed_elements_mut()?;
+            for (i, inode) in node.inodes.borrow().iter().enumerate() {
+                let elem = &mut leaves[i];
+                let ptr = elem as *const FixedLeafElement as *const u8;
+                let value = inode.value().ok_or(RoltError::InvalidInode)?;
+                // the bucket refuses other values, so this is a bug
+                if value.len() != width || inode.flags() != 0 {
+                    return Err!(RoltError::InvalidInode);
+                }
+                elem.pos = unsafe { addr.sub(ptr as usize) } as u32;
+                elem.k_size = inode.key().len() as u32;
+                unsafe {
+                    copy_nonoverlapping(value.as_ptr(), values.add(i * width), width);
+                    copy_nonoverlapping(inode.key().as_ptr(), addr, inode.key().len());
+                    addr = addr.add(inode.key().len());
+                }
+            }
+            return Ok(());
+        }
         match *node.node_type.borrow() {
             NodeType::Branch => {
                 let branches = p.branch_elements_mut()?;
@@ -330,6 +337,7 @@ impl Node {
                 for (i, inode) in node.inodes.borrow().iter().enumerate() {
                     let elem = &mut leaves[i];
                     let ptr = elem as *const LeafPageElement as *const u8;
+                    elem.flags = inode.flags();
                     elem.pos = unsafe { addr.sub(ptr as usize) } as u32;
                     elem.k_size = inode.key().len() as u32;
                     let value = inode.value().ok_or(RoltError::InvalidInode)?;
@@ -349,49 +357,72 @@ impl Node {
     fn page_elem_size(&self) -> usize {
         match *self.node_type.borrow() {
             NodeType::Branch => BranchPageElement::SIZE,
+            NodeType::Leaf if self.fixed_width().is_some() => FixedLeafElement::SIZE,
             NodeType::Leaf => LeafPageElement::SIZE,
         }
     }
+    // value width of a leaf written as a fixed leaf page
+    fn fixed_width(&self) -> Option<usize> {
+        match *self.node_type.borrow() {
+            NodeType::Leaf => self.bucket().value_width.map(|w| w as usize),
+            NodeType::Branch => None,
+        }
+    }
     fn page_size(&self) -> u64 {
         self.bucket().tx().unwrap().db().unwrap().page_size()
     }
     // write nodes to dirty pages
     pub(crate) fn spill(&mut self) -> Result<()> {
-        {
-            // spill children
-            let mut children = self.children.borrow_mut();
-            children.sort_by(|a, b| a.inodes.borrow()[0].key().cmp(b.inodes.borrow()[0].key()));
-            for child in children.iter_mut() {
-                child.spill()?;
+        if *self.spilled.borrow() {
+            return Ok(());
+        }
+        // spill children, they may add siblings to this node while splitting
+        let mut children = self.children.borrow().clone();
+        children.sort_by_cached_key(|c| c.inodes.borrow().first().map(|i| i.key().clone()));
+        for child in children.iter_mut() {
+            child.spill()?;
+        }
+        self.children.borrow_mut().clear();
+
+        // a node emptied by deletes is dropped from its parent instead of
+        // being written
+        if self.inodes.borrow().is_empty() {
+            if let Some(mut p) = self.parent() {
+                let id = self.page_id();
+                p.inodes.borrow_mut().retain(|i| i.page_id() != Some(id));
+                p.remove_child(self);
+                self.bucket_mut().nodes.remove(&id);
+                return self.free();
             }
-            children.clear();
+            // a root left without children is an empty leaf
+            *self.node_type.borrow_mut() = NodeType::Leaf;
         }
 
-        let mut nodes = match self.split()? {
-            None => vec![self.clone()],
-            Some(p) => p.children.borrow().clone(),
-        };
+        let nodes = self.split()?;
         let b = self.bucket_mut();
         let tx = b.tx()?;
         let db = tx.db()?;
-        for node in nodes.iter_mut() {
-            let id = *node.page_id.borrow_mut();
+        for node in nodes.iter() {
+            let id = node.page_id();
             // skip meta pages
             if id > 0 {
                 let mut free_list = db.free_list.write();
-                let mut p = tx.page(id)?;
-                let page = &mut *p;
+                let p = tx.page(id)?;
                 // free old page
                 // probably free page id 0 or 1
-                free_list.free(tx.id(), page)?;
+                free_list.free(tx.id(), &p)?;
                 *node.page_id.borrow_mut() = 0;
             }
-            // find a free page for this node
-            let mut ptr = tx.allocate(node.size() as u64)?;
-            let page = unsafe { &mut **ptr };
+        }
+        // find free pages for the split nodes in one run
+        let sizes: Vec<u64> = nodes.iter().map(|n| n.size() as u64).collect();
+        let pages = tx.allocate_run(&sizes)?;
+        for (node, mut ptr) in nodes.iter().zip(pages) {
+            let page = &mut *ptr;
             // write node to page
             *node.page_id.borrow_mut() = page.id;
             node.write(page)?;
+            *node.spilled.borrow_mut() = true;
 
             // parent inodes
             if let Some(mut p) = node.parent() {
@@ -399,14 +430,15 @@ impl Node {
                     None => node.inodes.borrow()[0].key().clone(),
                     Some(k) => k.clone(),
                 };
-                p.put(&key, node.inodes.borrow()[0].key(), &[], node.page_id(), 0);
-                *node.key.borrow_mut() = Some(key);
+                let first = node.inodes.borrow()[0].key().clone();
+                p.put(&key, first.clone(), &[], node.page_id(), 0);
+                *node.key.borrow_mut() = Some(first);
             }
         }
 
         // if root node split and create a new root, we spill new root
         if let Some(p) = self.parent() {
-            if *p.page_id.borrow() == 0 {
+            if p.page_id() == 0 {
                 self.children.borrow_mut().clear();
                 *self = p;
                 return self.spill();
@@ -419,165 +451,98 @@ impl Node {
         let n = self.clone();
         let id = {
             let inodes = self.inodes.borrow();
-            let inode = inodes.get(index).ok_or(anyhow!("inode index not valid"))?;
+            let inode = inodes
+                .get(index)
+                .ok_or(RoltError::from("inode index not valid"))?;
             inode.page_id().unwrap()
         };
         let b = { self.bucket_mut() };
         Ok(b.node(id, WeakNode::from(&n)))
     }
 
+    // merge a node that lost keys into a sibling once it's under a quarter
+    // of a page, then look at the parent it took a key from
     pub(crate) fn rebalance(&mut self) -> Result<()> {
-        if !self.unbalanced {
+        if !self.unbalanced.replace(false) {
             return Ok(());
         }
-        // self.unbalanced = false;
-        // this node is root
-        if self.parent().is_none() {
-            let mut inodes = self.inodes.borrow_mut();
-            // root node is branch and only has one inode
-            if !self.is_leaf() && inodes.len() == 1 {
-                // move up child
-                let mut child = self
-                    .bucket_mut()
-                    .node(inodes[0].page_id().unwrap(), WeakNode::from(self));
-
-                *self.node_type.borrow_mut() = *child.node_type.borrow();
-                *inodes = child.inodes.borrow_mut().drain(..).collect();
-                *self.children.borrow_mut() = child.children.borrow_mut().drain(..).collect();
-                {
-                    // assign new parent to children of new parent
-                    for inode in inodes.iter() {
-                        if let Some(child) =
-                            self.bucket_mut().nodes.get_mut(&inode.page_id().unwrap())
-                        {
-                            *child.parent.borrow_mut() = WeakNode::from(self);
-                        }
-                    }
-                }
-                *child.parent.borrow_mut() = WeakNode::new();
-                self.bucket_mut()
-                    .nodes
-                    .borrow_mut()
-                    .remove(&child.page_id.borrow());
-                // free child page
-                child.free()?;
-            }
-
+        let threshold = self.page_size() as usize / 4;
+        let min_keys = if self.is_leaf() { 1 } else { 2 };
+        if self.size() > threshold && self.inodes.borrow().len() > min_keys {
             return Ok(());
         }
-
-        // if node has no keys
-        if self.num_children() == 0 {
-            let key = self.0.key.borrow().clone().unwrap();
-            let parent = &mut self.parent().unwrap();
-            // remove this node from its parent
-
-            self.bucket_mut()
-                .nodes
-                .borrow_mut()
-                .remove(&self.page_id.borrow());
-            // remove this node from its parent
-            parent.remove(&key);
+        let mut parent = match self.parent() {
+            Some(p) => p,
+            None => return self.collapse_root(),
+        };
+        // appends past the last key find the rightmost leaf again
+        self.bucket_mut().tail = None;
+        // a node without keys goes away, spill does the same for one still
+        // empty when written
+        if self.inodes.borrow().is_empty() {
+            if let Some(key) = self.key.borrow().clone() {
+                parent.remove(&key);
+            }
             parent.remove_child(self);
+            self.bucket_mut().nodes.remove(&self.page_id());
             self.free()?;
-            parent.rebalance()?;
-            return Ok(());
+            return parent.rebalance();
         }
-
-        {
-            let (next_sibling, mut sibling) = match self.parent().unwrap().child_index(self) {
-                Some(i) => {
-                    if i == 0 {
-                        (true, self.next_sibling().unwrap())
-                    } else {
-                        (false, self.prev_sibling().unwrap())
-                    }
-                }
-                None => (false, self.prev_sibling().unwrap()),
-            };
-            // move sibling to this node
-            if next_sibling {
-                let bucket = self.bucket_mut();
-                // move children of sibling to this node
-                for page_id in sibling.inodes.borrow().iter().map(|i| i.page_id().unwrap()) {
-                    if let Some(child) = bucket.nodes.borrow_mut().get_mut(&page_id) {
-                        // remove this child from its parent
-                        child.parent().unwrap().remove_child(child);
-                        *child.parent.borrow_mut() = WeakNode::from(self);
-                        child
-                            .parent()
-                            .unwrap()
-                            .children
-                            .borrow_mut()
-                            .push(child.clone());
-                    }
-                }
-                // move inodes to this node
-                self.inodes
-                    .borrow_mut()
-                    .append(&mut *sibling.inodes.borrow_mut());
-                // remove sibling from parent
-                let parent = &mut self.parent().unwrap();
-                parent.remove(sibling.key.borrow().as_ref().unwrap());
-                parent.remove_child(&sibling);
-                // remove sibling from bucket
-                self.bucket_mut().nodes.remove(&sibling.page_id.borrow());
-                sibling.free()?;
-            } else {
-                // combine this node into sibling
-                for page_id in self.inodes.borrow().iter().map(|i| i.page_id().unwrap()) {
-                    if let Some(child) = self.bucket_mut().nodes.get_mut(&page_id) {
-                        let parent = &mut child.parent().unwrap();
-                        parent.remove_child(child);
-                        // parent is sibling
-                        *child.parent.borrow_mut() = WeakNode::from(&sibling);
-                        // assign child to new parent
-                        parent.children.borrow_mut().push(child.clone());
-                    }
+        // the only child has no sibling to merge with
+        let index = match parent.child_index(self) {
+            Some(i) if parent.inodes.borrow().len() > 1 => i,
+            _ => return Ok(()),
+        };
+        // the first child takes in its next sibling, others move into the
+        // previous one
+        let (into, mut from) = match index {
+            0 => (self.clone(), parent.child_at(1)?),
+            i => (parent.child_at(i - 1)?, self.clone()),
+        };
+        if !into.is_leaf() {
+            let ids: Vec<Option<PageId>> =
+                from.inodes.borrow().iter().map(|i| i.page_id()).collect();
+            for id in ids {
+                let id = id.ok_or(RoltError::InvalidInode)?;
+                if let Some(child) = self.bucket_mut().nodes.get(&id).cloned() {
+                    from.remove_child(&child);
+                    *child.parent.borrow_mut() = WeakNode::from(&into);
+                    into.children.borrow_mut().push(child);
                 }
-                sibling
-                    .inodes
-                    .borrow_mut()
-                    .append(&mut self.inodes.borrow_mut());
-                let parent = &mut self.parent().ok_or(anyhow!("parent not valid"))?;
-                parent.remove(self.key.borrow().as_ref().unwrap());
-                parent.remove_child(self);
-
-                self.bucket_mut().nodes.remove(&self.page_id.borrow());
-                self.free()?;
             }
-            self.parent()
-                .ok_or(anyhow!("parent not valid"))?
-                .rebalance()?;
         }
-        Ok(())
-    }
-    // return next sibling of this node
-    fn next_sibling(&self) -> Option<Node> {
-        match self.parent() {
-            // its root node
-            None => None,
-            Some(mut parent) => {
-                let index = parent.child_index(self);
-                match index {
-                    Some(i) => parent.child_at(i + 1).ok(),
-                    None => None,
-                }
-            }
+        into.inodes
+            .borrow_mut()
+            .append(&mut from.inodes.borrow_mut());
+        if let Some(key) = from.key.borrow().clone() {
+            parent.remove(&key);
         }
+        parent.remove_child(&from);
+        self.bucket_mut().nodes.remove(&from.page_id());
+        from.free()?;
+        parent.rebalance()
     }
-    // find previous sibling
-    fn prev_sibling(&self) -> Option<Node> {
-        match self.parent() {
-            None => None,
-            Some(mut parent) => {
-                let index = parent.child_index(self);
-                match index {
-                    None => None,
-                    Some(i) => parent.child_at(i - 1).ok(),
-                }
-            }
+
+    // a root branch left with one child takes over the child's inodes
+    fn collapse_root(&mut self) -> Result<()> {
+        if self.is_leaf() || self.inodes.borrow().len() != 1 {
+            return Ok(());
+        }
+        self.bucket_mut().tail = None;
+        let mut child = self.child_at(0)?;
+        *self.node_type.borrow_mut() = *child.node_type.borrow();
+        *self.inodes.borrow_mut() = child.inodes.borrow_mut().drain(..).collect();
+        self.remove_child(&child);
+        let grandchildren: Vec<Node> = child.children.borrow_mut().drain(..).collect();
+        for node in grandchildren.iter() {
+            *node.parent.borrow_mut() = WeakNode::from(self);
         }
+        self.children.borrow_mut().extend(grandchildren);
+        self.bucket_mut().nodes.remove(&child.page_id());
+        child.free()?;
+        // the root may be a lone branch again
+        self.unbalanced.set(true);
+        self.rebalance()
     }
     // get the index of given child node
     fn child_index(&self, child: &Node) -> Option<usize> {
@@ -601,13 +566,41 @@ impl Node {
     }
 
     // remove a key from node
-    fn remove(&mut self, key: &[u8]) {
+    pub(crate) fn remove(&mut self, key: &[u8]) {
         let mut inodes = self.inodes.borrow_mut();
-        if let Ok(i) = inodes.binary_search_by(|i| i.key().as_slice().cmp(key)) {
+        if let Ok(i) = search_keys(&inodes, key, |i| i.key()) {
             inodes.remove(i);
+            self.mark_unbalanced();
         };
     }
 
+    // have the commit-time rebalance look at this node
+    fn mark_unbalanced(&self) {
+        self.unbalanced.set(true);
+        self.bucket_mut().dirty = true;
+    }
+
+    // take out the inodes of sorted keys in one pass, missing keys are skipped
+    pub(crate) fn remove_sorted(&mut self, keys: &[&[u8]]) -> Vec<Inode> {
+        let mut removed = Vec::new();
+        let mut keys = keys.iter().peekable();
+        self.inodes.borrow_mut().retain(|inode| {
+            let key = &inode.key()[..];
+            while keys.next_if(|k| **k < key).is_some() {}
+            match keys.next_if(|k| **k == key) {
+                Some(_) => {
+                    removed.push(inode.clone());
+                    false
+                }
+                None => true,
+            }
+        });
+        if !removed.is_empty() {
+            self.mark_unbalanced();
+        }
+        removed
+    }
+
     fn parent(&self) -> Option<Node> {
         self.parent.borrow().upgrade()
     }
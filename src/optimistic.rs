@@ -0,0 +1,172 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+
+use crate::{
+    db::{WeakDB, DB},
+    error::{Result, RoltError},
+    snapshot::SnapshotHandle,
+    transaction::{Transaction, Txid},
+    watch::{ChangeEvent, ChangeKey},
+    Err,
+};
+
+// keys modified by recent commits, kept while some snapshot is pinned so
+// optimistic txs can tell whether anything they touched changed since
+#[derive(Debug, Default)]
+pub(crate) struct CommitHistory {
+    commits: VecDeque<(Txid, HashSet<ChangeKey>)>,
+}
+
+impl CommitHistory {
+    // remember the keys of a commit, forgetting commits no pinned snapshot
+    // predates. oldest is the txid of the oldest pinned snapshot
+    pub(crate) fn record(
+        &mut self,
+        oldest: Option<Txid>,
+        tx_id: Txid,
+        changes: &[(ChangeEvent, Vec<u8>)],
+    ) {
+        let oldest = match oldest {
+            Some(oldest) => oldest,
+            None => return self.commits.clear(),
+        };
+        while matches!(self.commits.front(), Some(&(id, _)) if id <= oldest) {
+            self.commits.pop_front();
+        }
+        let keys = changes
+            .iter()
+            .map(|(e, _)| (e.bucket.clone(), e.key.clone()))
+            .collect();
+        self.commits.push_back((tx_id, keys));
+    }
+
+    // whether a commit after base modified one of keys
+    fn modified<'a>(&self, base: Txid, mut keys: impl Iterator<Item = &'a ChangeKey>) -> bool {
+        let after: Vec<&HashSet<ChangeKey>> = self
+            .commits
+            .iter()
+            .filter(|(id, _)| *id > base)
+            .map(|(_, keys)| keys)
+            .collect();
+        keys.any(|k| after.iter().any(|keys| keys.contains(k)))
+    }
+}
+
+// a write tx that doesn't hold the writer while it runs. it reads from the
+// version it started at and buffers its writes, several of them can be open
+// at once. commit replays the writes in a regular write tx, unless a commit
+// made since the start modified a key this tx read or wrote, in which case
+// it fails with RoltError::Conflict and can be retried from scratch.
+// conflicts are tracked per key of top-level buckets, scans aren't covered
+#[derive(Debug)]
+pub struct OptimisticTransaction {
+    db: WeakDB,
+    // pins the base version and keeps the commit history recording
+    snapshot: SnapshotHandle,
+    base: Transaction,
+    reads: BTreeSet<ChangeKey>,
+    // None deletes the key
+    writes: BTreeMap<ChangeKey, Option<Vec<u8>>>,
+}
+
+impl DB {
+    // begin an optimistic write tx against the latest committed version,
+    // see OptimisticTransaction
+    pub fn optimistic_tx(&self) -> Result<OptimisticTransaction> {
+        if self.read_only {
+            return Err!(RoltError::ReadOnly);
+        }
+        let snapshot = self.snapshot_handle()?;
+        Ok(OptimisticTransaction {
+            db: WeakDB::from(self),
+            base: snapshot.tx()?,
+            snapshot,
+            reads: BTreeSet::new(),
+            writes: BTreeMap::new(),
+        })
+    }
+}
+
+impl OptimisticTransaction {
+    // txid of the version this tx reads from
+    pub fn base_id(&self) -> Txid {
+        self.snapshot.tx_id()
+    }
+
+    // value of key in a top-level bucket, this tx's own writes included
+    pub fn get<B: AsRef<[u8]>, K: AsRef<[u8]>>(
+        &mut self,
+        bucket: B,
+        key: K,
+    ) -> Result<Option<Vec<u8>>> {
+        let k = (bucket.as_ref().to_vec(), key.as_ref().to_vec());
+        if let Some(value) = self.writes.get(&k) {
+            return Ok(value.clone());
+        }
+        let value = self
+            .base
+            .bucket(&k.0)
+            .and_then(|b| b.get(&k.1).map(|v| v.to_vec()));
+        self.reads.insert(k);
+        Ok(value)
+    }
+
+    // buffer a put, the bucket is created at commit if it doesn't exist
+    pub fn put<B: AsRef<[u8]>, K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &mut self,
+        bucket: B,
+        key: K,
+        value: V,
+    ) -> Result<()> {
+        if key.as_ref().is_empty() {
+            return Err!("key must not be empty");
+        }
+        let k = (bucket.as_ref().to_vec(), key.as_ref().to_vec());
+        self.writes.insert(k, Some(value.as_ref().to_vec()));
+        Ok(())
+    }
+
+    // buffer a delete, deleting a missing key is not an error
+    pub fn delete<B: AsRef<[u8]>, K: AsRef<[u8]>>(&mut self, bucket: B, key: K) -> Result<()> {
+        let k = (bucket.as_ref().to_vec(), key.as_ref().to_vec());
+        self.writes.insert(k, None);
+        Ok(())
+    }
+
+    // check for conflicts and write the buffered changes in one write tx,
+    // returning its txid. fails while another write tx is open
+    pub fn commit(self) -> Result<Txid> {
+        let db = self.db.upgrade().ok_or(RoltError::TxNotValid)?;
+        let tx = db.tx(true)?;
+        let res = self.validate(&db).and_then(|_| self.apply(&tx));
+        if let Err(e) = res {
+            tx.rollback()?;
+            return Err(e);
+        }
+        tx.commit()?;
+        Ok(tx.id())
+    }
+
+    // drop the buffered changes, same as dropping the tx
+    pub fn rollback(self) {}
+
+    fn validate(&self, db: &DB) -> Result<()> {
+        let keys = self.reads.iter().chain(self.writes.keys());
+        if db.commits.lock().modified(self.base_id(), keys) {
+            return Err!(RoltError::Conflict);
+        }
+        Ok(())
+    }
+
+    fn apply(&self, tx: &Transaction) -> Result<()> {
+        for ((bucket, key), value) in self.writes.iter() {
+            match value {
+                Some(value) => tx.create_bucket_if_not_exist(bucket)?.put(key, value)?,
+                None if tx.bucket(bucket).is_some() => {
+                    tx.create_bucket_if_not_exist(bucket)?.delete(key)?
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -6,23 +6,31 @@ use std::{
 };
 
 use memoffset::offset_of;
+use parking_lot::Mutex;
 
 use crate::{
     error::{Result, RoltError},
     meta::Meta,
+    transaction::Txid,
     Err,
 };
 
 pub type PageType = u8;
 pub type PageId = u64;
 
+// on-disk structs are packed so they can be read at any address, like the
+// page of an inline bucket inside a leaf value. explicit padding keeps the
+// layout of the unpacked repr(C) structs they replace
 #[derive(Debug, Clone)]
-#[repr(C)]
+#[repr(C, packed)]
 pub(crate) struct Page {
     pub(crate) id: PageId,
     pub(crate) page_type: PageType,
+    // width of every value on a fixed leaf page, zero on other pages
+    pub(crate) value_width: u8,
     pub(crate) count: u16,
     pub(crate) overflow: u32, // 0 means page allocated in one page block, 1 means 2 blocks
+    pub(crate) txid: Txid,    // tx that last wrote the page
     pub(crate) ptr: PhantomData<u8>,
 }
 
@@ -31,6 +39,8 @@ impl Page {
     pub const LEAF_PAGE: PageType = 0x02; // data
     pub const META_PAGE: PageType = 0x03; // meta data
     pub const FREE_LIST_PAGE: PageType = 0x04; // free pages
+    pub const FIXED_LEAF_PAGE: PageType = 0x05; // data with fixed-width values
+    pub const BLOB_PAGE: PageType = 0x06; // chunk of a large value
 
     pub fn ptr(&self) -> *const u8 {
         &self.ptr as *const PhantomData<u8> as *const u8
@@ -43,7 +53,7 @@ impl Page {
     }
 
     pub(crate) fn is_leaf(&self) -> bool {
-        self.page_type == Self::LEAF_PAGE
+        matches!(self.page_type, Self::LEAF_PAGE | Self::FIXED_LEAF_PAGE)
     }
     // dereference meta data
     pub(crate) fn meta(&self) -> Result<&Meta> {
@@ -114,20 +124,173 @@ impl Page {
             Ok(&mut *elem)
         }
     }
+    // elements of a fixed leaf page, the values follow them in a dense array
+    pub fn fixed_elements(&self) -> Result<&[FixedLeafElement]> {
+        match self.page_type {
+            Page::FIXED_LEAF_PAGE => unsafe {
+                let addr = self.ptr() as *const FixedLeafElement;
+                Ok(from_raw_parts(addr, self.count as usize))
+            },
+            _ => Err!(RoltError::InvalidPageType),
+        }
+    }
+    pub fn fixed_elements_mut(&self) -> Result<&mut [FixedLeafElement]> {
+        unsafe {
+            let elem = self.fixed_elements()?;
+            let elem = elem as *const [FixedLeafElement] as *mut [FixedLeafElement];
+            Ok(&mut *elem)
+        }
+    }
+    // start of the value array of a fixed leaf page
+    pub(crate) fn fixed_values_ptr(&self) -> *const u8 {
+        unsafe { self.ptr().add(self.count as usize * FixedLeafElement::SIZE) }
+    }
+    // key, value and flags of element i of either kind of leaf page
+    pub(crate) fn leaf_at(&self, i: usize) -> Result<(&[u8], &[u8], u32)> {
+        if self.page_type == Page::FIXED_LEAF_PAGE {
+            let e = self
+                .fixed_elements()?
+                .get(i)
+                .ok_or(RoltError::InvalidInode)?;
+            let width = self.value_width as usize;
+            let value = unsafe { from_raw_parts(self.fixed_values_ptr().add(i * width), width) };
+            return Ok((e.key(), value, 0));
+        }
+        let e = self
+            .leaf_elements()?
+            .get(i)
+            .ok_or(RoltError::InvalidInode)?;
+        Ok((e.key(), e.value(), e.flags))
+    }
+    // header of a blob chunk, its data follows
+    pub(crate) fn blob_chunk(&self) -> &BlobChunk {
+        unsafe { &*(self.ptr() as *const BlobChunk) }
+    }
+    pub(crate) fn blob_chunk_mut(&mut self) -> &mut BlobChunk {
+        unsafe { &mut *(self.ptr_mut() as *mut BlobChunk) }
+    }
     // get a page from buffer
-    pub(crate) fn from_buf(buf: &[u8], id: PageId, page_size: u64) -> &Page {
-        unsafe { &*(buf[(id * page_size) as usize..].as_ptr() as *const u8 as *const Page) }
+    // the page id's header has to lie within buf, an offset that doesn't
+    // fit in usize is out of range rather than wrapped onto another page
+    pub(crate) fn from_buf(buf: &[u8], id: PageId, page_size: u64) -> Result<&Page> {
+        match Self::header_offset(buf.len(), id, page_size) {
+            Some(offset) => Ok(unsafe { &*(buf[offset..].as_ptr() as *const Page) }),
+            None => Err!(RoltError::PageOutOfRange(id)),
+        }
+    }
+    pub(crate) fn from_buf_mut(buf: &mut [u8], id: PageId, page_size: u64) -> Result<&mut Page> {
+        match Self::header_offset(buf.len(), id, page_size) {
+            Some(offset) => Ok(unsafe { &mut *(buf[offset..].as_mut_ptr() as *mut Page) }),
+            None => Err!(RoltError::PageOutOfRange(id)),
+        }
     }
-    pub(crate) fn from_buf_mut(buf: &mut [u8], id: PageId, page_size: u64) -> &mut Page {
-        unsafe { &mut *(buf[(id * page_size) as usize..].as_mut_ptr() as *mut Page) }
+    fn header_offset(len: usize, id: PageId, page_size: u64) -> Option<usize> {
+        page_offset(id, page_size)
+            .filter(|offset| offset.saturating_add(Self::page_header_size()) <= len)
     }
-    pub(crate) fn from_buf_direct(buf: &[u8]) -> &Page {
+    pub(crate) fn from_buf_direct(buf: &[u8]) -> Result<&Page> {
         Self::from_buf(buf, 0, 0)
     }
+    // bytes occupied by header, elements and payload
+    pub(crate) fn used_bytes(&self) -> usize {
+        let header = Self::page_header_size();
+        match self.page_type {
+            Page::BRANCH_PAGE => {
+                let elems = self.branch_elements().unwrap_or(&[]);
+                header
+                    + elems
+                        .iter()
+                        .map(|e| BranchPageElement::SIZE + e.k_size as usize)
+                        .sum::<usize>()
+            }
+            Page::LEAF_PAGE => {
+                let elems = self.leaf_elements().unwrap_or(&[]);
+                header
+                    + elems
+                        .iter()
+                        .map(|e| LeafPageElement::SIZE + (e.k_size + e.v_size) as usize)
+                        .sum::<usize>()
+            }
+            Page::FIXED_LEAF_PAGE => {
+                let elems = self.fixed_elements().unwrap_or(&[]);
+                header
+                    + elems
+                        .iter()
+                        .map(|e| {
+                            FixedLeafElement::SIZE + e.k_size as usize + self.value_width as usize
+                        })
+                        .sum::<usize>()
+            }
+            Page::BLOB_PAGE => header + BlobChunk::SIZE + { self.blob_chunk().len } as usize,
+            Page::META_PAGE => header + size_of::<Meta>(),
+            Page::FREE_LIST_PAGE => {
+                let mut count = self.count as usize;
+                // count overflow, the real count is stored in the first elem
+                if count == u16::MAX as usize {
+                    count = self.free_list().map(|l| l[0] as usize + 1).unwrap_or(0);
+                }
+                header + count * size_of::<PageId>()
+            }
+            _ => header,
+        }
+    }
+    // describe this page, page_size is the size of one page block
+    pub(crate) fn info(&self, page_size: u64) -> PageInfo {
+        let total = (self.overflow as usize + 1) * page_size as usize;
+        let used = self.used_bytes();
+        PageInfo {
+            id: self.id,
+            page_type: PageKind::from(self.page_type),
+            count: self.count as usize,
+            overflow: self.overflow,
+            txid: self.txid,
+            used_bytes: used,
+            free_bytes: total.saturating_sub(used),
+        }
+    }
+}
+
+// kind of a page, decoded from its header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageKind {
+    Branch,
+    Leaf,
+    Meta,
+    FreeList,
+    Blob,
+    Unknown(PageType),
+}
+
+impl From<PageType> for PageKind {
+    fn from(t: PageType) -> Self {
+        match t {
+            Page::BRANCH_PAGE => PageKind::Branch,
+            Page::LEAF_PAGE | Page::FIXED_LEAF_PAGE => PageKind::Leaf,
+            Page::META_PAGE => PageKind::Meta,
+            Page::FREE_LIST_PAGE => PageKind::FreeList,
+            Page::BLOB_PAGE => PageKind::Blob,
+            t => PageKind::Unknown(t),
+        }
+    }
+}
+
+// layout and utilization of a single page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageInfo {
+    pub id: PageId,
+    pub page_type: PageKind,
+    // number of elements stored in the page
+    pub count: usize,
+    // number of extra page blocks following the first one
+    pub overflow: u32,
+    // tx that last wrote the page
+    pub txid: Txid,
+    pub used_bytes: usize,
+    pub free_bytes: usize,
 }
 
 #[derive(Debug)]
-#[repr(C)]
+#[repr(C, packed)]
 pub struct BranchPageElement {
     // offset to key
     pub(crate) pos: u32,
@@ -148,8 +311,10 @@ impl BranchPageElement {
 }
 
 #[derive(Debug)]
-#[repr(C)]
+#[repr(C, packed)]
 pub struct LeafPageElement {
+    // Bucket::FLAG when the value is a nested bucket
+    pub(crate) flags: u32,
     // offset to key and value
     pub(crate) pos: u32,
     pub(crate) k_size: u32,
@@ -174,31 +339,170 @@ impl LeafPageElement {
     }
 }
 
+// element of a fixed leaf page, flags and value size are implied by the page
+#[derive(Debug)]
+#[repr(C, packed)]
+pub struct FixedLeafElement {
+    // offset to key
+    pub(crate) pos: u32,
+    pub(crate) k_size: u32,
+}
+
+impl FixedLeafElement {
+    pub(crate) const SIZE: usize = size_of::<Self>();
+    pub fn key(&self) -> &[u8] {
+        unsafe {
+            let pos = self.pos as usize;
+            let addr = (self as *const FixedLeafElement as *const u8).add(pos);
+            from_raw_parts(addr, self.k_size as usize)
+        }
+    }
+}
+
+// start of a blob page, chunks of one blob are chained through next
+#[derive(Debug)]
+#[repr(C, packed)]
+pub(crate) struct BlobChunk {
+    // page of the following chunk, 0 on the last one
+    pub(crate) next: PageId,
+    // bytes of data in this chunk
+    pub(crate) len: u64,
+}
+
+impl BlobChunk {
+    pub(crate) const SIZE: usize = size_of::<Self>();
+    pub(crate) fn data(&self) -> &[u8] {
+        unsafe {
+            let addr = (self as *const BlobChunk as *const u8).add(Self::SIZE);
+            from_raw_parts(addr, self.len as usize)
+        }
+    }
+}
+
+// a dirty page, backed by words so the free list ids in it are aligned
 #[derive(Debug, Clone)]
 pub(crate) struct VPage {
-    data: Vec<u8>,
+    data: Vec<u64>,
 }
 
 impl VPage {
     pub(crate) fn new(size: usize) -> Self {
         Self {
-            data: vec![0u8; size],
+            data: vec![0u64; size.div_ceil(size_of::<u64>())],
         }
     }
     pub(crate) fn data_ptr(&self) -> *const u8 {
-        self.data.as_ptr()
+        self.data.as_ptr() as *const u8
+    }
+    pub(crate) fn size(&self) -> usize {
+        self.data.len() * size_of::<u64>()
+    }
+}
+
+// recycles the buffers of single-page VPages across transactions, so write
+// heavy workloads don't allocate a fresh buffer for every dirty page
+#[derive(Debug)]
+pub(crate) struct PagePool {
+    page_size: usize,
+    bufs: Mutex<Vec<Vec<u64>>>,
+}
+
+impl PagePool {
+    // buffers kept around at most, the rest are freed
+    const MAX_BUFFERS: usize = 256;
+    pub(crate) fn new(page_size: usize) -> Self {
+        Self {
+            page_size,
+            bufs: Mutex::new(Vec::new()),
+        }
+    }
+    // a zeroed page of size bytes, and whether its buffer came from the pool
+    pub(crate) fn get(&self, size: usize) -> (VPage, bool) {
+        if size == self.page_size {
+            if let Some(data) = self.bufs.lock().pop() {
+                return (VPage { data }, true);
+            }
+        }
+        (VPage::new(size), false)
+    }
+    // hand back a page once it's written, multi-page buffers are dropped
+    pub(crate) fn put(&self, mut page: VPage) {
+        if page.size() != self.page_size || self.bufs.lock().len() >= Self::MAX_BUFFERS {
+            return;
+        }
+        page.data.fill(0);
+        self.bufs.lock().push(page.data);
     }
 }
 
 impl Deref for VPage {
     type Target = Page;
     fn deref(&self) -> &Self::Target {
-        unsafe { &*(self.data.as_ptr() as *const Page) }
+        unsafe { &*(self.data_ptr() as *const Page) }
     }
 }
 
 impl DerefMut for VPage {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { &mut *(self.data.as_mut_ptr() as *mut Page) }
+        unsafe { &mut *(self.data.as_mut_ptr() as *mut u8 as *mut Page) }
+    }
+}
+
+// byte offset of a page in the file, None when it doesn't fit in usize as
+// happens for large files on 32-bit targets
+pub(crate) fn page_offset(id: PageId, page_size: u64) -> Option<usize> {
+    id.checked_mul(page_size)
+        .and_then(|offset| usize::try_from(offset).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bucket::IBucket;
+
+    #[test]
+    fn test_layout() {
+        // on-disk layout is the same as before the structs were packed
+        assert_eq!(Page::page_header_size(), 24);
+        assert_eq!(std::mem::offset_of!(Page, count), 10);
+        assert_eq!(std::mem::offset_of!(Page, txid), 16);
+        assert_eq!(BranchPageElement::SIZE, 16);
+        assert_eq!(LeafPageElement::SIZE, 16);
+        assert_eq!(FixedLeafElement::SIZE, 8);
+        assert_eq!(IBucket::SIZE, 16);
+        assert_eq!(std::mem::offset_of!(Meta, free_list), 24);
+        assert_eq!(size_of::<Meta>(), 72);
+    }
+
+    #[test]
+    fn test_unaligned_leaf() {
+        let header = Page::page_header_size();
+        let mut buf = vec![0u8; 1 + header + LeafPageElement::SIZE + 4];
+        // a leaf starting at an odd address, like an inline bucket's page
+        let page = &mut buf[1..];
+        page[8] = Page::LEAF_PAGE;
+        page[10] = 1;
+        let elem = header;
+        page[elem + 4] = LeafPageElement::SIZE as u8;
+        page[elem + 8] = 2;
+        page[elem + 12] = 2;
+        page[elem + 16..].copy_from_slice(b"kkvv");
+        let p = Page::from_buf_direct(&buf[1..]).unwrap();
+        let e = &p.leaf_elements().unwrap()[0];
+        assert_eq!((e.key(), e.value()), (&b"kk"[..], &b"vv"[..]));
+    }
+
+    #[test]
+    fn page_out_of_range() {
+        let buf = vec![0u8; 2 * 4096];
+        assert!(Page::from_buf(&buf, 1, 4096).is_ok());
+        // past the end, an offset overflowing u64 and a truncated header
+        for (id, page_size) in [(2, 4096), (u64::MAX, 4096), (1, 8192 - 8)] {
+            assert!(matches!(
+                Page::from_buf(&buf, id, page_size),
+                Err(RoltError::PageOutOfRange(found)) if found == id
+            ));
+        }
+        assert!(Page::from_buf_direct(&buf[..8]).is_err());
     }
 }
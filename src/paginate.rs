@@ -0,0 +1,42 @@
+use crate::{bucket::Bucket, error::Result};
+
+// one page of entries returned by Bucket::page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryPage<'a> {
+    pub entries: Vec<(&'a [u8], &'a [u8])>,
+    // pass as after to get the following page, None on the last page
+    pub next: Option<Vec<u8>>,
+}
+
+impl Bucket {
+    // up to limit entries with keys after the given one, or from the first
+    // key when after is None. nested buckets and expired keys are skipped
+    pub fn page(&self, after: Option<&[u8]>, limit: usize) -> Result<EntryPage<'_>> {
+        let mut c = self.cursor();
+        let mut pair = match after {
+            None => c.first()?,
+            Some(after) => {
+                let pair = c.seek(after)?;
+                match pair.key() {
+                    Some(key) if key == after => c.next()?,
+                    _ => pair,
+                }
+            }
+        };
+        let mut entries = Vec::new();
+        let mut next = None;
+        while let Some((key, value)) = pair.key().zip(pair.value()) {
+            if !pair.is_bucket() && !self.expired(key) {
+                if entries.len() == limit {
+                    // there is more, resume after the last entry returned
+                    let last = entries.last().map(|e: &(&[u8], &[u8])| e.0);
+                    next = Some(last.or(after).unwrap_or_default().to_vec());
+                    break;
+                }
+                entries.push((key, value));
+            }
+            pair = c.next()?;
+        }
+        Ok(EntryPage { entries, next })
+    }
+}
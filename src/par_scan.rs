@@ -0,0 +1,114 @@
+use std::collections::HashSet;
+
+use either::Either;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::{
+    bucket::Bucket,
+    error::{Result, RoltError},
+    page::{Page, PageId},
+    transaction::ReadTransaction,
+    Err,
+};
+
+impl ReadTransaction {
+    // call f with every key and value of a top-level bucket, splitting its
+    // tree at branch boundaries into about ranges subtrees that are scanned
+    // on the rayon pool when that feature is on. keys arrive in order within
+    // a subtree, subtrees run concurrently. nested buckets, blobs and
+    // expired keys are skipped
+    pub fn par_scan<N, F>(&self, bucket: N, ranges: usize, f: F) -> Result<()>
+    where
+        N: AsRef<[u8]>,
+        F: Fn(&[u8], &[u8]) + Sync,
+    {
+        let b = match self.0.bucket(bucket) {
+            Some(b) => b,
+            None => return Err!(RoltError::BucketNotFound),
+        };
+        let expired: HashSet<Vec<u8>> = b.expired_keys()?.into_iter().collect();
+        let scan = Scan {
+            mmap: self.0.mmap(),
+            page_size: self.0.page_size(),
+            num_pages: self.0.meta().num_pages,
+            expired,
+            f,
+        };
+        if b.root_id() == 0 {
+            // inline bucket, its only page lives in the parent's leaf
+            return match b.page_node(0)?.upgrade() {
+                Either::Left(p) => scan.leaf(p),
+                Either::Right(_) => Err!("read tx holds a dirty node"),
+            };
+        }
+        let parts = scan.split(b.root_id(), ranges.max(1))?;
+        #[cfg(feature = "rayon")]
+        return parts.par_iter().try_for_each(|&id| scan.subtree(id));
+        #[cfg(not(feature = "rayon"))]
+        parts.iter().try_for_each(|&id| scan.subtree(id))
+    }
+}
+
+// reads the pages of a read tx straight from the mapped file, which every
+// thread can share
+struct Scan<'a, F> {
+    mmap: &'a [u8],
+    page_size: u64,
+    num_pages: PageId,
+    expired: HashSet<Vec<u8>>,
+    f: F,
+}
+
+impl<F: Fn(&[u8], &[u8]) + Sync> Scan<'_, F> {
+    fn page(&self, id: PageId) -> Result<&Page> {
+        if id >= self.num_pages {
+            return Err!(RoltError::PageOutOfRange(id));
+        }
+        Page::from_buf(self.mmap, id, self.page_size)
+    }
+
+    // roots of subtrees covering the tree in key order, replacing branch
+    // pages by their children level by level until there are enough
+    fn split(&self, root: PageId, ranges: usize) -> Result<Vec<PageId>> {
+        let mut parts = vec![root];
+        while parts.len() < ranges {
+            let mut next = Vec::with_capacity(parts.len());
+            for &id in parts.iter() {
+                let p = self.page(id)?;
+                match p.page_type {
+                    Page::BRANCH_PAGE => next.extend(p.branch_elements()?.iter().map(|e| e.id)),
+                    _ => next.push(id),
+                }
+            }
+            if next.len() == parts.len() {
+                break;
+            }
+            parts = next;
+        }
+        Ok(parts)
+    }
+
+    fn subtree(&self, id: PageId) -> Result<()> {
+        let p = self.page(id)?;
+        match p.page_type {
+            Page::BRANCH_PAGE => p
+                .branch_elements()?
+                .iter()
+                .try_for_each(|e| self.subtree(e.id)),
+            _ if p.is_leaf() => self.leaf(p),
+            _ => Err!(RoltError::InvalidPageType),
+        }
+    }
+
+    fn leaf(&self, p: &Page) -> Result<()> {
+        for i in 0..p.count as usize {
+            let (key, value, flags) = p.leaf_at(i)?;
+            if flags & (Bucket::FLAG | Bucket::BLOB_FLAG) != 0 || self.expired.contains(key) {
+                continue;
+            }
+            (self.f)(key, value);
+        }
+        Ok(())
+    }
+}
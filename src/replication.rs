@@ -0,0 +1,347 @@
+use std::{
+    fs::{File, OpenOptions},
+    hash::Hasher,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use fnv::FnvHasher;
+use parking_lot::Mutex;
+
+use crate::{
+    bucket::Bucket,
+    db::DB,
+    error::{Result, RoltError},
+    transaction::Txid,
+    watch::{ChangeEvent, ChangeOp},
+    Err,
+};
+
+// bytes before a record body: body length (u32) and its fnv checksum (u64)
+const RECORD_HEADER_SIZE: usize = 12;
+
+// one key written by a committed tx, value is empty for deletes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    // names of the buckets from the top level down to the one holding key
+    pub path: Vec<Vec<u8>>,
+    pub key: Vec<u8>,
+    pub op: ChangeOp,
+    pub value: Vec<u8>,
+}
+
+// everything a committed write tx changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    pub tx_id: Txid,
+    pub entries: Vec<LogEntry>,
+}
+
+impl LogRecord {
+    // replay the record onto a follower in one write tx
+    pub fn apply(&self, db: &DB) -> Result<()> {
+        let tx = db.tx(true)?;
+        {
+            let mut root = tx.root.write();
+            for e in self.entries.iter() {
+                let b = walk(&mut root, &e.path)?;
+                match e.op {
+                    ChangeOp::Insert | ChangeOp::Update => b.put(&e.key, &e.value)?,
+                    ChangeOp::Delete => b.delete(&e.key)?,
+                    ChangeOp::CreateBucket => {
+                        if b.get_bucket(&e.key).is_none() {
+                            let flags = match e.value[..].try_into() {
+                                Ok(flags) => u32::from_le_bytes(flags),
+                                Err(_) => Bucket::FLAG,
+                            };
+                            b.create_nested(&e.key, flags & Bucket::ENGINE_FLAGS)?;
+                        }
+                    }
+                }
+            }
+        }
+        tx.commit()
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&self.tx_id.to_le_bytes());
+        body.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for e in self.entries.iter() {
+            body.push(match e.op {
+                ChangeOp::Insert => 0,
+                ChangeOp::Update => 1,
+                ChangeOp::Delete => 2,
+                ChangeOp::CreateBucket => 3,
+            });
+            body.extend_from_slice(&(e.path.len() as u32).to_le_bytes());
+            for field in e.path.iter().chain([&e.key, &e.value]) {
+                body.extend_from_slice(&(field.len() as u32).to_le_bytes());
+                body.extend_from_slice(field);
+            }
+        }
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + body.len());
+        record.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        record.extend_from_slice(&checksum(&body).to_le_bytes());
+        record.extend_from_slice(&body);
+        record
+    }
+
+    fn decode(mut body: &[u8]) -> Result<Self> {
+        let tx_id = u64::from_le_bytes(take(&mut body, 8)?.try_into()?);
+        let count = u32::from_le_bytes(take(&mut body, 4)?.try_into()?);
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let op = match take(&mut body, 1)?[0] {
+                0 => ChangeOp::Insert,
+                1 => ChangeOp::Update,
+                2 => ChangeOp::Delete,
+                3 => ChangeOp::CreateBucket,
+                op => return Err!(format!("unknown log op {}", op)),
+            };
+            let depth = u32::from_le_bytes(take(&mut body, 4)?.try_into()?);
+            let mut field = || -> Result<Vec<u8>> {
+                let len = u32::from_le_bytes(take(&mut body, 4)?.try_into()?);
+                Ok(take(&mut body, len as usize)?.to_vec())
+            };
+            // bounded by the body, a corrupt depth runs out of bytes first
+            let mut path = Vec::new();
+            for _ in 0..depth {
+                path.push(field()?);
+            }
+            let (key, value) = (field()?, field()?);
+            entries.push(LogEntry {
+                path,
+                key,
+                op,
+                value,
+            });
+        }
+        Ok(Self { tx_id, entries })
+    }
+}
+
+// the bucket at path below root, buckets missing on the follower are created
+fn walk<'a>(root: &'a mut Bucket, path: &[Vec<u8>]) -> Result<&'a mut Bucket> {
+    let mut b = root;
+    for name in path {
+        b = match b.get_bucket(name) {
+            Some(child) => unsafe { &mut *child },
+            None => b.create_nested(name, Bucket::FLAG)?,
+        };
+    }
+    Ok(b)
+}
+
+fn take<'a>(buf: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    if buf.len() < n {
+        return Err!("log record is truncated");
+    }
+    let (head, rest) = buf.split_at(n);
+    *buf = rest;
+    Ok(head)
+}
+
+fn checksum(body: &[u8]) -> u64 {
+    let mut hash = FnvHasher::default();
+    hash.write(body);
+    hash.finish()
+}
+
+// append-only file of committed write txs
+#[derive(Debug)]
+pub(crate) struct ReplicationLog {
+    path: PathBuf,
+    file: Mutex<File>,
+    // set once an append fails, the log has a gap from then on
+    broken: Arc<AtomicBool>,
+}
+
+impl ReplicationLog {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+            broken: Arc::new(AtomicBool::new(false)),
+        })
+    }
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+    pub(crate) fn broken(&self) -> bool {
+        self.broken.load(Ordering::Acquire)
+    }
+    // append the changes of a committed tx. the tx is durable already, so a
+    // failed append breaks the log instead of failing the commit, and no
+    // later record is written past the gap
+    pub(crate) fn append(&self, tx_id: Txid, changes: &[(ChangeEvent, Vec<u8>)]) {
+        if self.broken() {
+            return;
+        }
+        if self.write(tx_id, changes).is_err() {
+            self.broken.store(true, Ordering::Release);
+        }
+    }
+    // keys of the ttl index are left out, followers see expired keys as
+    // deletes once the leader sweeps them
+    fn write(&self, tx_id: Txid, changes: &[(ChangeEvent, Vec<u8>)]) -> Result<()> {
+        let entries: Vec<LogEntry> = changes
+            .iter()
+            .filter(|(e, _)| !e.is_ttl())
+            .map(|(e, value)| LogEntry {
+                path: e.path.clone(),
+                key: e.key.clone(),
+                op: e.op,
+                value: value.clone(),
+            })
+            .collect();
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let record = LogRecord { tx_id, entries }.encode();
+        let mut file = self.file.lock();
+        file.write_all(&record)?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
+
+// reads log records in commit order. once it runs out of records it can be
+// polled again to pick up txs committed since, which is how followers tail
+#[derive(Debug)]
+pub struct ReplicationStream {
+    file: File,
+    from_tx_id: Txid,
+    // the leader's broken flag, for streams opened through the leader
+    broken: Option<Arc<AtomicBool>>,
+}
+
+impl ReplicationStream {
+    // open a log written by a leader, skipping records older than from_tx_id
+    pub fn open<P: AsRef<Path>>(path: P, from_tx_id: Txid) -> Result<Self> {
+        Ok(Self {
+            file: File::open(path)?,
+            from_tx_id,
+            broken: None,
+        })
+    }
+
+    fn read_record(&mut self) -> Result<Option<LogRecord>> {
+        let start = self.file.stream_position()?;
+        let mut header = [0u8; RECORD_HEADER_SIZE];
+        if !self.read_full(&mut header)? {
+            self.file.seek(SeekFrom::Start(start))?;
+            return Ok(None);
+        }
+        let len = u32::from_le_bytes(header[..4].try_into()?) as usize;
+        let sum = u64::from_le_bytes(header[4..].try_into()?);
+        let mut body = vec![0u8; len];
+        // a record still being appended, retry from its start later
+        if !self.read_full(&mut body)? || checksum(&body) != sum {
+            self.file.seek(SeekFrom::Start(start))?;
+            return Ok(None);
+        }
+        LogRecord::decode(&body).map(Some)
+    }
+
+    // fill buf, false when the file ends first
+    fn read_full(&mut self, buf: &mut [u8]) -> Result<bool> {
+        let mut read = 0;
+        while read < buf.len() {
+            match self.file.read(&mut buf[read..])? {
+                0 => return Ok(false),
+                n => read += n,
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl Iterator for ReplicationStream {
+    type Item = Result<LogRecord>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.read_record() {
+                Ok(Some(r)) if r.tx_id < self.from_tx_id => continue,
+                // every record before the gap has been read
+                Ok(None)
+                    if self
+                        .broken
+                        .as_ref()
+                        .is_some_and(|b| b.load(Ordering::Acquire)) =>
+                {
+                    return Some(Err!(RoltError::ReplicationBroken))
+                }
+                Ok(r) => return r.map(Ok),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+impl DB {
+    // stream the records of the replication log starting at from_tx_id
+    pub fn replication_stream(&self, from_tx_id: Txid) -> Result<ReplicationStream> {
+        let log = self
+            .replication
+            .as_ref()
+            .ok_or(RoltError::from("replication log is not enabled"))?;
+        let mut stream = ReplicationStream::open(log.path(), from_tx_id)?;
+        stream.broken = Some(log.broken.clone());
+        Ok(stream)
+    }
+    // whether a commit failed to reach the replication log, followers
+    // need a fresh copy of the db once it is
+    pub fn replication_broken(&self) -> bool {
+        self.replication.as_ref().is_some_and(|log| log.broken())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::DBBuilder;
+
+    #[test]
+    fn failed_append_keeps_commit() {
+        let path = "./tests/broken_leader.db";
+        let log = "./tests/broken_leader.log";
+        for p in [path, log] {
+            let _ = std::fs::remove_file(p);
+        }
+        let db = DBBuilder::default()
+            .replication_log(log)
+            .open(path)
+            .unwrap();
+        let tx = db.tx(true).unwrap();
+        tx.create_bucket("test").unwrap().put("a", "1").unwrap();
+        tx.commit().unwrap();
+        drop(tx);
+
+        // appends through a read-only handle fail
+        *db.replication.as_ref().unwrap().file.lock() = File::open(log).unwrap();
+        let tx = db.tx(true).unwrap();
+        tx.create_bucket_if_not_exist("test")
+            .unwrap()
+            .put("b", "2")
+            .unwrap();
+        tx.commit().unwrap();
+        drop(tx);
+        assert!(db.replication_broken());
+        assert!(!db.poisoned());
+
+        let tx = db.tx(false).unwrap();
+        assert_eq!(tx.bucket("test").unwrap().get("b"), Some(&b"2"[..]));
+        drop(tx);
+
+        let mut stream = db.replication_stream(0).unwrap();
+        assert!(stream.next().unwrap().is_ok());
+        let err = stream.next().unwrap().unwrap_err();
+        assert!(matches!(err, RoltError::ReplicationBroken));
+    }
+}
@@ -0,0 +1,303 @@
+use std::{collections::HashSet, fs::File, mem::size_of, path::Path};
+
+use crate::{
+    blob::parse_descriptor,
+    bucket::{Bucket, IBucket},
+    check::{elements_fit, guess_page_size, leaf_entries, sorted, CheckError, IssueKind},
+    db::{map_file, DB},
+    error::Result,
+    meta::Meta,
+    page::{page_offset, Page, PageId},
+    transaction::Txid,
+    Err,
+};
+
+// bucket of the new file that takes keys of leaf pages the tree no longer
+// reaches
+pub const LOST_AND_FOUND: &[u8] = b"lost+found";
+
+// what a salvage recovered and what it had to leave behind
+#[derive(Debug, Clone, Default)]
+pub struct SalvageReport {
+    // txid of the meta page the tree was read from, None if neither had the
+    // magic number
+    pub tx_id: Option<Txid>,
+    pub buckets: u64,
+    pub keys: u64,
+    // of keys, those found on intact leaf pages outside the tree and put in
+    // lost+found
+    pub orphan_keys: u64,
+    // pages and values that couldn't be copied
+    pub skipped: Vec<CheckError>,
+}
+
+// copy every key that can still be read from the damaged file at src into
+// a new db at dst. the tree is followed from the newest meta page with the
+// magic number, whatever its checksum, skipping pages that fail validation.
+// afterwards the rest of the file is scanned for intact leaf pages that are
+// neither reached nor free, whose keys go to lost+found, newer pages first
+pub fn salvage<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Result<SalvageReport> {
+    let dst = dst.as_ref();
+    if dst.exists() {
+        return Err!("salvage target already exists");
+    }
+    let file = File::open(src)?;
+    let mmap = map_file(&file)?;
+    let page_size = guess_page_size(&mmap);
+    let mut s = Salvager {
+        mmap: &mmap,
+        page_size,
+        num_pages: mmap.len() as u64 / page_size,
+        visited: HashSet::new(),
+        report: SalvageReport::default(),
+    };
+    let meta = s.best_meta();
+    let free = meta.as_ref().map(|m| s.free_pages(m)).unwrap_or_default();
+
+    let db = DB::open(dst)?;
+    let tx = db.tx(true)?;
+    {
+        let mut root = tx.root.write();
+        if let Some(meta) = meta {
+            tx.set_application_id(meta.application_id)?;
+            s.report.tx_id = Some(meta.tx_id);
+            s.copy_tree(meta.root.root, &[], &mut root);
+        }
+        s.copy_orphans(&free, &mut root)?;
+    }
+    tx.commit()?;
+    Ok(s.report)
+}
+
+struct Salvager<'a> {
+    mmap: &'a [u8],
+    page_size: u64,
+    num_pages: PageId,
+    // pages already copied, so a damaged tree can't loop or copy twice
+    visited: HashSet<PageId>,
+    report: SalvageReport,
+}
+
+impl<'a> Salvager<'a> {
+    fn skip(&mut self, kind: IssueKind, id: PageId, path: &[Vec<u8>], message: &str) {
+        self.report
+            .skipped
+            .push(CheckError::new(kind, id, path, message));
+    }
+
+    // meta page with the magic number, a matching checksum and the highest
+    // txid, in that order of preference
+    fn best_meta(&self) -> Option<Meta> {
+        (0..2.min(self.num_pages))
+            .filter_map(|id| {
+                Page::from_buf(self.mmap, id, self.page_size)
+                    .and_then(Page::meta)
+                    .ok()
+            })
+            .filter(|m| m.has_magic())
+            .max_by_key(|m| (m.sum_matches(), { m.tx_id }))
+            .cloned()
+    }
+
+    // ids in the free list of meta, empty when it can't be read
+    fn free_pages(&self, meta: &Meta) -> HashSet<PageId> {
+        let id = meta.free_list;
+        let word = |i: usize| -> Option<u64> {
+            let start = page_offset(id, self.page_size)? + Page::page_header_size() + i * 8;
+            let bytes = self.mmap.get(start..start + size_of::<u64>())?;
+            Some(u64::from_ne_bytes(bytes.try_into().ok()?))
+        };
+        let p = match Page::from_buf(self.mmap, id, self.page_size) {
+            Ok(p) if id >= 2 && id < self.num_pages && p.page_type == Page::FREE_LIST_PAGE => p,
+            _ => return HashSet::new(),
+        };
+        let (count, begin) = match p.count {
+            // count overflow, the real count is stored in the first elem
+            u16::MAX => (word(0).unwrap_or(0) as usize, 1),
+            n => (n as usize, 0),
+        };
+        (begin..begin + count).map_while(word).collect()
+    }
+
+    // a page whose blocks are all in the file and not copied yet, marking
+    // them as copied
+    fn page(&mut self, id: PageId, path: &[Vec<u8>]) -> Option<&'a Page> {
+        let p = match Page::from_buf(self.mmap, id, self.page_size) {
+            Ok(p) if id >= 2 && id < self.num_pages => p,
+            _ => {
+                self.skip(IssueKind::OutOfRange, id, path, "page is out of range");
+                return None;
+            }
+        };
+        let last = id + p.overflow as PageId;
+        if last >= self.num_pages {
+            let message = "page overflows past the end of the file";
+            self.skip(IssueKind::OutOfRange, id, path, message);
+            return None;
+        }
+        if (id..=last).any(|block| self.visited.contains(&block)) {
+            let message = "page is referenced more than once";
+            self.skip(IssueKind::SharedPage, id, path, message);
+            return None;
+        }
+        self.visited.extend(id..=last);
+        Some(p)
+    }
+
+    // copy the tree rooted at id into dest
+    fn copy_tree(&mut self, id: PageId, path: &[Vec<u8>], dest: &mut Bucket) {
+        let p = match self.page(id, path) {
+            Some(p) => p,
+            None => return,
+        };
+        let span = (p.overflow as usize + 1) * self.page_size as usize;
+        if !elements_fit(p, span) {
+            let message = "elements run past the end of the page";
+            return self.skip(IssueKind::CorruptPage, id, path, message);
+        }
+        match p.page_type {
+            Page::BRANCH_PAGE => {
+                for e in p.branch_elements().unwrap_or(&[]) {
+                    self.copy_tree(e.id, path, dest);
+                }
+            }
+            Page::LEAF_PAGE | Page::FIXED_LEAF_PAGE => {
+                for (key, value, flags) in leaf_entries(p) {
+                    self.copy_entry(id, key, value, flags, path, dest);
+                }
+            }
+            t => {
+                let message = format!("unexpected page type {}", t);
+                self.skip(IssueKind::CorruptPage, id, path, &message)
+            }
+        }
+    }
+
+    // copy one leaf element of page leaf into dest
+    fn copy_entry(
+        &mut self,
+        leaf: PageId,
+        key: &[u8],
+        value: &[u8],
+        flags: u32,
+        path: &[Vec<u8>],
+        dest: &mut Bucket,
+    ) {
+        let copied = if flags & Bucket::FLAG != 0 {
+            self.copy_bucket(leaf, key, value, flags, path, dest)
+        } else if flags & Bucket::BLOB_FLAG != 0 {
+            match self.read_blob(value, path) {
+                Some(data) => dest.put_blob(key, &mut &data[..]).map(|_| ()),
+                None => Err!("blob chain is broken"),
+            }
+        } else {
+            let user = (flags >> Bucket::USER_FLAGS_SHIFT) as u16;
+            dest.put_with_flags(key, value, user)
+        };
+        match copied {
+            Ok(()) => self.report.keys += (flags & Bucket::FLAG == 0) as u64,
+            Err(e) => {
+                let message = format!("key {:?} not copied: {}", String::from_utf8_lossy(key), e);
+                self.skip(IssueKind::CorruptPage, leaf, path, &message);
+            }
+        }
+    }
+
+    fn copy_bucket(
+        &mut self,
+        leaf: PageId,
+        key: &[u8],
+        value: &[u8],
+        flags: u32,
+        path: &[Vec<u8>],
+        dest: &mut Bucket,
+    ) -> Result<()> {
+        if value.len() < IBucket::SIZE {
+            return Err!("nested bucket header is truncated");
+        }
+        let b = unsafe { std::ptr::read_unaligned(value.as_ptr() as *const IBucket) };
+        // keep dup and fixed width modes
+        let child = dest.create_nested(key, flags & Bucket::ENGINE_FLAGS)?;
+        child.bucket.sequence = b.sequence;
+        self.report.buckets += 1;
+        let mut path = path.to_vec();
+        path.push(key.to_vec());
+        if b.root != 0 {
+            self.copy_tree(b.root, &path, child);
+            return Ok(());
+        }
+        // inline bucket, its page lives in the value
+        let buf = &value[IBucket::SIZE..];
+        let inline = match Page::from_buf_direct(buf) {
+            Ok(inline) => inline,
+            Err(_) => return Err!("inline bucket is truncated"),
+        };
+        if !inline.is_leaf() || !elements_fit(inline, buf.len()) {
+            return Err!("inline bucket is not a leaf");
+        }
+        for (key, value, flags) in leaf_entries(inline) {
+            self.copy_entry(leaf, key, value, flags, &path, child);
+        }
+        Ok(())
+    }
+
+    // contents of the blob behind descriptor, None if its chain is broken
+    fn read_blob(&mut self, descriptor: &[u8], path: &[Vec<u8>]) -> Option<Vec<u8>> {
+        let (len, mut id) = parse_descriptor(descriptor).ok()?;
+        let mut data = Vec::new();
+        while id != 0 {
+            let p = self.page(id, path)?;
+            let span = (p.overflow as usize + 1) * self.page_size as usize;
+            if p.page_type != Page::BLOB_PAGE || p.used_bytes() > span {
+                return None;
+            }
+            let chunk = p.blob_chunk();
+            data.extend_from_slice(chunk.data());
+            id = chunk.next;
+        }
+        (data.len() as u64 == len).then_some(data)
+    }
+
+    // put the keys of intact leaf pages that are neither copied nor free
+    // into lost+found. nested buckets on them can't be placed and are left
+    fn copy_orphans(&mut self, free: &HashSet<PageId>, root: &mut Bucket) -> Result<()> {
+        let mut orphans: Vec<&Page> = (2..self.num_pages)
+            .filter(|id| !self.visited.contains(id) && !free.contains(id))
+            .filter_map(|id| Some((id, Page::from_buf(self.mmap, id, self.page_size).ok()?)))
+            .filter(|&(id, p)| { p.id } == id && self.intact_leaf(p))
+            .map(|(_, p)| p)
+            .collect();
+        orphans.sort_by_key(|p| p.txid);
+        let before = self.report.keys;
+        for p in orphans {
+            // overlaps the blocks of an orphan copied before
+            let blocks = p.id..=p.id + p.overflow as PageId;
+            if blocks.clone().any(|block| self.visited.contains(&block)) {
+                continue;
+            }
+            self.visited.extend(blocks);
+            let lost = root.create_bucket_if_not_exist(LOST_AND_FOUND)?;
+            let path = [LOST_AND_FOUND.to_vec()];
+            for (key, value, flags) in leaf_entries(p) {
+                if flags & Bucket::FLAG != 0 {
+                    let message = "nested bucket on an orphan page not copied";
+                    self.skip(IssueKind::LeakedPage, p.id, &path, message);
+                    continue;
+                }
+                self.copy_entry(p.id, key, value, flags, &path, lost);
+            }
+        }
+        self.report.orphan_keys = self.report.keys - before;
+        Ok(())
+    }
+
+    // a non-empty leaf page that fits in the file and holds sorted keys
+    fn intact_leaf(&self, p: &Page) -> bool {
+        let span = (p.overflow as usize + 1) * self.page_size as usize;
+        p.is_leaf()
+            && p.count > 0
+            && { p.id } + (p.overflow as PageId) < self.num_pages
+            && elements_fit(p, span)
+            && sorted(leaf_entries(p).iter().map(|e| e.0), &[])
+    }
+}
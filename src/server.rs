@@ -0,0 +1,441 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{BufReader, BufWriter, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    bucket::Bucket,
+    db::{DBBuilder, DB},
+    error::{Result, RoltError},
+    transaction::Transaction,
+    Err,
+};
+
+// frames are a little-endian u32 length followed by the body. a request body
+// is an op byte and its fields, a response body a status byte and its fields.
+// every field is a u32 length followed by that many bytes
+pub const OP_GET: u8 = 1; // bucket, key -> value
+pub const OP_PUT: u8 = 2; // bucket, key, value
+pub const OP_DELETE: u8 = 3; // bucket, key
+pub const OP_SCAN: u8 = 4; // bucket, prefix -> key, value, key, value, ...
+pub const OP_BEGIN: u8 = 5;
+pub const OP_COMMIT: u8 = 6;
+pub const OP_ROLLBACK: u8 = 7;
+
+pub const STATUS_OK: u8 = 0;
+pub const STATUS_NOT_FOUND: u8 = 1;
+pub const STATUS_ERROR: u8 = 2; // message
+
+// frames above this size are refused rather than allocated
+const MAX_FRAME: u32 = 64 << 20;
+
+struct Request {
+    conn: u64,
+    op: u8,
+    fields: Vec<Vec<u8>>,
+    reply: mpsc::Sender<(u8, Vec<Vec<u8>>)>,
+}
+
+enum Job {
+    Request(Request),
+    // the connection went away, its open tx is rolled back
+    Close(u64),
+    Shutdown,
+}
+
+// serves a db file over tcp so processes on one machine can share it. the db
+// lives on its own thread and runs requests one at a time. ops outside
+// begin/commit run in a tx of their own, and one connection at a time may
+// hold a write tx. requests of other connections wait until it commits or
+// rolls back
+pub struct Server {
+    addr: SocketAddr,
+    jobs: mpsc::Sender<Job>,
+    stopped: Arc<AtomicBool>,
+    acceptor: Option<JoinHandle<()>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    pub fn start<P: AsRef<Path>, A: ToSocketAddrs>(path: P, addr: A) -> Result<Self> {
+        Self::start_with(DBBuilder::default(), path, addr)
+    }
+
+    pub fn start_with<P: AsRef<Path>, A: ToSocketAddrs>(
+        builder: DBBuilder,
+        path: P,
+        addr: A,
+    ) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let (jobs, rx) = mpsc::channel::<Job>();
+        let (opened_tx, opened) = mpsc::channel();
+        let worker = thread::Builder::new()
+            .name("roltdb-server".to_string())
+            .spawn(move || {
+                let db = match builder.open(path) {
+                    Ok(db) => {
+                        let _ = opened_tx.send(Ok(()));
+                        db
+                    }
+                    Err(e) => {
+                        let _ = opened_tx.send(Err(e));
+                        return;
+                    }
+                };
+                serve(&db, rx);
+            })?;
+        opened
+            .recv()
+            .map_err(|_| RoltError::from("db worker stopped"))??;
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let acceptor = {
+            let (jobs, stopped) = (jobs.clone(), stopped.clone());
+            thread::Builder::new()
+                .name("roltdb-accept".to_string())
+                .spawn(move || accept(listener, jobs, stopped))?
+        };
+        Ok(Self {
+            addr,
+            jobs,
+            stopped,
+            acceptor: Some(acceptor),
+            worker: Some(worker),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    // stop accepting, roll back open txs and close the db
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // wake the acceptor blocked in accept
+        let _ = TcpStream::connect(self.addr);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+        let _ = self.jobs.send(Job::Shutdown);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn accept(listener: TcpListener, jobs: mpsc::Sender<Job>, stopped: Arc<AtomicBool>) {
+    let mut next_conn = 0;
+    for stream in listener.incoming() {
+        if stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        let stream = match stream {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        next_conn += 1;
+        let (conn, jobs) = (next_conn, jobs.clone());
+        let _ = thread::Builder::new()
+            .name(format!("roltdb-conn-{}", conn))
+            .spawn(move || {
+                let _ = handle(conn, stream, &jobs);
+                let _ = jobs.send(Job::Close(conn));
+            });
+    }
+}
+
+// read requests off one connection and forward them to the db thread
+fn handle(conn: u64, stream: TcpStream, jobs: &mpsc::Sender<Job>) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    while let Some(body) = read_frame(&mut reader)? {
+        let (op, fields) = match body.split_first() {
+            Some((&op, rest)) => (op, decode_fields(rest)?),
+            None => return Err!("empty request"),
+        };
+        let (reply, response) = mpsc::channel();
+        jobs.send(Job::Request(Request {
+            conn,
+            op,
+            fields,
+            reply,
+        }))
+        .map_err(|_| RoltError::from("server is shutting down"))?;
+        let (status, fields) = response
+            .recv()
+            .map_err(|_| RoltError::from("server is shutting down"))?;
+        write_frame(&mut writer, status, &fields)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+fn serve(db: &DB, jobs: mpsc::Receiver<Job>) {
+    let mut txs: HashMap<u64, Transaction> = HashMap::new();
+    // requests of other connections waiting for the open tx to end, in the
+    // order they came in. no other tx can begin while it's open
+    let mut parked: VecDeque<Request> = VecDeque::new();
+    while let Ok(job) = jobs.recv() {
+        match job {
+            Job::Request(req) => {
+                if txs.keys().any(|&conn| conn != req.conn) {
+                    parked.push_back(req);
+                } else {
+                    answer(db, &mut txs, req);
+                }
+            }
+            Job::Close(conn) => {
+                if let Some(tx) = txs.remove(&conn) {
+                    let _ = tx.rollback();
+                }
+            }
+            Job::Shutdown => break,
+        }
+        // the tx ended, run what waited until one of them begins a tx of
+        // its own
+        while txs.is_empty() {
+            match parked.pop_front() {
+                Some(req) => answer(db, &mut txs, req),
+                None => break,
+            }
+        }
+    }
+    for (_, tx) in txs.drain() {
+        let _ = tx.rollback();
+    }
+}
+
+fn answer(db: &DB, txs: &mut HashMap<u64, Transaction>, req: Request) {
+    let response = match run(db, txs, req.conn, req.op, &req.fields) {
+        Ok(Some(fields)) => (STATUS_OK, fields),
+        Ok(None) => (STATUS_NOT_FOUND, Vec::new()),
+        Err(e) => (STATUS_ERROR, vec![e.to_string().into_bytes()]),
+    };
+    let _ = req.reply.send(response);
+}
+
+// run one request, None when the bucket or key is missing
+fn run(
+    db: &DB,
+    txs: &mut HashMap<u64, Transaction>,
+    conn: u64,
+    op: u8,
+    fields: &[Vec<u8>],
+) -> Result<Option<Vec<Vec<u8>>>> {
+    let field = |i: usize| -> Result<&[u8]> {
+        fields
+            .get(i)
+            .map(|f| f.as_slice())
+            .ok_or_else(|| format!("request is missing field {}", i).into())
+    };
+    match op {
+        OP_BEGIN => {
+            if txs.contains_key(&conn) {
+                return Err!("a tx is already open on this connection");
+            }
+            txs.insert(conn, db.tx(true)?);
+            Ok(Some(Vec::new()))
+        }
+        OP_COMMIT | OP_ROLLBACK => {
+            let tx = txs.remove(&conn).ok_or(RoltError::from("no tx is open"))?;
+            if op == OP_COMMIT {
+                tx.commit()?;
+            } else {
+                tx.rollback()?;
+            }
+            Ok(Some(Vec::new()))
+        }
+        OP_GET | OP_SCAN => {
+            let (bucket, arg) = (field(0)?, field(1)?);
+            let read = |tx: &Transaction| -> Result<Option<Vec<Vec<u8>>>> {
+                let b = match tx.bucket(bucket) {
+                    Some(b) => b,
+                    None => return Ok(None),
+                };
+                if op == OP_GET {
+                    return Ok(b.get(arg).map(|v| vec![v.to_vec()]));
+                }
+                scan(&b, arg).map(Some)
+            };
+            match txs.get(&conn) {
+                Some(tx) => read(tx),
+                None => read(&db.tx(false)?),
+            }
+        }
+        OP_PUT | OP_DELETE => {
+            let (bucket, key) = (field(0)?, field(1)?);
+            let write = |tx: &Transaction| -> Result<()> {
+                let mut b = tx.create_bucket_if_not_exist(bucket)?;
+                match op {
+                    OP_PUT => b.put(key, field(2)?),
+                    _ => b.delete(key),
+                }
+            };
+            match txs.get(&conn) {
+                Some(tx) => write(tx)?,
+                None => {
+                    let tx = db.tx(true)?;
+                    if let Err(e) = write(&tx) {
+                        tx.rollback()?;
+                        return Err(e);
+                    }
+                    tx.commit()?;
+                }
+            }
+            Ok(Some(Vec::new()))
+        }
+        op => Err!(format!("unknown op {}", op)),
+    }
+}
+
+// keys under prefix and their values, nested buckets and expired keys skipped
+fn scan(b: &Bucket, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut out = Vec::new();
+    let mut c = b.cursor();
+    let mut pair = c.seek_prefix(prefix)?;
+    while let Some(key) = pair.key() {
+        if !pair.is_bucket() && !b.expired(key) {
+            out.push(key.to_vec());
+            out.push(pair.value().unwrap_or(&[]).to_vec());
+        }
+        pair = c.next()?;
+    }
+    Ok(out)
+}
+
+fn read_frame<R: Read>(r: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_le_bytes(len);
+    if len > MAX_FRAME {
+        return Err!(format!("frame of {} bytes is too large", len));
+    }
+    let mut body = vec![0u8; len as usize];
+    r.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn write_frame<W: Write>(w: &mut W, head: u8, fields: &[Vec<u8>]) -> Result<()> {
+    let len = 1 + fields.iter().map(|f| 4 + f.len()).sum::<usize>();
+    let len = u32::try_from(len).map_err(|_| RoltError::from("frame is too large"))?;
+    w.write_all(&len.to_le_bytes())?;
+    w.write_all(&[head])?;
+    for f in fields {
+        w.write_all(&(f.len() as u32).to_le_bytes())?;
+        w.write_all(f)?;
+    }
+    Ok(())
+}
+
+fn decode_fields(mut buf: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut fields = Vec::new();
+    while !buf.is_empty() {
+        if buf.len() < 4 {
+            return Err!("truncated field length");
+        }
+        let (len, rest) = buf.split_at(4);
+        let len = u32::from_le_bytes(len.try_into()?) as usize;
+        if rest.len() < len {
+            return Err!("truncated field");
+        }
+        let (field, rest) = rest.split_at(len);
+        fields.push(field.to_vec());
+        buf = rest;
+    }
+    Ok(fields)
+}
+
+// blocking client for Server
+pub struct Client {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Client {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        })
+    }
+
+    pub fn get(&mut self, bucket: &[u8], key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let fields = self.call(OP_GET, &[bucket, key])?;
+        Ok(fields.and_then(|mut f| f.pop()))
+    }
+
+    pub fn put(&mut self, bucket: &[u8], key: &[u8], value: &[u8]) -> Result<()> {
+        self.call(OP_PUT, &[bucket, key, value]).map(|_| ())
+    }
+
+    pub fn delete(&mut self, bucket: &[u8], key: &[u8]) -> Result<()> {
+        self.call(OP_DELETE, &[bucket, key]).map(|_| ())
+    }
+
+    // pairs whose key starts with prefix, in key order
+    pub fn scan(&mut self, bucket: &[u8], prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let fields = self.call(OP_SCAN, &[bucket, prefix])?.unwrap_or_default();
+        let mut fields = fields.into_iter();
+        let mut pairs = Vec::new();
+        while let (Some(k), Some(v)) = (fields.next(), fields.next()) {
+            pairs.push((k, v));
+        }
+        Ok(pairs)
+    }
+
+    pub fn begin(&mut self) -> Result<()> {
+        self.call(OP_BEGIN, &[]).map(|_| ())
+    }
+
+    pub fn commit(&mut self) -> Result<()> {
+        self.call(OP_COMMIT, &[]).map(|_| ())
+    }
+
+    pub fn rollback(&mut self) -> Result<()> {
+        self.call(OP_ROLLBACK, &[]).map(|_| ())
+    }
+
+    fn call(&mut self, op: u8, fields: &[&[u8]]) -> Result<Option<Vec<Vec<u8>>>> {
+        let fields: Vec<Vec<u8>> = fields.iter().map(|f| f.to_vec()).collect();
+        write_frame(&mut self.writer, op, &fields)?;
+        self.writer.flush()?;
+        let body =
+            read_frame(&mut self.reader)?.ok_or(RoltError::from("server closed the connection"))?;
+        let (status, rest) = body
+            .split_first()
+            .ok_or(RoltError::from("empty response"))?;
+        let mut fields = decode_fields(rest)?;
+        match *status {
+            STATUS_OK => Ok(Some(fields)),
+            STATUS_NOT_FOUND => Ok(None),
+            _ => {
+                let message = fields.pop().unwrap_or_default();
+                Err!(String::from_utf8_lossy(&message).into_owned())
+            }
+        }
+    }
+}
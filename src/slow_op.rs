@@ -0,0 +1,78 @@
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+use crate::{page::PageId, transaction::Txid};
+
+// durations above which an operation is reported as slow, None disables the check
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlowOpThresholds {
+    pub commit: Option<Duration>,
+    pub fsync: Option<Duration>,
+    pub page_write: Option<Duration>,
+}
+
+// time spent in each phase of a commit
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommitTimings {
+    pub rebalance: Duration,
+    pub spill: Duration,
+    // freeing and rewriting the free list page
+    pub free_list: Duration,
+    // writing dirty pages, including their fsync
+    pub write_pages: Duration,
+    // writing the meta page, including its fsync
+    pub write_meta: Duration,
+    pub total: Duration,
+}
+
+// an operation that exceeded its threshold
+#[derive(Debug, Clone, Copy)]
+pub enum SlowOp {
+    Commit { tx_id: Txid, timings: CommitTimings },
+    Fsync { elapsed: Duration },
+    PageWrite { page_id: PageId, elapsed: Duration },
+}
+
+pub(crate) type SlowOpCallback = Arc<dyn Fn(&SlowOp) + Send + Sync>;
+
+// thresholds together with the callback fired when one is exceeded
+#[derive(Clone)]
+pub(crate) struct SlowOpHook {
+    thresholds: SlowOpThresholds,
+    callback: SlowOpCallback,
+}
+
+impl SlowOpHook {
+    pub(crate) fn new(thresholds: SlowOpThresholds, callback: SlowOpCallback) -> Self {
+        Self {
+            thresholds,
+            callback,
+        }
+    }
+    pub(crate) fn commit(&self, tx_id: Txid, timings: CommitTimings) {
+        if exceeds(self.thresholds.commit, timings.total) {
+            (self.callback)(&SlowOp::Commit { tx_id, timings });
+        }
+    }
+    pub(crate) fn fsync(&self, elapsed: Duration) {
+        if exceeds(self.thresholds.fsync, elapsed) {
+            (self.callback)(&SlowOp::Fsync { elapsed });
+        }
+    }
+    pub(crate) fn page_write(&self, page_id: PageId, elapsed: Duration) {
+        if exceeds(self.thresholds.page_write, elapsed) {
+            (self.callback)(&SlowOp::PageWrite { page_id, elapsed });
+        }
+    }
+}
+
+fn exceeds(threshold: Option<Duration>, elapsed: Duration) -> bool {
+    matches!(threshold, Some(t) if elapsed >= t)
+}
+
+impl Debug for SlowOpHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SlowOpHook")
+            .field("thresholds", &self.thresholds)
+            .finish()
+    }
+}
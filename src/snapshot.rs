@@ -0,0 +1,47 @@
+use crate::{
+    db::WeakDB,
+    error::{Result, RoltError},
+    meta::Meta,
+    transaction::{Transaction, Txid},
+    Err,
+};
+
+// pins one committed version of the db. read txs opened from the handle all
+// see that version, no matter how many writes commit in the meantime, and
+// pages it references are kept out of reuse until the handle is released
+#[derive(Debug)]
+pub struct SnapshotHandle {
+    db: WeakDB,
+    meta: Meta,
+}
+
+impl SnapshotHandle {
+    pub(crate) fn new(db: WeakDB, meta: Meta) -> Self {
+        Self { db, meta }
+    }
+    // txid of the pinned version
+    pub fn tx_id(&self) -> Txid {
+        self.meta.tx_id
+    }
+    // open a read-only tx as of the pinned version
+    pub fn tx(&self) -> Result<Transaction> {
+        if self.db.upgrade().is_none() {
+            return Err!(RoltError::TxNotValid);
+        }
+        Ok(Transaction::with_meta(
+            self.db.clone(),
+            self.meta.clone(),
+            false,
+        ))
+    }
+    // unpin the version, same as dropping the handle
+    pub fn release(self) {}
+}
+
+impl Drop for SnapshotHandle {
+    fn drop(&mut self) {
+        if let Some(db) = self.db.upgrade() {
+            db.release_snapshot(self.meta.tx_id);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// counters updated by db internals
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    pub(crate) write_calls: AtomicU64,
+    pub(crate) write_bytes: AtomicU64,
+    pub(crate) syncs: AtomicU64,
+    pub(crate) allocations: AtomicU64,
+    pub(crate) allocated_pages: AtomicU64,
+    pub(crate) free_list_hits: AtomicU64,
+    pub(crate) free_list_misses: AtomicU64,
+    pub(crate) pooled_pages: AtomicU64,
+    pub(crate) readahead_pages: AtomicU64,
+    pub(crate) resets: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+    pub(crate) fn snapshot(&self) -> Stats {
+        let get = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        Stats {
+            write_calls: get(&self.write_calls),
+            write_bytes: get(&self.write_bytes),
+            syncs: get(&self.syncs),
+            allocations: get(&self.allocations),
+            allocated_pages: get(&self.allocated_pages),
+            free_list_hits: get(&self.free_list_hits),
+            free_list_misses: get(&self.free_list_misses),
+            pooled_pages: get(&self.pooled_pages),
+            readahead_pages: get(&self.readahead_pages),
+            resets: get(&self.resets),
+        }
+    }
+    // zero every counter
+    pub(crate) fn reset(&self) {
+        Self::add(&self.resets, 1);
+        for c in [
+            &self.write_calls,
+            &self.write_bytes,
+            &self.syncs,
+            &self.allocations,
+            &self.allocated_pages,
+            &self.free_list_hits,
+            &self.free_list_misses,
+            &self.pooled_pages,
+            &self.readahead_pages,
+        ] {
+            c.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+// point-in-time copy of the db counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    // positioned writes issued to the file
+    pub write_calls: u64,
+    pub write_bytes: u64,
+    pub syncs: u64,
+    // page allocations requested by write transactions
+    pub allocations: u64,
+    pub allocated_pages: u64,
+    // allocations served from the free list
+    pub free_list_hits: u64,
+    // allocations that had to grow the file
+    pub free_list_misses: u64,
+    // dirty pages that reused a buffer of an earlier write
+    pub pooled_pages: u64,
+    // leaf pages a sequential cursor asked the kernel to read ahead
+    pub readahead_pages: u64,
+    // times the counters were reset, see DB::reset_stats
+    pub resets: u64,
+}
+
+impl Stats {
+    // counts since prev was taken, or since the last reset when the
+    // counters were reset in between
+    pub fn since(&self, prev: &Stats) -> Stats {
+        if self.resets != prev.resets {
+            return *self;
+        }
+        let delta = |now: u64, then: u64| now.saturating_sub(then);
+        Stats {
+            write_calls: delta(self.write_calls, prev.write_calls),
+            write_bytes: delta(self.write_bytes, prev.write_bytes),
+            syncs: delta(self.syncs, prev.syncs),
+            allocations: delta(self.allocations, prev.allocations),
+            allocated_pages: delta(self.allocated_pages, prev.allocated_pages),
+            free_list_hits: delta(self.free_list_hits, prev.free_list_hits),
+            free_list_misses: delta(self.free_list_misses, prev.free_list_misses),
+            pooled_pages: delta(self.pooled_pages, prev.pooled_pages),
+            readahead_pages: delta(self.readahead_pages, prev.readahead_pages),
+            resets: self.resets,
+        }
+    }
+}
+
+// memory a write tx holds in materialized nodes, counted as the page bytes
+// their keys and values take. see DBBuilder::node_memory_limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TxMemory {
+    // nodes read from pages so far
+    pub nodes: u64,
+    pub bytes: u64,
+    // most bytes held at any point of the tx
+    pub peak_bytes: u64,
+}
+
+impl TxMemory {
+    // bytes after adding add and dropping drop, or None past limit
+    pub(crate) fn resize(&self, add: u64, drop: u64, limit: Option<u64>) -> Option<u64> {
+        let bytes = self.bytes.saturating_sub(drop) + add;
+        match limit {
+            Some(limit) if bytes > limit && add > drop => None,
+            _ => Some(bytes),
+        }
+    }
+
+    pub(crate) fn set(&mut self, bytes: u64) {
+        self.bytes = bytes;
+        self.peak_bytes = self.peak_bytes.max(bytes);
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+
+// when a commit fsyncs the file. a synced commit fsyncs twice, once after
+// its pages so the meta page never lands before them, then after the meta
+// page. an unsynced commit is visible right away but a crash or power loss
+// may lose it, along with every unsynced commit before it. commits that
+// were synced stay intact
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncPolicy {
+    // every commit is durable once commit returns
+    #[default]
+    Always,
+    // sync every nth commit, at most n - 1 commits can be lost
+    EveryNCommits(u64),
+    // sync the first commit once this much time has passed since the last
+    // sync, commits of at most that long can be lost
+    Interval(Duration),
+    // leave writing back to the os, the file is only synced on close
+    Never,
+}
+
+// commits written since the last fsync and when it happened
+#[derive(Debug)]
+pub(crate) struct SyncState {
+    unsynced: u64,
+    last: Instant,
+}
+
+impl Default for SyncState {
+    fn default() -> Self {
+        Self {
+            unsynced: 0,
+            last: Instant::now(),
+        }
+    }
+}
+
+impl SyncState {
+    // whether the commit being written should sync, counting it either way
+    pub(crate) fn due(&mut self, policy: SyncPolicy) -> bool {
+        self.unsynced += 1;
+        let due = match policy {
+            SyncPolicy::Always => true,
+            SyncPolicy::EveryNCommits(n) => self.unsynced >= n.max(1),
+            SyncPolicy::Interval(interval) => self.last.elapsed() >= interval,
+            SyncPolicy::Never => false,
+        };
+        if due {
+            self.synced();
+        }
+        due
+    }
+
+    pub(crate) fn synced(&mut self) {
+        self.unsynced = 0;
+        self.last = Instant::now();
+    }
+
+    pub(crate) fn pending(&self) -> bool {
+        self.unsynced > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_due() {
+        let mut state = SyncState::default();
+        assert!(state.due(SyncPolicy::Always));
+        assert!(!state.pending());
+        let every = SyncPolicy::EveryNCommits(3);
+        assert!(!state.due(every));
+        assert!(!state.due(every));
+        assert!(state.pending());
+        assert!(state.due(every));
+        assert!(!state.due(SyncPolicy::Never));
+        assert!(state.pending());
+        assert!(!state.due(SyncPolicy::Interval(Duration::from_secs(3600))));
+        assert!(state.due(SyncPolicy::Interval(Duration::ZERO)));
+        // zero is treated as one
+        assert!(state.due(SyncPolicy::EveryNCommits(0)));
+    }
+}
@@ -1,42 +1,94 @@
 use crate::{
+    backup::{BackupOptions, SnapshotSink},
     bucket::Bucket,
+    check::CheckReport,
     data::RawPtr,
     db::{WeakDB, DB},
-    error::Result,
+    error::{Result, RoltError},
+    free_list::FreeList,
     meta::Meta,
-    page::{Page, PageId, VPage},
+    page::{Page, PageId, PageInfo, VPage},
+    slow_op::CommitTimings,
+    stats::{Counters, TxMemory},
+    utils::debug_span,
+    watch::{self, ChangeEvent, ChangeSet, Changes},
+    Err,
+};
+use memmap::Mmap;
+use parking_lot::{
+    MappedRwLockReadGuard, MappedRwLockWriteGuard, RwLock, RwLockReadGuard, RwLockWriteGuard,
 };
-use anyhow::anyhow;
-use parking_lot::{MappedRwLockWriteGuard, RwLock, RwLockWriteGuard};
 use std::{
-    collections::HashMap,
-    io::Cursor,
+    collections::{HashMap, HashSet},
+    io::{Cursor, Write},
     ops::Deref,
     rc::{Rc, Weak},
     slice::from_raw_parts,
+    sync::{atomic::Ordering as AtomicOrdering, Arc},
+    time::Instant,
 };
 pub type Txid = u64;
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Transaction(pub(crate) Rc<ITransaction>);
 
 #[derive(Debug, Clone)]
 pub struct WeakTransaction(pub(crate) Weak<ITransaction>);
 
 #[allow(dead_code)]
-#[derive(Debug)]
 pub struct ITransaction {
     pub(crate) writable: bool,
     db: RwLock<WeakDB>,
     managed: bool,
     pub root: RwLock<Bucket>,
-    pages: RwLock<HashMap<PageId, VPage>>,
+    pub(crate) pages: RwLock<HashMap<PageId, VPage>>,
     meta: RwLock<Meta>,
-    // commit_handlers: Vec<Box<dyn Fn()>>, // call functions after commit
+    // committed state the tx started from, reads of a read-only tx resolve
+    // against it even if the writer grows or remaps the file meanwhile
+    base: Meta,
+    // the mapping current when the tx started, held so a remap can't unmap
+    // it under a read tx
+    mapping: Option<Arc<Mmap>>,
+    // keys modified by this tx, published to watchers on commit
+    pub(crate) changes: RwLock<Changes>,
+    // run before commit, any error turns the commit into a rollback
+    validators: RwLock<Validators>,
+    memory: RwLock<TxMemory>,
+    state: RwLock<TxState>,
+}
+
+// a tx commits or rolls back at most once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxState {
+    Active,
+    Committed,
+    RolledBack,
+}
+
+type Validator = Box<dyn Fn(&Transaction) -> Result<()>>;
+
+#[derive(Default)]
+struct Validators(Vec<Validator>);
+
+impl std::fmt::Debug for Validators {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Validators({})", self.0.len())
+    }
 }
 
 impl Transaction {
     pub fn new(db: WeakDB, writable: bool) -> Self {
-        let tx = Self(Rc::new(ITransaction::new(db, writable)));
+        let mut meta = match db.upgrade() {
+            None => Meta::default(),
+            Some(db) => db.meta().unwrap(),
+        };
+        if writable {
+            meta.tx_id += 1;
+        }
+        Self::with_meta(db, meta, writable)
+    }
+    // create a tx over the given meta instead of the latest one
+    pub(crate) fn with_meta(db: WeakDB, meta: Meta, writable: bool) -> Self {
+        let tx = Self(Rc::new(ITransaction::new(db, meta, writable)));
         {
             let mut b = tx.root.write();
             b.tx = WeakTransaction(Rc::downgrade(&tx));
@@ -46,23 +98,93 @@ impl Transaction {
     }
 }
 
+// a read-only tx, it only offers what a reader may do so writes through it
+// fail to compile rather than at runtime
+#[derive(Debug, Clone)]
+pub struct ReadTransaction(pub(crate) Transaction);
+
+// the writable tx, it commits on drop unless it was committed or rolled back
+#[derive(Debug, Clone)]
+pub struct WriteTransaction(pub(crate) Transaction);
+
+impl ReadTransaction {
+    pub fn id(&self) -> Txid {
+        self.0.id()
+    }
+
+    pub fn root(&self) -> RwLockReadGuard<'_, Bucket> {
+        self.0.root()
+    }
+
+    pub fn bucket<N: AsRef<[u8]>>(&self, name: N) -> Option<MappedRwLockReadGuard<'_, Bucket>> {
+        self.0.bucket(name)
+    }
+
+    pub fn buckets(&self) -> Result<Vec<Vec<u8>>> {
+        self.0.buckets()
+    }
+
+    pub fn page_info(&self, id: PageId) -> Result<PageInfo> {
+        self.0.page_info(id)
+    }
+
+    pub fn pages(&self) -> Pages<'_> {
+        self.0.pages()
+    }
+
+    pub fn dump_page(&self, id: PageId) -> Result<String> {
+        self.0.dump_page(id)
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<u64> {
+        self.0.write_to(w)
+    }
+
+    pub fn backup<W: Write>(&self, w: &mut W, opts: BackupOptions) -> Result<u64> {
+        self.0.backup(w, opts)
+    }
+
+    pub fn backup_to<S: SnapshotSink + ?Sized>(
+        &self,
+        sink: &mut S,
+        opts: BackupOptions,
+    ) -> Result<u64> {
+        self.0.backup_to(sink, opts)
+    }
+
+    pub fn check(&self) -> Result<CheckReport> {
+        self.0.check()
+    }
+}
+
+impl Deref for WriteTransaction {
+    type Target = Transaction;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 #[allow(dead_code)]
 impl ITransaction {
-    pub fn new(db: WeakDB, writable: bool) -> Self {
-        let mut meta = match db.upgrade() {
-            None => Meta::default(),
-            Some(db) => db.meta().unwrap(),
-        };
-        if writable {
-            meta.tx_id += 1;
+    pub(crate) fn new(db: WeakDB, meta: Meta, writable: bool) -> Self {
+        let keep_values = db.upgrade().is_some_and(|db| db.replication.is_some());
+        if let Some(db) = db.upgrade().filter(|_| !writable) {
+            db.open_reads.fetch_add(1, AtomicOrdering::Relaxed);
         }
+        let mapping = db.upgrade().filter(|_| !writable).map(|db| db.pin_mmap());
         ITransaction {
+            base: meta.clone(),
+            mapping,
             db: RwLock::new(db),
             managed: false,
-            // commit_handlers: Vec::new(),
+            validators: RwLock::default(),
+            memory: RwLock::default(),
+            state: RwLock::new(TxState::Active),
             pages: RwLock::new(HashMap::new()),
             writable,
             meta: RwLock::new(meta),
+            changes: RwLock::new(Changes::new(keep_values)),
             root: RwLock::new(Bucket::new(WeakTransaction::new())),
         }
     }
@@ -70,49 +192,181 @@ impl ITransaction {
     pub(crate) fn page(&self, id: PageId) -> Result<RawPtr<Page>> {
         let pages = self.pages.read();
         if let Some(page) = pages.get(&id) {
-            Ok(RawPtr::new(page))
-        } else {
+            return Ok(RawPtr::new(page));
+        }
+        if self.writable {
             // get page from mmap
-            Ok(RawPtr::new(&*self.db().unwrap().page(id)))
+            return Ok(RawPtr::new(self.db()?.page(id)?));
+        }
+        if id >= self.base.num_pages {
+            return Err!(RoltError::PageOutOfRange(id));
+        }
+        Ok(RawPtr::new(Page::from_buf(
+            self.mmap(),
+            id,
+            self.page_size(),
+        )?))
+    }
+
+    // the file as mapped when a read tx started, the current mapping for a
+    // write tx since it may grow the file
+    pub(crate) fn mmap(&self) -> &[u8] {
+        let mapping = match (self.writable, self.db()) {
+            (true, Ok(db)) => db.mmap() as *const [u8],
+            _ => match &self.mapping {
+                Some(m) => &m[..] as *const [u8],
+                None => &[] as *const [u8],
+            },
+        };
+        // the current mapping outlives the write tx, the pinned one self
+        unsafe { &*mapping }
+    }
+
+    // describe the page with the given id as seen by this tx
+    pub fn page_info(&self, id: PageId) -> Result<PageInfo> {
+        if id >= self.meta.read().num_pages {
+            return Err!(RoltError::PageOutOfRange(id));
         }
+        if !self.pages.read().contains_key(&id) {
+            let db = self.db()?;
+            if (id + 1) * db.page_size() > self.mmap().len() as u64 {
+                return Err!(RoltError::PageOutOfRange(id));
+            }
+        }
+        let page = self.page(id)?;
+        Ok(page.info(self.page_size()))
+    }
+
+    // iterate over allocated pages, skipping free ones and overflow blocks
+    pub fn pages(&self) -> Pages<'_> {
+        Pages {
+            tx: self,
+            next: 0,
+            free: self.free_pages().unwrap_or_default(),
+        }
+    }
+
+    // stamp the file with the id of the application using it, written with
+    // the commit of this tx. see DBBuilder::application_id
+    pub fn set_application_id(&self, id: u32) -> Result<()> {
+        if !self.writable {
+            return Err!(RoltError::NotWritable);
+        }
+        self.meta.write().application_id = id;
+        Ok(())
+    }
+
+    // pages free as seen by this tx. read txs take them from the free list
+    // page they started with, so they never wait on the writer's free list
+    pub(crate) fn free_pages(&self) -> Result<HashSet<PageId>> {
+        let db = self.db()?;
+        if self.writable {
+            return Ok(db.free_list.read().ids());
+        }
+        let id = self.base.free_list;
+        if (id + 1) * db.page_size() > self.mmap().len() as u64 {
+            return Err!(RoltError::PageOutOfRange(id));
+        }
+        let page = self.page(id)?;
+        let ids = FreeList::ids_in(&page)?;
+        Ok(ids.iter().copied().collect())
     }
 
     pub(crate) fn db(&self) -> Result<DB> {
         self.db
             .read()
             .upgrade()
-            .ok_or(anyhow!("db in tx is not valid"))
+            .ok_or_else(|| "db in tx is not valid".into())
     }
 
-    pub fn create_bucket(&self, name: String) -> Result<MappedRwLockWriteGuard<Bucket>> {
+    pub fn create_bucket<N: AsRef<[u8]>>(&self, name: N) -> Result<MappedRwLockWriteGuard<Bucket>> {
         if !self.writable() {
-            return Err(anyhow!("read-only tx cannot create bucket"));
+            return Err!(RoltError::NotWritable);
         }
-        let b = self.root.write();
-        Ok(RwLockWriteGuard::map(b, |f| f.create_bucket(name).unwrap()))
+        let mut b = self.root.write();
+        b.create_bucket(name.as_ref())?;
+        Ok(RwLockWriteGuard::map(b, |f| unsafe {
+            &mut *f.get_bucket(name.as_ref()).unwrap()
+        }))
     }
 
-    pub fn create_bucket_if_not_exist(
+    // a top-level bucket whose keys hold sets of values, see
+    // Bucket::create_dup_bucket
+    pub fn create_dup_bucket<N: AsRef<[u8]>>(
         &self,
-        name: String,
+        name: N,
+    ) -> Result<MappedRwLockWriteGuard<'_, Bucket>> {
+        if !self.writable() {
+            return Err!(RoltError::NotWritable);
+        }
+        let mut b = self.root.write();
+        b.create_dup_bucket(name.as_ref())?;
+        Ok(RwLockWriteGuard::map(b, |f| unsafe {
+            &mut *f.get_bucket(name.as_ref()).unwrap()
+        }))
+    }
+
+    // a top-level bucket whose values are all width bytes, see
+    // Bucket::create_fixed_bucket
+    pub fn create_fixed_bucket<N: AsRef<[u8]>>(
+        &self,
+        name: N,
+        width: u8,
+    ) -> Result<MappedRwLockWriteGuard<'_, Bucket>> {
+        if !self.writable() {
+            return Err!(RoltError::NotWritable);
+        }
+        let mut b = self.root.write();
+        b.create_fixed_bucket(name.as_ref(), width)?;
+        Ok(RwLockWriteGuard::map(b, |f| unsafe {
+            &mut *f.get_bucket(name.as_ref()).unwrap()
+        }))
+    }
+
+    pub fn create_bucket_if_not_exist<N: AsRef<[u8]>>(
+        &self,
+        name: N,
     ) -> Result<MappedRwLockWriteGuard<Bucket>> {
         if !self.writable() {
-            return Err(anyhow!("read-only tx cannot create bucket"));
+            return Err!(RoltError::NotWritable);
         }
-        let b = self.root.write();
-        Ok(RwLockWriteGuard::map(b, |f| {
-            f.create_bucket_if_not_exist(name).unwrap()
+        let mut b = self.root.write();
+        b.create_bucket_if_not_exist(name.as_ref())?;
+        Ok(RwLockWriteGuard::map(b, |f| unsafe {
+            &mut *f.get_bucket(name.as_ref()).unwrap()
         }))
     }
 
+    // read view of the root bucket, which holds the top-level keys and buckets
+    pub fn root(&self) -> RwLockReadGuard<'_, Bucket> {
+        self.root.read()
+    }
+
+    // open an existing top-level bucket, read-only txs included
+    pub fn bucket<N: AsRef<[u8]>>(&self, name: N) -> Option<MappedRwLockReadGuard<'_, Bucket>> {
+        let b = self.root.read();
+        RwLockReadGuard::try_map(b, |root| root.bucket(name.as_ref())).ok()
+    }
+
+    // names of the top-level buckets in key order
+    pub fn buckets(&self) -> Result<Vec<Vec<u8>>> {
+        self.root.read().buckets()
+    }
+
     pub fn rollback(&self) -> Result<()> {
+        match self.state() {
+            TxState::Active => {}
+            TxState::Committed => return Err!(RoltError::TxCommitted),
+            TxState::RolledBack => return Ok(()),
+        }
+        *self.state.write() = TxState::RolledBack;
         let db = self.db()?;
         if self.writable {
             let tx_id = self.id();
             let mut free_list = db.free_list.write();
             free_list.rollback(tx_id);
             let free_list_id = db.meta()?.free_list;
-            let free_list_page = &*db.page(free_list_id);
+            let free_list_page = db.page(free_list_id)?;
             // reload free_list
             free_list.reload(free_list_page);
         }
@@ -122,27 +376,96 @@ impl ITransaction {
 
     // write change to disk and update meta page
     pub fn commit(&self) -> Result<()> {
+        self.commit_inner().map(|_| ())
+    }
+
+    // commit and return the keys this tx modified, so callers can
+    // invalidate exactly what changed
+    pub fn commit_with_changes(&self) -> Result<ChangeSet> {
+        let events = self.commit_inner()?;
+        Ok(ChangeSet {
+            tx_id: self.id(),
+            changes: events.into_iter().filter(|e| !e.is_ttl()).collect(),
+        })
+    }
+
+    // register a check run before every commit of this tx, if it fails the
+    // tx is rolled back and commit returns its error
+    pub fn before_commit<F>(&self, f: F)
+    where
+        F: Fn(&Transaction) -> Result<()> + 'static,
+    {
+        self.validators.write().0.push(Box::new(f));
+    }
+
+    fn validate(&self) -> Result<()> {
+        let validators = self.validators.read();
+        if validators.0.is_empty() {
+            return Ok(());
+        }
+        let tx = self.root.read().tx.upgrade().ok_or(RoltError::TxNotValid)?;
+        for f in validators.0.iter() {
+            f(&tx)?;
+        }
+        Ok(())
+    }
+
+    fn commit_inner(&self) -> Result<Vec<ChangeEvent>> {
         if !self.writable() {
-            return Err(anyhow!("cannot commit read-only tx"));
+            return Err!(RoltError::NotWritable);
         }
+        match self.state() {
+            TxState::Active => {}
+            TxState::Committed => return Err!(RoltError::TxCommitted),
+            TxState::RolledBack => return Err!(RoltError::TxRolledBack),
+        }
+        let res = self.validate().and_then(|_| self.write_commit());
+        // a commit that failed before the meta page was written leaves the
+        // tx rolled back
+        if res.is_err() && self.state() == TxState::Active {
+            self.rollback()?;
+        }
+        res
+    }
+
+    fn write_commit(&self) -> Result<Vec<ChangeEvent>> {
+        debug_span!("commit", tx_id = self.id());
+        let start = Instant::now();
+        let mut timings = CommitTimings::default();
         {
             let mut root = self
                 .root
                 .try_write()
-                .ok_or(anyhow!("cannot acquire root write lock"))?;
+                .ok_or(RoltError::from("cannot acquire root write lock"))?;
 
+            // reclaim expired keys of buckets touched by this tx
+            {
+                debug_span!("sweep_expired");
+                root.sweep_expired()?;
+            }
             // rebalance
-            root.rebalance()?;
+            {
+                debug_span!("rebalance");
+                let now = Instant::now();
+                root.rebalance()?;
+                timings.rebalance = now.elapsed();
+            }
             // spill
-            root.spill()?;
+            {
+                debug_span!("spill");
+                let now = Instant::now();
+                root.spill()?;
+                timings.spill = now.elapsed();
+            }
         }
+        let now = Instant::now();
         {
             let mut meta = self.meta.write();
             // todo
             meta.root.root = self.root.read().bucket.root;
             let db = self.db()?;
             let mut free_list = db.free_list.write();
-            let p = &*db.page(meta.free_list);
+            let p = db.page(meta.free_list)?;
             // free free_list
             free_list.free(meta.free_list, p)?;
         }
@@ -156,110 +479,258 @@ impl ITransaction {
 
             {
                 let mut page = self.allocate(free_list_size as u64)?;
-                let page = unsafe { &mut **page };
+                let page = &mut *page;
                 let free_list = db.free_list.write();
                 free_list.write(page)?;
                 self.meta.write().free_list = page.id;
             }
+            timings.free_list = now.elapsed();
+            let sync = db.sync_due();
             // write dirty pages to disk
-            if let Err(e) = self.write_pages() {
-                self.rollback()?;
+            let now = Instant::now();
+            if let Err(e) = self.write_pages(sync) {
+                db.poison();
                 return Err(e);
             }
+            timings.write_pages = now.elapsed();
 
             // write dirty pages to disk
-            if let Err(e) = self.write_meta() {
-                self.rollback()?;
+            let now = Instant::now();
+            if let Err(e) = self.write_meta(sync) {
+                db.poison();
                 return Err(e);
             }
+            *self.state.write() = TxState::Committed;
+            timings.write_meta = now.elapsed();
+            // map pages the file grew by
+            db.refresh()?;
             // close tx
             // let b = vec![0u8; 4096];
             // db.write_at(4096, Cursor::new(b));
+            timings.total = start.elapsed();
+            db.metrics.commit(timings.total);
+            if let Some(hook) = &db.slow_ops {
+                hook.commit(self.id(), timings);
+            }
+            let changes = self.changes.write().take();
+            if changes.is_empty() {
+                return Ok(Vec::new());
+            }
+            let oldest = db.snapshots.lock().keys().next().copied();
+            db.commits.lock().record(oldest, self.id(), &changes);
+            // the tx is durable at this point, a failed append breaks the log
+            // but not the commit
+            if let Some(log) = &db.replication {
+                log.append(self.id(), &changes);
+            }
+            let events: Vec<ChangeEvent> = changes.into_iter().map(|(e, _)| e).collect();
+            watch::publish(&mut db.watchers.lock(), &events);
+            Ok(events)
         }
-        Ok(())
     }
 
-    fn page_size(&self) -> u64 {
+    pub(crate) fn page_size(&self) -> u64 {
         self.db().unwrap().page_size()
     }
 
-    pub(crate) fn allocate(&self, data_size: u64) -> Result<RawPtr<*mut Page>> {
+    pub(crate) fn allocate(&self, data_size: u64) -> Result<RawPtr<Page>> {
+        let mut pages = self.allocate_run(&[data_size])?;
+        Ok(pages.remove(0))
+    }
+    // allocate one page run per size out of a single contiguous span, so
+    // pages spilled together sit next to each other on disk
+    pub(crate) fn allocate_run(&self, sizes: &[u64]) -> Result<Vec<RawPtr<Page>>> {
         let page_size = self.page_size();
-        let num = if data_size % page_size == 0 {
-            data_size / page_size
-        } else {
-            data_size / page_size + 1
-        };
+        let counts: Vec<u64> = sizes
+            .iter()
+            .map(|size| (size / page_size + (size % page_size != 0) as u64).max(1))
+            .collect();
+        let num: u64 = counts.iter().sum();
+        let mut page_id = self.allocate_ids(num)?;
+        let db = self.db()?;
+        let mut ptrs = Vec::with_capacity(counts.len());
+        let mut pages = self.pages.write();
+        for num in counts {
+            let (mut page, pooled) = db.page_pool.get((num * page_size) as usize);
+            if pooled {
+                Counters::add(&db.counters.pooled_pages, 1);
+            }
+            page.id = page_id;
+            page.overflow = (num - 1) as u32;
+            page.txid = self.id();
+            ptrs.push(RawPtr(&mut *page as *mut Page));
+            pages.insert(page_id, page);
+            page_id += num;
+        }
+        Ok(ptrs)
+    }
+    // reserve num adjacent pages, from the free list or past the end of
+    // the file. the caller writes them, see allocate_run and write_now
+    pub(crate) fn allocate_ids(&self, num: u64) -> Result<PageId> {
+        debug_span!("allocate", pages = num);
         let db = self.db()?;
+        db.metrics.allocate(num);
+        Counters::add(&db.counters.allocations, 1);
+        Counters::add(&db.counters.allocated_pages, num);
         let page_id = match db.free_list.write().allocate(num as usize) {
             None => {
+                Counters::add(&db.counters.free_list_misses, 1);
                 let page_id = self.meta.read().num_pages;
                 self.meta.write().num_pages += num;
                 page_id
             }
-            Some(id) => id,
+            Some(id) => {
+                Counters::add(&db.counters.free_list_hits, 1);
+                id
+            }
         };
-        let mut page = VPage::new(self.page_size() as usize);
-        page.id = page_id;
-        let ptr = &mut *page as *mut Page;
-        let ptr = RawPtr::new(&ptr);
-        self.pages.write().insert(page_id, page);
-        Ok(ptr)
+        Ok(page_id)
+    }
+    // write a page reserved by allocate_ids before commit, so the tx doesn't
+    // hold its buffer. nothing committed points at it yet, so it skips the
+    // double write buffer. the file is mapped through it for later reads
+    pub(crate) fn write_now(&self, page: &VPage) -> Result<()> {
+        let db = self.db()?;
+        let page_size = db.page_size();
+        let size = (page.overflow as u64 + 1) * page_size;
+        let offset = page.id * page_size;
+        db.map_through(offset + size)?;
+        db.write_vectored_at(
+            offset,
+            &[unsafe { from_raw_parts(page.data_ptr(), size as usize) }],
+        )?;
+        db.metrics.pages_written(1);
+        Ok(())
     }
     // write pages to disk
-    fn write_pages(&self) -> Result<()> {
+    fn write_pages(&self, sync: bool) -> Result<()> {
         let mut pages: Vec<(PageId, VPage)> =
             self.pages.write().drain().map(|(id, p)| (id, p)).collect();
         pages.sort_by(|x, y| x.0.cmp(&y.0));
+        debug_span!("write_pages", count = pages.len());
 
-        let mut db = self.db()?;
+        let db = self.db()?;
         {
             let page_size = db.page_size();
-            // write pages to file
-            for (page_id, p) in pages.iter() {
-                let size = ((p.overflow + 1) as u64) * page_size;
-                let offset = page_id * page_size;
-                let buf = unsafe { from_raw_parts(p.data_ptr(), size as usize) };
-                db.write_at(offset, Cursor::new(buf))?;
+            if let Some(buffer) = &db.double_write {
+                let copies: Vec<(PageId, &[u8])> = pages
+                    .iter()
+                    .map(|(id, p)| {
+                        let size = ((p.overflow + 1) as u64) * page_size;
+                        (*id, unsafe { from_raw_parts(p.data_ptr(), size as usize) })
+                    })
+                    .collect();
+                buffer.write(self.id(), page_size, &copies)?;
+            }
+            // write each run of adjacent pages with one vectored write
+            let mut i = 0;
+            while i < pages.len() {
+                let first = pages[i].0;
+                let mut next = first;
+                let mut bufs = Vec::new();
+                while let Some((page_id, p)) = pages.get(i) {
+                    if *page_id != next {
+                        break;
+                    }
+                    let size = ((p.overflow + 1) as u64) * page_size;
+                    bufs.push(unsafe { from_raw_parts(p.data_ptr(), size as usize) });
+                    next += p.overflow as PageId + 1;
+                    i += 1;
+                }
+                let now = Instant::now();
+                db.write_vectored_at(first * page_size, &bufs)?;
+                if let Some(hook) = &db.slow_ops {
+                    hook.page_write(first, now.elapsed());
+                }
             }
+            db.metrics.pages_written(pages.len() as u64);
+        }
+        for (_, p) in pages {
+            db.page_pool.put(p);
+        }
+        if sync {
+            db.sync()?;
         }
-        db.sync()?;
 
         Ok(())
     }
     // write meta to disk
-    fn write_meta(&self) -> Result<()> {
+    fn write_meta(&self, sync: bool) -> Result<()> {
+        debug_span!("write_meta");
         let mut meta = self.meta.write();
         let mut db = self.db()?;
         let page_size = db.page_size();
         let offset = meta.page_id * page_size;
         let mut buf = vec![0u8; page_size as usize];
-        let p = Page::from_buf_mut(&mut buf, 0, 0);
+        let p = Page::from_buf_mut(&mut buf, 0, 0)?;
         meta.write(p)?;
         // p.page_type = 1;
         db.write_at(offset, Cursor::new(buf))?;
-        db.sync()?;
+        if sync {
+            db.sync()?;
+        }
         Ok(())
     }
 
+    // bytes of nodes this tx holds so far
+    pub fn memory(&self) -> TxMemory {
+        *self.memory.read()
+    }
+    // count a node read from a page of size bytes. reads never fail, they
+    // only make later puts hit the limit sooner
+    pub(crate) fn charge_node(&self, size: usize) {
+        let mut memory = self.memory.write();
+        memory.nodes += 1;
+        let bytes = memory.bytes + size as u64;
+        memory.set(bytes);
+    }
+    // account for a put or delete that adds add bytes to nodes and drops
+    // drop, refusing to go past the limit
+    pub(crate) fn resize_nodes(&self, add: usize, drop: usize) -> Result<()> {
+        let limit = self.db()?.node_memory_limit;
+        let mut memory = self.memory.write();
+        match memory.resize(add as u64, drop as u64, limit) {
+            Some(bytes) => memory.set(bytes),
+            None => return Err!(RoltError::NodeMemoryLimit(limit.unwrap_or(0))),
+        }
+        Ok(())
+    }
+
+    pub fn state(&self) -> TxState {
+        *self.state.read()
+    }
+
     pub fn writable(&self) -> bool {
         self.writable
     }
 
-    pub(crate) fn id(&self) -> Txid {
+    pub fn id(&self) -> Txid {
         self.meta.read().tx_id
     }
+    pub(crate) fn set_id(&self, tx_id: Txid) {
+        self.meta.write().tx_id = tx_id;
+    }
+    // shrink the file this tx commits to num pages
+    pub(crate) fn set_num_pages(&self, num: PageId) {
+        self.meta.write().num_pages = num;
+    }
 
     pub(crate) fn page_id(&self) -> PageId {
         self.meta.read().page_id
     }
+
+    pub(crate) fn meta(&self) -> Meta {
+        self.meta.read().clone()
+    }
 }
 // drop for RC<> will called when a reference is dropped
 impl Drop for Transaction {
     fn drop(&mut self) {
         // panic happened
         if std::thread::panicking() {
-            self.rollback().unwrap();
+            if self.state() == TxState::Active {
+                self.rollback().unwrap();
+            }
             return;
         }
         // one owned by user
@@ -271,13 +742,50 @@ impl Drop for Transaction {
             if !self.writable {
                 self.rollback().unwrap();
             } else {
-                self.db().unwrap().release_write_tx();
-                self.commit().unwrap();
+                let mut db = self.db().unwrap();
+                // commit unless the user already committed or rolled back. a
+                // failed commit, a vetoed one included, is rolled back already
+                if self.state() == TxState::Active {
+                    if let Err(e) = self.commit() {
+                        db.metrics.implicit_commit_failed(&e);
+                    }
+                }
+                db.release_write_tx();
             }
         }
     }
 }
 
+impl Drop for ITransaction {
+    fn drop(&mut self) {
+        if let Some(db) = self.db.read().upgrade().filter(|_| !self.writable) {
+            db.open_reads.fetch_sub(1, AtomicOrdering::Relaxed);
+            drop(self.mapping.take());
+            db.unmap_superseded();
+        }
+    }
+}
+
+impl std::fmt::Debug for Transaction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// ids and counts only, deriving would walk every dirty page and node. the
+// locks are only tried, a tx can be printed while a bucket of it is held
+impl std::fmt::Debug for ITransaction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Transaction")
+            .field("id", &self.meta.try_read().map(|m| m.tx_id))
+            .field("writable", &self.writable)
+            .field("state", &self.state.try_read().map(|s| *s))
+            .field("dirty_pages", &self.pages.try_read().map(|p| p.len()))
+            .field("buckets", &self.root.try_read().map(|r| r.open_buckets()))
+            .finish()
+    }
+}
+
 impl Deref for Transaction {
     type Target = Rc<ITransaction>;
 
@@ -286,6 +794,29 @@ impl Deref for Transaction {
     }
 }
 
+// iterator over allocated pages of a tx
+pub struct Pages<'a> {
+    tx: &'a ITransaction,
+    next: PageId,
+    free: HashSet<PageId>,
+}
+
+impl<'a> Iterator for Pages<'a> {
+    type Item = PageInfo;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let id = self.next;
+            if self.free.contains(&id) {
+                self.next += 1;
+                continue;
+            }
+            let info = self.tx.page_info(id).ok()?;
+            self.next += info.overflow as PageId + 1;
+            return Some(info);
+        }
+    }
+}
+
 impl WeakTransaction {
     pub(crate) fn new() -> Self {
         Self(Weak::new())
@@ -0,0 +1,316 @@
+use either::Either;
+
+use crate::{
+    blob::parse_descriptor,
+    bucket::Bucket,
+    error::Result,
+    page::{LeafPageElement, Page, PageId},
+};
+
+// shape of a bucket's b+tree, nested buckets are not included
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TreeStats {
+    // levels from the root down to the leaves, 1 for a single leaf
+    pub depth: usize,
+    pub branch_pages: u64,
+    pub leaf_pages: u64,
+    // extra pages of branch and leaf pages spanning more than one page
+    pub overflow_pages: u64,
+    // average number of children of a branch page
+    pub avg_fanout: f64,
+    // how full pages are by level. the root is only counted as root, pages
+    // spanning more than one page only as overflow
+    pub root_fill: FillStats,
+    pub internal_fill: FillStats,
+    pub leaf_fill: FillStats,
+    pub overflow_fill: FillStats,
+}
+
+// utilization of a group of pages, as the fraction of their bytes in use
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FillStats {
+    pub pages: u64,
+    pub avg: f64,
+    pub p10: f64,
+    pub p50: f64,
+    pub p90: f64,
+}
+
+impl FillStats {
+    fn from(mut samples: Vec<f64>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_unstable_by(f64::total_cmp);
+        let at = |p: usize| samples[(samples.len() * p / 100).min(samples.len() - 1)];
+        Self {
+            pages: samples.len() as u64,
+            avg: samples.iter().sum::<f64>() / samples.len() as f64,
+            p10: at(10),
+            p50: at(50),
+            p90: at(90),
+        }
+    }
+}
+
+// sizes of the keys and values of a bucket, nested buckets are not included
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Histogram {
+    pub keys: SizeHistogram,
+    // blobs count with their full length
+    pub values: SizeHistogram,
+    // values whose entry doesn't fit in a page on its own, stored inline
+    // they make their leaf span several pages. blobs are counted too
+    pub overflow_values: u64,
+    pub blobs: u64,
+}
+
+// sizes in bytes bucketed by power of two
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SizeHistogram {
+    pub count: u64,
+    pub total: u64,
+    pub min: u64,
+    pub max: u64,
+    // classes[0] counts empty ones, classes[i] sizes in [2^(i-1), 2^i)
+    pub classes: Vec<u64>,
+}
+
+impl SizeHistogram {
+    fn add(&mut self, size: u64) {
+        let class = (u64::BITS - size.leading_zeros()) as usize;
+        if self.classes.len() <= class {
+            self.classes.resize(class + 1, 0);
+        }
+        self.classes[class] += 1;
+        self.min = if self.count == 0 {
+            size
+        } else {
+            self.min.min(size)
+        };
+        self.max = self.max.max(size);
+        self.count += 1;
+        self.total += size;
+    }
+
+    pub fn avg(&self) -> f64 {
+        match self.count {
+            0 => 0.0,
+            n => self.total as f64 / n as f64,
+        }
+    }
+}
+
+// approximate size of a key range, see Bucket::estimate_range
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RangeEstimate {
+    pub keys: u64,
+    // bytes of their keys and values
+    pub bytes: u64,
+}
+
+// whether each level of a root to leaf path is on the last node of its level
+fn rightmost(path: &[(usize, usize)]) -> Vec<bool> {
+    let mut last = true;
+    path.iter()
+        .map(|(index, count)| {
+            let on_last = last;
+            last = last && index + 1 >= *count;
+            on_last
+        })
+        .collect()
+}
+
+// fill of every page seen, by level
+#[derive(Default)]
+struct FillSamples {
+    root: Vec<f64>,
+    internal: Vec<f64>,
+    leaf: Vec<f64>,
+    overflow: Vec<f64>,
+}
+
+impl Bucket {
+    // walk the tree of this bucket as seen by its tx, dirty nodes included
+    pub fn tree_stats(&self) -> Result<TreeStats> {
+        let page_size = self.tx()?.db()?.page_size();
+        let mut stats = TreeStats::default();
+        let mut children = 0;
+        let mut fill = FillSamples::default();
+        let root = self.root_id();
+        self.tree_stats_at(root, 1, page_size, &mut stats, &mut children, &mut fill)?;
+        if stats.branch_pages > 0 {
+            stats.avg_fanout = children as f64 / stats.branch_pages as f64;
+        }
+        stats.root_fill = FillStats::from(fill.root);
+        stats.internal_fill = FillStats::from(fill.internal);
+        stats.leaf_fill = FillStats::from(fill.leaf);
+        stats.overflow_fill = FillStats::from(fill.overflow);
+        Ok(stats)
+    }
+
+    // estimate the entries with keys in [start, end) from the two paths
+    // down to the leaves holding the bounds, assuming the subtrees between
+    // them have the fanout and key sizes seen on those paths. an empty end
+    // means no upper bound. the count is exact when both bounds fall in one
+    // leaf, nested buckets count as entries
+    pub fn estimate_range(&self, start: &[u8], end: &[u8]) -> Result<RangeEstimate> {
+        let mut c = self.cursor();
+        c.seek_to(start)?;
+        let (from, from_bytes) = (c.path(), c.leaf_bytes());
+        if end.is_empty() {
+            c.last()?;
+        } else {
+            c.seek_to(end)?;
+        }
+        let mut to = c.path();
+        if end.is_empty() {
+            // past the last key
+            if let Some(leaf) = to.last_mut() {
+                leaf.0 = leaf.1;
+            }
+        }
+        let to_bytes = c.leaf_bytes();
+        // average fanout of each level. the last node of a level is often
+        // only partly filled, it is left out when the other path has one
+        let (from_last, to_last) = (rightmost(&from), rightmost(&to));
+        let fanout: Vec<f64> = (0..from.len().min(to.len()))
+            .map(|level| {
+                let counts = [
+                    (from[level].1, from_last[level]),
+                    (to[level].1, to_last[level]),
+                ];
+                let full: Vec<usize> = counts.iter().filter(|c| !c.1).map(|c| c.0).collect();
+                match full.len() {
+                    0 => (counts[0].0 + counts[1].0) as f64 / 2.0,
+                    n => full.iter().sum::<usize>() as f64 / n as f64,
+                }
+            })
+            .collect();
+        // entries before a path, every index skipping an average subtree
+        let position = |path: &[(usize, usize)]| {
+            let (mut pos, mut subtree) = (0.0, 1.0);
+            for (level, (index, _)) in path.iter().enumerate().rev() {
+                pos += *index as f64 * subtree;
+                subtree *= fanout.get(level).copied().unwrap_or(1.0);
+            }
+            pos
+        };
+        let keys = (position(&to) - position(&from)).max(0.0);
+        let leaf_keys = from.last().map_or(0, |l| l.1) + to.last().map_or(0, |l| l.1);
+        let per_key = match leaf_keys {
+            0 => 0.0,
+            n => (from_bytes + to_bytes) as f64 / n as f64,
+        };
+        Ok(RangeEstimate {
+            keys: keys.round() as u64,
+            bytes: (keys * per_key).round() as u64,
+        })
+    }
+
+    // key and value sizes of every entry of this bucket as seen by its tx
+    pub fn histogram(&self) -> Result<Histogram> {
+        let page_size = self.tx()?.db()?.page_size() as usize;
+        // room for entries in a page
+        let room = page_size - Page::page_header_size();
+        let mut hist = Histogram::default();
+        let mut c = self.cursor();
+        let mut pair = c.first()?;
+        while let (Some(key), Some(value)) = (pair.key(), pair.value()) {
+            if !pair.is_bucket() {
+                let len = match pair.is_blob() {
+                    true => {
+                        hist.blobs += 1;
+                        parse_descriptor(value)?.0 as usize
+                    }
+                    false => value.len(),
+                };
+                hist.keys.add(key.len() as u64);
+                hist.values.add(len as u64);
+                if LeafPageElement::SIZE + key.len() + len > room {
+                    hist.overflow_values += 1;
+                }
+            }
+            pair = c.next()?;
+        }
+        Ok(hist)
+    }
+
+    // bytes of the pages this bucket and its nested buckets take up. an
+    // inline bucket lives in its parent's leaf and adds nothing
+    pub fn disk_size(&self) -> Result<u64> {
+        let page_size = self.tx()?.db()?.page_size();
+        Ok(self.disk_pages()? * page_size)
+    }
+
+    fn disk_pages(&self) -> Result<u64> {
+        let mut pages = 0;
+        if self.root_id() != 0 {
+            let stats = self.tree_stats()?;
+            pages += stats.branch_pages + stats.leaf_pages + stats.overflow_pages;
+        }
+        let mut c = self.cursor();
+        let mut pair = c.first()?;
+        while let Some(key) = pair.key() {
+            if pair.is_bucket() {
+                if let Some(child) = self.get_bucket(key) {
+                    pages += unsafe { &*child }.disk_pages()?;
+                }
+            }
+            pair = c.next()?;
+        }
+        Ok(pages)
+    }
+
+    fn tree_stats_at(
+        &self,
+        id: PageId,
+        depth: usize,
+        page_size: u64,
+        stats: &mut TreeStats,
+        children: &mut u64,
+        fill: &mut FillSamples,
+    ) -> Result<()> {
+        let page_node = self.page_node(id)?;
+        let (overflow, used, child_ids): (u64, usize, Vec<PageId>) = match page_node.upgrade() {
+            Either::Left(p) if p.is_leaf() => (p.overflow as u64, p.used_bytes(), vec![]),
+            Either::Left(p) => (
+                p.overflow as u64,
+                p.used_bytes(),
+                p.branch_elements()?.iter().map(|b| b.id).collect(),
+            ),
+            Either::Right(n) => (
+                // not written yet, count the pages it will take
+                (n.size().max(1) as u64 - 1) / page_size,
+                n.size(),
+                n.inodes
+                    .borrow()
+                    .iter()
+                    .filter_map(|i| i.page_id())
+                    .collect(),
+            ),
+        };
+        stats.overflow_pages += overflow;
+        let samples = if depth == 1 {
+            &mut fill.root
+        } else if overflow > 0 {
+            &mut fill.overflow
+        } else if page_node.is_leaf() {
+            &mut fill.leaf
+        } else {
+            &mut fill.internal
+        };
+        samples.push(used as f64 / ((overflow + 1) * page_size) as f64);
+        if page_node.is_leaf() {
+            stats.leaf_pages += 1;
+            stats.depth = stats.depth.max(depth);
+            return Ok(());
+        }
+        stats.branch_pages += 1;
+        *children += child_ids.len() as u64;
+        for child in child_ids {
+            self.tree_stats_at(child, depth + 1, page_size, stats, children, fill)?;
+        }
+        Ok(())
+    }
+}
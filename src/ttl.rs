@@ -0,0 +1,131 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    bucket::Bucket,
+    error::{Result, RoltError},
+    Err,
+};
+
+// nested bucket mapping keys to their deadline in unix millis (big-endian)
+pub(crate) const TTL_BUCKET: &[u8] = b"\x00roltdb.ttl";
+
+// the ttl index name can't be used for user keys or buckets
+pub(crate) fn reserved(key: &[u8]) -> Result<()> {
+    if key == TTL_BUCKET {
+        return Err!(RoltError::ReservedKey);
+    }
+    Ok(())
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn decode_deadline(bytes: &[u8]) -> Option<u64> {
+    bytes.try_into().ok().map(u64::from_be_bytes)
+}
+
+impl Bucket {
+    // put a key that expires after ttl, expired keys are hidden from get
+    // and reclaimed when a write tx commits or by purge_expired
+    pub fn put_with_ttl<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Duration,
+    ) -> Result<()> {
+        let key = key.as_ref();
+        // the ttl index is a nested bucket, which a fixed bucket can't hold
+        if self.value_width.is_some() {
+            return Err!(RoltError::IncompatibleValue);
+        }
+        self.put(key, value)?;
+        let deadline = now_millis().saturating_add(ttl.as_millis() as u64);
+        let index = match self.ttl_index() {
+            Some(b) => unsafe { &mut *b },
+            None => self.create_nested(TTL_BUCKET, Self::FLAG)?,
+        };
+        index.put(key, deadline.to_be_bytes())
+    }
+
+    // delete every expired key, returning how many were removed
+    pub fn purge_expired(&mut self) -> Result<usize> {
+        let index = match self.ttl_index() {
+            None => return Ok(0),
+            Some(b) => unsafe { &mut *b },
+        };
+        let expired = self.expired_keys()?;
+        for key in expired.iter() {
+            index.remove(key)?;
+            self.remove(key)?;
+        }
+        Ok(expired.len())
+    }
+
+    // keys whose deadline already passed
+    pub(crate) fn expired_keys(&self) -> Result<Vec<Vec<u8>>> {
+        let index = match self.ttl_index() {
+            None => return Ok(Vec::new()),
+            Some(b) => unsafe { &*b },
+        };
+        let now = now_millis();
+        let mut expired = Vec::new();
+        let mut c = index.cursor();
+        let first = c.first()?;
+        let mut entry = first.key().zip(first.value());
+        while let Some((key, value)) = entry {
+            if matches!(decode_deadline(value), Some(d) if d <= now) {
+                expired.push(key.to_vec());
+            }
+            let pair = c.next()?;
+            entry = pair.key().zip(pair.value());
+        }
+        Ok(expired)
+    }
+
+    // the ttl index of this bucket. a missing index is remembered, buckets
+    // that never used a ttl then skip the search on every get and put
+    fn ttl_index(&self) -> Option<*mut Bucket> {
+        if self.ttl_absent.get() {
+            return None;
+        }
+        let index = self.get_bucket(TTL_BUCKET);
+        self.ttl_absent.set(index.is_none());
+        index
+    }
+
+    // whether key has a deadline that already passed
+    pub(crate) fn expired(&self, key: &[u8]) -> bool {
+        let index = match self.ttl_index() {
+            None => return false,
+            Some(b) => unsafe { &*b },
+        };
+        let mut c = index.cursor();
+        match c.seek(key) {
+            Ok(pair) if pair.key() == Some(key) => {
+                matches!(pair.value().and_then(decode_deadline), Some(d) if d <= now_millis())
+            }
+            _ => false,
+        }
+    }
+
+    // drop the deadline of a key
+    pub(crate) fn clear_ttl(&mut self, key: &[u8]) -> Result<()> {
+        match self.ttl_index() {
+            None => Ok(()),
+            Some(index) => unsafe { &mut *index }.remove(key),
+        }
+    }
+
+    // purge expired keys in this bucket and every nested bucket opened so far
+    pub(crate) fn sweep_expired(&mut self) -> Result<()> {
+        self.purge_expired()?;
+        for child in self.buckets.borrow_mut().values_mut() {
+            child.sweep_expired()?;
+        }
+        Ok(())
+    }
+}
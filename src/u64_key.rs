@@ -0,0 +1,55 @@
+use std::ops::{Bound, RangeBounds};
+
+use crate::{bucket::Bucket, error::Result};
+
+// u64 keys are stored big-endian so byte order matches numeric order
+impl Bucket {
+    pub fn put_u64<V: AsRef<[u8]>>(&mut self, key: u64, value: V) -> Result<()> {
+        self.put(key.to_be_bytes(), value)
+    }
+
+    pub fn get_u64(&self, key: u64) -> Option<&[u8]> {
+        self.get(key.to_be_bytes())
+    }
+
+    pub fn delete_u64(&mut self, key: u64) -> Result<()> {
+        self.delete(key.to_be_bytes())
+    }
+
+    // put value under the next sequence number and return it
+    pub fn put_next<V: AsRef<[u8]>>(&mut self, value: V) -> Result<u64> {
+        let key = self.next_sequence()?;
+        self.put_u64(key, value)?;
+        Ok(key)
+    }
+
+    // entries with u64 keys in range, in numeric order. keys that are not
+    // eight bytes, nested buckets and expired keys are skipped
+    pub fn range_u64<R: RangeBounds<u64>>(&self, range: R) -> Result<Vec<(u64, &[u8])>> {
+        let start = match range.start_bound() {
+            Bound::Included(&n) => Some(n),
+            Bound::Excluded(&n) => n.checked_add(1),
+            Bound::Unbounded => Some(0),
+        };
+        let start = match start {
+            Some(n) => n,
+            None => return Ok(Vec::new()),
+        };
+        let mut entries = Vec::new();
+        let mut c = self.cursor();
+        let mut pair = c.seek(&start.to_be_bytes())?;
+        while let Some((key, value)) = pair.key().zip(pair.value()) {
+            if let Ok(bytes) = <[u8; 8]>::try_from(key) {
+                let n = u64::from_be_bytes(bytes);
+                if !range.contains(&n) {
+                    break;
+                }
+                if !pair.is_bucket() && !self.expired(key) {
+                    entries.push((n, value));
+                }
+            }
+            pair = c.next()?;
+        }
+        Ok(entries)
+    }
+}
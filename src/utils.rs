@@ -1,4 +1,4 @@
-use std::{mem::size_of, slice::from_raw_parts};
+use std::{cmp::Ordering, mem::size_of, slice::from_raw_parts};
 
 pub(crate) unsafe fn struct_to_slice<T: Sized>(p: &T) -> &[u8] {
     from_raw_parts((p as *const T) as *const u8, size_of::<T>())
@@ -8,3 +8,85 @@ pub(crate) unsafe fn struct_to_slice<T: Sized>(p: &T) -> &[u8] {
 pub(crate) unsafe fn arr_to_slice<T: Sized>(p: &[T]) -> &[u8] {
     from_raw_parts(p.as_ptr() as *const u8, size_of::<T>())
 }
+
+// enter a debug-level span until the end of the current scope,
+// compiled away unless the `tracing` feature is enabled
+macro_rules! debug_span {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($($arg)*).entered();
+    };
+}
+pub(crate) use debug_span;
+
+// binary search for target in items sorted by key, like binary_search_by.
+// whatever prefix target shares with both bounds of the range is shared by
+// every key inside it, so comparisons start past it. long common prefixes
+// such as timestamps or namespaces are only compared once
+pub(crate) fn search_keys<T, F>(items: &[T], target: &[u8], key: F) -> Result<usize, usize>
+where
+    F: Fn(&T) -> &[u8],
+{
+    let (mut lo, mut hi) = (0, items.len());
+    // bytes target shares with the key below lo and the key at hi
+    let (mut lo_common, mut hi_common) = (0, 0);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        let k = key(&items[mid]);
+        let skip = lo_common.min(hi_common);
+        let common = skip
+            + k[skip..]
+                .iter()
+                .zip(&target[skip..])
+                .take_while(|(a, b)| a == b)
+                .count();
+        match k[common..].cmp(&target[common..]) {
+            Ordering::Less => {
+                lo = mid + 1;
+                lo_common = common;
+            }
+            Ordering::Greater => {
+                hi = mid;
+                hi_common = common;
+            }
+            Ordering::Equal => return Ok(mid),
+        }
+    }
+    Err(lo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_keys() {
+        let mut keys: Vec<Vec<u8>> = (0..300u32)
+            .map(|i| format!("2024-01-01T00:{:03}", i * 7).into_bytes())
+            .collect();
+        keys.push(b"2024-01-01T00".to_vec());
+        keys.push(b"3".to_vec());
+        keys.sort();
+        let mut targets = keys.clone();
+        targets.extend(
+            [
+                "",
+                "1",
+                "2024",
+                "2024-01-01T00:0005",
+                "2024-01-01T00:999",
+                "4",
+            ]
+            .iter()
+            .map(|t| t.as_bytes().to_vec()),
+        );
+        for t in &targets {
+            assert_eq!(
+                search_keys(&keys, t, |k| k.as_slice()),
+                keys.binary_search(t),
+                "{:?}",
+                String::from_utf8_lossy(t)
+            );
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use either::Either;
+
+use crate::{
+    blob::parse_descriptor,
+    bucket::{Bucket, IBucket},
+    db::DB,
+    error::{Result, RoltError},
+    page::{Page, PageId},
+    Err,
+};
+
+impl DB {
+    // prefetch every page reachable from the given top-level buckets, their
+    // nested buckets and blobs included, so a service can fill the page
+    // cache at startup instead of paying for cold reads on its first
+    // requests. the tree is walked level by level and each level is advised
+    // before it is read. returns the number of pages advised
+    pub fn warm<N: AsRef<[u8]>>(&self, buckets: &[N]) -> Result<u64> {
+        let tx = self.tx(false)?;
+        let page_size = self.page_size();
+        let mmap = tx.mmap();
+        let num_pages = tx.meta().num_pages;
+        let page = |id: PageId| -> Result<&Page> {
+            if id >= num_pages {
+                return Err!(RoltError::PageOutOfRange(id));
+            }
+            Page::from_buf(mmap, id, page_size)
+        };
+
+        let mut level = Vec::new();
+        let mut blobs = Vec::new();
+        for name in buckets {
+            let b = tx.bucket(name).ok_or(RoltError::BucketNotFound)?;
+            match b.root_id() {
+                // inline, its page lives in the parent's leaf
+                0 => match b.page_node(0)?.upgrade() {
+                    Either::Left(p) => leaf(p, &mut level, &mut blobs)?,
+                    Either::Right(_) => return Err!("read tx holds a dirty node"),
+                },
+                root => level.push(root),
+            }
+        }
+        let mut advised = 0;
+        while !level.is_empty() {
+            for &id in level.iter() {
+                advised += self.advise(id, 1);
+            }
+            let mut next = Vec::new();
+            for &id in level.iter() {
+                let p = page(id)?;
+                if p.overflow > 0 {
+                    advised += self.advise(id + 1, p.overflow as u64);
+                }
+                if p.page_type == Page::BRANCH_PAGE {
+                    next.extend(p.branch_elements()?.iter().map(|e| e.id));
+                } else if p.is_leaf() {
+                    leaf(p, &mut next, &mut blobs)?;
+                }
+            }
+            level = next;
+        }
+        // chunks only know the next one, so chains are walked in order
+        for first in blobs {
+            let mut id = first;
+            while id != 0 {
+                advised += self.advise(id, 1);
+                let p = page(id)?;
+                if p.overflow > 0 {
+                    advised += self.advise(id + 1, p.overflow as u64);
+                }
+                id = p.blob_chunk().next;
+            }
+        }
+        Ok(advised)
+    }
+}
+
+// roots of the nested buckets and first chunks of the blobs in a leaf
+fn leaf(p: &Page, roots: &mut Vec<PageId>, blobs: &mut Vec<PageId>) -> Result<()> {
+    for i in 0..p.count as usize {
+        let (_, value, flags) = p.leaf_at(i)?;
+        if flags & Bucket::FLAG != 0 {
+            inline(value, roots, blobs)?;
+        } else if flags & Bucket::BLOB_FLAG != 0 {
+            blobs.push(parse_descriptor(value)?.1);
+        }
+    }
+    Ok(())
+}
+
+// a nested bucket header, followed by the bucket's page when it is inline
+fn inline(value: &[u8], roots: &mut Vec<PageId>, blobs: &mut Vec<PageId>) -> Result<()> {
+    if value.len() < IBucket::SIZE {
+        return Err!("nested bucket header is truncated");
+    }
+    let root = PageId::from_ne_bytes(value[..8].try_into()?);
+    if root != 0 {
+        roots.push(root);
+        return Ok(());
+    }
+    let data = &value[IBucket::SIZE..];
+    if data.len() < Page::page_header_size() {
+        return Ok(());
+    }
+    leaf(Page::from_buf_direct(data)?, roots, blobs)
+}
@@ -0,0 +1,145 @@
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    sync::mpsc::Sender,
+};
+
+use crate::{transaction::Txid, ttl::TTL_BUCKET};
+
+// kind of modification made to a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeOp {
+    Insert,
+    Update,
+    Delete,
+    // a nested bucket named by the key was created, the value carries its
+    // element flags (u32, little-endian)
+    CreateBucket,
+}
+
+// a key modified by a committed write tx
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    // name of the bucket holding the key, empty for the root bucket
+    pub bucket: Vec<u8>,
+    // names of the buckets from the top level down to the one holding the
+    // key, bucket is the last of them
+    pub path: Vec<Vec<u8>>,
+    pub key: Vec<u8>,
+    pub op: ChangeOp,
+}
+
+impl ChangeEvent {
+    // a change to a ttl index or the creation of one
+    pub(crate) fn is_ttl(&self) -> bool {
+        self.bucket == TTL_BUCKET || (self.op == ChangeOp::CreateBucket && self.key == TTL_BUCKET)
+    }
+}
+
+// keys modified by a committed write tx, in (bucket, key) order. keys of
+// the ttl index are left out
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeSet {
+    pub tx_id: Txid,
+    pub changes: Vec<ChangeEvent>,
+}
+
+// bucket name and key
+pub(crate) type ChangeKey = (Vec<u8>, Vec<u8>);
+
+// bucket path and key. a bucket sorts before the keys in it, since its path
+// is a prefix of theirs
+type PathKey = (Vec<Vec<u8>>, Vec<u8>);
+
+// changes of a write tx, coalesced per (bucket path, key)
+#[derive(Debug, Default)]
+pub(crate) struct Changes {
+    ops: BTreeMap<PathKey, (ChangeOp, Vec<u8>)>,
+    // keep the last written value of each key, the replication log needs them
+    keep_values: bool,
+}
+
+impl Changes {
+    pub(crate) fn new(keep_values: bool) -> Self {
+        Self {
+            ops: BTreeMap::new(),
+            keep_values,
+        }
+    }
+    pub(crate) fn record(&mut self, path: &[Vec<u8>], key: &[u8], op: ChangeOp, value: &[u8]) {
+        let value = if self.keep_values {
+            value.to_vec()
+        } else {
+            Vec::new()
+        };
+        match self.ops.entry((path.to_vec(), key.to_vec())) {
+            Entry::Vacant(e) => {
+                e.insert((op, value));
+            }
+            Entry::Occupied(mut e) => match (e.get().0, op) {
+                // the key did not exist before this tx
+                (ChangeOp::Insert, ChangeOp::Update) => {
+                    e.get_mut().1 = value;
+                }
+                (ChangeOp::Insert, ChangeOp::Delete) => {
+                    e.remove();
+                }
+                // the key existed before this tx
+                (ChangeOp::Delete, ChangeOp::Insert) => {
+                    e.insert((ChangeOp::Update, value));
+                }
+                (_, op) => {
+                    e.insert((op, value));
+                }
+            },
+        }
+    }
+    // drain the changes together with their values, empty unless kept
+    pub(crate) fn take(&mut self) -> Vec<(ChangeEvent, Vec<u8>)> {
+        std::mem::take(&mut self.ops)
+            .into_iter()
+            .map(|((path, key), (op, value))| {
+                let bucket = path.last().cloned().unwrap_or_default();
+                (
+                    ChangeEvent {
+                        bucket,
+                        path,
+                        key,
+                        op,
+                    },
+                    value,
+                )
+            })
+            .collect()
+    }
+}
+
+// a subscriber to changes under a key prefix of one bucket
+#[derive(Debug)]
+pub(crate) struct Watcher {
+    bucket: Vec<u8>,
+    prefix: Vec<u8>,
+    sender: Sender<ChangeEvent>,
+}
+
+impl Watcher {
+    pub(crate) fn new(bucket: &[u8], prefix: &[u8], sender: Sender<ChangeEvent>) -> Self {
+        Self {
+            bucket: bucket.to_vec(),
+            prefix: prefix.to_vec(),
+            sender,
+        }
+    }
+    fn matches(&self, e: &ChangeEvent) -> bool {
+        e.bucket == self.bucket && e.key.starts_with(&self.prefix)
+    }
+}
+
+// send committed changes to matching watchers, dropping the disconnected ones
+pub(crate) fn publish(watchers: &mut Vec<Watcher>, events: &[ChangeEvent]) {
+    watchers.retain(|w| {
+        events
+            .iter()
+            .filter(|e| w.matches(e))
+            .all(|e| w.sender.send(e.clone()).is_ok())
+    });
+}
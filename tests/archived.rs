@@ -0,0 +1,28 @@
+#![cfg(feature = "rkyv")]
+use rkyv::{Archive, Serialize};
+use roltdb::DB;
+
+#[derive(Archive, Serialize)]
+struct Record {
+    id: u64,
+    tags: Vec<String>,
+}
+
+#[test]
+fn archived_values() {
+    let path = "./tests/archived.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("records").unwrap();
+    let record = Record {
+        id: 42,
+        tags: vec!["a".to_string(), "b".to_string()],
+    };
+    // odd key length so the value is not aligned inside the node
+    b.put_archived("abc", &record).unwrap();
+    let archived = b.get_archived::<Record>("abc").unwrap().unwrap();
+    assert_eq!(archived.id, 42);
+    assert_eq!(archived.tags[1], "b");
+    assert!(b.get_archived::<Record>("missing").unwrap().is_none());
+}
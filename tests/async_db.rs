@@ -0,0 +1,45 @@
+#![cfg(feature = "async")]
+use std::time::Duration;
+
+use roltdb::{AsyncDB, DBBuilder};
+
+#[tokio::test]
+async fn update_and_view() {
+    let path = "./tests/async.db";
+    let _ = std::fs::remove_file(path);
+    let db = AsyncDB::open(path).await.unwrap();
+    db.update(|tx| {
+        let mut b = tx.create_bucket("test")?;
+        b.put("hello", "world")
+    })
+    .await
+    .unwrap();
+    let value = db
+        .update(|tx| {
+            let b = tx.create_bucket_if_not_exist("test")?;
+            Ok(b.get("hello").map(|v| v.to_vec()))
+        })
+        .await
+        .unwrap();
+    assert_eq!(value.as_deref(), Some(&b"world"[..]));
+    let writable = db.view(|tx| Ok(tx.writable())).await.unwrap();
+    assert!(!writable);
+}
+
+#[tokio::test]
+async fn close() {
+    let path = "./tests/async_close.db";
+    let _ = std::fs::remove_file(path);
+    let db = AsyncDB::open(path).await.unwrap();
+    db.update(|tx| tx.create_bucket("test")?.put("k", "v"))
+        .await
+        .unwrap();
+    db.close().await.unwrap();
+    // the worker let go of the file lock
+    let db = DBBuilder::default()
+        .lock_timeout(Some(Duration::ZERO))
+        .open(path)
+        .unwrap();
+    let tx = db.tx(false).unwrap();
+    assert_eq!(tx.bucket("test").unwrap().get("k"), Some(&b"v"[..]));
+}
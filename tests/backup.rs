@@ -0,0 +1,207 @@
+use std::{cell::Cell, fs::File, rc::Rc, time::Instant};
+
+use roltdb::{apply_incremental, BackupOptions, SnapshotSink, DB};
+
+#[test]
+fn backup() {
+    let path = "./tests/backup.db";
+    let copy = "./tests/backup_copy.db";
+    let _ = std::fs::remove_file(path);
+    let _ = std::fs::remove_file(copy);
+    let db = DB::open(path).unwrap();
+    {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket("test").unwrap();
+        b.put("k", "v").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+
+    let calls = Rc::new(Cell::new(0));
+    let seen = calls.clone();
+    let mut out = File::create(copy).unwrap();
+    let tx = db.tx(false).unwrap();
+    let start = Instant::now();
+    let written = tx
+        .backup(
+            &mut out,
+            BackupOptions {
+                rate_limit_bytes_per_sec: Some(1 << 20),
+                progress: Some(Box::new(move |done, total| {
+                    assert!(done <= total);
+                    seen.set(seen.get() + 1);
+                })),
+            },
+        )
+        .unwrap();
+    assert!(calls.get() > 0);
+    assert_eq!(out.metadata().unwrap().len(), written);
+    assert!(start.elapsed().as_secs_f64() >= written as f64 / (1 << 20) as f64 * 0.9);
+    drop(tx);
+    drop(db);
+
+    let db = DB::open(copy).unwrap();
+    let tx = db.tx(true).unwrap();
+    let b = tx.create_bucket_if_not_exist("test").unwrap();
+    assert_eq!(b.get("k"), Some(&b"v"[..]));
+    drop(b);
+    drop(tx);
+    std::fs::remove_file(copy).unwrap();
+}
+
+#[test]
+fn incremental() {
+    let path = "./tests/incremental.db";
+    let copy = "./tests/incremental_copy.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    for i in 0..20 {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist(format!("b{}", i)).unwrap();
+        b.put("k", "v").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+    let tx = db.tx(false).unwrap();
+    let base = tx.id();
+    let full = tx.write_to(&mut File::create(copy).unwrap()).unwrap();
+    drop(tx);
+    {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist("b0").unwrap();
+        b.put("k", "v2").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+
+    let tx = db.tx(false).unwrap();
+    let mut inc = Vec::new();
+    let pages = tx.write_changed_since(base, &mut inc).unwrap();
+    assert!(pages > 0);
+    assert!((inc.len() as u64) < full);
+    let head = tx.id();
+    drop(tx);
+    drop(db);
+    assert_eq!(apply_incremental(copy, &mut &inc[..]).unwrap(), head);
+
+    let db = DB::open(copy).unwrap();
+    let tx = db.tx(true).unwrap();
+    for i in 0..20 {
+        let b = tx.create_bucket_if_not_exist(format!("b{}", i)).unwrap();
+        let expected: &[u8] = if i == 0 { b"v2" } else { b"v" };
+        assert_eq!(b.get("k"), Some(expected));
+    }
+    drop(tx);
+    std::fs::remove_file(copy).unwrap();
+}
+
+#[test]
+fn incremental_onto_newer_backup() {
+    let path = "./tests/incremental_newer.db";
+    let copy = "./tests/incremental_newer_copy.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let put = |value: &str| {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+        b.put("k", value).unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    };
+    put("v1");
+    let base = db.tx(false).unwrap().id();
+    put("v2");
+    let tx = db.tx(false).unwrap();
+    let mut inc = Vec::new();
+    tx.write_changed_since(base, &mut inc).unwrap();
+    drop(tx);
+    // the backup moved past the incremental
+    put("v3");
+    let tx = db.tx(false).unwrap();
+    tx.write_to(&mut File::create(copy).unwrap()).unwrap();
+    drop(tx);
+    drop(db);
+
+    let err = apply_incremental(copy, &mut &inc[..]).unwrap_err();
+    assert!(err.to_string().contains("newer"));
+    let db = DB::open(copy).unwrap();
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    assert_eq!(tx.bucket("test").unwrap().get("k"), Some(&b"v3"[..]));
+    drop(tx);
+    drop(db);
+    std::fs::remove_file(copy).unwrap();
+}
+
+// collects chunks in memory, standing in for a remote upload
+#[derive(Default)]
+struct MemorySink {
+    total: u64,
+    data: Vec<u8>,
+    finished: bool,
+    aborted: bool,
+    fail_after: Option<usize>,
+}
+
+impl SnapshotSink for MemorySink {
+    fn open(&mut self, total: u64) -> roltdb::Result<()> {
+        self.total = total;
+        Ok(())
+    }
+    fn write_chunk(&mut self, chunk: &[u8]) -> roltdb::Result<()> {
+        if self.fail_after.is_some_and(|n| self.data.len() >= n) {
+            return Err("upload failed".into());
+        }
+        self.data.extend_from_slice(chunk);
+        Ok(())
+    }
+    fn finish(&mut self) -> roltdb::Result<()> {
+        self.finished = true;
+        Ok(())
+    }
+    fn abort(&mut self) {
+        self.aborted = true;
+    }
+}
+
+#[test]
+fn backup_to_sink() {
+    let path = "./tests/backup_sink.db";
+    let copy = "./tests/backup_sink_copy.db";
+    let _ = std::fs::remove_file(path);
+    let _ = std::fs::remove_file(copy);
+    let db = DB::open(path).unwrap();
+    {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket("test").unwrap();
+        b.put("k", "v").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+
+    let tx = db.tx(false).unwrap();
+    let mut sink = MemorySink::default();
+    let written = tx.backup_to(&mut sink, BackupOptions::default()).unwrap();
+    assert!(sink.finished && !sink.aborted);
+    assert_eq!(sink.total, written);
+    assert_eq!(sink.data.len() as u64, written);
+
+    let mut failing = MemorySink {
+        fail_after: Some(1),
+        ..Default::default()
+    };
+    assert!(tx
+        .backup_to(&mut failing, BackupOptions::default())
+        .is_err());
+    assert!(failing.aborted && !failing.finished);
+    drop(tx);
+    drop(db);
+
+    std::fs::write(copy, &sink.data).unwrap();
+    let db = DB::open(copy).unwrap();
+    let tx = db.tx(false).unwrap();
+    assert_eq!(tx.bucket("test").unwrap().get("k"), Some(&b"v"[..]));
+    drop(tx);
+    drop(db);
+    std::fs::remove_file(copy).unwrap();
+}
@@ -0,0 +1,28 @@
+use roltdb::{BenchOptions, KeyOrder, DB};
+
+#[test]
+fn bench() {
+    let path = "./tests/bench.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let opts = BenchOptions {
+        count: 500,
+        batch_size: 100,
+        key_size: 16,
+        value_size: 20,
+        order: KeyOrder::Random,
+    };
+    let report = roltdb::bench(&db, &opts).unwrap();
+    assert_eq!(report.writes, 500);
+    assert_eq!(report.reads, 500);
+    let latency = report.commit_latency;
+    assert!(latency.p50 <= latency.p90 && latency.p90 <= latency.p99);
+    assert!(latency.p99 <= latency.max && !latency.max.is_zero());
+    assert!(report.write_ops_per_sec() > 0.0);
+
+    let opts = BenchOptions {
+        key_size: 4,
+        ..Default::default()
+    };
+    assert!(roltdb::bench(&db, &opts).is_err());
+}
@@ -0,0 +1,150 @@
+use std::{
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+use roltdb::{DBBuilder, MaintenanceAction, MaintenanceOptions, DB};
+
+fn data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+fn read_blob(b: &roltdb::Bucket, key: &str) -> Option<Vec<u8>> {
+    let mut r = b.get_blob_reader(key).unwrap()?;
+    let mut out = Vec::new();
+    r.read_to_end(&mut out).unwrap();
+    assert_eq!(out.len() as u64, r.len());
+    Some(out)
+}
+
+#[test]
+fn blobs() {
+    let path = "./tests/blob.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let big = data(5 << 20);
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("files").unwrap();
+    assert_eq!(b.put_blob("big", &mut &big[..]).unwrap(), big.len() as u64);
+    assert_eq!(b.put_blob("small", &mut &b"tiny"[..]).unwrap(), 4);
+    b.put("plain", "v").unwrap();
+    // blobs only come back through a reader
+    assert_eq!(b.get("big"), None);
+    assert_eq!(b.get("small"), Some(&b"tiny"[..]));
+    assert_eq!(read_blob(&b, "big").unwrap(), big);
+    assert_eq!(read_blob(&b, "plain").unwrap(), b"v");
+    assert!(read_blob(&b, "missing").is_none());
+    // a blob in an inline bucket
+    let nested = b.create_bucket("nested").unwrap();
+    nested.put_blob("b", &mut &data(100_000)[..]).unwrap();
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    let b = tx.bucket("files").unwrap();
+    assert_eq!(read_blob(&b, "big").unwrap(), big);
+    let nested = b.bucket("nested").unwrap();
+    assert_eq!(read_blob(nested, "b").unwrap(), data(100_000));
+    let pair = b.cursor().seek(b"big").unwrap();
+    assert!(pair.is_blob());
+    drop(b);
+    drop(tx);
+
+    // replacing and deleting blobs gives their pages back
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("files").unwrap();
+    b.put_blob("big", &mut &data(3 << 20)[..]).unwrap();
+    b.put("small", "now plain").unwrap();
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("files").unwrap();
+    assert_eq!(read_blob(&b, "big").unwrap(), data(3 << 20));
+    b.delete("big").unwrap();
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    let used: u64 = tx
+        .pages()
+        .filter(|p| p.page_type == roltdb::PageKind::Blob)
+        .count() as u64;
+    assert!(used < 10);
+}
+
+#[test]
+fn compact_copies_blobs() {
+    let path = "./tests/blob_compact.db";
+    let _ = std::fs::remove_file(path);
+    {
+        let db = DB::open(path).unwrap();
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket("files").unwrap();
+        b.put_blob("a", &mut &data(1 << 20)[..]).unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+    let opts = MaintenanceOptions {
+        interval: Duration::ZERO,
+        compact_free_ratio: 0.0,
+        truncate_tail: true,
+    };
+    let db = DBBuilder::default().maintenance(opts).open(path).unwrap();
+    assert!(matches!(
+        db.maintain().unwrap(),
+        MaintenanceAction::Compacted { .. }
+    ));
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    let b = tx.bucket("files").unwrap();
+    assert_eq!(read_blob(&b, "a").unwrap(), data(1 << 20));
+}
+
+#[test]
+fn streaming_values() {
+    let path = "./tests/blob_stream.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let big = data(3 << 20);
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("files").unwrap();
+    let before = db.stats();
+    let mut w = b.value_writer("big", big.len() as u64).unwrap();
+    for piece in big.chunks(1000) {
+        w.write_all(piece).unwrap();
+    }
+    assert_eq!(w.remaining(), 0);
+    w.finish().unwrap();
+    // chunks go to the file as they fill rather than waiting for commit
+    assert!(db.stats().since(&before).write_bytes >= big.len() as u64);
+    let mut out = Vec::new();
+    io::copy(&mut b.value_reader("big").unwrap().unwrap(), &mut out).unwrap();
+    assert_eq!(out, big);
+    // small values are put inline
+    let mut w = b.value_writer("small", 5).unwrap();
+    io::copy(&mut &b"hello"[..], &mut w).unwrap();
+    w.finish().unwrap();
+    assert_eq!(b.get("small"), Some(&b"hello"[..]));
+    b.value_writer("empty", 0).unwrap().finish().unwrap();
+    assert_eq!(b.get("empty"), Some(&b""[..]));
+    // a writer stores nothing until the declared length is reached
+    let mut w = b.value_writer("short", 1 << 20).unwrap();
+    w.write_all(&big[..4096]).unwrap();
+    assert!(w.write_all(&big).is_err());
+    assert!(w.finish().is_err());
+    assert!(b.value_reader("short").unwrap().is_none());
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    let b = tx.bucket("files").unwrap();
+    let mut out = Vec::new();
+    io::copy(&mut b.value_reader("big").unwrap().unwrap(), &mut out).unwrap();
+    assert_eq!(out, big);
+}
@@ -0,0 +1,242 @@
+use std::{
+    fs::OpenOptions,
+    io::{Seek, SeekFrom, Write},
+};
+
+use roltdb::{DBBuilder, IssueKind, PageKind, RoltError, Severity, VerifyOptions, DB};
+
+fn fill(db: &DB) {
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("big").unwrap();
+    for i in 0..300u32 {
+        b.put(format!("key-{:05}", i), [3u8; 64]).unwrap();
+    }
+    drop(b);
+    let mut b = tx.create_bucket("small").unwrap();
+    b.put("a", "1").unwrap();
+    drop(b);
+    tx.commit().unwrap();
+}
+
+#[test]
+fn check_clean() {
+    let path = "./tests/check_clean.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    fill(&db);
+    let tx = db.tx(false).unwrap();
+    let report = tx.check().unwrap();
+    assert!(report.is_empty());
+    assert_eq!(report.severity(), None);
+}
+
+#[test]
+fn check_corrupt() {
+    let path = "./tests/check_corrupt.db";
+    let _ = std::fs::remove_file(path);
+    let page_size = page_size::get() as u64;
+    let leaf = {
+        let db = DB::open(path).unwrap();
+        fill(&db);
+        let tx = db.tx(false).unwrap();
+        tx.pages()
+            .find(|p| p.page_type == PageKind::Leaf && p.count > 10)
+            .unwrap()
+            .id
+    };
+    // clobber the page type of a leaf of the big bucket
+    let mut f = OpenOptions::new().write(true).open(path).unwrap();
+    f.seek(SeekFrom::Start(leaf * page_size + 8)).unwrap();
+    f.write_all(&[0x7f]).unwrap();
+    drop(f);
+
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    let mut found = Vec::new();
+    tx.check_with(|e| found.push(e)).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].page_id, leaf);
+    assert!(found[0].to_string().contains("unexpected page type"));
+    assert_eq!(found[0].kind, IssueKind::CorruptPage);
+    assert_eq!(found[0].severity, Severity::Error);
+    assert_eq!(found[0].bucket, vec![b"big".to_vec()]);
+    assert!(found[0].to_string().contains("in bucket big"));
+    assert_eq!(tx.check().unwrap().severity(), Some(Severity::Error));
+}
+
+#[test]
+fn verify_offline() {
+    let path = "./tests/verify.db";
+    let _ = std::fs::remove_file(path);
+    let page_size = page_size::get() as u64;
+    let (leaf, tx_id) = {
+        let db = DB::open(path).unwrap();
+        fill(&db);
+        let tx = db.tx(false).unwrap();
+        let leaf = tx
+            .pages()
+            .find(|p| p.page_type == PageKind::Leaf && p.count > 10)
+            .unwrap()
+            .id;
+        (leaf, tx.id())
+    };
+    let report = DB::verify(path, VerifyOptions::default()).unwrap();
+    assert!(report.is_ok(), "{:?}", report.errors);
+    assert_eq!(report.tx_id, Some(tx_id));
+
+    let mut f = OpenOptions::new().write(true).open(path).unwrap();
+    f.seek(SeekFrom::Start(leaf * page_size + 8)).unwrap();
+    f.write_all(&[0x7f]).unwrap();
+    // break the checksum of both meta pages through their version field
+    for id in 0..2 {
+        f.seek(SeekFrom::Start(id * page_size + 28)).unwrap();
+        f.write_all(&[0xff]).unwrap();
+    }
+    drop(f);
+
+    let report = DB::verify(path, VerifyOptions::default()).unwrap();
+    assert_eq!(report.tx_id, None);
+    let metas: Vec<_> = report.errors.iter().map(|e| e.page_id).collect();
+    assert_eq!(metas, [0, 1]);
+    assert!(report.errors[0].message.contains("checksum"));
+    assert_eq!(report.errors[0].kind, IssueKind::Meta);
+    // neither meta page is usable
+    assert_eq!(report.severity(), Some(Severity::Fatal));
+    let err = DB::open(path).unwrap_err();
+    assert!(matches!(err, RoltError::Corruption(_)));
+
+    let opts = VerifyOptions {
+        ignore_checksum: true,
+        ..Default::default()
+    };
+    let report = DB::verify(path, opts).unwrap();
+    assert_eq!(report.tx_id, Some(tx_id));
+    assert_eq!(report.errors.len(), 3);
+    assert_eq!(report.errors[2].page_id, leaf);
+    assert!(report.errors[2].message.contains("unexpected page type"));
+}
+
+#[test]
+fn torn_write() {
+    let path = "./tests/torn_write.db";
+    let _ = std::fs::remove_file(path);
+    let page_size = page_size::get() as u64;
+    let (leaf, tx_id) = {
+        let db = DB::open(path).unwrap();
+        fill(&db);
+        let tx = db.tx(false).unwrap();
+        let leaf = tx
+            .pages()
+            .find(|p| p.page_type == PageKind::Leaf && p.count > 10)
+            .unwrap()
+            .id;
+        (leaf, tx.id())
+    };
+    // stamp a reachable leaf with a txid the meta page never committed
+    let mut f = OpenOptions::new().write(true).open(path).unwrap();
+    f.seek(SeekFrom::Start(leaf * page_size + 16)).unwrap();
+    f.write_all(&(tx_id + 1).to_ne_bytes()).unwrap();
+    drop(f);
+
+    let report = DB::verify(path, VerifyOptions::default()).unwrap();
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].kind, IssueKind::TornWrite);
+    assert_eq!(report.errors[0].page_id, leaf);
+    assert_eq!(report.errors[0].bucket, vec![b"big".to_vec()]);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    assert_eq!(tx.check().unwrap().severity(), Some(Severity::Error));
+}
+
+#[test]
+fn double_write() {
+    let path = "./tests/double_write.db";
+    let _ = std::fs::remove_file(path);
+    let page_size = page_size::get() as u64;
+    let builder = || DBBuilder::default().double_write(true);
+    let leaf = {
+        let db = builder().open(path).unwrap();
+        fill(&db);
+        let tx = db.tx(false).unwrap();
+        tx.pages()
+            .find(|p| p.page_type == PageKind::Leaf && p.count > 10)
+            .unwrap()
+            .id
+    };
+    // tear a page the last commit wrote
+    let mut f = OpenOptions::new().write(true).open(path).unwrap();
+    f.seek(SeekFrom::Start(leaf * page_size + 100)).unwrap();
+    f.write_all(&[0xee; 256]).unwrap();
+    f.seek(SeekFrom::Start(leaf * page_size + 8)).unwrap();
+    f.write_all(&[0x7f]).unwrap();
+    drop(f);
+    assert!(!DB::verify(path, VerifyOptions::default()).unwrap().is_ok());
+
+    let db = builder().open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    let b = tx.bucket("big").unwrap();
+    assert_eq!(b.get("key-00123"), Some(&[3u8; 64][..]));
+    drop(b);
+    drop(tx);
+
+    // the buffer of a later commit doesn't touch pages of earlier ones
+    let tx = db.tx(true).unwrap();
+    tx.create_bucket_if_not_exist("small")
+        .unwrap()
+        .put("b", "2")
+        .unwrap();
+    tx.commit().unwrap();
+    drop(tx);
+    drop(db);
+    let db = builder().open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    let _ = std::fs::remove_file("./tests/double_write.db.dwb");
+}
+
+// fnv-1a, the meta page checksum
+fn fnv64(buf: &[u8]) -> u64 {
+    buf.iter().fold(0xcbf29ce484222325, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[test]
+fn old_format_version() {
+    let path = "./tests/old_version.db";
+    let _ = std::fs::remove_file(path);
+    let page_size = page_size::get() as u64;
+    {
+        let db = DB::open(path).unwrap();
+        fill(&db);
+    }
+    // stamp both meta pages with version 2 and a matching checksum. the meta
+    // is 72 bytes starting 8 bytes before the magic number, its last 8 bytes
+    // are the checksum
+    let mut bytes = std::fs::read(path).unwrap();
+    let magic = 0xF0F43Fu32.to_ne_bytes();
+    for id in 0..2 {
+        let page = &mut bytes[(id * page_size) as usize..((id + 1) * page_size) as usize];
+        let start = page.windows(4).position(|w| w == magic).unwrap() - 8;
+        let meta = &mut page[start..start + 72];
+        meta[12..16].copy_from_slice(&2u32.to_ne_bytes());
+        let sum = fnv64(&meta[..64]);
+        meta[64..].copy_from_slice(&sum.to_ne_bytes());
+    }
+    std::fs::write(path, &bytes).unwrap();
+
+    let err = DB::open(path).unwrap_err();
+    assert!(matches!(
+        err,
+        RoltError::IncompatibleVersion {
+            expected: 3,
+            found: 2
+        }
+    ));
+    let err = DB::verify(path, VerifyOptions::default()).unwrap_err();
+    assert!(matches!(
+        err,
+        RoltError::IncompatibleVersion { found: 2, .. }
+    ));
+}
@@ -0,0 +1,28 @@
+#![cfg(feature = "serde")]
+use roltdb::{Bincode, Json, DB};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct User {
+    id: u64,
+    name: String,
+}
+
+#[test]
+fn typed_values() {
+    let path = "./tests/codec.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("users").unwrap();
+    let user = User {
+        id: 1,
+        name: "rolt".to_string(),
+    };
+    b.put_typed::<Bincode, _>(b"bin", &user).unwrap();
+    b.put_typed::<Json, _>(b"json", &user).unwrap();
+    assert_eq!(b.get_typed::<Bincode, User>(b"bin").unwrap(), Some(user));
+    let json: User = b.get_typed::<Json, _>(b"json").unwrap().unwrap();
+    assert_eq!(json.name, "rolt");
+    assert_eq!(b.get_typed::<Json, User>(b"missing").unwrap(), None);
+}
@@ -0,0 +1,622 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use roltdb::{DBBuilder, Metrics, PageKind, DB};
+
+// counts nodes materialized from pages
+#[derive(Default)]
+struct Misses(AtomicU64);
+
+impl Metrics for Misses {
+    fn cache_miss(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn zero_copy_scan() {
+    let path = "./tests/cursor.db";
+    let _ = std::fs::remove_file(path);
+    let misses = Arc::new(Misses::default());
+    let db = DBBuilder::default()
+        .metrics(misses.clone())
+        .open(path)
+        .unwrap();
+    {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket("scan").unwrap();
+        for i in 0..100u32 {
+            b.put(format!("k{:03}", i), i.to_be_bytes()).unwrap();
+        }
+        drop(b);
+        tx.commit().unwrap();
+    }
+    let before = misses.0.load(Ordering::Relaxed);
+
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("scan").unwrap();
+    let mut c = b.cursor();
+    let mut pair = c.first().unwrap();
+    let mut n = 0u32;
+    while let Some(key) = pair.key() {
+        assert_eq!(key, format!("k{:03}", n).as_bytes());
+        assert_eq!(pair.value(), Some(&n.to_be_bytes()[..]));
+        n += 1;
+        pair = c.next().unwrap();
+    }
+    assert_eq!(n, 100);
+    let pair = c.seek(b"k050").unwrap();
+    assert_eq!(pair.key(), Some(&b"k050"[..]));
+    assert_eq!(b.get("k099"), Some(&99u32.to_be_bytes()[..]));
+    // reads never turn pages into owned nodes
+    assert_eq!(misses.0.load(Ordering::Relaxed), before);
+    drop(b);
+    drop(tx);
+
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("scan").unwrap();
+    assert!(b.get("k001").is_some());
+    assert_eq!(misses.0.load(Ordering::Relaxed), before);
+    b.put("k001", "dirty").unwrap();
+    assert!(misses.0.load(Ordering::Relaxed) > before);
+}
+
+#[test]
+fn bucket_flag_persists() {
+    let path = "./tests/bucket_flag.db";
+    let _ = std::fs::remove_file(path);
+    {
+        let db = DBBuilder::default().open(path).unwrap();
+        let tx = db.tx(true).unwrap();
+        tx.create_bucket("nested").unwrap().put("k", "v").unwrap();
+        tx.commit().unwrap();
+    }
+    let db = DBBuilder::default().open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    let root = tx.root.read();
+    let mut c = root.cursor();
+    let pair = c.first().unwrap();
+    assert_eq!(pair.key(), Some(&b"nested"[..]));
+    assert!(pair.is_bucket());
+}
+
+#[test]
+fn read_ahead() {
+    let path = "./tests/read_ahead.db";
+    let _ = std::fs::remove_file(path);
+    {
+        let db = DBBuilder::default().open(path).unwrap();
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket("scan").unwrap();
+        for i in 0..2000u32 {
+            b.put(format!("k{:05}", i), [7u8; 64]).unwrap();
+        }
+        drop(b);
+        tx.commit().unwrap();
+    }
+    let db = DBBuilder::default().open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("scan").unwrap();
+
+    // point lookups don't read ahead
+    assert!(b.get("k01000").is_some());
+    assert_eq!(db.stats().readahead_pages, 0);
+
+    let mut c = b.cursor();
+    let mut pair = c.first().unwrap();
+    let mut n = 0u32;
+    while let Some(key) = pair.key() {
+        assert_eq!(key, format!("k{:05}", n).as_bytes());
+        n += 1;
+        pair = c.next().unwrap();
+    }
+    assert_eq!(n, 2000);
+    assert!(db.stats().readahead_pages > 0);
+}
+
+#[test]
+fn first_last() {
+    let path = "./tests/first_last.db";
+    let _ = std::fs::remove_file(path);
+    let db = DBBuilder::default().open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    assert_eq!(b.first(), None);
+    assert_eq!(b.last(), None);
+    for i in 0..1000u32 {
+        b.put(format!("k{:04}", i), i.to_be_bytes()).unwrap();
+    }
+    // nested buckets sort after the keys but aren't entries
+    b.create_bucket("z-nested").unwrap();
+    assert_eq!(b.last().map(|(k, _)| k), Some(&b"k0999"[..]));
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("test").unwrap();
+    assert_eq!(b.first(), Some((&b"k0000"[..], &0u32.to_be_bytes()[..])));
+    assert_eq!(b.last(), Some((&b"k0999"[..], &999u32.to_be_bytes()[..])));
+
+    // walking back from the end visits every key
+    let mut c = b.cursor();
+    let mut pair = c.last().unwrap();
+    let mut n = 1000u32;
+    while let Some(key) = pair.key() {
+        if !pair.is_bucket() {
+            n -= 1;
+            assert_eq!(key, format!("k{:04}", n).as_bytes());
+        }
+        pair = c.prev().unwrap();
+    }
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn user_flags() {
+    let path = "./tests/user_flags.db";
+    let _ = std::fs::remove_file(path);
+    {
+        let db = DBBuilder::default().open(path).unwrap();
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket("test").unwrap();
+        b.put_with_flags("pinned", "v", 0x8001).unwrap();
+        b.put("plain", "v").unwrap();
+        b.put_with_flags("reset", "v", 7).unwrap();
+        b.put("reset", "v2").unwrap();
+        b.create_bucket("nested").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+    let db = DBBuilder::default().open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("test").unwrap();
+    let mut c = b.cursor();
+    let pair = c.seek(b"pinned").unwrap();
+    assert_eq!(pair.user_flags(), 0x8001);
+    assert!(!pair.is_bucket());
+    assert_eq!(c.seek(b"plain").unwrap().user_flags(), 0);
+    assert_eq!(c.seek(b"reset").unwrap().user_flags(), 0);
+    assert!(c.seek(b"nested").unwrap().is_bucket());
+    // flagged keys read like any other
+    assert_eq!(b.get("pinned"), Some(&b"v"[..]));
+}
+
+#[test]
+fn seek_prefix() {
+    let path = "./tests/seek_prefix.db";
+    let _ = std::fs::remove_file(path);
+    let db = DBBuilder::default().open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    for user in ["alice", "bob", "carol"] {
+        for i in 0..300u32 {
+            b.put(format!("{}/{:03}", user, i), "v").unwrap();
+        }
+    }
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("test").unwrap();
+    let mut c = b.cursor();
+    let mut pair = c.seek_prefix(b"bob/").unwrap();
+    let mut n = 0u32;
+    while let Some(key) = pair.key() {
+        assert_eq!(key, format!("bob/{:03}", n).as_bytes());
+        n += 1;
+        pair = c.next().unwrap();
+    }
+    assert_eq!(n, 300);
+    // stays at the end instead of running into carol's keys
+    assert_eq!(c.next().unwrap().key(), None);
+
+    let pair = c.seek_prefix(b"bob/299").unwrap();
+    assert_eq!(pair.key(), Some(&b"bob/299"[..]));
+    assert_eq!(c.prev().unwrap().key(), None);
+
+    assert_eq!(c.seek_prefix(b"dave/").unwrap().key(), None);
+    assert_eq!(c.seek_prefix(b"b").unwrap().key(), Some(&b"bob/000"[..]));
+    // a plain seek drops the bound
+    c.seek(b"bob/299").unwrap();
+    assert_eq!(c.next().unwrap().key(), Some(&b"carol/000"[..]));
+}
+
+#[test]
+fn reset() {
+    let path = "./tests/cursor_reset.db";
+    let _ = std::fs::remove_file(path);
+    let db = DBBuilder::default().open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    for i in 0..500u32 {
+        b.put(format!("k{:03}", i), i.to_be_bytes()).unwrap();
+    }
+    let mut c = b.cursor();
+    assert_eq!(c.seek_prefix(b"k499").unwrap().key(), Some(&b"k499"[..]));
+    c.reset();
+    assert_eq!(c.first().unwrap().key(), Some(&b"k000"[..]));
+    assert_eq!(c.next().unwrap().key(), Some(&b"k001"[..]));
+    c.reset();
+    // the reused cursor keeps no bound from the earlier seek_prefix
+    c.seek(b"k199").unwrap();
+    assert_eq!(c.next().unwrap().key(), Some(&b"k200"[..]));
+    drop(c);
+    for i in 0..500u32 {
+        assert_eq!(b.get(format!("k{:03}", i)), Some(&i.to_be_bytes()[..]));
+    }
+}
+
+#[test]
+fn current() {
+    let path = "./tests/cursor_current.db";
+    let _ = std::fs::remove_file(path);
+    let db = DBBuilder::default().open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    for i in 0..300u32 {
+        b.put(format!("k{:03}", i), i.to_be_bytes()).unwrap();
+    }
+    let mut c = b.cursor();
+    assert_eq!(c.current().unwrap().key(), None);
+    c.first().unwrap();
+    assert_eq!(c.current().unwrap().key(), Some(&b"k000"[..]));
+    c.next().unwrap();
+    let pair = c.current().unwrap();
+    assert_eq!(pair.key(), Some(&b"k001"[..]));
+    assert_eq!(pair.value(), Some(&1u32.to_be_bytes()[..]));
+    // asking again doesn't move
+    assert_eq!(c.current().unwrap().key(), Some(&b"k001"[..]));
+    c.seek(b"k150a").unwrap();
+    assert_eq!(c.current().unwrap().key(), Some(&b"k151"[..]));
+    c.last().unwrap();
+    assert_eq!(c.current().unwrap().key(), Some(&b"k299"[..]));
+    assert_eq!(c.next().unwrap().key(), None);
+    assert_eq!(c.current().unwrap().key(), None);
+    c.seek_prefix(b"k12").unwrap();
+    while c.next().unwrap().key().is_some() {}
+    assert_eq!(c.current().unwrap().key(), None);
+}
+
+#[test]
+fn paginate() {
+    let path = "./tests/paginate.db";
+    let _ = std::fs::remove_file(path);
+    let db = DBBuilder::default().open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    for i in 0..250u32 {
+        b.put(format!("k{:03}", i), i.to_be_bytes()).unwrap();
+    }
+    b.create_bucket("k100-nested").unwrap();
+
+    let mut after: Option<Vec<u8>> = None;
+    let mut seen = Vec::new();
+    let mut pages = 0;
+    loop {
+        let page = b.page(after.as_deref(), 100).unwrap();
+        assert!(page.entries.len() <= 100);
+        seen.extend(page.entries.iter().map(|(k, _)| k.to_vec()));
+        pages += 1;
+        match page.next {
+            Some(next) => after = Some(next),
+            None => break,
+        }
+    }
+    assert_eq!(pages, 3);
+    let want: Vec<Vec<u8>> = (0..250u32)
+        .map(|i| format!("k{:03}", i).into_bytes())
+        .collect();
+    assert_eq!(seen, want);
+
+    // after doesn't have to be an existing key
+    let page = b.page(Some(b"k1"), 2).unwrap();
+    assert_eq!(page.entries[0], (&b"k100"[..], &100u32.to_be_bytes()[..]));
+    assert_eq!(page.next, Some(b"k101".to_vec()));
+    let page = b.page(Some(b"k249"), 10).unwrap();
+    assert_eq!((page.entries.len(), page.next), (0, None));
+    // a zero limit still says where to resume
+    let page = b.page(None, 0).unwrap();
+    let page = b.page(page.next.as_deref(), 1).unwrap();
+    assert_eq!(page.entries[0].0, b"k000");
+}
+
+#[test]
+fn par_scan() {
+    let path = "./tests/par_scan.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("data").unwrap();
+    for i in 0..5000u32 {
+        b.put(format!("k{:05}", i), i.to_be_bytes()).unwrap();
+    }
+    b.put_with_ttl("k99999", "gone", Duration::ZERO).unwrap();
+    // big enough to get pages of its own
+    b.put_blob("blob", &mut &vec![7u8; 100_000][..]).unwrap();
+    b.create_bucket("nested").unwrap().put("a", "b").unwrap();
+    drop(b);
+    tx.create_bucket("small").unwrap().put("x", "1").unwrap();
+    tx.commit().unwrap();
+    drop(tx);
+
+    let want: Vec<(Vec<u8>, Vec<u8>)> = (0..5000u32)
+        .map(|i| (format!("k{:05}", i).into_bytes(), i.to_be_bytes().to_vec()))
+        .collect();
+    let tx = db.read_tx().unwrap();
+    for ranges in [1, 8, 1000] {
+        let seen = Mutex::new(Vec::new());
+        tx.par_scan("data", ranges, |k, v| {
+            seen.lock().unwrap().push((k.to_vec(), v.to_vec()))
+        })
+        .unwrap();
+        let mut seen = seen.into_inner().unwrap();
+        seen.sort();
+        assert_eq!(seen, want);
+    }
+    // an inline bucket is scanned from its parent's leaf
+    let seen = Mutex::new(Vec::new());
+    tx.par_scan("small", 4, |k, _| seen.lock().unwrap().push(k.to_vec()))
+        .unwrap();
+    assert_eq!(seen.into_inner().unwrap(), vec![b"x".to_vec()]);
+    assert!(tx.par_scan("missing", 4, |_, _| {}).is_err());
+}
+
+#[test]
+fn warm() {
+    let path = "./tests/warm.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket("big").unwrap();
+        for i in 0..2000u32 {
+            b.put(format!("k{:05}", i), [7u8; 64]).unwrap();
+        }
+        b.put_blob("blob", &mut &[1u8; 100_000][..]).unwrap();
+        let nested = b.create_bucket("nested").unwrap();
+        for i in 0..500u32 {
+            nested.put(format!("k{:05}", i), [8u8; 64]).unwrap();
+        }
+        drop(b);
+        // inline, with a blob of its own
+        let mut tiny = tx.create_bucket("tiny").unwrap();
+        tiny.put("k", "v").unwrap();
+        tiny.put_blob("blob", &mut &[2u8; 20_000][..]).unwrap();
+        drop(tiny);
+        tx.commit().unwrap();
+    }
+    let tx = db.tx(false).unwrap();
+    let pages: u64 = tx
+        .pages()
+        .filter(|p| {
+            matches!(
+                p.page_type,
+                PageKind::Branch | PageKind::Leaf | PageKind::Blob
+            )
+        })
+        .map(|p| p.overflow as u64 + 1)
+        .sum();
+    drop(tx);
+
+    // every page but the leaf holding the top-level buckets
+    let before = db.stats().readahead_pages;
+    assert_eq!(db.warm(&["big", "tiny"]).unwrap(), pages - 1);
+    assert_eq!(db.stats().readahead_pages - before, pages - 1);
+    assert!(db.warm(&["tiny"]).unwrap() > 0);
+    assert!(db.warm(&["missing"]).is_err());
+}
+
+#[test]
+fn read_your_writes() {
+    let path = "./tests/read_your_writes.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let key = |i: u64| format!("k{:05}", i);
+    let mut model = BTreeMap::new();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    for i in (0..4000).step_by(2) {
+        b.put(key(i), i.to_be_bytes()).unwrap();
+        model.insert(key(i), i.to_be_bytes().to_vec());
+    }
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    // everything below is read back before commit, most of it from subtrees
+    // that only exist as pages when it is first touched
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+    let walk = |b: &roltdb::Bucket, model: &BTreeMap<String, Vec<u8>>| {
+        let mut c = b.cursor();
+        let mut pair = c.first().unwrap();
+        let mut expected = model.iter();
+        while let Some(k) = pair.key() {
+            let (ek, ev) = expected.next().unwrap();
+            assert_eq!((k, pair.value().unwrap()), (ek.as_bytes(), &ev[..]));
+            pair = c.next().unwrap();
+        }
+        assert!(expected.next().is_none());
+        let mut pair = c.last().unwrap();
+        let mut expected = model.iter().rev();
+        while let Some(k) = pair.key() {
+            assert_eq!(k, expected.next().unwrap().0.as_bytes());
+            pair = c.prev().unwrap();
+        }
+        assert!(expected.next().is_none());
+    };
+    let mut seed = 7u64;
+    for step in 0..6000 {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let i = (seed >> 33) % 4400;
+        let k = key(i);
+        match (seed >> 20) % 3 {
+            0 => {
+                b.put(&k, step.to_string()).unwrap();
+                model.insert(k.clone(), step.to_string().into_bytes());
+            }
+            1 => {
+                b.delete(&k).unwrap();
+                model.remove(&k);
+            }
+            _ => {}
+        }
+        assert_eq!(b.get(&k), model.get(&k).map(|v| &v[..]), "{}", k);
+        // seek lands on the first live key at or after k
+        let mut c = b.cursor();
+        let found = c.seek(k.as_bytes()).unwrap().key().map(|k| k.to_vec());
+        let expected = model.range(k..).next().map(|(k, _)| k.as_bytes().to_vec());
+        assert_eq!(found, expected);
+        if step % 1000 == 0 {
+            walk(&b, &model);
+        }
+    }
+    // whole leaves emptied in place are stepped over
+    let gone: Vec<String> = model
+        .range(key(1000)..key(2500))
+        .map(|(k, _)| k.clone())
+        .collect();
+    assert_eq!(b.delete_batch(&gone).unwrap(), gone.len());
+    gone.iter().for_each(|k| drop(model.remove(k)));
+    walk(&b, &model);
+    // nested buckets read back their own writes too
+    let nested = b.create_bucket("nested").unwrap();
+    nested.put("a", "1").unwrap();
+    assert_eq!(b.bucket("nested").unwrap().get("a"), Some(&b"1"[..]));
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("test").unwrap();
+    assert_eq!(b.bucket("nested").unwrap().get("a"), Some(&b"1"[..]));
+    for (k, v) in model.iter() {
+        assert_eq!(b.get(k), Some(&v[..]));
+    }
+    assert!(tx.check().unwrap().is_empty());
+    drop(b);
+    drop(tx);
+
+    // emptying the whole tree leaves an empty root
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+    let keys: Vec<&String> = model.keys().collect();
+    b.delete_batch(&keys).unwrap();
+    assert_eq!(b.cursor().first().unwrap().key(), Some(&b"nested"[..]));
+    drop(b);
+    tx.commit().unwrap();
+    assert!(tx.check().unwrap().is_empty());
+}
+
+#[test]
+fn read_your_writes_ranges() {
+    let path = "./tests/read_your_writes_ranges.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("nums").unwrap();
+    for n in (0..50_000).step_by(10) {
+        b.put_u64(n, "disk").unwrap();
+    }
+    // small enough to be stored inline in its parent
+    let inline = b.create_bucket("inline").unwrap();
+    inline.put("a", "1").unwrap();
+    inline.put("c", "3").unwrap();
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("nums").unwrap();
+    let mut model: BTreeMap<u64, &[u8]> =
+        (0..50_000).step_by(10).map(|n| (n, &b"disk"[..])).collect();
+    // new keys between the ones on disk, some on leaf boundaries
+    for n in (5..50_000).step_by(70) {
+        b.put_u64(n, "new").unwrap();
+        b.put_u64(n + 2, "new").unwrap();
+        model.insert(n, b"new");
+        model.insert(n + 2, b"new");
+    }
+    for n in (0..50_000).step_by(130) {
+        b.delete_u64(n).unwrap();
+        model.remove(&n);
+    }
+    for (n, v) in model.iter() {
+        assert_eq!(b.get_u64(*n), Some(*v), "{}", n);
+    }
+    for (lo, hi) in [(0, 100), (1234, 9876), (20_000, 20_500), (49_000, 60_000)] {
+        let expected: Vec<(u64, &[u8])> = model.range(lo..hi).map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(b.range_u64(lo..hi).unwrap(), expected);
+    }
+    let page = b.page(Some(&5u64.to_be_bytes()), 3).unwrap();
+    let keys: Vec<u64> = model.range(6..).take(3).map(|(k, _)| *k).collect();
+    let got: Vec<u64> = page
+        .entries
+        .iter()
+        .map(|(k, _)| u64::from_be_bytes((*k).try_into().unwrap()))
+        .collect();
+    assert_eq!(got, keys);
+
+    let inline = b.create_bucket_if_not_exist("inline").unwrap();
+    inline.put("b", "2").unwrap();
+    inline.delete("c").unwrap();
+    inline.put("d", "4").unwrap();
+    assert_eq!(inline.get("b"), Some(&b"2"[..]));
+    assert_eq!(inline.get("c"), None);
+    let page = inline.page(None, 10).unwrap();
+    assert_eq!(
+        page.entries,
+        vec![(&b"a"[..], &b"1"[..]), (b"b", b"2"), (b"d", b"4")]
+    );
+    let mut c = inline.cursor();
+    assert_eq!(c.seek(b"c").unwrap().key(), Some(&b"d"[..]));
+    assert_eq!(c.seek_prefix(b"b").unwrap().value(), Some(&b"2"[..]));
+    assert_eq!(c.next().unwrap().key(), None);
+}
+
+#[test]
+fn seek_ge_le() {
+    let path = "./tests/seek_ge_le.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    for i in (2..4000u32).step_by(2) {
+        b.put(format!("k{:04}", i), "v").unwrap();
+    }
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("test").unwrap();
+    let key = |i: u32| format!("k{:04}", i).into_bytes();
+    let mut c = b.cursor();
+    for i in 0..4001u32 {
+        // present keys are even, missing ones odd
+        let ge = (i.max(2)..4000).find(|n| n % 2 == 0);
+        let le = (2..=i.min(3998)).rev().find(|n| n % 2 == 0);
+        let found = c.seek_ge(&key(i)).unwrap().key().map(|k| k.to_vec());
+        assert_eq!(found, ge.map(key), "{}", i);
+        let found = c.seek_le(&key(i)).unwrap().key().map(|k| k.to_vec());
+        assert_eq!(found, le.map(key), "{}", i);
+        // the cursor walks on from where it landed
+        if let Some(le) = le {
+            let next = Some(le + 2).filter(|n| *n < 4000).map(key);
+            assert_eq!(c.next().unwrap().key().map(|k| k.to_vec()), next);
+        }
+    }
+    assert_eq!(c.seek_le(b"a").unwrap().key(), None);
+    assert_eq!(c.seek_ge(b"z").unwrap().key(), None);
+    assert_eq!(c.seek_le(b"z").unwrap().key(), Some(&b"k3998"[..]));
+}
@@ -0,0 +1,60 @@
+use roltdb::DB;
+
+#[test]
+fn dup_bucket() {
+    let path = "./tests/dup.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_dup_bucket("index").unwrap();
+    b.put_dup("red", "apple").unwrap();
+    b.put_dup("red", "cherry").unwrap();
+    b.put_dup("red", "apple").unwrap();
+    b.put_dup("yellow", "banana").unwrap();
+    // enough values that the set of one key outgrows an inline page
+    for i in 0..500 {
+        b.put_dup("many", format!("{:04}", i)).unwrap();
+    }
+    assert!(b.put("red", "plain").is_err());
+    assert!(b.create_bucket("nested").is_err());
+    assert_eq!(b.get_all("red"), vec![&b"apple"[..], &b"cherry"[..]]);
+    assert_eq!(b.get("red"), Some(&b"apple"[..]));
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("index").unwrap();
+    assert_eq!(b.get_all("red"), vec![&b"apple"[..], &b"cherry"[..]]);
+    assert_eq!(b.get_all("many").len(), 500);
+    assert!(b.get_all("blue").is_empty());
+    assert!(b.buckets().unwrap().is_empty());
+    b.delete_dup("red", "apple").unwrap();
+    b.delete_dup("red", "missing").unwrap();
+    b.delete_dup("yellow", "banana").unwrap();
+    assert_eq!(b.get_all("red"), vec![&b"cherry"[..]]);
+    assert_eq!(b.get("yellow"), None);
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("index").unwrap();
+    let mut c = b.cursor();
+    let pair = c.seek(b"many").unwrap();
+    assert_eq!(pair.key(), Some(&b"many"[..]));
+    assert_eq!(c.first_dup().unwrap(), Some(&b"0000"[..]));
+    assert_eq!(c.next_dup().unwrap(), Some(&b"0001"[..]));
+    let mut n = 2;
+    while c.next_dup().unwrap().is_some() {
+        n += 1;
+    }
+    assert_eq!(n, 500);
+    // moving the cursor starts over at the first value of the next key
+    let pair = c.next().unwrap();
+    assert_eq!(pair.key(), Some(&b"red"[..]));
+    assert_eq!(c.next_dup().unwrap(), None);
+    assert_eq!(c.first_dup().unwrap(), Some(&b"cherry"[..]));
+    drop(b);
+    assert!(tx.check().unwrap().is_empty());
+}
@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use roltdb::{CsvOptions, Encoding, DB};
+
+#[test]
+fn export_csv() {
+    let path = "./tests/export.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("csv").unwrap();
+    b.put("a", "plain").unwrap();
+    b.put("b", "x,\"y\"").unwrap();
+    b.put("c", [0xffu8, 0x00]).unwrap();
+    // expired key and the ttl index bucket are left out
+    b.put_with_ttl("gone", "v", Duration::ZERO).unwrap();
+
+    let mut out = Vec::new();
+    let opts = CsvOptions {
+        value: Encoding::Hex,
+        ..Default::default()
+    };
+    assert_eq!(b.export_csv(&mut out, opts).unwrap(), 3);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "key,value\na,706c61696e\nb,782c227922\nc,ff00\n"
+    );
+
+    let mut out = Vec::new();
+    let opts = CsvOptions {
+        key: Encoding::Base64,
+        header: false,
+        ..Default::default()
+    };
+    assert!(b.export_csv(&mut out, opts).is_err());
+    b.delete("c").unwrap();
+    let mut out = Vec::new();
+    b.export_csv(&mut out, opts).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "YQ==,plain\nYg==,\"x,\"\"y\"\"\"\n"
+    );
+}
+
+#[test]
+fn dump_restore() {
+    let (src, dst) = ("./tests/dump_src.db", "./tests/dump_dst.db");
+    let _ = std::fs::remove_file(src);
+    let _ = std::fs::remove_file(dst);
+    let big: Vec<u8> = (0..100_000).map(|i| (i * 31 % 251) as u8).collect();
+    let db = DB::open(src).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("events").unwrap();
+    for i in 0..2000u32 {
+        b.put(format!("k{:04}", i), i.to_be_bytes()).unwrap();
+    }
+    b.put_with_flags("flagged", "v", 7).unwrap();
+    b.put_with_ttl("later", "v", Duration::from_secs(3600))
+        .unwrap();
+    b.put_with_ttl("gone", "v", Duration::ZERO).unwrap();
+    b.put_blob("blob", &mut &big[..]).unwrap();
+    b.next_sequence().unwrap();
+    b.next_sequence().unwrap();
+    let nested = b.create_bucket("nested").unwrap();
+    nested.put("a", "1").unwrap();
+    nested.next_sequence().unwrap();
+    let dup = b.create_dup_bucket("dup").unwrap();
+    dup.put_dup("k", "x").unwrap();
+    dup.put_dup("k", "y").unwrap();
+    let fixed = b.create_fixed_bucket("fixed", 4).unwrap();
+    fixed.put("a", 9u32.to_be_bytes()).unwrap();
+    let mut out = Vec::new();
+    // 2000 keys, flagged, later, gone, blob, a, the two values of k, the
+    // fixed value and the two deadlines
+    assert_eq!(b.dump(&mut out).unwrap(), 2010);
+    drop(b);
+    drop(tx);
+
+    let db = DB::open(dst).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("copy").unwrap();
+    b.put("k0000", "overwritten").unwrap();
+    assert_eq!(b.restore(&mut &out[..]).unwrap(), 2010);
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("copy").unwrap();
+    for i in 0..2000u32 {
+        assert_eq!(b.get(format!("k{:04}", i)), Some(&i.to_be_bytes()[..]));
+    }
+    assert_eq!(b.cursor().seek(b"flagged").unwrap().user_flags(), 7);
+    assert_eq!(b.get("later"), Some(&b"v"[..]));
+    // the deadline came along, the key is still expired
+    assert_eq!(b.get("gone"), None);
+    let mut blob = Vec::new();
+    let mut r = b.get_blob_reader("blob").unwrap().unwrap();
+    std::io::Read::read_to_end(&mut r, &mut blob).unwrap();
+    assert_eq!(blob, big);
+    assert_eq!(b.sequence(), 2);
+    let nested = b.bucket("nested").unwrap();
+    assert_eq!((nested.get("a"), nested.sequence()), (Some(&b"1"[..]), 1));
+    assert_eq!(b.bucket("dup").unwrap().get_all("k"), vec![b"x", b"y"]);
+    assert_eq!(
+        b.bucket("fixed").unwrap().get("a"),
+        Some(&9u32.to_be_bytes()[..])
+    );
+    assert!(tx.check().unwrap().is_empty());
+    drop(b);
+    drop(tx);
+
+    // a cut off or foreign stream fails the restore
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("bad").unwrap();
+    assert!(b.restore(&mut &out[..out.len() - 1]).is_err());
+    assert!(b.restore(&mut &b"not a dump"[..]).is_err());
+}
@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use roltdb::{DBBuilder, MaintenanceAction, MaintenanceOptions, DB};
+
+#[test]
+fn fixed_width_values() {
+    let path = "./tests/fixed.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut fixed = tx.create_fixed_bucket("fixed", 8).unwrap();
+    for i in 0..2000u64 {
+        fixed.put(i.to_be_bytes(), (i * 7).to_le_bytes()).unwrap();
+    }
+    assert!(fixed.put("short", "v").is_err());
+    assert!(fixed.put_with_flags("k", [0u8; 8], 1).is_err());
+    assert!(fixed.put_with_ttl("k", [0u8; 8], Duration::ZERO).is_err());
+    assert!(fixed.create_bucket("nested").is_err());
+    drop(fixed);
+    let mut plain = tx.create_bucket("plain").unwrap();
+    for i in 0..2000u64 {
+        plain.put(i.to_be_bytes(), (i * 7).to_le_bytes()).unwrap();
+    }
+    drop(plain);
+    assert!(tx.create_fixed_bucket("zero", 0).is_err());
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(true).unwrap();
+    let mut fixed = tx.create_bucket_if_not_exist("fixed").unwrap();
+    assert_eq!(
+        fixed.get(5u64.to_be_bytes()),
+        Some(&35u64.to_le_bytes()[..])
+    );
+    // the width is kept in the file
+    assert!(fixed.put("short", "v").is_err());
+    fixed.put(5u64.to_be_bytes(), 1u64.to_le_bytes()).unwrap();
+    fixed.delete(6u64.to_be_bytes()).unwrap();
+    drop(fixed);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    let fixed = tx.bucket("fixed").unwrap();
+    let mut c = fixed.cursor();
+    let mut pair = c.first().unwrap();
+    let mut n = 0;
+    while let Some(key) = pair.key() {
+        let i = u64::from_be_bytes(key.try_into().unwrap());
+        let want = if i == 5 { 1 } else { i * 7 };
+        assert_eq!(pair.value(), Some(&want.to_le_bytes()[..]));
+        n += 1;
+        pair = c.next().unwrap();
+    }
+    assert_eq!(n, 1999);
+    let plain = tx.bucket("plain").unwrap();
+    assert!(fixed.tree_stats().unwrap().leaf_pages < plain.tree_stats().unwrap().leaf_pages);
+}
+
+#[test]
+fn compact_keeps_modes() {
+    let path = "./tests/fixed_compact.db";
+    let _ = std::fs::remove_file(path);
+    {
+        let db = DB::open(path).unwrap();
+        let tx = db.tx(true).unwrap();
+        let mut fixed = tx.create_fixed_bucket("fixed", 4).unwrap();
+        fixed.put("a", "1234").unwrap();
+        drop(fixed);
+        let mut dup = tx.create_dup_bucket("dup").unwrap();
+        dup.put_dup("k", "x").unwrap();
+        dup.put_dup("k", "y").unwrap();
+        drop(dup);
+        tx.commit().unwrap();
+    }
+    let opts = MaintenanceOptions {
+        interval: Duration::ZERO,
+        compact_free_ratio: 0.0,
+        truncate_tail: true,
+    };
+    let db = DBBuilder::default().maintenance(opts).open(path).unwrap();
+    assert!(matches!(
+        db.maintain().unwrap(),
+        MaintenanceAction::Compacted { .. }
+    ));
+    let tx = db.tx(true).unwrap();
+    let mut fixed = tx.create_bucket_if_not_exist("fixed").unwrap();
+    assert_eq!(fixed.get("a"), Some(&b"1234"[..]));
+    assert!(fixed.put("b", "1").is_err());
+    drop(fixed);
+    let dup = tx.create_bucket_if_not_exist("dup").unwrap();
+    assert_eq!(dup.get_all("k"), vec![&b"x"[..], &b"y"[..]]);
+    drop(dup);
+    tx.rollback().unwrap();
+}
@@ -0,0 +1,40 @@
+use roltdb::{GroupBy, DB};
+
+#[test]
+fn groups() {
+    let path = "./tests/groups.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    // big groups spread over many leaves
+    for ns in ["alpha", "beta", "gamma"] {
+        for i in 0..500 {
+            b.put(format!("{}/{:04}", ns, i), [1u8; 32]).unwrap();
+        }
+    }
+    b.put("beta", "whole key").unwrap();
+    b.put("betamax", "whole key").unwrap();
+    b.put("\u{7f}/\u{7f}", "last").unwrap();
+    b.create_bucket("gamma/nested").unwrap();
+
+    let names: Vec<Vec<u8>> = b.groups(GroupBy::Delimiter(b'/')).map(|g| g.0).collect();
+    let expected: Vec<&[u8]> = vec![
+        b"alpha/", b"beta", b"beta/", b"betamax", b"gamma/", b"\x7f/",
+    ];
+    assert_eq!(names, expected);
+
+    let mut groups = b.groups(GroupBy::Delimiter(b'/'));
+    let (prefix, entries) = groups.nth(2).unwrap();
+    assert_eq!(prefix, b"beta/");
+    assert_eq!(entries.count(), 500);
+    // the nested bucket starts no entry of its group
+    let (_, entries) = groups.nth(1).unwrap();
+    assert_eq!(entries.count(), 500);
+
+    let names: Vec<Vec<u8>> = b.groups(GroupBy::Len(2)).map(|g| g.0).collect();
+    let expected: Vec<&[u8]> = vec![b"al", b"be", b"ga", b"\x7f/"];
+    assert_eq!(names, expected);
+    let (_, entries) = b.groups(GroupBy::Len(2)).nth(1).unwrap();
+    assert_eq!(entries.count(), 502);
+}
@@ -0,0 +1,332 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use roltdb::{DBBuilder, MaintenanceAction, MaintenanceOptions, Metrics, RoltError, DB};
+
+fn file_len(path: &str) -> u64 {
+    std::fs::metadata(path).unwrap().len()
+}
+
+#[test]
+fn truncate_tail() {
+    let path = "./tests/truncate_tail.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let before = file_len(path);
+    // nothing is free yet, only space allocated past the last page goes
+    let action = db.maintain().unwrap();
+    assert!(matches!(action, MaintenanceAction::Truncated { .. }));
+    assert!(file_len(path) < before);
+    assert_eq!(db.maintain().unwrap(), MaintenanceAction::Nothing);
+}
+
+#[test]
+fn compact() {
+    let path = "./tests/auto_compact.db";
+    let _ = std::fs::remove_file(path);
+    {
+        let db = DB::open(path).unwrap();
+        // rewrite the same keys so older versions pile up as free pages
+        for _ in 0..10 {
+            let tx = db.tx(true).unwrap();
+            let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+            for i in 0..100u32 {
+                b.put(format!("k{:03}", i), [9u8; 64]).unwrap();
+            }
+            drop(b);
+            let mut nested = tx.create_bucket_if_not_exist("nested").unwrap();
+            nested.put("a", "b").unwrap();
+            drop(nested);
+            tx.commit().unwrap();
+        }
+    }
+    let opts = MaintenanceOptions {
+        interval: Duration::ZERO,
+        compact_free_ratio: 0.3,
+        truncate_tail: true,
+    };
+    let db = DBBuilder::default().maintenance(opts).open(path).unwrap();
+    let before = file_len(path);
+    let tx_id = db.refresh().unwrap();
+    match db.maintain().unwrap() {
+        MaintenanceAction::Compacted {
+            before_pages,
+            after_pages,
+        } => assert!(after_pages < before_pages),
+        action => panic!("expected a compaction, got {:?}", action),
+    }
+    // opening a write tx runs the due maintenance first
+    let tx = db.tx(true).unwrap();
+    tx.create_bucket_if_not_exist("test")
+        .unwrap()
+        .put("k100", "v")
+        .unwrap();
+    tx.commit().unwrap();
+    drop(tx);
+    assert!(file_len(path) < before);
+    assert!(db.refresh().unwrap() > tx_id);
+
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    let b = tx.bucket("test").unwrap();
+    assert_eq!(b.get("k000"), Some(&[9u8; 64][..]));
+    assert_eq!(b.get("k099"), Some(&[9u8; 64][..]));
+    assert_eq!(b.get("k100"), Some(&b"v"[..]));
+    drop(b);
+    assert_eq!(tx.bucket("nested").unwrap().get("a"), Some(&b"b"[..]));
+    drop(tx);
+    // a read tx holds the old file, no compaction while it's open
+    let _reader = db.tx(false).unwrap();
+    let action = db.maintain().unwrap();
+    assert!(!matches!(action, MaintenanceAction::Compacted { .. }));
+}
+
+#[derive(Default)]
+struct Failures(AtomicU64);
+
+impl Metrics for Failures {
+    fn maintenance_failed(&self, _error: &RoltError) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn failed_maintenance_keeps_tx() {
+    let path = "./tests/failed_maintenance.db";
+    let shadow = "./tests/failed_maintenance.db.compact";
+    let _ = std::fs::remove_file(path);
+    let _ = std::fs::remove_dir(shadow);
+    {
+        let db = DB::open(path).unwrap();
+        for _ in 0..5 {
+            let tx = db.tx(true).unwrap();
+            let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+            for i in 0..100u32 {
+                b.put(format!("k{:03}", i), [9u8; 64]).unwrap();
+            }
+            drop(b);
+            tx.commit().unwrap();
+        }
+    }
+    // compaction can't create its shadow file over a directory
+    std::fs::create_dir(shadow).unwrap();
+    let failures = Arc::new(Failures::default());
+    let db = DBBuilder::default()
+        .metrics(failures.clone())
+        .maintenance(MaintenanceOptions {
+            interval: Duration::ZERO,
+            compact_free_ratio: 0.1,
+            truncate_tail: true,
+        })
+        .open(path)
+        .unwrap();
+    let tx = db.tx(true).unwrap();
+    tx.create_bucket_if_not_exist("test")
+        .unwrap()
+        .put("k100", "v")
+        .unwrap();
+    tx.commit().unwrap();
+    drop(tx);
+    assert_eq!(failures.0.load(Ordering::Relaxed), 1);
+    assert!(!db.poisoned());
+    let tx = db.tx(false).unwrap();
+    assert_eq!(tx.bucket("test").unwrap().get("k100"), Some(&b"v"[..]));
+    drop(tx);
+    drop(db);
+    std::fs::remove_dir(shadow).unwrap();
+    let _ = std::fs::remove_file("./tests/failed_maintenance.db.compact.lock");
+}
+
+#[test]
+fn gc_leaked_pages() {
+    use std::{
+        fs::OpenOptions,
+        io::{Seek, SeekFrom, Write},
+    };
+
+    let path = "./tests/gc.db";
+    let _ = std::fs::remove_file(path);
+    let free_list = {
+        let db = DB::open(path).unwrap();
+        for _ in 0..3 {
+            let tx = db.tx(true).unwrap();
+            let mut b = tx.create_bucket_if_not_exist("data").unwrap();
+            for i in 0..500u32 {
+                b.put(format!("k{:04}", i), [1u8; 100]).unwrap();
+            }
+            drop(b);
+            tx.commit().unwrap();
+        }
+        assert_eq!(db.gc().unwrap(), 0);
+        let tx = db.tx(false).unwrap();
+        tx.pages()
+            .find(|p| p.page_type == roltdb::PageKind::FreeList)
+            .unwrap()
+    };
+    assert!(free_list.count > 0);
+    // forget every free page, as a crash or an old bug could have
+    let mut f = OpenOptions::new().write(true).open(path).unwrap();
+    f.seek(SeekFrom::Start(free_list.id * page_size::get() as u64 + 10))
+        .unwrap();
+    f.write_all(&0u16.to_ne_bytes()).unwrap();
+    drop(f);
+
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    assert_eq!(tx.check().unwrap().issues.len(), free_list.count);
+    drop(tx);
+    assert_eq!(db.gc().unwrap(), free_list.count as u64);
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    assert_eq!(
+        tx.bucket("data").unwrap().get("k0499"),
+        Some(&[1u8; 100][..])
+    );
+}
+
+// rewrite the same keys so older versions pile up as free pages
+fn churn(db: &DB) {
+    for _ in 0..5 {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+        for i in 0..100u32 {
+            b.put(format!("k{:03}", i), [9u8; 64]).unwrap();
+        }
+        drop(b);
+        tx.commit().unwrap();
+    }
+}
+
+#[test]
+fn compact_waits_for_followers() {
+    let path = "./tests/compact_followers.db";
+    let _ = std::fs::remove_file(path);
+    let opts = MaintenanceOptions {
+        interval: Duration::from_secs(3600),
+        compact_free_ratio: 0.1,
+        truncate_tail: false,
+    };
+    let db = DBBuilder::default().maintenance(opts).open(path).unwrap();
+    churn(&db);
+    let follower = DBBuilder::default().read_only(true).open(path).unwrap();
+    assert_eq!(db.maintain().unwrap(), MaintenanceAction::Nothing);
+    // the writer still commits after a refused compaction
+    let tx = db.tx(true).unwrap();
+    tx.create_bucket_if_not_exist("test")
+        .unwrap()
+        .put("k100", "v")
+        .unwrap();
+    tx.commit().unwrap();
+    drop(tx);
+    let tx = follower.tx(false).unwrap();
+    assert_eq!(tx.bucket("test").unwrap().get("k100"), Some(&b"v"[..]));
+    drop(tx);
+    drop(follower);
+    assert!(matches!(
+        db.maintain().unwrap(),
+        MaintenanceAction::Compacted { .. }
+    ));
+    // followers opened after the swap read the new file
+    let follower = DBBuilder::default().read_only(true).open(path).unwrap();
+    let tx = follower.tx(false).unwrap();
+    assert_eq!(tx.bucket("test").unwrap().get("k100"), Some(&b"v"[..]));
+}
+
+#[test]
+fn unlocked_follower_sees_swap() {
+    let path = "./tests/compact_unlocked.db";
+    let _ = std::fs::remove_file(path);
+    let opts = MaintenanceOptions {
+        interval: Duration::from_secs(3600),
+        compact_free_ratio: 0.1,
+        truncate_tail: false,
+    };
+    let db = DBBuilder::default()
+        .no_lock(true)
+        .maintenance(opts)
+        .open(path)
+        .unwrap();
+    churn(&db);
+    let follower = DBBuilder::default()
+        .no_lock(true)
+        .read_only(true)
+        .open(path)
+        .unwrap();
+    assert!(follower.tx(false).is_ok());
+    // nothing tells the writer about the follower
+    assert!(matches!(
+        db.maintain().unwrap(),
+        MaintenanceAction::Compacted { .. }
+    ));
+    assert!(matches!(
+        follower.tx(false).unwrap_err(),
+        RoltError::FileReplaced
+    ));
+}
+
+#[test]
+fn truncate_tail_keeps_free_pages_without_tx() {
+    let path = "./tests/truncate_busy.db";
+    let _ = std::fs::remove_file(path);
+    {
+        let db = DB::open(path).unwrap();
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket("big").unwrap();
+        for i in 0..500u32 {
+            b.put(format!("k{:04}", i), [1u8; 100]).unwrap();
+        }
+        drop(b);
+        tx.commit().unwrap();
+        drop(tx);
+        let tx = db.tx(true).unwrap();
+        let keys: Vec<String> = (0..500u32).map(|i| format!("k{:04}", i)).collect();
+        tx.create_bucket_if_not_exist("big")
+            .unwrap()
+            .delete_batch(&keys)
+            .unwrap();
+        tx.commit().unwrap();
+    }
+    // freed pages are reused after a reopen, which leaves the end free
+    for i in 0..2 {
+        let db = DB::open(path).unwrap();
+        let tx = db.tx(true).unwrap();
+        tx.create_bucket_if_not_exist("small")
+            .unwrap()
+            .put("k", format!("v{}", i))
+            .unwrap();
+        tx.commit().unwrap();
+    }
+    let opts = MaintenanceOptions {
+        interval: Duration::from_secs(3600),
+        compact_free_ratio: 1.0,
+        truncate_tail: true,
+    };
+    let db = DBBuilder::default().maintenance(opts).open(path).unwrap();
+    // the write tx is taken, the free tail must stay free
+    let tx = db.tx(true).unwrap();
+    assert!(matches!(
+        db.maintain().unwrap_err(),
+        RoltError::WritableTxNotAllowed
+    ));
+    tx.create_bucket_if_not_exist("small")
+        .unwrap()
+        .put("k2", "v")
+        .unwrap();
+    tx.commit().unwrap();
+    drop(tx);
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    drop(tx);
+    assert!(matches!(
+        db.maintain().unwrap(),
+        MaintenanceAction::Truncated { .. }
+    ));
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    assert_eq!(tx.bucket("small").unwrap().get("k2"), Some(&b"v"[..]));
+}
@@ -0,0 +1,195 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use roltdb::{DBBuilder, Metrics, SlowOp, SlowOpThresholds, Stats, SyncPolicy};
+
+#[derive(Default)]
+struct Counter {
+    commits: AtomicU64,
+    fsyncs: AtomicU64,
+    pages: AtomicU64,
+}
+
+impl Metrics for Counter {
+    fn commit(&self, _elapsed: Duration) {
+        self.commits.fetch_add(1, Ordering::Relaxed);
+    }
+    fn fsync(&self, _elapsed: Duration) {
+        self.fsyncs.fetch_add(1, Ordering::Relaxed);
+    }
+    fn pages_written(&self, count: u64) {
+        self.pages.fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn commit_metrics() {
+    let path = "./tests/metrics.db";
+    let _ = std::fs::remove_file(path);
+    let counter = Arc::new(Counter::default());
+    let db = DBBuilder::default()
+        .metrics(counter.clone())
+        .open(path)
+        .unwrap();
+    {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket("test").unwrap();
+        b.put(b"hello", b"world").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+    assert!(counter.commits.load(Ordering::Relaxed) >= 1);
+    assert!(counter.fsyncs.load(Ordering::Relaxed) >= 2);
+    assert!(counter.pages.load(Ordering::Relaxed) >= 1);
+
+    let stats = db.stats();
+    assert!(stats.write_calls >= 2);
+    assert!(stats.syncs >= 2);
+    assert_eq!(
+        stats.free_list_hits + stats.free_list_misses,
+        stats.allocations
+    );
+}
+
+#[test]
+fn slow_ops() {
+    let path = "./tests/slow_ops.db";
+    let _ = std::fs::remove_file(path);
+    let commits = Arc::new(AtomicU64::new(0));
+    let counter = commits.clone();
+    let thresholds = SlowOpThresholds {
+        commit: Some(Duration::ZERO),
+        ..Default::default()
+    };
+    let db = DBBuilder::default()
+        .slow_ops(thresholds, move |op| {
+            if let SlowOp::Commit { .. } = op {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        })
+        .open(path)
+        .unwrap();
+    {
+        let tx = db.tx(true).unwrap();
+        drop(tx.create_bucket("test").unwrap());
+        tx.commit().unwrap();
+    }
+    assert!(commits.load(Ordering::Relaxed) >= 1);
+}
+
+#[test]
+fn vectored_writes() {
+    let path = "./tests/vectored.db";
+    let _ = std::fs::remove_file(path);
+    let db = DBBuilder::default().open(path).unwrap();
+    let before = db.stats();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    for i in 0..200u32 {
+        b.put(format!("k{:04}", i), [1u8; 64]).unwrap();
+    }
+    drop(b);
+    tx.commit().unwrap();
+    let after = db.stats();
+    // adjacent dirty pages go out in one write, the meta page in another
+    assert!(after.allocated_pages - before.allocated_pages > 2);
+    assert_eq!(after.write_calls - before.write_calls, 2);
+}
+
+#[test]
+fn page_pool() {
+    let path = "./tests/page_pool.db";
+    let _ = std::fs::remove_file(path);
+    let db = DBBuilder::default().open(path).unwrap();
+    for round in 0..3u32 {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+        b.put(format!("k{}", round), "v").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+    // buffers of pages written by earlier txs are handed out again
+    assert!(db.stats().pooled_pages > 0);
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("test").unwrap();
+    assert_eq!(b.get("k0"), Some(&b"v"[..]));
+    assert_eq!(b.get("k2"), Some(&b"v"[..]));
+}
+
+#[test]
+fn sync_policy() {
+    let path = "./tests/sync_policy.db";
+    let commit = |db: &roltdb::DB, i: u32| {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+        b.put(i.to_be_bytes(), "v").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    };
+    let syncs = |policy| {
+        let _ = std::fs::remove_file(path);
+        let db = DBBuilder::default().sync_policy(policy).open(path).unwrap();
+        for i in 0..7 {
+            commit(&db, i);
+        }
+        db.stats().syncs
+    };
+    // a synced commit fsyncs after its pages and after its meta page
+    assert_eq!(syncs(SyncPolicy::Always), 14);
+    assert_eq!(syncs(SyncPolicy::EveryNCommits(3)), 4);
+    assert_eq!(syncs(SyncPolicy::Never), 0);
+    assert_eq!(syncs(SyncPolicy::Interval(Duration::from_secs(3600))), 0);
+
+    // unsynced commits are still visible and get synced on close
+    let _ = std::fs::remove_file(path);
+    {
+        let db = DBBuilder::default()
+            .sync_policy(SyncPolicy::Never)
+            .open(path)
+            .unwrap();
+        commit(&db, 1);
+    }
+    let db = DBBuilder::default().open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    assert_eq!(
+        tx.bucket("test").unwrap().get(1u32.to_be_bytes()),
+        Some(&b"v"[..])
+    );
+}
+
+#[test]
+fn stats_delta() {
+    let path = "./tests/stats_delta.db";
+    let _ = std::fs::remove_file(path);
+    let db = DBBuilder::default().open(path).unwrap();
+    let commit = |key: &str| {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+        b.put(key, "v").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    };
+    commit("a");
+    let prev = db.stats();
+    commit("b");
+    let delta = db.stats_since(&prev);
+    assert_eq!(delta, db.stats().since(&prev));
+    assert_eq!(delta.syncs, db.stats().syncs - prev.syncs);
+    assert!(delta.write_calls > 0 && delta.write_calls < db.stats().write_calls);
+
+    db.reset_stats();
+    let zero = Stats {
+        resets: 1,
+        ..Default::default()
+    };
+    assert_eq!(db.stats(), zero);
+    commit("c");
+    // a reset in between makes the delta the counts since the reset
+    let delta = db.stats_since(&prev);
+    assert_eq!(delta, db.stats());
+}
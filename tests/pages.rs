@@ -0,0 +1,145 @@
+use roltdb::{DBBuilder, GrowthPolicy, PageKind, DB};
+
+#[test]
+fn page_info() {
+    let path = "./tests/pages.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    let pages: Vec<_> = tx.pages().collect();
+    assert_eq!(pages.len(), 4);
+    assert_eq!(pages[0].page_type, PageKind::Meta);
+    assert_eq!(pages[1].page_type, PageKind::Meta);
+    assert_eq!(pages[2].page_type, PageKind::FreeList);
+    assert_eq!(pages[3].page_type, PageKind::Leaf);
+
+    let info = tx.page_info(3).unwrap();
+    assert_eq!(info.count, 0);
+    assert_eq!(info.used_bytes + info.free_bytes, page_size::get());
+    assert!(tx.page_info(100).is_err());
+}
+
+#[test]
+fn read_tx_free_pages() {
+    let path = "./tests/pages_snapshot.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    for i in 0..2000u32 {
+        b.put(format!("k{:03}", i), [7u8; 64]).unwrap();
+    }
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let reader = db.tx(false).unwrap();
+    // meta pages are rewritten in place, leave them out
+    let ids = |tx: &roltdb::Transaction| -> Vec<_> {
+        tx.pages()
+            .filter(|p| p.page_type != PageKind::Meta)
+            .map(|p| p.id)
+            .collect()
+    };
+    let before = ids(&reader);
+
+    // the writer rewrites, and so frees, the pages the reader still sees
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+    for i in 0..2000u32 {
+        b.put(format!("k{:03}", i), [8u8; 64]).unwrap();
+    }
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    assert_eq!(ids(&reader), before);
+}
+
+#[test]
+fn growth_policy() {
+    let path = "./tests/growth.db";
+    let _ = std::fs::remove_file(path);
+    let page_size = page_size::get() as u64;
+    let step = 256 * page_size;
+    let db = DBBuilder::default()
+        .growth(GrowthPolicy::Step(step))
+        .open(path)
+        .unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    for i in 0..2000u32 {
+        b.put(i.to_be_bytes(), [7u8; 64]).unwrap();
+    }
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+    let len = std::fs::metadata(path).unwrap().len();
+    let used = db.tx(false).unwrap().pages().map(|p| p.id).max().unwrap() + 1;
+    // the file grew a whole step ahead of the pages in use
+    assert!(len >= step && len > used * page_size);
+    drop(db);
+
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("test").unwrap();
+    for i in 0..2000u32 {
+        assert_eq!(b.get(i.to_be_bytes()), Some(&[7u8; 64][..]));
+    }
+}
+
+#[test]
+fn dump_page() {
+    let path = "./tests/pages_dump.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("dump").unwrap();
+    for i in 0..200u32 {
+        b.put(format!("key-{:03}", i), "value\n").unwrap();
+    }
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    let meta = tx.dump_page(0).unwrap();
+    assert!(meta.starts_with("page 0"));
+    assert!(meta.contains("type meta"));
+    assert!(meta.contains("checksum ok"));
+    let leaf = tx
+        .pages()
+        .find(|p| p.page_type == PageKind::Leaf && p.count > 10)
+        .unwrap();
+    let dump = tx.dump_page(leaf.id).unwrap();
+    assert!(dump.contains("type leaf"));
+    assert!(dump.contains(r#"key 7 "key-"#));
+    assert!(dump.contains(r#"value 6 "value\n""#));
+    assert!(dump.contains("hex:\n00000000"));
+    assert!(tx.dump_page(10_000).is_err());
+}
+
+#[test]
+fn deterministic() {
+    // same operations, byte-identical files
+    let run = |path: &str| {
+        let _ = std::fs::remove_file(path);
+        let db = DBBuilder::default().deterministic(true).open(path).unwrap();
+        for round in 0..3u32 {
+            let tx = db.tx(true).unwrap();
+            for i in 0..16u32 {
+                let mut b = tx.create_bucket_if_not_exist(format!("b{}", i)).unwrap();
+                for k in 0..100u32 {
+                    b.put(format!("k{:03}", k * 3 + round), [round as u8; 48])
+                        .unwrap();
+                }
+            }
+            tx.commit().unwrap();
+        }
+        drop(db);
+        std::fs::read(path).unwrap()
+    };
+    let a = run("./tests/deterministic_a.db");
+    let b = run("./tests/deterministic_b.db");
+    assert!(a == b);
+}
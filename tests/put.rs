@@ -1,4 +1,6 @@
-use roltdb::DB;
+use std::time::Duration;
+
+use roltdb::{DBBuilder, GrowthPolicy, OpenPhase, RoltError, Transaction, TxState, DB};
 
 #[test]
 fn open() {
@@ -8,7 +10,7 @@ fn open() {
     if tx2.is_ok() {
         panic!("tx2 should not be created");
     }
-    let mut b = tx.create_bucket_if_not_exist("test".to_string()).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("test").unwrap();
     b.put(b"hello", b"hello world").unwrap();
     b.put(b"a", b"a").unwrap();
     let res = b.get(b"a").unwrap();
@@ -16,3 +18,675 @@ fn open() {
     let res = b.get(b"hello").unwrap();
     assert_eq!(res, b"hello world");
 }
+
+#[test]
+fn put_as_ref() {
+    let path = "./tests/as_ref.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    b.put("str", "value").unwrap();
+    b.put(String::from("string"), vec![1u8, 2]).unwrap();
+    b.put([7u8; 2], b"array").unwrap();
+    assert_eq!(b.get("str"), Some(&b"value"[..]));
+    let key: Vec<u8> = b"string".to_vec();
+    assert_eq!(b.get(&key), Some(&[1u8, 2][..]));
+    assert_eq!(b.get([7u8; 2]), Some(&b"array"[..]));
+}
+
+#[test]
+fn binary_bucket_name() {
+    let path = "./tests/binary_name.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let name = [0xffu8, 0x00, 0x7f];
+    let mut b = tx.create_bucket(name).unwrap();
+    b.put(b"k", b"v").unwrap();
+    drop(b);
+    let b = tx.create_bucket_if_not_exist(&name[..]).unwrap();
+    assert_eq!(b.get(b"k"), Some(&b"v"[..]));
+}
+
+#[test]
+fn many_buckets_one_tx() {
+    let path = "./tests/many_buckets.db";
+    let _ = std::fs::remove_file(path);
+    let names: Vec<String> = (0..16).map(|i| format!("bucket-{:02}", i)).collect();
+    {
+        let db = DB::open(path).unwrap();
+        let tx = db.tx(true).unwrap();
+        for name in names.iter() {
+            let mut b = tx.create_bucket(name).unwrap();
+            for i in 0..100u32 {
+                b.put(format!("{}-{:03}", name, i), [1u8; 32]).unwrap();
+            }
+        }
+        tx.commit().unwrap();
+    }
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    for name in names.iter() {
+        let b = tx.bucket(name).unwrap();
+        for i in 0..100u32 {
+            assert_eq!(b.get(format!("{}-{:03}", name, i)), Some(&[1u8; 32][..]));
+        }
+    }
+}
+
+#[test]
+fn large_value() {
+    let path = "./tests/large_value.db";
+    let _ = std::fs::remove_file(path);
+    let value: Vec<u8> = (0..20_000u32).map(|i| i as u8).collect();
+    {
+        let db = DB::open(path).unwrap();
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket("test").unwrap();
+        b.put("big", &value).unwrap();
+        b.put("small", "v").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("test").unwrap();
+    assert_eq!(b.get("big"), Some(&value[..]));
+    assert_eq!(b.get("small"), Some(&b"v"[..]));
+}
+
+#[test]
+fn bucket_depth_limit() {
+    let path = "./tests/bucket_depth.db";
+    let _ = std::fs::remove_file(path);
+    let db = DBBuilder::default()
+        .max_bucket_depth(Some(2))
+        .open(path)
+        .unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut top = tx.create_bucket("top").unwrap();
+    let child = top.create_bucket("child").unwrap();
+    child.put("k", "v").unwrap();
+    let err = child.create_bucket("grandchild").unwrap_err();
+    assert!(matches!(err, RoltError::BucketTooDeep(2)));
+    // the ttl index of a bucket at the limit doesn't count as a level
+    child
+        .put_with_ttl("t", "v", Duration::from_secs(60))
+        .unwrap();
+    assert!(top.create_bucket_if_not_exist("child").is_ok());
+}
+
+#[test]
+fn bucket_writable() {
+    let path = "./tests/bucket_writable.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    assert!(tx.create_bucket("test").unwrap().writable());
+    tx.commit().unwrap();
+    drop(tx);
+    let tx = db.tx(false).unwrap();
+    assert!(!tx.bucket("test").unwrap().writable());
+}
+
+#[test]
+fn pop_first_last() {
+    let path = "./tests/pop.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("queue").unwrap();
+    for i in 0..5u8 {
+        b.put([i], [i * 10]).unwrap();
+    }
+    assert_eq!(b.pop_first().unwrap(), Some((vec![0], vec![0])));
+    assert_eq!(b.pop_last().unwrap(), Some((vec![4], vec![40])));
+    assert_eq!(b.pop_first().unwrap(), Some((vec![1], vec![10])));
+    assert_eq!(b.get([0]), None);
+    assert_eq!(b.get([4]), None);
+    assert_eq!(b.pop_last().unwrap(), Some((vec![3], vec![30])));
+    assert_eq!(b.pop_last().unwrap(), Some((vec![2], vec![20])));
+    assert_eq!(b.pop_first().unwrap(), None);
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("queue").unwrap();
+    assert!(b.first().is_none());
+}
+
+#[test]
+fn before_commit() {
+    let path = "./tests/before_commit.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    // every order must reference an existing user
+    let check = |tx: &Transaction| -> roltdb::Result<()> {
+        let (users, orders) = match (tx.bucket("users"), tx.bucket("orders")) {
+            (Some(u), Some(o)) => (u, o),
+            _ => return Ok(()),
+        };
+        let mut c = orders.cursor();
+        let mut pair = c.first()?;
+        while let Some(user) = pair.value() {
+            if users.get(user).is_none() {
+                let user = String::from_utf8_lossy(user);
+                return Err(format!("unknown user {:?}", user).into());
+            }
+            pair = c.next()?;
+        }
+        Ok(())
+    };
+
+    let tx = db.tx(true).unwrap();
+    tx.before_commit(check);
+    tx.create_bucket("users")
+        .unwrap()
+        .put("alice", "1")
+        .unwrap();
+    tx.create_bucket("orders")
+        .unwrap()
+        .put("o1", "alice")
+        .unwrap();
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(true).unwrap();
+    tx.before_commit(check);
+    let mut b = tx.create_bucket_if_not_exist("orders").unwrap();
+    b.put("o2", "bob").unwrap();
+    drop(b);
+    let err = tx.commit().unwrap_err();
+    assert!(matches!(err, RoltError::Other(_)));
+    assert_eq!(err.to_string(), "unknown user \"bob\"");
+    // the tx stays rolled back, dropping it doesn't commit
+    assert_eq!(tx.state(), TxState::RolledBack);
+    drop(tx);
+
+    // a veto of the commit on drop rolls back instead of panicking
+    let tx = db.tx(true).unwrap();
+    tx.before_commit(check);
+    let mut b = tx.create_bucket_if_not_exist("orders").unwrap();
+    b.put("o2", "bob").unwrap();
+    drop(b);
+    drop(tx);
+
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("orders").unwrap();
+    assert_eq!(b.get("o2"), None);
+    b.put("o3", "alice").unwrap();
+    assert_eq!(b.get("o1"), Some(&b"alice"[..]));
+}
+
+#[test]
+fn commit_once() {
+    let path = "./tests/commit_once.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    tx.create_bucket("test").unwrap().put("k", "v").unwrap();
+    assert_eq!(tx.state(), TxState::Active);
+    tx.commit().unwrap();
+    assert_eq!(tx.state(), TxState::Committed);
+    let err = tx.commit().unwrap_err();
+    assert!(matches!(err, RoltError::TxCommitted));
+    let err = tx.rollback().unwrap_err();
+    assert!(matches!(err, RoltError::TxCommitted));
+    drop(tx);
+
+    let tx = db.tx(true).unwrap();
+    assert!(tx.bucket("test").is_some());
+    tx.rollback().unwrap();
+    tx.rollback().unwrap();
+    let err = tx.commit().unwrap_err();
+    assert!(matches!(err, RoltError::TxRolledBack));
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    assert_eq!(tx.bucket("test").unwrap().get("k"), Some(&b"v"[..]));
+}
+
+#[test]
+fn root_bucket() {
+    let path = "./tests/root_bucket.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("tenant").unwrap();
+    b.put("name", "acme").unwrap();
+    b.create_bucket("users").unwrap().put("alice", "1").unwrap();
+    drop(b);
+    tx.root.write().put("version", "2").unwrap();
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    let root = tx.root();
+    assert_eq!(root.get("version"), Some(&b"2"[..]));
+    let tenant = root.bucket("tenant").unwrap();
+    assert_eq!(tenant.get("name"), Some(&b"acme"[..]));
+    let users = tenant.bucket("users").unwrap();
+    assert_eq!(users.get("alice"), Some(&b"1"[..]));
+    // plain keys and missing names don't open as buckets
+    assert!(root.bucket("version").is_none());
+    assert!(tenant.bucket("name").is_none());
+    assert!(tenant.bucket("orders").is_none());
+}
+
+#[test]
+fn list_buckets() {
+    let path = "./tests/list_buckets.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    assert!(tx.buckets().unwrap().is_empty());
+    for name in ["users", "orders", "audit"] {
+        tx.create_bucket(name).unwrap().put("k", "v").unwrap();
+    }
+    tx.create_bucket_if_not_exist("users")
+        .unwrap()
+        .create_bucket("nested")
+        .unwrap();
+    tx.root.write().put("version", "2").unwrap();
+    tx.root
+        .write()
+        .put_with_ttl("session", "1", Duration::from_secs(60))
+        .unwrap();
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    assert_eq!(
+        tx.buckets().unwrap(),
+        [b"audit".to_vec(), b"orders".to_vec(), b"users".to_vec()]
+    );
+}
+
+#[test]
+fn typed_txs() {
+    let path = "./tests/typed_txs.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.write_tx().unwrap();
+    tx.create_bucket("users")
+        .unwrap()
+        .put("alice", "1")
+        .unwrap();
+    let id = tx.id();
+    // committed on drop
+    drop(tx);
+
+    let tx = db.read_tx().unwrap();
+    assert_eq!(tx.id(), id);
+    assert_eq!(tx.buckets().unwrap(), [b"users".to_vec()]);
+    let b = tx.bucket("users").unwrap();
+    assert_eq!(b.get("alice"), Some(&b"1"[..]));
+    assert!(tx.check().unwrap().is_empty());
+}
+
+#[test]
+fn temp_db() {
+    let leftovers = || {
+        let prefix = format!("roltdb-{}-", std::process::id());
+        std::fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .filter(|e| {
+                let name = e.as_ref().unwrap().file_name();
+                name.to_string_lossy().starts_with(&prefix)
+            })
+            .count()
+    };
+    let db = DB::open_temp().unwrap();
+    let other = DB::open_temp().unwrap();
+    assert_eq!(leftovers(), 0);
+    for db in [&db, &other] {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket("test").unwrap();
+        for i in 0..2000u32 {
+            b.put(i.to_be_bytes(), [7u8; 64]).unwrap();
+        }
+        drop(b);
+        tx.commit().unwrap();
+    }
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("test").unwrap();
+    assert_eq!(b.get(1999u32.to_be_bytes()), Some(&[7u8; 64][..]));
+    assert_eq!(leftovers(), 0);
+}
+
+#[test]
+fn open_progress() {
+    let path = "./tests/open_progress.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    for round in 0..3u32 {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+        for i in 0..500u32 {
+            b.put(i.to_be_bytes(), round.to_be_bytes()).unwrap();
+        }
+        drop(b);
+        tx.commit().unwrap();
+        drop(tx);
+    }
+    drop(db);
+
+    let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = events.clone();
+    let db = DBBuilder::default()
+        .open_progress(move |phase, done, total| sink.lock().unwrap().push((phase, done, total)))
+        .open(path)
+        .unwrap();
+    let events = events.lock().unwrap();
+    let mut phases: Vec<OpenPhase> = events.iter().map(|e| e.0).collect();
+    phases.dedup();
+    assert_eq!(
+        phases,
+        [OpenPhase::Lock, OpenPhase::Map, OpenPhase::FreeList]
+    );
+    for phase in phases {
+        let last = events.iter().rev().find(|e| e.0 == phase).unwrap();
+        assert_eq!(last.1, last.2, "{:?}", phase);
+    }
+    // rewriting the bucket three times left freed pages behind
+    let free = events.iter().find(|e| e.0 == OpenPhase::FreeList).unwrap();
+    assert!(free.2 > 0);
+    assert!(db.tx(false).unwrap().bucket("test").is_some());
+}
+
+#[test]
+fn application_id() {
+    let path = "./tests/application_id.db";
+    let _ = std::fs::remove_file(path);
+    let db = DBBuilder::default()
+        .application_id(0xC0FFEE)
+        .open(path)
+        .unwrap();
+    assert_eq!(db.application_id().unwrap(), 0xC0FFEE);
+    let tx = db.tx(true).unwrap();
+    drop(tx.create_bucket("test").unwrap());
+    tx.commit().unwrap();
+    drop(tx);
+    drop(db);
+
+    // the id survives commits and is checked only when asked for
+    assert_eq!(DB::open(path).unwrap().application_id().unwrap(), 0xC0FFEE);
+    let err = DBBuilder::default()
+        .application_id(7)
+        .open(path)
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        RoltError::ApplicationId {
+            expected: 7,
+            found: 0xC0FFEE
+        }
+    ));
+
+    // restamp the file for another application
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    tx.set_application_id(7).unwrap();
+    tx.commit().unwrap();
+    drop(tx);
+    drop(db);
+    let db = DBBuilder::default().application_id(7).open(path).unwrap();
+    assert!(db.tx(false).unwrap().bucket("test").is_some());
+    assert!(db.tx(false).unwrap().set_application_id(8).is_err());
+}
+
+#[test]
+fn node_memory_limit() {
+    let path = "./tests/node_memory.db";
+    let _ = std::fs::remove_file(path);
+    let db = DBBuilder::default()
+        .node_memory_limit(Some(64 << 10))
+        .open(path)
+        .unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    let mut written = 0;
+    let err = loop {
+        match b.put(format!("key-{:06}", written), [7u8; 100]) {
+            Ok(()) => written += 1,
+            Err(e) => break e,
+        }
+    };
+    assert!(matches!(err, RoltError::NodeMemoryLimit(65536)));
+    let memory = tx.memory();
+    assert!(memory.bytes <= 64 << 10 && memory.bytes > 60 << 10);
+    // the refused put left nothing behind, deletes still go through
+    assert_eq!(b.get(format!("key-{:06}", written)), None);
+    b.delete("key-000000").unwrap();
+    assert!(tx.memory().bytes < memory.bytes);
+    assert_eq!(tx.memory().peak_bytes, memory.bytes);
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    // the next tx starts from nothing and counts nodes it reads
+    let tx = db.tx(true).unwrap();
+    assert_eq!(tx.memory().bytes, 0);
+    let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+    b.put("key-000001", "v").unwrap();
+    let memory = tx.memory();
+    assert!(memory.nodes >= 1 && memory.bytes > 0);
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+    let tx = db.tx(false).unwrap();
+    assert_eq!(
+        tx.bucket("test").unwrap().get("key-000001"),
+        Some(&b"v"[..])
+    );
+}
+
+#[test]
+fn typed_errors() {
+    let path = "./tests/typed_errors.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    let err = tx.create_bucket("test").unwrap_err();
+    assert!(matches!(err, RoltError::NotWritable));
+    drop(tx);
+    let tx = db.tx(true).unwrap();
+    drop(tx.create_bucket("test").unwrap());
+    let err = tx.create_bucket("test").unwrap_err();
+    assert!(matches!(err, RoltError::BucketExist));
+    drop(tx);
+    // a directory can't be opened as a db file
+    let dir = "./tests/dir_as_db";
+    let _ = std::fs::create_dir(dir);
+    let err = DB::open(dir).unwrap_err();
+    assert!(matches!(err, RoltError::Io(_)));
+    std::fs::remove_dir(dir).unwrap();
+    let _ = std::fs::remove_file("./tests/dir_as_db.lock");
+}
+
+#[test]
+fn invalid_config() {
+    let path = "./tests/invalid_config.db";
+    let _ = std::fs::remove_file(path);
+    let invalid = |builder: DBBuilder| {
+        let err = builder.open(path).unwrap_err();
+        assert!(matches!(err, RoltError::InvalidConfig(_)), "{}", err);
+        // nothing was created
+        assert!(!std::path::Path::new(path).exists());
+    };
+    invalid(DBBuilder::default().num_pages(2));
+    invalid(DBBuilder::default().page_size(3000));
+    invalid(DBBuilder::default().page_size(page_size::get() as u64 * 2));
+    invalid(DBBuilder::default().max_bucket_depth(Some(0)));
+    invalid(DBBuilder::default().growth(GrowthPolicy::Step(0)));
+    invalid(DBBuilder::default().read_only(true).replication_log(path));
+    assert!(DBBuilder::default().num_pages(4).validate().is_ok());
+}
+
+#[test]
+fn debug_output() {
+    let path = "./tests/debug_output.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let out = format!("{:?}", db);
+    assert!(
+        out.starts_with("DB {") && out.contains("debug_output.db"),
+        "{}",
+        out
+    );
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("users").unwrap();
+    b.put("alice", "secret").unwrap();
+    b.create_bucket("nested").unwrap();
+    let out = format!("{:?}", *b);
+    assert!(
+        out.contains("\"users\"") && out.contains("\"nested\""),
+        "{}",
+        out
+    );
+    assert!(!out.contains("secret"), "{}", out);
+    // the root bucket is held, the tx still prints
+    let out = format!("{:?}", tx);
+    assert!(
+        out.contains("writable: true") && !out.contains("secret"),
+        "{}",
+        out
+    );
+}
+
+#[test]
+fn delete_batch() {
+    let path = "./tests/delete_batch.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    for i in 0..5000u32 {
+        b.put(i.to_be_bytes(), [1; 64]).unwrap();
+    }
+    b.create_bucket("nested").unwrap();
+    // every third key, unsorted, with duplicates and missing keys
+    let mut keys: Vec<[u8; 4]> = (0..6000u32)
+        .rev()
+        .step_by(3)
+        .map(u32::to_be_bytes)
+        .collect();
+    keys.extend_from_within(..10);
+    let expected = (0..5000u32).filter(|i| (5999 - i) % 3 == 0).count();
+    assert_eq!(b.delete_batch(&keys).unwrap(), expected);
+    for i in 0..5000u32 {
+        assert_eq!(
+            b.get(i.to_be_bytes()).is_some(),
+            (5999 - i) % 3 != 0,
+            "{}",
+            i
+        );
+    }
+    let err = b.delete_batch(&["nested"]).unwrap_err();
+    assert!(matches!(err, RoltError::IncompatibleValue));
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("test").unwrap();
+    let left = (0..5000u32)
+        .filter(|i| b.get(i.to_be_bytes()).is_some())
+        .count();
+    assert_eq!(left, 5000 - expected);
+    assert!(b.bucket("nested").is_some());
+}
+
+#[test]
+fn put_over_nested_bucket() {
+    let path = "./tests/put_over_nested.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    let child = b.create_bucket("child").unwrap();
+    for i in 0..500u32 {
+        child.put(i.to_be_bytes(), [1; 64]).unwrap();
+    }
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+    let err = b.put("child", "plain").unwrap_err();
+    assert!(matches!(err, RoltError::IncompatibleValue));
+    let err = b.put_with_flags("child", "plain", 1).unwrap_err();
+    assert!(matches!(err, RoltError::IncompatibleValue));
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    let b = tx.bucket("test").unwrap();
+    let child = b.bucket("child").unwrap();
+    assert_eq!(child.get(499u32.to_be_bytes()), Some(&[1u8; 64][..]));
+}
+
+#[test]
+fn shared_handles() {
+    let path = "./tests/shared_handles.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let other = db.clone();
+    let tx = db.tx(true).unwrap();
+    drop(tx.create_bucket("test").unwrap());
+    // the writer is shared by every handle
+    assert!(matches!(
+        other.tx(true).unwrap_err(),
+        RoltError::WritableTxNotAllowed
+    ));
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = other.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+    b.put("k", "v").unwrap();
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+    // dropping one handle leaves the db open for the rest
+    drop(db);
+    let tx = other.tx(false).unwrap();
+    assert_eq!(tx.bucket("test").unwrap().get("k"), Some(&b"v"[..]));
+    drop(tx);
+    drop(other);
+    // the last one closed the file and released its lock
+    let db = DBBuilder::default()
+        .lock_timeout(Some(Duration::from_millis(10)))
+        .open(path)
+        .unwrap();
+    let tx = db.tx(false).unwrap();
+    assert_eq!(tx.bucket("test").unwrap().get("k"), Some(&b"v"[..]));
+}
+
+#[test]
+fn lock_file_removed_on_close() {
+    let path = "./tests/lock_file.db";
+    let lock = std::path::Path::new("./tests/lock_file.db.lock");
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    assert!(lock.exists());
+    drop(db);
+    assert!(!lock.exists());
+    // a later writer takes a fresh lock file
+    let db = DB::open(path).unwrap();
+    assert!(lock.exists());
+    assert!(matches!(
+        DBBuilder::default()
+            .lock_timeout(Some(Duration::from_millis(10)))
+            .open(path)
+            .unwrap_err(),
+        RoltError::Locked
+    ));
+    drop(db);
+    assert!(!lock.exists());
+}
@@ -0,0 +1,92 @@
+use std::time::{Duration, Instant};
+
+use roltdb::{DBBuilder, RoltError, DB};
+
+#[test]
+fn read_only_replica() {
+    let path = "./tests/replica.db";
+    let _ = std::fs::remove_file(path);
+    let db = DBBuilder::default().num_pages(8).open(path).unwrap();
+    let replica = DBBuilder::default().read_only(true).open(path).unwrap();
+    assert!(replica.tx(true).is_err());
+    {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket("test").unwrap();
+        b.put("k", "v1").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+    let tx = replica.tx(false).unwrap();
+    assert_eq!(tx.bucket("test").unwrap().get("k"), Some(&b"v1"[..]));
+    drop(tx);
+
+    // grow the file past the replica's mapping
+    for i in 0..3 {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+        b.put("k", format!("v{}", i + 2)).unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+    let tx = replica.tx(false).unwrap();
+    assert!(tx.bucket("missing").is_none());
+    assert_eq!(tx.bucket("test").unwrap().get("k"), Some(&b"v4"[..]));
+    drop(tx);
+    drop(replica);
+    drop(db);
+    assert!(DB::open(path).is_ok());
+}
+
+#[test]
+fn lock_timeout() {
+    let path = "./tests/lock_timeout.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let start = Instant::now();
+    let err = DBBuilder::default()
+        .lock_timeout(Some(Duration::from_millis(50)))
+        .open(path)
+        .unwrap_err();
+    assert!(matches!(err, RoltError::Locked));
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    // readers do not wait for the writer
+    let replica = DBBuilder::default()
+        .read_only(true)
+        .lock_timeout(Some(Duration::ZERO))
+        .open(path);
+    assert!(replica.is_ok());
+    drop(db);
+    assert!(DBBuilder::default()
+        .lock_timeout(Some(Duration::ZERO))
+        .open(path)
+        .is_ok());
+}
+
+#[test]
+fn no_lock() {
+    let path = "./tests/no_lock.db";
+    let _ = std::fs::remove_file(path);
+    let _ = std::fs::remove_file("./tests/no_lock.db.lock");
+    let builder = DBBuilder::default()
+        .no_lock(true)
+        .lock_timeout(Some(Duration::ZERO));
+    let db = builder.open(path).unwrap();
+    assert!(!std::path::Path::new("./tests/no_lock.db.lock").exists());
+    {
+        let tx = db.tx(true).unwrap();
+        tx.create_bucket("test").unwrap().put("k", "v").unwrap();
+        tx.commit().unwrap();
+    }
+    drop(db);
+    // nothing keeps a locking writer out either
+    let locked = DBBuilder::default()
+        .lock_timeout(Some(Duration::ZERO))
+        .open(path)
+        .unwrap();
+    let db = builder.open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    assert_eq!(tx.bucket("test").unwrap().get("k"), Some(&b"v"[..]));
+    drop(tx);
+    drop(db);
+    drop(locked);
+}
@@ -0,0 +1,105 @@
+use roltdb::{ChangeOp, DBBuilder, ReplicationStream, DB};
+
+#[test]
+fn log_shipping() {
+    let path = "./tests/leader.db";
+    let log = "./tests/leader.log";
+    let follower_path = "./tests/follower.db";
+    for p in [path, log, follower_path] {
+        let _ = std::fs::remove_file(p);
+    }
+    let leader = DBBuilder::default()
+        .replication_log(log)
+        .open(path)
+        .unwrap();
+    let follower = DB::open(follower_path).unwrap();
+    {
+        let tx = leader.tx(true).unwrap();
+        let mut b = tx.create_bucket("users").unwrap();
+        b.put("a", "1").unwrap();
+        b.put("b", "2").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+    let mut stream = leader.replication_stream(0).unwrap();
+    let first = stream.next().unwrap().unwrap();
+    assert_eq!(first.entries.len(), 3);
+    // the bucket sorts before its keys
+    assert_eq!(first.entries[0].op, ChangeOp::CreateBucket);
+    assert_eq!(first.entries[0].key, b"users");
+    assert_eq!(first.entries[1].op, ChangeOp::Insert);
+    assert_eq!(first.entries[1].path, [b"users".to_vec()]);
+    assert_eq!(first.entries[2].value, b"2");
+    first.apply(&follower).unwrap();
+    assert!(stream.next().is_none());
+
+    {
+        let tx = leader.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist("users").unwrap();
+        b.put("a", "3").unwrap();
+        b.delete("b").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+    // the stream picks up txs committed after it ran dry
+    let second = stream.next().unwrap().unwrap();
+    assert!(second.tx_id > first.tx_id);
+    second.apply(&follower).unwrap();
+    assert!(stream.next().is_none());
+
+    let mut tail = ReplicationStream::open(log, second.tx_id).unwrap();
+    assert_eq!(tail.next().unwrap().unwrap(), second);
+
+    let tx = follower.tx(true).unwrap();
+    let b = tx.create_bucket_if_not_exist("users").unwrap();
+    assert_eq!(b.get("a"), Some(&b"3"[..]));
+    assert_eq!(b.get("b"), None);
+    drop(b);
+    drop(tx);
+    std::fs::remove_file(log).unwrap();
+}
+
+#[test]
+fn nested_buckets() {
+    let path = "./tests/nested_leader.db";
+    let log = "./tests/nested_leader.log";
+    let follower_path = "./tests/nested_follower.db";
+    for p in [path, log, follower_path] {
+        let _ = std::fs::remove_file(p);
+    }
+    let leader = DBBuilder::default()
+        .replication_log(log)
+        .open(path)
+        .unwrap();
+    let follower = DB::open(follower_path).unwrap();
+    {
+        let tx = leader.tx(true).unwrap();
+        let mut a = tx.create_bucket("a").unwrap();
+        a.put("k", "top").unwrap();
+        a.create_bucket("b").unwrap().put("k", "nested").unwrap();
+        a.create_bucket("empty").unwrap();
+        a.create_dup_bucket("tags")
+            .unwrap()
+            .put_dup("k", "x")
+            .unwrap();
+        drop(a);
+        // same name as the nested bucket, at the top level
+        tx.create_bucket("b").unwrap().put("k", "other").unwrap();
+        tx.commit().unwrap();
+    }
+    for record in leader.replication_stream(0).unwrap() {
+        record.unwrap().apply(&follower).unwrap();
+    }
+
+    let tx = follower.tx(false).unwrap();
+    let a = tx.bucket("a").unwrap();
+    assert_eq!(a.get("k"), Some(&b"top"[..]));
+    assert_eq!(a.bucket("b").unwrap().get("k"), Some(&b"nested"[..]));
+    assert!(a.bucket("empty").is_some());
+    assert_eq!(a.bucket("tags").unwrap().get_all("k"), [&b"x"[..]]);
+    drop(a);
+    assert_eq!(tx.bucket("b").unwrap().get("k"), Some(&b"other"[..]));
+    assert_eq!(tx.buckets().unwrap(), [b"a".to_vec(), b"b".to_vec()]);
+    drop(tx);
+    std::fs::remove_file(log).unwrap();
+}
@@ -0,0 +1,89 @@
+#![cfg(feature = "server")]
+use std::{thread, time::Duration};
+
+use roltdb::{Client, Server};
+
+#[test]
+fn serve_over_tcp() {
+    let path = "./tests/server.db";
+    let _ = std::fs::remove_file(path);
+    let server = Server::start(path, "127.0.0.1:0").unwrap();
+    let addr = server.local_addr();
+
+    let mut c = Client::connect(addr).unwrap();
+    assert_eq!(c.get(b"b", b"k").unwrap(), None);
+    c.put(b"b", b"a1", b"1").unwrap();
+    c.put(b"b", b"a2", b"2").unwrap();
+    c.put(b"b", b"z", b"3").unwrap();
+    assert_eq!(c.get(b"b", b"a1").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(
+        c.scan(b"b", b"a").unwrap(),
+        vec![
+            (b"a1".to_vec(), b"1".to_vec()),
+            (b"a2".to_vec(), b"2".to_vec())
+        ]
+    );
+    c.delete(b"b", b"a2").unwrap();
+    assert_eq!(c.get(b"b", b"a2").unwrap(), None);
+
+    // writes inside begin/rollback are discarded
+    c.begin().unwrap();
+    c.put(b"b", b"t", b"x").unwrap();
+    assert_eq!(c.get(b"b", b"t").unwrap(), Some(b"x".to_vec()));
+    let mut other = Client::connect(addr).unwrap();
+    c.rollback().unwrap();
+    assert_eq!(c.get(b"b", b"t").unwrap(), None);
+
+    c.begin().unwrap();
+    c.put(b"b", b"t", b"y").unwrap();
+    c.commit().unwrap();
+    assert_eq!(other.get(b"b", b"t").unwrap(), Some(b"y".to_vec()));
+    assert!(c.commit().is_err());
+
+    // an open tx is rolled back when its connection goes away
+    c.begin().unwrap();
+    c.put(b"b", b"gone", b"1").unwrap();
+    drop(c);
+    let mut found = Some(Vec::new());
+    for _ in 0..100 {
+        match other.get(b"b", b"gone") {
+            Ok(v) => {
+                found = v;
+                break;
+            }
+            Err(_) => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+    assert_eq!(found, None);
+    server.stop();
+}
+
+#[test]
+fn others_wait_for_open_tx() {
+    let path = "./tests/server_wait.db";
+    let _ = std::fs::remove_file(path);
+    let server = Server::start(path, "127.0.0.1:0").unwrap();
+    let addr = server.local_addr();
+
+    let mut a = Client::connect(addr).unwrap();
+    a.begin().unwrap();
+    a.put(b"b", b"k", b"a").unwrap();
+    // requests of the other connection wait for a instead of failing
+    let other = thread::spawn(move || {
+        let mut b = Client::connect(addr).unwrap();
+        let seen = b.get(b"b", b"k").unwrap();
+        b.put(b"b", b"k", b"b").unwrap();
+        b.begin().unwrap();
+        b.put(b"b", b"k2", b"b").unwrap();
+        b.commit().unwrap();
+        seen
+    });
+    thread::sleep(Duration::from_millis(200));
+    assert!(!other.is_finished());
+    assert_eq!(a.get(b"b", b"k").unwrap(), Some(b"a".to_vec()));
+    a.commit().unwrap();
+    assert_eq!(other.join().unwrap(), Some(b"a".to_vec()));
+    assert_eq!(a.get(b"b", b"k").unwrap(), Some(b"b".to_vec()));
+    assert_eq!(a.get(b"b", b"k2").unwrap(), Some(b"b".to_vec()));
+    server.stop();
+}
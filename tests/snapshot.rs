@@ -0,0 +1,120 @@
+use roltdb::{RoltError, DB};
+
+#[test]
+fn snapshot_handle() {
+    let path = "./tests/snapshot.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    {
+        let tx = db.tx(true).unwrap();
+        drop(tx.create_bucket("test").unwrap());
+        tx.commit().unwrap();
+    }
+    let snapshot = db.snapshot_handle().unwrap();
+    let pinned = snapshot.tx_id();
+    {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+        b.put("k", "v").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+    let tx = snapshot.tx().unwrap();
+    assert!(!tx.writable());
+    // the put committed after the snapshot isn't visible through it
+    assert_eq!(tx.bucket("test").unwrap().get("k"), None);
+    assert_eq!(snapshot.tx_id(), pinned);
+    assert!(db.snapshot_handle().unwrap().tx_id() > pinned);
+    drop(tx);
+    let tx = db.tx(false).unwrap();
+    assert_eq!(tx.bucket("test").unwrap().get("k"), Some(&b"v"[..]));
+    drop(tx);
+    snapshot.release();
+}
+
+#[test]
+fn read_tx_pinned() {
+    let path = "./tests/snapshot_pinned.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    tx.create_bucket("test").unwrap().put("k", "old").unwrap();
+    tx.commit().unwrap();
+    drop(tx);
+
+    let reader = db.tx(false).unwrap();
+    let num_pages = reader.pages().count();
+
+    // grow and remap the file under the reader
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+    b.put("k", "new").unwrap();
+    for i in 0..2000u32 {
+        b.put(format!("k{:04}", i), [7u8; 64]).unwrap();
+    }
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let b = reader.bucket("test").unwrap();
+    assert_eq!(b.get("k"), Some(&b"old"[..]));
+    assert_eq!(b.get("k0000"), None);
+    assert_eq!(reader.pages().count(), num_pages);
+    let last = db.tx(false).unwrap().pages().last().unwrap().id;
+    assert!(reader.page_info(last).is_err());
+}
+
+#[test]
+fn optimistic_tx() {
+    let path = "./tests/optimistic.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    {
+        let tx = db.tx(true).unwrap();
+        tx.create_bucket("test").unwrap().put("a", "0").unwrap();
+        tx.commit().unwrap();
+    }
+    // disjoint keys both commit
+    let mut t1 = db.optimistic_tx().unwrap();
+    let mut t2 = db.optimistic_tx().unwrap();
+    assert_eq!(t1.get("test", "a").unwrap(), Some(b"0".to_vec()));
+    t1.put("test", "b", "1").unwrap();
+    t2.put("other", "c", "2").unwrap();
+    assert_eq!(t2.get("other", "c").unwrap(), Some(b"2".to_vec()));
+    let id = t1.commit().unwrap();
+    assert!(id > t2.base_id());
+    t2.commit().unwrap();
+
+    // the loser of two read-modify-writes gets a conflict
+    let mut t1 = db.optimistic_tx().unwrap();
+    let mut t2 = db.optimistic_tx().unwrap();
+    t1.get("test", "a").unwrap();
+    t2.get("test", "a").unwrap();
+    t1.put("test", "a", "1").unwrap();
+    t2.put("test", "a", "2").unwrap();
+    t1.commit().unwrap();
+    let err = t2.commit().unwrap_err();
+    assert!(matches!(err, RoltError::Conflict));
+    // a regular write tx conflicts too, a retry sees its value
+    let mut t3 = db.optimistic_tx().unwrap();
+    assert_eq!(t3.get("test", "a").unwrap(), Some(b"1".to_vec()));
+    {
+        let tx = db.tx(true).unwrap();
+        tx.create_bucket_if_not_exist("test")
+            .unwrap()
+            .delete("a")
+            .unwrap();
+        tx.commit().unwrap();
+    }
+    t3.delete("test", "b").unwrap();
+    assert!(t3.commit().is_err());
+    let mut t4 = db.optimistic_tx().unwrap();
+    assert_eq!(t4.get("test", "a").unwrap(), None);
+    t4.delete("test", "b").unwrap();
+    t4.commit().unwrap();
+
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("test").unwrap();
+    assert_eq!((b.get("a"), b.get("b")), (None, None));
+    assert_eq!(tx.bucket("other").unwrap().get("c"), Some(&b"2"[..]));
+}
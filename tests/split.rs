@@ -0,0 +1,414 @@
+use roltdb::{PageKind, RangeEstimate, DB};
+
+fn key(i: u32) -> String {
+    format!("key-{:05}", i)
+}
+
+#[test]
+fn many_keys() {
+    let path = "./tests/many_keys.db";
+    let _ = std::fs::remove_file(path);
+    let value = [7u8; 64];
+    {
+        let db = DB::open(path).unwrap();
+        for round in 0..3u32 {
+            let tx = db.tx(true).unwrap();
+            let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+            for i in round * 200..(round + 1) * 200 {
+                b.put(key(i), value).unwrap();
+            }
+            drop(b);
+            tx.commit().unwrap();
+            drop(tx);
+        }
+    }
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("test").unwrap();
+    for i in 0..600 {
+        assert_eq!(b.get(key(i)), Some(&value[..]), "{}", key(i));
+    }
+}
+
+#[test]
+fn split_pages_contiguous() {
+    let path = "./tests/split_contiguous.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    for i in 0..200 {
+        b.put(key(i), [7u8; 64]).unwrap();
+    }
+    drop(b);
+    tx.commit().unwrap();
+    let tx_id = tx.id();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    let leaves: Vec<_> = tx
+        .pages()
+        .filter(|p| p.page_type == PageKind::Leaf && p.txid == tx_id && p.count > 1)
+        .map(|p| p.id)
+        .collect();
+    assert!(leaves.len() > 1);
+    assert!(leaves.windows(2).all(|w| w[1] == w[0] + 1));
+}
+
+#[test]
+fn tree_stats() {
+    let path = "./tests/tree_stats.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("small").unwrap();
+    b.put(key(0), "v").unwrap();
+    drop(b);
+    let mut b = tx.create_bucket("big").unwrap();
+    for i in 0..600 {
+        b.put(key(i), [7u8; 64]).unwrap();
+    }
+    b.put("large", vec![1u8; 3 * page_size::get()]).unwrap();
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    let stats = tx.bucket("small").unwrap().tree_stats().unwrap();
+    assert_eq!(
+        (stats.depth, stats.branch_pages, stats.leaf_pages),
+        (1, 0, 1)
+    );
+    assert_eq!(stats.avg_fanout, 0.0);
+
+    let stats = tx.bucket("big").unwrap().tree_stats().unwrap();
+    assert_eq!(stats.depth, 2);
+    assert_eq!(stats.branch_pages, 1);
+    assert!(stats.leaf_pages > 1);
+    assert!(stats.overflow_pages >= 3);
+    assert_eq!(stats.avg_fanout, stats.leaf_pages as f64);
+    // the leaf with the large value only counts as overflow
+    assert_eq!(stats.root_fill.pages, 1);
+    assert_eq!(stats.overflow_fill.pages, 1);
+    assert_eq!(stats.internal_fill.pages, 0);
+    let leaf = stats.leaf_fill;
+    assert_eq!(leaf.pages, stats.leaf_pages - 1);
+    assert!(leaf.p10 <= leaf.p50 && leaf.p50 <= leaf.p90 && leaf.p90 <= 1.0);
+    assert!(leaf.avg > 0.4 && leaf.avg <= 1.0);
+}
+
+#[test]
+fn disk_size() {
+    let path = "./tests/disk_size.db";
+    let _ = std::fs::remove_file(path);
+    let page_size = page_size::get() as u64;
+    let pages = |s: roltdb::TreeStats| s.branch_pages + s.leaf_pages + s.overflow_pages;
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("tenant").unwrap();
+    for i in 0..300 {
+        b.put(key(i), [7u8; 64]).unwrap();
+    }
+    b.create_bucket("small").unwrap().put(key(0), "v").unwrap();
+    let big = b.create_bucket("big").unwrap();
+    for i in 0..600 {
+        big.put(key(i), [7u8; 64]).unwrap();
+    }
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("tenant").unwrap();
+    let own = pages(b.tree_stats().unwrap());
+    let mut nested = 0;
+    for name in ["big", "small"] {
+        let child = b.create_bucket_if_not_exist(name).unwrap();
+        nested += pages(child.tree_stats().unwrap());
+    }
+    assert_eq!(b.disk_size().unwrap(), (own + nested) * page_size);
+    assert!(own > 1 && nested > 2);
+}
+
+#[test]
+fn histogram() {
+    let path = "./tests/histogram.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    for i in 0..100u32 {
+        b.put(format!("key-{:04}", i), vec![1u8; 100]).unwrap();
+    }
+    b.put("empty", "").unwrap();
+    b.put("big", vec![2u8; 10_000]).unwrap();
+    b.put_blob("blob", &mut &vec![3u8; 100_000][..]).unwrap();
+    b.create_bucket("nested").unwrap();
+
+    let hist = b.histogram().unwrap();
+    assert_eq!(hist.keys.count, 103);
+    assert_eq!(hist.keys.min, 3);
+    assert_eq!(hist.keys.max, 8);
+    // 100 falls in [64, 128)
+    assert_eq!(hist.values.classes[7], 100);
+    assert_eq!(hist.values.classes[0], 1);
+    assert_eq!(hist.values.max, 100_000);
+    assert_eq!(hist.blobs, 1);
+    assert_eq!(hist.overflow_values, 2);
+}
+
+#[test]
+fn append() {
+    let path = "./tests/append.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    for round in 0..4u32 {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist("log").unwrap();
+        for i in round * 1000..(round + 1) * 1000 {
+            b.put(key(i), [7u8; 64]).unwrap();
+        }
+        // out of order puts and deletes between appends
+        b.put(key(round * 1000 + 10), [8u8; 64]).unwrap();
+        b.delete(key(round * 1000 + 20)).unwrap();
+        b.put(key(round * 1000 + 999), [9u8; 64]).unwrap();
+        b.put(key(round * 1000 + 1000), [7u8; 64]).unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("log").unwrap();
+    let mut c = b.cursor();
+    let mut pair = c.first().unwrap();
+    let mut expected = (0..=4000).filter(|i| i % 1000 != 20);
+    while let Some(k) = pair.key() {
+        let i = expected.next().unwrap();
+        assert_eq!(k, key(i).as_bytes());
+        let v = match i % 1000 {
+            10 => 8,
+            999 => 9,
+            _ => 7,
+        };
+        assert_eq!(pair.value(), Some(&[v; 64][..]));
+        pair = c.next().unwrap();
+    }
+    assert_eq!(expected.next(), None);
+    assert!(tx.check().unwrap().is_empty());
+    // leaves filled by appends are split full, not at half
+    let stats = b.tree_stats().unwrap();
+    assert!(stats.leaf_fill.avg > 0.8, "{:?}", stats.leaf_fill);
+}
+
+#[test]
+fn append_log() {
+    let path = "./tests/append_log.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    for round in 0..3u64 {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist("events").unwrap();
+        for i in 0..500u64 {
+            let n = round * 500 + i;
+            assert_eq!(b.append(n.to_le_bytes()).unwrap(), n + 1);
+        }
+        assert_eq!(b.sequence(), (round + 1) * 500);
+        drop(b);
+        tx.commit().unwrap();
+    }
+
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("events").unwrap();
+    let mut c = b.cursor();
+    let mut pair = c.first().unwrap();
+    let mut n = 0u64;
+    while let Some(k) = pair.key() {
+        assert_eq!(k, (n + 1).to_be_bytes());
+        assert_eq!(pair.value(), Some(&n.to_le_bytes()[..]));
+        n += 1;
+        pair = c.next().unwrap();
+    }
+    assert_eq!(n, 1500);
+    drop(b);
+    drop(tx);
+
+    // a failed append doesn't use up a sequence
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_fixed_bucket("fixed", 8).unwrap();
+    assert_eq!(b.append(7u64.to_be_bytes()).unwrap(), 1);
+    assert!(b.append("short").is_err());
+    assert_eq!(b.append(8u64.to_be_bytes()).unwrap(), 2);
+}
+
+#[test]
+fn estimate_range() {
+    let path = "./tests/estimate_range.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    // keys of 9 bytes and values of 31 bytes
+    for i in 0..50_000u32 {
+        b.put(key(i), [1u8; 31]).unwrap();
+    }
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    let b = tx.bucket("test").unwrap();
+    for (lo, hi) in [
+        (0, 50_000),
+        (1000, 2000),
+        (12_345, 40_000),
+        (49_000, 50_000),
+    ] {
+        let est = b
+            .estimate_range(key(lo).as_bytes(), key(hi).as_bytes())
+            .unwrap();
+        let keys = (hi - lo) as f64;
+        assert!(
+            (est.keys as f64 - keys).abs() < keys * 0.2,
+            "{:?} for {}",
+            est,
+            keys
+        );
+        assert!((est.bytes as f64 - keys * 40.0).abs() < keys * 40.0 * 0.2);
+    }
+    // bounds within one leaf are counted exactly
+    let est = b
+        .estimate_range(key(100).as_bytes(), key(105).as_bytes())
+        .unwrap();
+    assert_eq!(
+        est,
+        RangeEstimate {
+            keys: 5,
+            bytes: 200
+        }
+    );
+    // an empty end is open, an empty start the first key
+    let all = b.estimate_range(b"", b"").unwrap();
+    assert!((all.keys as f64 - 50_000.0).abs() < 10_000.0, "{:?}", all);
+    let est = b.estimate_range(key(45_000).as_bytes(), b"").unwrap();
+    assert!((est.keys as f64 - 5000.0).abs() < 1000.0, "{:?}", est);
+    // empty and reversed ranges
+    assert_eq!(b.estimate_range(b"a", b"b").unwrap().keys, 0);
+    let est = b
+        .estimate_range(key(2000).as_bytes(), key(1000).as_bytes())
+        .unwrap();
+    assert_eq!(est.keys, 0);
+}
+
+#[test]
+fn delete_every_key() {
+    let path = "./tests/delete_every_key.db";
+    let _ = std::fs::remove_file(path);
+    let keys: Vec<String> = (0..2000).map(key).collect();
+    {
+        let db = DB::open(path).unwrap();
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket("test").unwrap();
+        for k in keys.iter() {
+            b.put(k, [7u8; 512]).unwrap();
+        }
+        drop(b);
+        tx.commit().unwrap();
+        drop(tx);
+        let tx = db.tx(false).unwrap();
+        // leaves under branches under the root
+        assert!(tx.bucket("test").unwrap().tree_stats().unwrap().depth >= 3);
+    }
+    {
+        let db = DB::open(path).unwrap();
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+        for k in keys.iter() {
+            b.delete(k).unwrap();
+        }
+        drop(b);
+        tx.commit().unwrap();
+    }
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    let stats = tx.bucket("test").unwrap().tree_stats().unwrap();
+    assert_eq!(
+        (stats.depth, stats.branch_pages, stats.leaf_pages),
+        (1, 0, 1)
+    );
+    assert!(tx.bucket("test").unwrap().get(key(0)).is_none());
+    drop(tx);
+    // the emptied root takes keys again
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+    for k in keys.iter().take(500) {
+        b.put(k, "v").unwrap();
+    }
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    assert_eq!(tx.bucket("test").unwrap().get(key(499)), Some(&b"v"[..]));
+}
+
+#[test]
+fn rebalance_after_deletes() {
+    let path = "./tests/rebalance_deletes.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    for i in 0..20000 {
+        b.put(key(i), [7u8; 16]).unwrap();
+    }
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+    let before = {
+        let tx = db.tx(false).unwrap();
+        let stats = tx.bucket("test").unwrap().tree_stats().unwrap();
+        stats.leaf_pages
+    };
+
+    // keep every hundredth key, the rest go in one batch
+    let tx = db.tx(true).unwrap();
+    let keys: Vec<String> = (0..20000).filter(|i| i % 100 != 0).map(key).collect();
+    let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+    assert_eq!(b.delete_batch(&keys).unwrap(), 19800);
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    let b = tx.bucket("test").unwrap();
+    let stats = b.tree_stats().unwrap();
+    assert!(
+        stats.leaf_pages * 10 < before,
+        "{} of {}",
+        stats.leaf_pages,
+        before
+    );
+    assert!(stats.depth <= 2);
+    for i in 0..20000 {
+        assert_eq!(b.get(key(i)).is_some(), i % 100 == 0, "{}", key(i));
+    }
+    drop(b);
+    drop(tx);
+
+    // single deletes rebalance too
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("test").unwrap();
+    for i in (0..20000).step_by(100).skip(1) {
+        b.delete(key(i)).unwrap();
+    }
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+    let tx = db.tx(false).unwrap();
+    assert!(tx.check().unwrap().is_empty());
+    let stats = tx.bucket("test").unwrap().tree_stats().unwrap();
+    assert_eq!((stats.depth, stats.leaf_pages), (1, 1));
+    assert_eq!(tx.bucket("test").unwrap().get(key(0)), Some(&[7u8; 16][..]));
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use roltdb::{RoltError, DB};
+
+#[test]
+fn expiration() {
+    let path = "./tests/ttl.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("cache").unwrap();
+    b.put_with_ttl("gone", "v", Duration::ZERO).unwrap();
+    b.put_with_ttl("live", "v", Duration::from_secs(3600))
+        .unwrap();
+    b.put_with_ttl("reset", "v", Duration::ZERO).unwrap();
+    b.put("reset", "v2").unwrap();
+    assert_eq!(b.get("gone"), None);
+    assert_eq!(b.get("live"), Some(&b"v"[..]));
+    assert_eq!(b.get("reset"), Some(&b"v2"[..]));
+    assert_eq!(b.purge_expired().unwrap(), 1);
+    assert_eq!(b.purge_expired().unwrap(), 0);
+    b.put_with_ttl("swept", "v", Duration::ZERO).unwrap();
+    drop(b);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(true).unwrap();
+    let b = tx.create_bucket_if_not_exist("cache").unwrap();
+    assert_eq!(b.get("live"), Some(&b"v"[..]));
+    assert_eq!(b.get("swept"), None);
+}
+
+#[test]
+fn delete() {
+    let path = "./tests/delete.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("test").unwrap();
+    b.put("a", "1").unwrap();
+    b.put("b", "2").unwrap();
+    b.delete("a").unwrap();
+    b.delete("missing").unwrap();
+    assert_eq!(b.get("a"), None);
+    assert_eq!(b.get("b"), Some(&b"2"[..]));
+}
+
+#[test]
+fn ttl_index_reserved() {
+    let path = "./tests/ttl_reserved.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let index = b"\x00roltdb.ttl";
+    let mut b = tx.create_bucket("cache").unwrap();
+    // a get first finds no index, the ttl put after it must still count
+    assert_eq!(b.get("k"), None);
+    b.put_with_ttl("k", "v", Duration::ZERO).unwrap();
+    assert_eq!(b.get("k"), None);
+    assert!(matches!(b.put(index, "v"), Err(RoltError::ReservedKey)));
+    assert!(matches!(
+        b.create_bucket(index),
+        Err(RoltError::ReservedKey)
+    ));
+    assert!(b.bucket(index).is_none());
+    drop(b);
+    assert!(matches!(
+        tx.create_bucket_if_not_exist(index),
+        Err(RoltError::ReservedKey)
+    ));
+    assert!(tx.bucket(index).is_none());
+}
@@ -0,0 +1,50 @@
+use roltdb::DB;
+
+#[test]
+fn u64_keys() {
+    let path = "./tests/u64_key.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("ids").unwrap();
+    // 256 sorts before 2 as little-endian or decimal text, not here
+    for n in [256u64, 2, 1 << 40, 3, u64::MAX] {
+        b.put_u64(n, n.to_string()).unwrap();
+    }
+    b.put("not-a-u64", "v").unwrap();
+    assert_eq!(b.get_u64(256), Some(&b"256"[..]));
+    let keys: Vec<u64> = b.range_u64(..).unwrap().iter().map(|e| e.0).collect();
+    assert_eq!(keys, vec![2, 3, 256, 1 << 40, u64::MAX]);
+    let keys: Vec<u64> = b.range_u64(3..=256).unwrap().iter().map(|e| e.0).collect();
+    assert_eq!(keys, vec![3, 256]);
+    assert!(b.range_u64(257..1 << 40).unwrap().is_empty());
+    b.delete_u64(3).unwrap();
+    assert_eq!(b.get_u64(3), None);
+    drop(b);
+
+    let mut log = tx.create_bucket("log").unwrap();
+    assert_eq!(log.sequence(), 0);
+    assert_eq!(log.put_next("first").unwrap(), 1);
+    assert_eq!(log.put_next("second").unwrap(), 2);
+    drop(log);
+    tx.commit().unwrap();
+    drop(tx);
+
+    // the sequence is saved even when only it changed
+    let tx = db.tx(true).unwrap();
+    let mut log = tx.create_bucket_if_not_exist("log").unwrap();
+    assert_eq!(log.next_sequence().unwrap(), 3);
+    drop(log);
+    tx.commit().unwrap();
+    drop(tx);
+
+    let tx = db.tx(true).unwrap();
+    let mut log = tx.create_bucket_if_not_exist("log").unwrap();
+    assert_eq!(log.sequence(), 3);
+    assert_eq!(log.put_next("fourth").unwrap(), 4);
+    let entries = log.range_u64(..).unwrap();
+    assert_eq!(
+        entries,
+        vec![(1, &b"first"[..]), (2, &b"second"[..]), (4, &b"fourth"[..])]
+    );
+}
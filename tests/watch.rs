@@ -0,0 +1,81 @@
+use roltdb::{ChangeOp, DB};
+
+#[test]
+fn watch_prefix() {
+    let path = "./tests/watch.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let events = db.watch_prefix("users", "u:");
+    {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket("users").unwrap();
+        b.put("u:1", "a").unwrap();
+        b.put("u:1", "b").unwrap();
+        b.put("u:2", "a").unwrap();
+        b.delete("u:2").unwrap();
+        b.put("other", "a").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+    let received: Vec<_> = events.try_iter().collect();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].key, b"u:1");
+    assert_eq!(received[0].op, ChangeOp::Insert);
+    {
+        let tx = db.tx(true).unwrap();
+        let mut b = tx.create_bucket_if_not_exist("users").unwrap();
+        b.delete("u:1").unwrap();
+        drop(b);
+        tx.commit().unwrap();
+    }
+    let received: Vec<_> = events.try_iter().collect();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].op, ChangeOp::Delete);
+}
+
+#[test]
+fn commit_with_changes() {
+    let path = "./tests/commit_changes.db";
+    let _ = std::fs::remove_file(path);
+    let db = DB::open(path).unwrap();
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket("users").unwrap();
+    b.put("alice", "1").unwrap();
+    b.put("bob", "1").unwrap();
+    drop(b);
+    let set = tx.commit_with_changes().unwrap();
+    assert_eq!(set.tx_id, tx.id());
+    let keys: Vec<_> = set.changes.iter().map(|e| (&e.key[..], e.op)).collect();
+    assert_eq!(
+        keys,
+        [
+            (&b"users"[..], ChangeOp::CreateBucket),
+            (&b"alice"[..], ChangeOp::Insert),
+            (&b"bob"[..], ChangeOp::Insert)
+        ]
+    );
+    drop(tx);
+
+    let tx = db.tx(true).unwrap();
+    let mut b = tx.create_bucket_if_not_exist("users").unwrap();
+    b.put("alice", "2").unwrap();
+    b.delete("bob").unwrap();
+    b.put_with_ttl("carol", "1", std::time::Duration::from_secs(60))
+        .unwrap();
+    drop(b);
+    let set = tx.commit_with_changes().unwrap();
+    // the ttl index entry of carol isn't reported
+    let keys: Vec<_> = set
+        .changes
+        .iter()
+        .map(|e| (&e.bucket[..], &e.key[..], e.op))
+        .collect();
+    assert_eq!(
+        keys,
+        [
+            (&b"users"[..], &b"alice"[..], ChangeOp::Update),
+            (&b"users"[..], &b"bob"[..], ChangeOp::Delete),
+            (&b"users"[..], &b"carol"[..], ChangeOp::Insert),
+        ]
+    );
+}